@@ -1,10 +1,30 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 mod watcher;
 
-use highrust_transpiler::{transpile_file, transpile_source, TranspilerError};
+use highrust_transpiler::check::{check_package, render, CargoCommand};
+use highrust_transpiler::codegen::AsyncRuntime;
+use highrust_transpiler::config::{Config, CONFIG_FILE};
+use highrust_transpiler::diagnostic::explanation;
+use highrust_transpiler::emit::{emit, Stage};
+use highrust_transpiler::explain::explain_position;
+use highrust_transpiler::incremental::{BuildReport, ProjectBuild};
+use highrust_transpiler::package::generate_package_in;
+use highrust_transpiler::sourcemap::{LineIndex, Position};
+use highrust_transpiler::{
+    transpile_file_to_string, transpile_file_with, transpile_project_with, TranspileOptions, TranspilerError,
+};
 use std::process;
 use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use tracing_subscriber::EnvFilter;
+
+/// Exit code when the project has errors, from the transpiler or from rustc
+const EXIT_ERRORS: i32 = 1;
+/// Exit code when the check could not run, e.g. cargo is missing or the
+/// configuration is invalid
+const EXIT_FAILURE: i32 = 2;
 
 /// HighRust Transpiler CLI
 #[derive(Parser)]
@@ -17,104 +37,506 @@ use std::fs;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Log filter for the transpiler's phases, e.g. `parser=trace` or
+    /// `ownership=debug,codegen=debug`; overrides `-v`
+    #[arg(long, global = true, value_name = "FILTER")]
+    log: Option<String>,
+    /// Log more of what the transpiler does to stderr: `-v` for info, `-vv`
+    /// for debug and `-vvv` for trace
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Transpile a HighRust source file to Rust
+    /// Transpile a HighRust source file, or a project directory, to Rust
     Transpile {
-        /// Path to the input .hrs file
+        /// Path to the input .hrs file or project directory; defaults to the
+        /// source roots of the nearest highrust.toml
         #[arg(short, long)]
-        input: String,
-        /// Path to the output .rs file
+        input: Option<String>,
+        /// Path to the output .rs file, or output directory for a project
         #[arg(short, long)]
         output: Option<String>,
+        /// Runtime for an async main: tokio, async-std or minimal
+        #[arg(long)]
+        runtime: Option<AsyncRuntime>,
+        /// Generate a complete Cargo package, in the output directory or the
+        /// one highrust.toml names
+        #[arg(long)]
+        package: bool,
+        /// End each generated statement with a `// hrs:N` comment naming its
+        /// source line
+        #[arg(long)]
+        line_comments: bool,
+        /// Write a comment above each inferred `mut`, borrow, `.clone()`,
+        /// `.to_string()` and lifetime parameter saying why it was inferred
+        #[arg(long)]
+        explain: bool,
+        /// How to report errors: rendered for people, or as one JSON object
+        /// per diagnostic on stdout. With json, stdout holds nothing else, so
+        /// give --output to keep the generated code.
+        #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+        message_format: MessageFormat,
+        /// Instead of transpiling, print the input file's forms at these
+        /// stages of the pipeline, comma-separated: tokens, ast, ownership,
+        /// ir or rust. Written to --output when given.
+        #[arg(long, value_delimiter = ',', value_name = "STAGES")]
+        emit: Vec<Stage>,
+        /// Format of the --emit output
+        #[arg(long, value_enum, default_value_t = EmitFormat::Text)]
+        emit_format: EmitFormat,
+    },
+    /// Transpile the project into its Cargo package and run `cargo check`,
+    /// reporting rustc's errors against the HighRust sources.
+    ///
+    /// Exits with 0 when there are no errors, 1 when the project has errors
+    /// (or warnings, with --deny-warnings), and 2 when the check cannot run.
+    Check(CheckArgs),
+    /// Like `check`, but runs `cargo build`
+    Build {
+        #[command(flatten)]
+        args: CheckArgs,
+        /// Build with optimizations
+        #[arg(long)]
+        release: bool,
+    },
+    /// Explain a diagnostic code, e.g. `highrust explain E0102`, or what
+    /// ownership inference decided for the binding at a position, e.g.
+    /// `highrust explain src/main.hrs:8:9`
+    Explain {
+        /// A code such as E0102, or FILE:LINE:COL naming a binding's
+        /// declaration or one of its uses
+        target: String,
     },
     /// Print version information
     Version,
-    /// Watch source files and trigger transpilation on changes (scaffold)
+    /// Transpile a project, then transpile it again as its sources change,
+    /// redoing only the modules each change affects
     Watch {
-        /// Path to the source directory or file to watch
+        /// Project directory to watch; defaults to the source roots of the
+        /// nearest highrust.toml
         #[arg(short, long)]
-        path: String,
+        path: Option<String>,
+        /// Output directory; defaults to the project directory itself, or
+        /// for highrust.toml's source roots to the one it names
+        #[arg(short, long)]
+        output: Option<String>,
     },
 }
 
+/// How diagnostics are reported.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MessageFormat {
+    /// Rendered against the source with carets, on stderr
+    Human,
+    /// One JSON object per diagnostic, on stdout
+    Json,
+}
+
+/// How `--emit` prints the pipeline's forms.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum EmitFormat {
+    /// Readable text, under a header per stage
+    Text,
+    /// One JSON object with a key per stage
+    Json,
+}
+
+/// Options of `highrust check` and `highrust build`.
+#[derive(Args)]
+struct CheckArgs {
+    /// Source directory to check instead of the source roots of highrust.toml
+    #[arg(short, long)]
+    input: Option<String>,
+    /// Directory to generate the package in instead of the one highrust.toml names
+    #[arg(short, long)]
+    output: Option<String>,
+    /// Runtime for an async main: tokio, async-std or minimal
+    #[arg(long)]
+    runtime: Option<AsyncRuntime>,
+    /// Fail on warnings as well as errors
+    #[arg(long)]
+    deny_warnings: bool,
+}
+
 fn main() {
     let cli = Cli::parse();
+    init_logging(cli.log.as_deref(), cli.verbose);
 
     match &cli.command {
-        Commands::Transpile { input, output } => {
-            println!(
-                "Transpile command invoked. Input: {}, Output: {:?}",
-                input, output
-            );
+        Commands::Transpile { input, output, runtime, package, line_comments, explain, message_format, emit, emit_format } => {
+            let format = *message_format;
+                            // Settings come from the nearest highrust.toml; flags override them
+                            let config = load_config(EXIT_ERRORS);
+                            let mut options = config.as_ref().map(Config::transpile_options).unwrap_or_default();
+                            if let Some(runtime) = runtime {
+                                options.async_runtime = *runtime;
+                            }
+                            options.line_comments |= *line_comments;
+                            options.explain |= *explain;
+                            if !emit.is_empty() {
+                                emit_or_exit(input.as_deref(), output.as_deref(), emit, *emit_format, &options, format);
+                                return;
+                            }
+                            if *package {
+                                let config = package_config(config, input.as_deref());
+                                let package_dir = output.as_ref().map_or_else(|| config.package_dir(), |dir| dir.into());
+                                match generate_package_in(&config, &package_dir, &options) {
+                                    Ok(package) if format == MessageFormat::Human => {
+                                        println!(
+                                            "Package generated in '{}': {} files, {} changed, {} removed.",
+                                            package.dir.display(),
+                                            package.files.len(),
+                                            package.changed.len(),
+                                            package.removed.len()
+                                        );
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        report_error(&e, None, format);
+                                        process::exit(1);
+                                    }
+                                }
+                                return;
+                            }
                             // Run the transpiler pipeline
-                            let input_path = input;
+                            let Some(input_path) = input else {
+                                // Without an input, transpile the configured project
+                                let Some(config) = config else {
+                                    eprintln!("No input given and no {} found", CONFIG_FILE);
+                                    process::exit(1);
+                                };
+                                for root in config.source_roots() {
+                                    transpile_project_or_exit(&root, &config.output_dir(&root), &options, format);
+                                }
+                                return;
+                            };
+                            if Path::new(input_path).is_dir() {
+                                // A project directory transpiles in place unless an output directory is given
+                                let out_dir = output.as_deref().unwrap_or(input_path);
+                                transpile_project_or_exit(Path::new(input_path), Path::new(out_dir), &options, format);
+                                return;
+                            }
                             match output {
                                 Some(output_path) => {
                                     // Output to file
-                                    match transpile_file(input_path, output_path) {
-                                        Ok(()) => {
+                                    match transpile_file_with(input_path, output_path, &options) {
+                                        Ok(()) if format == MessageFormat::Human => {
                                             println!(
                                                 "Transpilation succeeded. Rust code written to '{}'.",
                                                 output_path
                                             );
                                         }
+                                        Ok(()) => {}
                                         Err(e) => {
-                                            eprintln!("Transpilation failed: {}", format_transpiler_error(&e));
+                                            report_error(&e, Some(Path::new(input_path)), format);
                                             process::exit(1);
                                         }
                                     }
                                 }
                                 None => {
                                     // Output to stdout
-                                    match fs::read_to_string(input_path) {
-                                        Ok(source) => {
-                                            match transpile_source(&source) {
-                                                Ok(rust_code) => {
-                                                    println!("{}", rust_code);
-                                                }
-                                                Err(e) => {
-                                                    eprintln!("Transpilation failed: {}", format_transpiler_error(&e));
-                                                    process::exit(1);
-                                                }
-                                            }
+                                    match transpile_file_to_string(input_path, &options) {
+                                        Ok(rust_code) if format == MessageFormat::Human => {
+                                            println!("{}", rust_code);
                                         }
+                                        Ok(_) => {}
                                         Err(e) => {
-                                            eprintln!("Failed to read input file '{}': {}", input_path, e);
+                                            report_error(&e, Some(Path::new(input_path)), format);
                                             process::exit(1);
                                         }
                                     }
                                 }
                             }
                             
-                            /// Formats a TranspilerError for user-friendly output.
-                            fn format_transpiler_error(e: &TranspilerError) -> String {
-                                match *e {
-                                    TranspilerError::ParseError(ref msg) => format!("Parse error: {}", msg),
-                                    TranspilerError::LoweringError(ref le) => format!("Lowering error: {:?}", le),
-                                    TranspilerError::CodegenError(ref ce) => format!("Codegen error: {:?}", ce),
-                                    TranspilerError::OwnershipError(ref oe) => format!("Ownership error: {:?}", oe),
-                                    TranspilerError::IoError(ref ioe) => format!("I/O error: {}", ioe),
-                                }
-                            }
         }
+        Commands::Check(args) => check_or_build(args, CargoCommand::Check),
+        Commands::Build { args, release } => check_or_build(args, CargoCommand::Build { release: *release }),
+        Commands::Explain { target } => explain_or_exit(target),
         Commands::Version => {
             // This will print the version from Cargo.toml via clap
             println!("HighRust CLI version {}", env!("CARGO_PKG_VERSION"));
         }
-        Commands::Watch { path } => {
-            println!(
-                "Watch command scaffold invoked. Path: {}",
-                path
-            );
-            // This is a scaffold for the file watcher.
-            // When fully implemented, this will start watching the given path for changes
-            // and trigger transpilation as needed.
-            // See watcher.rs for the watcher implementation.
-            let mut watcher = watcher::Watcher::new(/* In the future: vec![PathBuf::from(path)] */);
-            let _ = watcher.watch();
+        Commands::Watch { path, output } => watch(path.as_deref(), output.as_deref()),
+    }
+}
+
+/// Sends the transpiler's log to stderr, filtered by `--log` or else by the
+/// `-v` count; warnings and errors are always shown.
+fn init_logging(filter: Option<&str>, verbose: u8) {
+    let level = match verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let filter = match filter.map(EnvFilter::try_new) {
+        Some(Ok(filter)) => filter,
+        Some(Err(e)) => {
+            eprintln!("Invalid --log filter: {}", e);
+            process::exit(EXIT_FAILURE);
+        }
+        None => EnvFilter::new(level),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .without_time()
+        .init();
+}
+
+/// Loads the nearest highrust.toml above the current directory, exiting
+/// with `exit_code` on an invalid file.
+fn load_config(exit_code: i32) -> Option<Config> {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    match Config::discover(&cwd) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            process::exit(exit_code);
+        }
+    }
+}
+
+/// The configuration a package is generated from: the loaded one, or the
+/// defaults for the current directory, with `input` as the source root.
+fn package_config(config: Option<Config>, input: Option<&str>) -> Config {
+    let mut config = config.unwrap_or_else(|| Config {
+        root: std::env::current_dir().unwrap_or_default(),
+        ..Config::default()
+    });
+    if let Some(input_path) = input {
+        config.build.source_roots = vec![Path::new(input_path).canonicalize().unwrap_or_else(|_| input_path.into())];
+    }
+    config
+}
+
+/// Generates the project's package and runs cargo on it, printing rustc's
+/// diagnostics against the HighRust sources, then exits with a status for CI.
+fn check_or_build(args: &CheckArgs, command: CargoCommand) -> ! {
+    let config = load_config(EXIT_FAILURE);
+    let mut options = config.as_ref().map(Config::transpile_options).unwrap_or_default();
+    if let Some(runtime) = args.runtime {
+        options.async_runtime = runtime;
+    }
+    let config = package_config(config, args.input.as_deref());
+    let package_dir = args.output.as_ref().map_or_else(|| config.package_dir(), |dir| dir.into());
+    let package = match generate_package_in(&config, &package_dir, &options) {
+        Ok(package) => package,
+        Err(TranspilerError::IoError(e)) => {
+            eprintln!("error: cannot write the package: {}", e);
+            process::exit(EXIT_FAILURE);
+        }
+        Err(e) => {
+            report_error(&e, None, MessageFormat::Human);
+            process::exit(EXIT_ERRORS);
+        }
+    };
+    let report = match check_package(&package.dir, command) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("error: cannot run cargo: {}", e);
+            process::exit(EXIT_FAILURE);
+        }
+    };
+    for diagnostic in &report.diagnostics {
+        eprintln!("{}", render(diagnostic));
+    }
+    let (errors, warnings) = (report.error_count(), report.warning_count());
+    if !report.success && errors == 0 {
+        // Cargo failed before rustc could report anything, e.g. resolving dependencies
+        eprint!("{}", report.cargo_output);
+        process::exit(EXIT_FAILURE);
+    }
+    let verb = if command == CargoCommand::Check { "checking" } else { "building" };
+    if errors > 0 || (args.deny_warnings && warnings > 0) {
+        eprintln!("error: {} failed: {} errors, {} warnings", verb, errors, warnings);
+        process::exit(EXIT_ERRORS);
+    }
+    eprintln!("Finished {} '{}': {} warnings", verb, package.dir.display(), warnings);
+    process::exit(0);
+}
+
+/// Prints the forms of the `input` file at `stages` of the pipeline, or
+/// writes them to `output`, exiting on failure.
+fn emit_or_exit(
+    input: Option<&str>,
+    output: Option<&str>,
+    stages: &[Stage],
+    emit_format: EmitFormat,
+    options: &TranspileOptions,
+    format: MessageFormat,
+) {
+    let Some(input_path) = input.map(Path::new).filter(|input| input.is_file()) else {
+        eprintln!("--emit needs an input .hrs file");
+        process::exit(1);
+    };
+    let source = match fs::read_to_string(input_path) {
+        Ok(source) => source,
+        Err(e) => {
+            report_error(&TranspilerError::IoError(e), Some(input_path), format);
+            process::exit(1);
+        }
+    };
+    let emitted = match emit(&source, Some(input_path), stages, options) {
+        Ok(emitted) => emitted,
+        Err(e) => {
+            report_error(&e, Some(input_path), format);
+            process::exit(1);
+        }
+    };
+    let text = match emit_format {
+        EmitFormat::Text => emitted.to_text(),
+        EmitFormat::Json => emitted.to_json() + "\n",
+    };
+    match output {
+        Some(output_path) => {
+            if let Err(e) = fs::write(output_path, text) {
+                eprintln!("Failed to write '{}': {}", output_path, e);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", text),
+    }
+}
+
+/// Prints the explanation of a diagnostic code, or of the binding at a
+/// `FILE:LINE:COL` position, exiting on failure.
+fn explain_or_exit(target: &str) {
+    let is_code = target.len() == 5 && target.starts_with(['E', 'e']) && target[1..].chars().all(|c| c.is_ascii_digit());
+    if is_code {
+        match explanation(target) {
+            Some(text) => print!("{}", text),
+            None => {
+                eprintln!("{} is not a HighRust diagnostic code", target);
+                process::exit(EXIT_ERRORS);
+            }
+        }
+        return;
+    }
+
+    let mut parts = target.rsplitn(3, ':');
+    let (Some(column), Some(line), Some(file)) = (parts.next(), parts.next(), parts.next()) else {
+        eprintln!("Expected a code such as E0102 or a position such as main.hrs:3:9, got '{}'", target);
+        process::exit(EXIT_FAILURE);
+    };
+    let (Ok(line), Ok(column)) = (line.parse(), column.parse()) else {
+        eprintln!("Invalid position '{}': line and column must be numbers", target);
+        process::exit(EXIT_FAILURE);
+    };
+    let path = Path::new(file);
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            report_error(&TranspilerError::IoError(e), Some(path), MessageFormat::Human);
+            process::exit(EXIT_FAILURE);
+        }
+    };
+    match explain_position(&source, Some(path), Position::new(line, column)) {
+        Ok(Some(binding)) => print!("{}", binding.describe(&LineIndex::new(&source))),
+        Ok(None) => {
+            eprintln!("No binding at {}", target);
+            process::exit(EXIT_ERRORS);
+        }
+        Err(e) => {
+            report_error(&e, Some(path), MessageFormat::Human);
+            process::exit(EXIT_ERRORS);
+        }
+    }
+}
+
+/// Watches the project at `path`, or else the configured source roots,
+/// transpiling into `output` or the configured output directories. Only
+/// returns when watching fails.
+fn watch(path: Option<&str>, output: Option<&str>) {
+    let config = load_config(EXIT_FAILURE);
+    let options = config.as_ref().map(Config::transpile_options).unwrap_or_default();
+    let projects: Vec<(PathBuf, PathBuf)> = match (path, &config) {
+        (Some(path), _) => vec![(PathBuf::from(path), PathBuf::from(output.unwrap_or(path)))],
+        (None, Some(config)) => config
+            .source_roots()
+            .into_iter()
+            .map(|root| {
+                let out_dir = output.map_or_else(|| config.output_dir(&root), PathBuf::from);
+                (root, out_dir)
+            })
+            .collect(),
+        (None, None) => {
+            eprintln!("No --path given and no {} found", CONFIG_FILE);
+            process::exit(EXIT_FAILURE);
+        }
+    };
+    let mut builds = Vec::new();
+    for (root, out_dir) in projects {
+        if !root.is_dir() {
+            eprintln!("Cannot watch '{}': not a project directory", root.display());
+            process::exit(EXIT_FAILURE);
         }
+        println!("Watching '{}' for changes, transpiling into '{}'.", root.display(), out_dir.display());
+        // Events name absolute paths
+        let root = root.canonicalize().unwrap_or(root);
+        builds.push(ProjectBuild::new(root, out_dir, options.clone()));
+    }
+
+    if let Err(e) = watcher::Watcher::new(builds).watch(print_build) {
+        eprintln!("Watching failed: {}", e);
+        process::exit(EXIT_FAILURE);
+    }
+}
+
+/// Prints what a build of a watched project did in a line, after the
+/// diagnostics of its errors, unless it did nothing.
+fn print_build(build: &ProjectBuild, report: &BuildReport) {
+    if report.is_empty() {
+        return;
+    }
+    for e in &report.errors {
+        report_error(e, None, MessageFormat::Human);
     }
-}
\ No newline at end of file
+    let plural = |count: usize, noun: &str| format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" });
+    let mut summary = format!(
+        "Transpiled {} into '{}': {} written, {} removed",
+        plural(report.transpiled.len(), "module"),
+        build.out_dir().display(),
+        plural(report.written.len(), "file"),
+        report.removed.len()
+    );
+    if !report.errors.is_empty() {
+        summary.push_str(&format!(", {}", plural(report.errors.len(), "error")));
+    }
+    println!("{}.", summary);
+}
+
+/// Transpiles the project under `root` into `out_dir`, exiting on failure.
+fn transpile_project_or_exit(root: &Path, out_dir: &Path, options: &TranspileOptions, format: MessageFormat) {
+    match transpile_project_with(root, out_dir, options) {
+        Ok(_) if format == MessageFormat::Json => {}
+        Ok(written) => {
+            println!("Transpilation succeeded. {} files written to '{}'.", written.len(), out_dir.display());
+        }
+        Err(e) => {
+            report_error(&e, None, format);
+            process::exit(1);
+        }
+    }
+}
+
+/// Prints a transpiler error as a diagnostic against its source: the file
+/// the error names, or else `input`. JSON goes to stdout, and rendered
+/// diagnostics to stderr.
+fn report_error(e: &TranspilerError, input: Option<&Path>, format: MessageFormat) {
+    for diagnostic in e.diagnostics() {
+        // The path as given, even when it does not exist
+        let diagnostic = match input {
+            Some(input) => diagnostic.with_file(input),
+            None => diagnostic,
+        };
+        let source = diagnostic.file.as_ref().and_then(|file| fs::read_to_string(file).ok());
+        match format {
+            MessageFormat::Human => eprintln!("{}", diagnostic.render(source.as_deref())),
+            MessageFormat::Json => println!("{}", diagnostic.to_json(source.as_deref())),
+        }
+    }
+}
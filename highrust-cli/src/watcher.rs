@@ -1,47 +1,106 @@
-//! File Watcher Skeleton for HighRust CLI
+//! Watching projects and transpiling them as their sources change.
 //!
-//! This module provides the scaffolding for a file watcher component
-//! that will monitor source files and trigger transpilation when changes are detected.
-//!
-//! # Intended Usage
-//! - The watcher will be started by the CLI (see `main.rs`).
-//! - When fully implemented, it will watch for changes in source files and
-//!   invoke the transpiler as needed.
-//! - This is a skeleton; no actual file watching or transpilation logic is present yet.
+//! `highrust watch` builds each project once, then waits for changes to the
+//! `.hrs` files under their source roots and the Rust files they import.
+//! Editors save in bursts of events (a write, a rename, a metadata change),
+//! so events are collected until none has arrived for the debounce
+//! interval, and each burst leads to one incremental rebuild of the
+//! projects it touched.
+
+use highrust_transpiler::incremental::{BuildReport, ProjectBuild};
+use highrust_transpiler::modules::SOURCE_EXTENSION;
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecursiveMode, Result as NotifyResult, Watcher as _};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use tracing::warn;
 
-use notify::{RecommendedWatcher, Result as NotifyResult, Watcher as NotifyWatcher, RecursiveMode, Event};
+/// How long the sources must be quiet before a burst of changes is built.
+pub const DEBOUNCE: Duration = Duration::from_millis(100);
 
-/// Struct representing the file watcher for the HighRust CLI.
+/// Keeps the output of projects up to date with their sources.
 pub struct Watcher {
-    // The actual watcher will be stored here in the future.
-    // watcher: RecommendedWatcher,
+    builds: Vec<ProjectBuild>,
+    debounce: Duration,
 }
 
 impl Watcher {
-    /// Create a new file watcher.
-    ///
-    /// # Arguments
-    /// * `paths` - A list of paths to watch for changes.
-    ///
-    /// # Returns
-    /// A new `Watcher` instance.
-    pub fn new(/*paths: Vec<PathBuf>*/) -> Self {
-        // Placeholder for future implementation.
-        Watcher {
-            // watcher: ...
-        }
+    /// Creates a watcher of the projects `builds` transpile. Their roots
+    /// should be absolute, as the paths of file events are.
+    pub fn new(builds: Vec<ProjectBuild>) -> Self {
+        Watcher { builds, debounce: DEBOUNCE }
     }
 
-    /// Start watching for file changes and trigger transpilation.
-    ///
-    /// This is a placeholder; no logic is implemented yet.
-    pub fn watch(&mut self) -> NotifyResult<()> {
-        // Placeholder for future implementation.
+    /// Builds every project, then rebuilds them as their sources change,
+    /// passing each build's report to `report`. Runs until watching fails;
+    /// errors of single events are logged and the events skipped.
+    pub fn watch(&mut self, mut report: impl FnMut(&ProjectBuild, &BuildReport)) -> NotifyResult<()> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        for build in &self.builds {
+            watcher.watch(build.root(), RecursiveMode::Recursive)?;
+        }
+        for build in &mut self.builds {
+            let built = build.rebuild();
+            report(build, &built);
+        }
+
+        while let Ok(event) = events.recv() {
+            let mut changed = self.source_paths(event);
+            loop {
+                match events.recv_timeout(self.debounce) {
+                    Ok(event) => changed.extend(self.source_paths(event)),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+            for build in &mut self.builds {
+                if changed.iter().any(|path| path.starts_with(build.root())) {
+                    let built = build.rebuild();
+                    report(build, &built);
+                }
+            }
+        }
         Ok(())
     }
+
+    /// The paths of `event` that may change a project's sources: `.hrs`
+    /// files, the Rust files they import, and directories removed or
+    /// renamed with the files in them.
+    /// Hidden directories and `target` are not sources, as when building.
+    /// An error in place of the event is logged and changes nothing.
+    fn source_paths(&self, event: NotifyResult<Event>) -> Vec<PathBuf> {
+        let event = match event {
+            Ok(event) => event,
+            Err(error) => {
+                warn!(target: "watch", %error, "watching failed for an event");
+                return Vec::new();
+            }
+        };
+        let moves_directories = matches!(event.kind, EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)));
+        if matches!(event.kind, EventKind::Access(_)) {
+            return Vec::new();
+        }
+        event
+            .paths
+            .into_iter()
+            .filter(|path| match path.extension() {
+                Some(extension) if extension == "rs" => self.builds.iter().any(|build| build.imports_rust_file(path)),
+                Some(extension) => extension == SOURCE_EXTENSION,
+                None => moves_directories,
+            })
+            .filter(|path| self.builds.iter().any(|build| is_source_path(build.root(), path)))
+            .collect()
+    }
 }
 
-// Additional documentation:
-// - When implemented, this module will use the `notify` crate to watch for file changes.
-// - It will communicate with the transpiler to trigger recompilation as needed.
-// - See `main.rs` for how to start the watcher from the CLI.
\ No newline at end of file
+/// Whether `path` is under `root` outside hidden directories and `target`.
+fn is_source_path(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root).is_ok_and(|relative| {
+        relative.components().all(|part| match part {
+            Component::Normal(name) => name != "target" && !name.to_string_lossy().starts_with('.'),
+            _ => false,
+        })
+    })
+}
@@ -0,0 +1,65 @@
+//! Tests for the `highrust` command-line interface.
+//!
+//! These tests run the built binary on scratch files and check that its
+//! output matches what the transpiler library writes, and that errors name
+//! the file they are about.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Creates an empty scratch directory for a test.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("highrust-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs the CLI with `args` from `dir`.
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_highrust-cli")).args(args).current_dir(dir).output().unwrap()
+}
+
+/// Runs the CLI with `args` from `dir`, panicking if it fails.
+fn highrust(dir: &Path, args: &[&str]) -> Output {
+    let output = run(dir, args);
+    assert!(output.status.success(), "highrust failed: {}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[test]
+fn test_stdout_matches_output_file_with_rust_imports() {
+    let root = scratch_dir("stdout-imports");
+    fs::write(root.join("parsing.rs"), "pub fn fill(data: &mut Vec<u8>) { data.push(0); }").unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(
+        root.join("src/main.hrs"),
+        "import rust \"../parsing.rs\" as parsing
+         fn main() {
+             let data: Vec<u8> = vec(1, 2);
+             parsing::fill(data);
+         }",
+    )
+    .unwrap();
+
+    // Imports resolve from the input's directory, not the working directory
+    let stdout = highrust(&root, &["transpile", "-i", "src/main.hrs"]).stdout;
+    highrust(&root, &["transpile", "-i", "src/main.hrs", "-o", "main.rs"]);
+    let written = fs::read_to_string(root.join("main.rs")).unwrap();
+    assert!(written.contains("parsing::fill(&mut data);"), "got: {}", written);
+    assert_eq!(String::from_utf8_lossy(&stdout), format!("{}\n", written));
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_json_error_for_missing_input_names_the_file() {
+    let root = scratch_dir("missing-input");
+    for args in [&["-o", "main.rs"][..], &[]] {
+        let output = run(&root, &[&["transpile", "-i", "missing.hrs", "--message-format", "json"][..], args].concat());
+        assert!(!output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("\"file\":\"missing.hrs\""), "got: {}", stdout);
+    }
+    let _ = fs::remove_dir_all(&root);
+}
@@ -7,48 +7,72 @@
 //! Each node includes documentation and, where appropriate, source position information
 //! for diagnostics and source mapping.
 
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// Represents a span in the source file for diagnostics and source mapping.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
 }
 
 /// The root of a HighRust AST: a module (source file).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Module {
     pub items: Vec<ModuleItem>,
     pub span: Span,
 }
 
 /// Top-level items in a module.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ModuleItem {
     Import(Import),
     Export(Export),
     Data(DataDef),
     Function(FunctionDef),
     EmbeddedRust(EmbeddedRustBlock),
+    /// Source that does not parse, kept by error recovery in place of the
+    /// item it was meant to be
+    Error(Span),
 }
 
 /// Import statement (e.g., `import foo::bar` or `import rust "foo.rs"`).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Import {
     pub path: Vec<String>,
+    /// Names imported from the module at `path`, as in `import { a, b as c } from foo`;
+    /// empty when `path` itself names what is imported
+    pub names: Vec<ImportName>,
+    /// Alias of a path import, as in `import foo::bar as baz`
+    pub alias: Option<String>,
     pub is_rust: bool,
+    /// The file of `import rust "foo.rs"`, relative to the importing file;
+    /// `path` is then the name of the module it becomes
+    pub rust_file: Option<String>,
+    /// Public functions and structs read from the imported Rust source, named
+    /// as the importing module refers to them (e.g. `foo::parse`)
+    pub items: Vec<ModuleItem>,
+    pub span: Span,
+}
+
+/// A name in an import list, with an optional alias.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportName {
+    pub name: String,
+    pub alias: Option<String>,
     pub span: Span,
 }
 
 /// Export statement (e.g., `export foo`).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Export {
     pub name: String,
     pub span: Span,
 }
 
 /// Data type definition: struct, enum, or tagged union.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataDef {
     pub name: String,
     pub kind: DataKind,
@@ -57,7 +81,7 @@ pub struct DataDef {
 }
 
 /// Kinds of data types.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DataKind {
     Struct(Vec<Field>),
     Enum(Vec<EnumVariant>),
@@ -65,7 +89,7 @@ pub enum DataKind {
 }
 
 /// Field in a struct or record.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Field {
     pub name: String,
     pub ty: Type,
@@ -73,7 +97,7 @@ pub struct Field {
 }
 
 /// Enum variant.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnumVariant {
     pub name: String,
     pub fields: Vec<Field>,
@@ -81,7 +105,7 @@ pub struct EnumVariant {
 }
 
 /// Tagged union variant.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaggedVariant {
     pub tag: String,
     pub ty: Type,
@@ -89,26 +113,26 @@ pub struct TaggedVariant {
 }
 
 /// Type parameter for generics.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TypeParam {
     pub name: String,
     pub span: Span,
 }
 
 /// Function definition (sync or async).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDef {
     pub name: String,
     pub params: Vec<Param>,
     pub ret_type: Option<Type>,
     pub body: Block,
     pub is_async: bool,
-    pub is_rust: bool, // true if @rust function
+    pub is_rust: bool, // true if @rust function; the body is then one EmbeddedRust statement holding the whole function
     pub span: Span,
 }
 
 /// Function parameter.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Param {
     pub name: String,
     pub ty: Option<Type>,
@@ -116,14 +140,14 @@ pub struct Param {
 }
 
 /// Block of statements.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
     pub stmts: Vec<Stmt>,
     pub span: Span,
 }
 
 /// Statements in HighRust.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Stmt {
     Let {
         pattern: Pattern,
@@ -132,6 +156,9 @@ pub enum Stmt {
         span: Span,
     },
     Expr(Expr),
+    /// Final expression of a block without a trailing semicolon; its value is
+    /// the value of the block.
+    TailExpr(Expr),
     Return(Option<Expr>, Span),
     If {
         cond: Expr,
@@ -157,14 +184,17 @@ pub enum Stmt {
     },
     Try {
         block: Block,
-        catch: Option<Block>,
+        catch: Option<CatchClause>,
         span: Span,
     },
     EmbeddedRust(EmbeddedRustBlock),
+    /// Source that does not parse, kept by error recovery in place of the
+    /// statement it was meant to be
+    Error(Span),
 }
 
 /// Expressions in HighRust.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Expr {
     Literal(Literal, Span),
     Variable(String, Span),
@@ -179,15 +209,27 @@ pub enum Expr {
         field: String,
         span: Span,
     },
+    /// Optional chaining `base?.field`; as a call's callee, `base?.method(args)`
+    OptionalChain {
+        base: Box<Expr>,
+        field: String,
+        span: Span,
+    },
+    Struct {
+        name: String,
+        fields: Vec<(String, Expr)>,
+        span: Span,
+    },
     Block(Block),
     Await {
         expr: Box<Expr>,
         span: Span,
     },
+    /// List comprehension `[body for pat in iter if cond]`; clauses apply left
+    /// to right and the first is always a `for`
     Comprehension {
-        pattern: Pattern,
-        iterable: Box<Expr>,
         body: Box<Expr>,
+        clauses: Vec<ComprehensionClause>,
         span: Span,
     },
     Match {
@@ -199,8 +241,123 @@ pub enum Expr {
     // Add more as needed (e.g., binary ops, unary ops)
 }
 
+impl Expr {
+    /// Returns the source span covered by this expression.
+    pub fn span(&self) -> &Span {
+        match self {
+            Expr::Literal(_, span)
+            | Expr::Variable(_, span)
+            | Expr::Wildcard(span)
+            | Expr::Try(_, span) => span,
+            Expr::Call { span, .. }
+            | Expr::FieldAccess { span, .. }
+            | Expr::OptionalChain { span, .. }
+            | Expr::Struct { span, .. }
+            | Expr::Await { span, .. }
+            | Expr::Comprehension { span, .. }
+            | Expr::Match { span, .. } => span,
+            Expr::Block(block) => &block.span,
+        }
+    }
+
+    /// Returns true if the expression awaits, directly or in a subexpression.
+    pub fn contains_await(&self) -> bool {
+        match self {
+            Expr::Await { .. } => true,
+            Expr::Literal(..) | Expr::Variable(..) | Expr::Wildcard(_) => false,
+            Expr::Call { func, args, .. } => func.contains_await() || args.iter().any(Expr::contains_await),
+            Expr::FieldAccess { base, .. } | Expr::OptionalChain { base, .. } => base.contains_await(),
+            Expr::Struct { fields, .. } => fields.iter().any(|(_, value)| value.contains_await()),
+            Expr::Block(block) => block.contains_await(),
+            Expr::Comprehension { body, clauses, .. } => {
+                body.contains_await()
+                    || clauses.iter().any(|clause| match clause {
+                        ComprehensionClause::For { iterable: expr, .. } | ComprehensionClause::If(expr) => expr.contains_await(),
+                    })
+            }
+            Expr::Match { expr, arms, .. } => expr.contains_await() || arms.iter().any(MatchArm::contains_await),
+            Expr::Try(inner, _) => inner.contains_await(),
+        }
+    }
+}
+
+impl FunctionDef {
+    /// Returns true if the function lowers to an `async fn`: it is declared
+    /// async or awaits in its body.
+    pub fn lowers_async(&self) -> bool {
+        self.is_async || self.body.contains_await()
+    }
+}
+
+impl Block {
+    /// Returns true if any expression in the block awaits, making the
+    /// enclosing function async.
+    pub fn contains_await(&self) -> bool {
+        self.stmts.iter().any(Stmt::contains_await)
+    }
+}
+
+impl Stmt {
+    /// Returns the source span covered by this statement.
+    pub fn span(&self) -> &Span {
+        match self {
+            Stmt::Expr(expr) | Stmt::TailExpr(expr) => expr.span(),
+            Stmt::EmbeddedRust(block) => &block.span,
+            Stmt::Return(_, span)
+            | Stmt::Let { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Match { span, .. }
+            | Stmt::Try { span, .. }
+            | Stmt::Error(span) => span,
+        }
+    }
+
+    /// Returns true if any expression in the statement awaits.
+    pub fn contains_await(&self) -> bool {
+        match self {
+            Stmt::Let { value: expr, .. }
+            | Stmt::Expr(expr)
+            | Stmt::TailExpr(expr)
+            | Stmt::Return(Some(expr), _) => expr.contains_await(),
+            Stmt::Return(None, _) | Stmt::EmbeddedRust(_) | Stmt::Error(_) => false,
+            Stmt::If { cond, then_branch, else_branch, .. } => {
+                cond.contains_await()
+                    || then_branch.contains_await()
+                    || else_branch.as_ref().is_some_and(Block::contains_await)
+            }
+            Stmt::While { cond: expr, body, .. } | Stmt::For { iterable: expr, body, .. } => {
+                expr.contains_await() || body.contains_await()
+            }
+            Stmt::Match { expr, arms, .. } => expr.contains_await() || arms.iter().any(MatchArm::contains_await),
+            Stmt::Try { block, catch, .. } => {
+                block.contains_await() || catch.as_ref().is_some_and(|c| c.body.contains_await())
+            }
+        }
+    }
+}
+
+impl MatchArm {
+    /// Returns true if the arm's guard or body awaits.
+    pub fn contains_await(&self) -> bool {
+        self.guard.as_ref().is_some_and(|guard| guard.contains_await()) || self.expr.contains_await()
+    }
+}
+
+/// `for` or `if` clause of a list comprehension.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ComprehensionClause {
+    For {
+        pattern: Pattern,
+        iterable: Expr,
+        span: Span,
+    },
+    If(Expr),
+}
+
 /// Pattern for let/match destructuring.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Pattern {
     Wildcard(Span),
     Variable(String, Span),
@@ -220,8 +377,30 @@ pub enum Pattern {
     Literal(Literal, Span),
 }
 
+impl Pattern {
+    /// Returns the source span covered by this pattern.
+    pub fn span(&self) -> &Span {
+        match self {
+            Pattern::Wildcard(span)
+            | Pattern::Variable(_, span)
+            | Pattern::Tuple(_, span)
+            | Pattern::TuplePair(_, _, span)
+            | Pattern::Literal(_, span) => span,
+            Pattern::Struct { span, .. } | Pattern::Enum { span, .. } => span,
+        }
+    }
+}
+
+/// `catch` clause of a try statement, binding the error to a name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatchClause {
+    pub binding: String,
+    pub body: Block,
+    pub span: Span,
+}
+
 /// Match arm for match statements.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchArm {
     pub pattern: Pattern,
     pub guard: Option<Box<Expr>>,
@@ -230,9 +409,9 @@ pub struct MatchArm {
 }
 
 /// Literal values.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Literal {
-    Int(i64),
+    Int(i128),
     Float(f64),
     Bool(bool),
     String(String),
@@ -240,14 +419,14 @@ pub enum Literal {
 }
 
 /// Embedded Rust block or @rust function.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddedRustBlock {
     pub code: String,
     pub span: Span,
 }
 
 /// Type annotation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Type {
     Named(String, Vec<Type>), // e.g., Foo, Option<T>, Result<T, E>
     Option(Box<Type>),        // Option<T>
@@ -255,4 +434,37 @@ pub enum Type {
     Tuple(Vec<Type>),
     Array(Box<Type>),
     // TODO: Function types, generics, etc.
+}
+
+/// Formats a type using Rust syntax, for diagnostics.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter<'_>, types: &[Type]) -> fmt::Result {
+            for (i, ty) in types.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", ty)?;
+            }
+            Ok(())
+        }
+        match self {
+            Type::Named(name, args) if name == "&" && args.len() == 1 => write!(f, "&{}", args[0]),
+            Type::Named(name, args) if name == "&mut" && args.len() == 1 => write!(f, "&mut {}", args[0]),
+            Type::Named(name, args) if args.is_empty() => write!(f, "{}", name),
+            Type::Named(name, args) => {
+                write!(f, "{}<", name)?;
+                list(f, args)?;
+                write!(f, ">")
+            }
+            Type::Option(inner) => write!(f, "Option<{}>", inner),
+            Type::Result(ok, err) => write!(f, "Result<{}, {}>", ok, err),
+            Type::Tuple(types) => {
+                write!(f, "(")?;
+                list(f, types)?;
+                write!(f, ")")
+            }
+            Type::Array(inner) => write!(f, "[{}]", inner),
+        }
+    }
 }
\ No newline at end of file
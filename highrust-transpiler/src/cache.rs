@@ -0,0 +1,220 @@
+//! On-disk cache of transpilation results, so repeated builds skip the work
+//! their unchanged modules already had done.
+//!
+//! A [`Cache`] keeps three kinds of entries per module: the AST parsed from
+//! its source, the ownership analysis of it, and the Rust generated from it.
+//! Each entry is a JSON file named by a hash of everything it was computed
+//! from, under a directory for the transpiler's version and the format of its
+//! entries, so entries never go stale: a changed input is a different key,
+//! and looking it up misses.
+//!
+//! An AST depends on the source alone. The analysis and the Rust of a module
+//! also depend on what its imports bring into scope from other modules, but
+//! only through their signatures: a module's key covers the items it imports
+//! without their spans and function bodies, so editing the body of an
+//! exported function leaves the modules importing it cached, while changing
+//! its parameters does not. The Rust also depends on the options it was
+//! generated with.
+//!
+//! The cache is only an optimization: an entry that cannot be read or
+//! written is a miss, and nothing fails because of it.
+
+use crate::ast::{Module, ModuleItem};
+use crate::codegen::GeneratedCode;
+use crate::ownership::OwnershipAnalysisResult;
+use crate::parser::ParseError;
+use crate::{parse_source, TranspileOptions};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Directory of a project's cache, relative to the directory holding its
+/// `highrust.toml`.
+pub const CACHE_DIR: &str = "target/highrust/cache";
+
+/// Version of the layout of cache entries. Bump it whenever the ASTs,
+/// analyses or generated code stored change shape or meaning, so builds do
+/// not read entries an earlier build of the same version stored.
+pub const CACHE_FORMAT: u32 = 1;
+
+/// The kinds of entries a cache keeps, each in a directory of its own.
+const AST: &str = "ast";
+const OWNERSHIP: &str = "ownership";
+const RUST: &str = "rust";
+
+/// A directory of cached ASTs, ownership analyses and generated Rust.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Opens the cache in `dir`, keeping this version's entries in a
+    /// subdirectory named after it and [`CACHE_FORMAT`]. Nothing is created
+    /// until stored.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Cache::with_format(dir, CACHE_FORMAT)
+    }
+
+    /// Opens the cache in `dir` for entries of `format` rather than
+    /// [`CACHE_FORMAT`].
+    pub fn with_format(dir: impl AsRef<Path>, format: u32) -> Self {
+        Cache { dir: dir.as_ref().join(format!("{}-{}", env!("CARGO_PKG_VERSION"), format)) }
+    }
+
+    /// The directory this version's entries of this format are kept in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Parses `source`, the contents of `file`, or returns the AST cached
+    /// for it. Sources that do not parse are not cached.
+    pub fn parse(&self, file: &Path, source: &str) -> Result<Module, ParseError> {
+        let key = hash(&[source]);
+        if let Some(ast) = self.get(AST, &key) {
+            debug!(target: "cache", file = %file.display(), "cached AST");
+            return Ok(ast);
+        }
+        info!(target: "parser", file = %file.display(), "parsing");
+        let ast = parse_source(source)?;
+        self.put(AST, &key, &ast);
+        Ok(ast)
+    }
+
+    /// Returns the entry of `kind` stored under `key`, if there is one that
+    /// can be read.
+    pub(crate) fn get<T: DeserializeOwned>(&self, kind: &str, key: &str) -> Option<T> {
+        let path = self.entry(kind, key);
+        let json = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&json) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                debug!(target: "cache", entry = %path.display(), %error, "unreadable entry");
+                None
+            }
+        }
+    }
+
+    /// Stores `value` as the entry of `kind` under `key`. The entry is
+    /// written next to its place and then renamed into it, so concurrent
+    /// builds never read half an entry.
+    pub(crate) fn put<T: Serialize>(&self, kind: &str, key: &str, value: &T) {
+        let path = self.entry(kind, key);
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        let stored = serde_json::to_string(value).map_err(std::io::Error::from).and_then(|json| {
+            std::fs::create_dir_all(self.dir.join(kind))?;
+            std::fs::write(&temporary, json)?;
+            std::fs::rename(&temporary, &path)
+        });
+        if let Err(error) = stored {
+            debug!(target: "cache", entry = %path.display(), %error, "entry not stored");
+            let _ = std::fs::remove_file(&temporary);
+        }
+    }
+
+    /// The file of the entry of `kind` under `key`.
+    fn entry(&self, kind: &str, key: &str) -> PathBuf {
+        self.dir.join(kind).join(key).with_extension("json")
+    }
+}
+
+/// The key of the ownership analysis of a module parsed from `source`, with
+/// its imports linked: its source and the signatures it imports.
+pub(crate) fn ownership_key(ast: &Module, source: &str) -> String {
+    hash(&[source, &interface(ast)])
+}
+
+/// The key of the Rust generated from a module whose analysis is under
+/// `ownership_key`, with `options`.
+pub(crate) fn rust_key(ownership_key: &str, options: &TranspileOptions) -> String {
+    let options = format!("{:?} {} {}", options.async_runtime, options.line_comments, options.explain);
+    hash(&[ownership_key, &options])
+}
+
+/// The ownership analysis cached under `key` in `cache`, or else the one
+/// `analyze` computes, cached for next time.
+pub(crate) fn ownership(
+    cache: Option<&Cache>,
+    key: &str,
+    analyze: impl FnOnce() -> OwnershipAnalysisResult,
+) -> OwnershipAnalysisResult {
+    if let Some(analysis) = cache.and_then(|cache| cache.get(OWNERSHIP, key)) {
+        return analysis;
+    }
+    let analysis = analyze();
+    if let Some(cache) = cache {
+        cache.put(OWNERSHIP, key, &analysis);
+    }
+    analysis
+}
+
+/// The Rust cached under `key` in `cache`, or else what `generate`
+/// generates, cached for next time unless it failed.
+pub(crate) fn rust<E>(
+    cache: Option<&Cache>,
+    key: &str,
+    generate: impl FnOnce() -> Result<GeneratedCode, E>,
+) -> Result<GeneratedCode, E> {
+    if let Some(generated) = cache.and_then(|cache| cache.get(RUST, key)) {
+        debug!(target: "cache", key, "cached Rust");
+        return Ok(generated);
+    }
+    let generated = generate()?;
+    if let Some(cache) = cache {
+        cache.put(RUST, key, &generated);
+    }
+    Ok(generated)
+}
+
+/// What a module's imports bring into scope from other modules, as JSON
+/// without spans or function bodies: the part of other modules that the
+/// module's analysis and Rust depend on.
+fn interface(ast: &Module) -> String {
+    let imports: Vec<serde_json::Value> = ast
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::Import(import) => {
+                let mut import = import.clone();
+                for item in &mut import.items {
+                    if let ModuleItem::Function(func) = item {
+                        func.body.stmts.clear();
+                    }
+                }
+                let mut json = serde_json::to_value(&import).ok()?;
+                strip_spans(&mut json);
+                Some(json)
+            }
+            _ => None,
+        })
+        .collect();
+    serde_json::Value::Array(imports).to_string()
+}
+
+/// Removes every `span` field from `json`.
+fn strip_spans(json: &mut serde_json::Value) {
+    match json {
+        serde_json::Value::Object(fields) => {
+            fields.remove("span");
+            fields.values_mut().for_each(strip_spans);
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(strip_spans),
+        _ => {}
+    }
+}
+
+/// Hashes `parts` with 128-bit FNV-1a, each after its length so that no two
+/// lists of parts run together into the same bytes, as a hexadecimal key.
+fn hash(parts: &[&str]) -> String {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    let mut hash = OFFSET;
+    for part in parts {
+        for byte in (part.len() as u64).to_le_bytes().iter().chain(part.as_bytes()) {
+            hash ^= u128::from(*byte);
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    format!("{:032x}", hash)
+}
@@ -0,0 +1,352 @@
+//! Checking generated packages with cargo.
+//!
+//! `cargo check` (or `cargo build`) runs on a generated package with JSON
+//! messages, and each span rustc reports is mapped through the source map of
+//! its file back to the HighRust source. Source maps locate the statement a
+//! span falls in; the span's text is then looked up in that statement's
+//! source, so spans of code written the same way in both languages keep
+//! their exact columns.
+
+use crate::diagnostic::{display_path, write_markers};
+use crate::sourcemap::{LineIndex, Position, SourceMap};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Cargo command run on a generated package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CargoCommand {
+    Check,
+    Build { release: bool },
+}
+
+/// A range of a file, with the text of its first line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: PathBuf,
+    pub start: Position,
+    /// End of the range, exclusive
+    pub end: Position,
+    pub line_text: String,
+}
+
+/// A span rustc reported, in the generated Rust and, when it maps, in the
+/// HighRust source.
+#[derive(Debug, Clone)]
+pub struct MappedSpan {
+    pub generated: Location,
+    pub source: Option<Location>,
+    pub label: Option<String>,
+    pub is_primary: bool,
+}
+
+/// A diagnostic rustc reported on a generated package.
+#[derive(Debug, Clone)]
+pub struct RustDiagnostic {
+    /// `error` or `warning`
+    pub level: String,
+    /// Error code, e.g. `E0308`
+    pub code: Option<String>,
+    pub message: String,
+    pub spans: Vec<MappedSpan>,
+    /// Notes and help attached to the diagnostic, e.g. `help: consider borrowing here`
+    pub notes: Vec<String>,
+}
+
+impl RustDiagnostic {
+    pub fn is_error(&self) -> bool {
+        self.level == "error"
+    }
+}
+
+/// Result of running cargo on a generated package.
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub diagnostics: Vec<RustDiagnostic>,
+    /// Whether cargo finished successfully
+    pub success: bool,
+    /// Cargo's own output on stderr, for failures rustc did not report
+    pub cargo_output: String,
+}
+
+impl CheckReport {
+    pub fn error_count(&self) -> usize {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.is_error()).count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.level == "warning").count()
+    }
+}
+
+/// Runs `command` on the package in `dir`, mapping rustc's diagnostics back
+/// to the HighRust sources. Fails only if cargo cannot be run.
+pub fn check_package(dir: &Path, command: CargoCommand) -> std::io::Result<CheckReport> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut cargo = Command::new(cargo);
+    match command {
+        CargoCommand::Check => cargo.arg("check"),
+        CargoCommand::Build { release: false } => cargo.arg("build"),
+        CargoCommand::Build { release: true } => cargo.args(["build", "--release"]),
+    };
+    let output = cargo
+        .arg("--message-format=json")
+        .arg("--manifest-path")
+        .arg(dir.join("Cargo.toml"))
+        .current_dir(dir)
+        .output()?;
+    let mut report = parse_messages(&String::from_utf8_lossy(&output.stdout), dir);
+    report.success &= output.status.success();
+    report.cargo_output = String::from_utf8_lossy(&output.stderr).into_owned();
+    Ok(report)
+}
+
+/// Reads the JSON messages of a cargo run on the package in `dir`.
+pub fn parse_messages(messages: &str, dir: &Path) -> CheckReport {
+    let mut mapper = Mapper { dir, sources: HashMap::new() };
+    let mut report = CheckReport { success: true, ..CheckReport::default() };
+    for line in messages.lines() {
+        let Ok(message) = serde_json::from_str::<CargoMessage>(line) else { continue };
+        match message.reason.as_str() {
+            "compiler-message" => {
+                if let Some(message) = message.message.filter(is_reported) {
+                    report.diagnostics.push(mapper.diagnostic(message));
+                }
+            }
+            "build-finished" => report.success = message.success.unwrap_or(false),
+            _ => {}
+        }
+    }
+    report
+}
+
+/// Returns false for rustc's summaries, e.g. "aborting due to 2 previous
+/// errors", which repeat what the other diagnostics say.
+fn is_reported(message: &CompilerMessage) -> bool {
+    matches!(message.level.as_str(), "error" | "warning")
+        && !(message.spans.is_empty()
+            && (message.message.starts_with("aborting due to") || message.message.ends_with("emitted")))
+}
+
+/// A message of `cargo --message-format=json`.
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<CompilerMessage>,
+    success: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct CompilerMessage {
+    message: String,
+    level: String,
+    code: Option<CompilerCode>,
+    #[serde(default)]
+    spans: Vec<CompilerSpan>,
+    #[serde(default)]
+    children: Vec<CompilerMessage>,
+}
+
+#[derive(Deserialize)]
+struct CompilerCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct CompilerSpan {
+    file_name: String,
+    line_start: usize,
+    line_end: usize,
+    column_start: usize,
+    column_end: usize,
+    is_primary: bool,
+    label: Option<String>,
+    #[serde(default)]
+    text: Vec<SpanLine>,
+    suggested_replacement: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SpanLine {
+    text: String,
+    highlight_start: usize,
+    highlight_end: usize,
+}
+
+/// Maps spans of a package's files through their source maps.
+struct Mapper<'a> {
+    dir: &'a Path,
+    /// Source map and HighRust source of each generated file, if it has them
+    sources: HashMap<PathBuf, Option<(SourceMap, LineIndex)>>,
+}
+
+impl Mapper<'_> {
+    fn diagnostic(&mut self, message: CompilerMessage) -> RustDiagnostic {
+        let mut spans: Vec<MappedSpan> = message.spans.iter().map(|span| self.span(span)).collect();
+        spans.sort_by_key(|span| !span.is_primary);
+        let notes = message
+            .children
+            .iter()
+            .map(|child| {
+                let replacement = child.spans.iter().find_map(|span| span.suggested_replacement.as_ref());
+                match replacement {
+                    Some(replacement) if !replacement.is_empty() => {
+                        format!("{}: {}: `{}`", child.level, child.message, replacement)
+                    }
+                    _ => format!("{}: {}", child.level, child.message),
+                }
+            })
+            .collect();
+        RustDiagnostic {
+            level: message.level,
+            code: message.code.map(|code| code.code),
+            message: message.message,
+            spans,
+            notes,
+        }
+    }
+
+    fn span(&mut self, span: &CompilerSpan) -> MappedSpan {
+        let file = self.dir.join(&span.file_name);
+        let (line_text, snippet) = match span.text.first() {
+            Some(line) => {
+                let highlight: String = line
+                    .text
+                    .chars()
+                    .skip(line.highlight_start.saturating_sub(1))
+                    .take(line.highlight_end.saturating_sub(line.highlight_start))
+                    .collect();
+                (line.text.clone(), highlight)
+            }
+            None => (String::new(), String::new()),
+        };
+        let start = Position::new(span.line_start, span.column_start);
+        let end = Position::new(span.line_end, span.column_end);
+        let source = self.sources(&file).and_then(|(map, source)| {
+            let (start, end) = locate(map, source, start, &snippet)?;
+            Some(Location {
+                file: map.source.clone()?,
+                start,
+                end,
+                line_text: source.line(start.line).unwrap_or("").to_string(),
+            })
+        });
+        MappedSpan {
+            generated: Location { file, start, end, line_text },
+            source,
+            label: span.label.clone(),
+            is_primary: span.is_primary,
+        }
+    }
+
+    /// The source map of `file` and the HighRust source it maps to.
+    fn sources(&mut self, file: &Path) -> Option<&(SourceMap, LineIndex)> {
+        self.sources
+            .entry(file.to_path_buf())
+            .or_insert_with(|| {
+                let map = SourceMap::read_for(file).ok()?;
+                let source = std::fs::read_to_string(map.source.as_ref()?).ok()?;
+                Some((map, LineIndex::new(&source)))
+            })
+            .as_ref()
+    }
+}
+
+/// Finds the source range of the generated code at `start` whose first line
+/// reads `snippet`: the mapping at or before `start` gives the statement, and
+/// the snippet is searched for in the statement's lines, preferring the
+/// column the mapping predicts. Without a match, the range is the start of
+/// the statement.
+fn locate(map: &SourceMap, source: &LineIndex, start: Position, snippet: &str) -> Option<(Position, Position)> {
+    let mapping = map.mapping_at(start)?;
+    let lines_down = start.line - mapping.generated.line;
+    let expected_column = (lines_down == 0).then(|| mapping.source.column + (start.column - mapping.generated.column));
+    let next_line = map.mappings.iter().map(|m| m.source.line).find(|&line| line > mapping.source.line);
+    let last_line = next_line.map_or(mapping.source.line + lines_down, |next| next - 1).max(mapping.source.line);
+    let width = snippet.chars().count();
+    if width > 0 {
+        let mut best: Option<(usize, Position)> = None;
+        for line in mapping.source.line..=last_line {
+            let Some(text) = source.line(line) else { break };
+            for (offset, _) in text.match_indices(snippet) {
+                let column = text[..offset].chars().count() + 1;
+                let distance = match expected_column {
+                    Some(expected) if line == mapping.source.line => column.abs_diff(expected),
+                    _ => usize::MAX / 2 + line.abs_diff(mapping.source.line + lines_down),
+                };
+                if best.is_none_or(|(best, _)| distance < best) {
+                    best = Some((distance, Position::new(line, column)));
+                }
+            }
+        }
+        if let Some((_, found)) = best {
+            return Some((found, Position::new(found.line, found.column + width)));
+        }
+    }
+    let found = mapping.source;
+    Some((found, Position::new(found.line, found.column + 1)))
+}
+
+/// Renders a diagnostic against the HighRust source, followed by the
+/// generated Rust it was reported on.
+///
+/// ```text
+/// error[E0425]: cannot find function `missing` in this scope
+///  --> src/main.hrs:2:5
+///   |
+/// 2 |     missing(1);
+///   |     ^^^^^^^ not found in this scope
+///   |
+///   = generated: target/highrust/package/src/main.rs:3:5
+///   |
+/// 3 |     missing(1);
+///   |     ^^^^^^^
+/// ```
+pub fn render(diagnostic: &RustDiagnostic) -> String {
+    let mut output = String::new();
+    let code = diagnostic.code.as_ref().map(|code| format!("[{}]", code)).unwrap_or_default();
+    let _ = writeln!(output, "{}{}: {}", diagnostic.level, code, diagnostic.message);
+    let width = diagnostic
+        .spans
+        .iter()
+        .flat_map(|span| [Some(&span.generated), span.source.as_ref()])
+        .flatten()
+        .map(|location| location.start.line.to_string().len())
+        .max()
+        .unwrap_or(1);
+    let gutter = " ".repeat(width);
+
+    for span in &diagnostic.spans {
+        let marker = if span.is_primary { '^' } else { '-' };
+        let label = span.label.as_deref().unwrap_or("");
+        let shown = span.source.as_ref().unwrap_or(&span.generated);
+        let arrow = if span.is_primary { "-->" } else { ":::" };
+        let _ = writeln!(output, "{}{} {}", gutter, arrow, display_location(shown));
+        let _ = writeln!(output, "{} |", gutter);
+        write_snippet(&mut output, shown, marker, label, width);
+        if let (Some(_), true) = (&span.source, span.is_primary) {
+            let _ = writeln!(output, "{} |", gutter);
+            let _ = writeln!(output, "{} = generated: {}", gutter, display_location(&span.generated));
+            let _ = writeln!(output, "{} |", gutter);
+            write_snippet(&mut output, &span.generated, marker, "", width);
+        }
+    }
+    for note in &diagnostic.notes {
+        let _ = writeln!(output, "{} = {}", gutter, note);
+    }
+    output
+}
+
+/// Writes the first line of `location` with `marker`s under its range.
+fn write_snippet(output: &mut String, location: &Location, marker: char, label: &str, width: usize) {
+    let _ = writeln!(output, "{:>width$} | {}", location.start.line, location.line_text, width = width);
+    write_markers(output, &location.line_text, location.start, location.end, marker, label, width);
+}
+
+/// `file:line:column`, with the file relative to the current directory when
+/// it is inside it.
+fn display_location(location: &Location) -> String {
+    format!("{}:{}:{}", display_path(&location.file), location.start.line, location.start.column)
+}
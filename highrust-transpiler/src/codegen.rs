@@ -4,15 +4,22 @@
 //! The main entry point is [`generate_rust_code`], which transforms the
 //! lowered IR into valid Rust code.
 
+use crate::ast::Span;
+use serde::{Deserialize, Serialize};
 use crate::lowering::{
-    LoweredBlock, LoweredData, LoweredDataKind, LoweredEnumVariant, LoweredExpr,
-    LoweredFunction, LoweredItem, LoweredLiteral, LoweredModule, LoweredParam, LoweredStmt,
-    LoweredType, LoweredPattern,
+    Conversion, LoweredBlock, LoweredData, LoweredDataKind, LoweredEmbeddedRust, LoweredEnumVariant, LoweredErrorEnum,
+    LoweredExpr, LoweredFunction, LoweredItem, LoweredLiteral, LoweredModule, LoweredParam, LoweredStmt,
+    LoweredType, LoweredPattern, LoweredMod, LoweredUse,
 };
 use std::fmt::Write;
+use std::str::FromStr;
 use crate::ownership::OwnershipAnalysisResult;
-use crate::ast::Span;
+use crate::types;
+use crate::diagnostic::Diagnostic;
+use crate::embedded::dedent_lines;
+use crate::sourcemap::{LineIndex, Position, SourceMap};
 use std::collections::HashSet;
+use tracing::{debug, trace};
 
 /// Error type for code generation failures.
 #[derive(Debug)]
@@ -31,6 +38,67 @@ impl From<std::fmt::Error> for CodegenError {
     }
 }
 
+impl std::fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodegenError::UnsupportedFeature(feature) => write!(f, "code generation does not support {}", feature),
+            CodegenError::FormatError(_) => write!(f, "failed to format the generated code"),
+            CodegenError::InvalidIr(message) => write!(f, "invalid IR: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CodegenError::FormatError(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl CodegenError {
+    /// Describes the error as a diagnostic. The IR carries no spans, so it
+    /// has no labels.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let code = match self {
+            CodegenError::UnsupportedFeature(_) => "E0200",
+            CodegenError::FormatError(_) => "E0201",
+            CodegenError::InvalidIr(_) => "E0202",
+        };
+        let diagnostic = Diagnostic::error(code, self.to_string());
+        match self {
+            CodegenError::UnsupportedFeature(_) => diagnostic.with_note("write this part in a `rust { }` block"),
+            _ => diagnostic.with_note("this is a transpiler bug"),
+        }
+    }
+}
+
+/// Runtime that drives an async `main`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AsyncRuntime {
+    /// `#[tokio::main]`
+    #[default]
+    Tokio,
+    /// `#[async_std::main]`
+    AsyncStd,
+    /// A thread-parking executor emitted alongside `main`, needing no dependencies
+    Minimal,
+}
+
+impl FromStr for AsyncRuntime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tokio" => Ok(AsyncRuntime::Tokio),
+            "async-std" => Ok(AsyncRuntime::AsyncStd),
+            "minimal" => Ok(AsyncRuntime::Minimal),
+            other => Err(format!("unknown async runtime '{}' (expected tokio, async-std or minimal)", other)),
+        }
+    }
+}
+
 /// Context for code generation.
 /// This struct holds configuration, state, and utilities needed during codegen.
 pub struct CodegenContext {
@@ -39,17 +107,42 @@ pub struct CodegenContext {
     /// Size of each indentation step
     indent_size: usize,
     /// Whether to add a comment indicating the code was transpiled
-    add_transpiler_comment: bool,
+    pub add_transpiler_comment: bool,
     /// Result of ownership analysis
     pub analysis_result: Option<OwnershipAnalysisResult>,
     /// Current function being processed
     pub current_function: Option<String>,
     /// Set of variables known to be mutable
     pub mutable_vars: HashSet<String>,
-    /// Set of variables that need .to_string() conversion
-    pub string_converted_vars: HashSet<String>,
-    /// Set of expression spans that need .to_string() conversion
-    pub string_converted_exprs: HashSet<Span>,
+    /// Runtime used for an async `main`
+    pub async_runtime: AsyncRuntime,
+    /// Lines of the HighRust source; when set, a source map is recorded
+    pub line_index: Option<LineIndex>,
+    /// Whether to end the first line of each statement with a `// hrs:N`
+    /// comment naming its source line
+    pub line_comments: bool,
+    /// Comments explaining inferred code, each written above the statement,
+    /// or function, whose source span it is paired with
+    pub explanations: Vec<(Span, String)>,
+    /// Source map of the code generated so far
+    source_map: SourceMap,
+    /// Bytes of the output already scanned for line breaks, and the number
+    /// of line breaks in them
+    lines_seen: (usize, usize),
+}
+
+/// Rust code generated from a module, with its source map.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedCode {
+    pub code: String,
+    /// Empty unless the context was given the module's source
+    pub source_map: SourceMap,
+}
+
+impl Default for CodegenContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CodegenContext {
@@ -62,8 +155,12 @@ impl CodegenContext {
             analysis_result: None,
             current_function: None,
             mutable_vars: HashSet::new(),
-            string_converted_vars: HashSet::new(),
-            string_converted_exprs: HashSet::new(),
+            async_runtime: AsyncRuntime::default(),
+            line_index: None,
+            line_comments: false,
+            explanations: Vec::new(),
+            source_map: SourceMap::default(),
+            lines_seen: (0, 0),
         }
     }
     
@@ -74,14 +171,6 @@ impl CodegenContext {
         if !analysis.mutable_vars.is_empty() {
             ctx.mutable_vars = analysis.mutable_vars.clone();
         }
-        
-        // Copy string conversion info from analysis
-        if !analysis.string_converted_vars.is_empty() {
-            ctx.string_converted_vars = analysis.string_converted_vars.clone();
-        }
-        if !analysis.string_converted_exprs.is_empty() {
-            ctx.string_converted_exprs = analysis.string_converted_exprs.clone();
-        }
         ctx.analysis_result = Some(analysis);
         ctx
     }
@@ -95,8 +184,12 @@ impl CodegenContext {
             analysis_result: None,
             current_function: None,
             mutable_vars: HashSet::new(),
-            string_converted_vars: HashSet::new(),
-            string_converted_exprs: HashSet::new(),
+            async_runtime: AsyncRuntime::default(),
+            line_index: None,
+            line_comments: false,
+            explanations: Vec::new(),
+            source_map: SourceMap::default(),
+            lines_seen: (0, 0),
         }
     }
 
@@ -116,6 +209,50 @@ impl CodegenContext {
             self.indent_level -= 1;
         }
     }
+
+    /// Records that the code about to be written to `output` comes from byte
+    /// `offset` of the source, returning the source line.
+    fn record(&mut self, output: &str, offset: usize) -> Option<usize> {
+        let source = self.line_index.as_ref()?.position(offset);
+        let (scanned, lines) = self.lines_seen;
+        let lines = lines + output[scanned..].matches('\n').count();
+        self.lines_seen = (output.len(), lines);
+        let line_start = output.rfind('\n').map_or(0, |newline| newline + 1);
+        let column = match output[line_start..].chars().count() {
+            // Code at the start of a line follows its indentation
+            0 => self.indent_level * self.indent_size,
+            written => written,
+        };
+        self.source_map.add(Position::new(lines + 1, column + 1), source);
+        Some(source.line)
+    }
+
+    /// Writes the explanations of code generated for the statement or
+    /// function at `span`, one comment line each.
+    fn explain(&self, span: &Span, output: &mut String) -> Result<(), CodegenError> {
+        for (_, explanation) in self.explanations.iter().filter(|(at, _)| at == span) {
+            writeln!(output, "{}// {}", self.indent(), explanation)?;
+        }
+        Ok(())
+    }
+
+    /// Ends the first line written to `output` since byte `start` with a
+    /// comment naming source line `line`, unless the line break is inside a
+    /// string literal.
+    fn annotate_line(&mut self, output: &mut String, start: usize, line: usize) {
+        let Some(end) = output[start..].find('\n').map(|newline| start + newline) else {
+            return;
+        };
+        let quotes = output[start..end].match_indices('"').filter(|(i, _)| !output[..*i].ends_with('\\')).count();
+        if quotes % 2 == 1 {
+            return;
+        }
+        let comment = format!(" // hrs:{}", line);
+        output.insert_str(end, &comment);
+        if end < self.lines_seen.0 {
+            self.lines_seen.0 += comment.len();
+        }
+    }
 }
 
 /// Generates Rust code from the given lowered module using the provided codegen context.
@@ -127,39 +264,121 @@ impl CodegenContext {
 ///
 /// # Returns
 ///
-/// A `Result` containing either the generated Rust code as a `String` or a `CodegenError`.
+/// A `Result` containing either the generated Rust code with its source map
+/// or a `CodegenError`. The source map is recorded when `ctx.line_index`
+/// holds the module's source.
 ///
 /// # Example
 ///
 /// ```ignore
 /// let module = lower_module(&ast_module)?;
-/// let ctx = CodegenContext::new();
-/// let rust_code = generate_rust_code(&module, &ctx)?;
+/// let mut ctx = CodegenContext::new();
+/// ctx.line_index = Some(LineIndex::new(source));
+/// let generated = generate_rust_code(&module, &mut ctx)?;
 /// ```
-pub fn generate_rust_code(module: &LoweredModule, ctx: &mut CodegenContext) -> Result<String, CodegenError> {
+pub fn generate_rust_code(module: &LoweredModule, ctx: &mut CodegenContext) -> Result<GeneratedCode, CodegenError> {
     let mut output = String::new();
+    ctx.source_map = SourceMap::default();
+    ctx.lines_seen = (0, 0);
     
     // Generate code for each item in the module
-    for item in &module.items {
+    for (i, item) in module.items.iter().enumerate() {
         match item {
             LoweredItem::Function(func) => {
+                trace!(target: "codegen", name = %func.name, "generating function");
                 // Store the current function name for special case handling
                 ctx.current_function = Some(func.name.clone());
                 
+                ctx.explain(&func.span, &mut output)?;
+                let start = output.len();
+                let line = ctx.record(&output, func.span.start);
                 generate_function(func, ctx, &mut output)?;
+                if let (Some(line), true) = (line, ctx.line_comments) {
+                    ctx.annotate_line(&mut output, start, line);
+                }
                 writeln!(output)?;
                 
                 // Clear current function when done
                 ctx.current_function = None;
             }
             LoweredItem::Data(data) => {
+                let start = output.len();
+                let line = ctx.record(&output, data.span.start);
                 generate_data(data, ctx, &mut output)?;
+                if let (Some(line), true) = (line, ctx.line_comments) {
+                    ctx.annotate_line(&mut output, start, line);
+                }
+                writeln!(output)?;
+            }
+            LoweredItem::ErrorEnum(error_enum) => {
+                generate_error_enum(error_enum, ctx, &mut output)?;
                 writeln!(output)?;
             }
+            LoweredItem::EmbeddedRust(rust) => {
+                generate_embedded_rust(rust, ctx, &mut output)?;
+                writeln!(output)?;
+            }
+            LoweredItem::Use(use_decl) => {
+                generate_use(use_decl, ctx, &mut output)?;
+            }
+            LoweredItem::Mod(mod_decl) => {
+                generate_mod(mod_decl, ctx, &mut output)?;
+            }
+        }
+        // Consecutive `mod` and `use` declarations are kept together
+        if matches!(item, LoweredItem::Use(_) | LoweredItem::Mod(_))
+            && !matches!(module.items.get(i + 1), Some(LoweredItem::Use(_) | LoweredItem::Mod(_)))
+        {
+            writeln!(output)?;
         }
     }
     
-    Ok(output)
+    debug!(target: "codegen", items = module.items.len(), bytes = output.len(), "generated module");
+    Ok(GeneratedCode { code: output, source_map: std::mem::take(&mut ctx.source_map) })
+}
+
+/// Emits the code of a `rust { ... }` block at the current indentation. The
+/// braces are dropped, so bindings made in a statement-level block stay in
+/// scope for the HighRust code after it. Each line maps to its own source line.
+fn generate_embedded_rust(rust: &LoweredEmbeddedRust, ctx: &mut CodegenContext, output: &mut String) -> Result<(), CodegenError> {
+    for (offset, line) in dedent_lines(&rust.code) {
+        if line.is_empty() {
+            writeln!(output)?;
+        } else {
+            ctx.record(output, rust.span.start + offset);
+            writeln!(output, "{}{}", ctx.indent(), line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Generates a `mod` declaration for an imported Rust file.
+fn generate_mod(mod_decl: &LoweredMod, ctx: &CodegenContext, output: &mut String) -> Result<(), CodegenError> {
+    if let Some(path) = &mod_decl.path {
+        writeln!(output, "{}#[path = {:?}]", ctx.indent(), path)?;
+    }
+    writeln!(output, "{}{}mod {};", ctx.indent(), visibility(mod_decl.is_pub), mod_decl.name)?;
+    Ok(())
+}
+
+/// Generates a `use` declaration, e.g. `pub use crate::net::{get, post as send};`.
+fn generate_use(use_decl: &LoweredUse, ctx: &CodegenContext, output: &mut String) -> Result<(), CodegenError> {
+    let visibility = if use_decl.is_pub { "pub " } else { "" };
+    let name = |(name, alias): &(String, Option<String>)| match alias {
+        Some(alias) => format!("{} as {}", name, alias),
+        None => name.clone(),
+    };
+    let mut path = use_decl.path.join("::");
+    match use_decl.names.as_slice() {
+        [] => {}
+        [single] => path = format!("{}::{}", path, name(single)),
+        names => path = format!("{}::{{{}}}", path, names.iter().map(name).collect::<Vec<_>>().join(", ")),
+    }
+    if let Some(alias) = &use_decl.alias {
+        path = format!("{} as {}", path, alias);
+    }
+    writeln!(output, "{}{}use {};", ctx.indent(), visibility, path)?;
+    Ok(())
 }
 
 /// Generates Rust code for a function definition.
@@ -168,25 +387,29 @@ fn generate_function(
     ctx: &mut CodegenContext,
     output: &mut String,
 ) -> Result<(), CodegenError> {
-    // Function signature
-    write!(output, "{}fn {}", ctx.indent(), func.name)?;
-    // Collect lifetimes
-    let mut lifetimes = Vec::new();
-    for param in &func.params {
-        if let Some(ref ty) = param.ty {
-            collect_lifetimes(ty, &mut lifetimes);
+    // Function signature; an async `main` runs under the configured runtime
+    let async_main = func.is_async && func.name == "main";
+    let name = match ctx.async_runtime {
+        AsyncRuntime::Tokio if async_main => {
+            writeln!(output, "{}#[tokio::main]", ctx.indent())?;
+            "main"
         }
-    }
-    if let Some(ref ret_ty) = func.ret_type {
-        collect_lifetimes(ret_ty, &mut lifetimes);
-    }
-    // If the function returns a reference and no lifetime is present, inject a default 'a
-    let mut needs_default_lifetime = false;
-    if let Some(LoweredType::Reference(_, lt)) = func.ret_type.as_ref() {
-        if lt.is_none() && lifetimes.is_empty() {
-            needs_default_lifetime = true;
-            lifetimes.push("a".to_string());
+        AsyncRuntime::AsyncStd if async_main => {
+            writeln!(output, "{}#[async_std::main]", ctx.indent())?;
+            "main"
         }
+        AsyncRuntime::Minimal if async_main => "async_main",
+        _ => func.name.as_str(),
+    };
+    write!(output, "{}{}", ctx.indent(), visibility(func.is_pub))?;
+    if func.is_async {
+        write!(output, "async ")?;
+    }
+    write!(output, "fn {}", name)?;
+    let mut lifetimes = function_lifetimes(func);
+    let needs_default_lifetime = needs_default_lifetime(func);
+    if needs_default_lifetime {
+        lifetimes.push("a".to_string());
     }
     if !lifetimes.is_empty() {
         let lifetime_list = lifetimes.iter().map(|lt| format!("'{}", lt)).collect::<Vec<_>>().join(", ");
@@ -223,17 +446,63 @@ fn generate_function(
     generate_block(&func.body, ctx, output)?;
     ctx.indent_level -= 1;
     writeln!(output, "{}}}", ctx.indent())?;
+    if async_main && ctx.async_runtime == AsyncRuntime::Minimal {
+        generate_minimal_entry(func, ctx, output)?;
+    }
     Ok(())
 }
 
-/// Returns true if the type is a reference type.
-fn is_ref_type(ty: Option<&LoweredType>) -> bool {
-    match ty {
-        Some(LoweredType::Named(name, _)) if name == "&" => true,
-        _ => false,
+/// Generates a synchronous `main` that drives `async_main` to completion on
+/// the current thread, together with the executor it uses.
+fn generate_minimal_entry(
+    func: &LoweredFunction,
+    ctx: &mut CodegenContext,
+    output: &mut String,
+) -> Result<(), CodegenError> {
+    let indent = ctx.indent();
+    writeln!(output)?;
+    write!(output, "{}fn main()", indent)?;
+    if let Some(ret_ty) = &func.ret_type {
+        write!(output, " -> ")?;
+        generate_type(ret_ty, ctx, output, None)?;
+    }
+    writeln!(output, " {{")?;
+    writeln!(output, "{}    block_on(async_main())", indent)?;
+    writeln!(output, "{}}}", indent)?;
+    writeln!(output)?;
+    for line in MINIMAL_EXECUTOR.lines() {
+        if line.is_empty() {
+            writeln!(output)?;
+        } else {
+            writeln!(output, "{}{}", indent, line)?;
+        }
     }
+    Ok(())
 }
 
+/// Executor for the minimal runtime: polls the future on the current thread,
+/// parking it until the future's waker unparks it.
+const MINIMAL_EXECUTOR: &str = "\
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut future = std::pin::pin!(future);
+    let waker = std::task::Waker::from(std::sync::Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = std::task::Context::from_waker(&waker);
+    loop {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}";
+
 /// Generates a type with a lifetime if it's a reference.
 fn generate_type_with_lifetime(
     ty: &LoweredType,
@@ -253,7 +522,7 @@ fn generate_type_with_lifetime(
         LoweredType::Named(name, inner) if name == "&" => {
             // Fallback for legacy IR
             write!(output, "&")?;
-            if let Some(inner_ty) = inner.get(0) {
+            if let Some(inner_ty) = inner.first() {
                 generate_type_with_lifetime(inner_ty, ctx, output, lifetime)?;
             }
             Ok(())
@@ -262,6 +531,27 @@ fn generate_type_with_lifetime(
     }
 }
 
+/// The lifetimes named in the types of `func`'s parameters and result.
+fn function_lifetimes(func: &LoweredFunction) -> Vec<String> {
+    let mut lifetimes = Vec::new();
+    for param in &func.params {
+        if let Some(ref ty) = param.ty {
+            collect_lifetimes(ty, &mut lifetimes);
+        }
+    }
+    if let Some(ref ret_ty) = func.ret_type {
+        collect_lifetimes(ret_ty, &mut lifetimes);
+    }
+    lifetimes
+}
+
+/// Whether `func` returns a reference without naming a lifetime anywhere in
+/// its signature; it is then given a default `'a` tying the result to its
+/// parameters.
+pub(crate) fn needs_default_lifetime(func: &LoweredFunction) -> bool {
+    matches!(func.ret_type, Some(LoweredType::Reference(_, None))) && function_lifetimes(func).is_empty()
+}
+
 /// Helper to collect lifetimes from types
 fn collect_lifetimes(ty: &LoweredType, out: &mut Vec<String>) {
     match ty {
@@ -283,7 +573,6 @@ fn collect_lifetimes(ty: &LoweredType, out: &mut Vec<String>) {
         LoweredType::Named(_, inner) => {
             for t in inner { collect_lifetimes(t, out); }
         },
-        _ => {}
     }
 }
 
@@ -352,11 +641,11 @@ fn generate_data(
 ) -> Result<(), CodegenError> {
     match &data.kind {
         LoweredDataKind::Struct(fields) => {
-            writeln!(output, "{}struct {} {{", ctx.indent(), data.name)?;
+            writeln!(output, "{}{}struct {} {{", ctx.indent(), visibility(data.is_pub), data.name)?;
             
             ctx.increase_indent();
             for field in fields {
-                write!(output, "{}{}: ", ctx.indent(), field.name)?;
+                write!(output, "{}{}{}: ", ctx.indent(), visibility(data.is_pub), field.name)?;
                 generate_type(&field.ty, ctx, output, None)?;
                 writeln!(output, ",")?;
             }
@@ -365,7 +654,7 @@ fn generate_data(
             writeln!(output, "{}}}", ctx.indent())?;
         }
         LoweredDataKind::Enum(variants) => {
-            writeln!(output, "{}enum {} {{", ctx.indent(), data.name)?;
+            writeln!(output, "{}{}enum {} {{", ctx.indent(), visibility(data.is_pub), data.name)?;
             
             ctx.increase_indent();
             for variant in variants {
@@ -380,6 +669,65 @@ fn generate_data(
     Ok(())
 }
 
+/// Visibility prefix of an item: exported items are `pub`.
+fn visibility(is_pub: bool) -> &'static str {
+    if is_pub {
+        "pub "
+    } else {
+        ""
+    }
+}
+
+/// Generates the module error enum along with its `Display`, `Error` and
+/// `From` impls.
+fn generate_error_enum(
+    error_enum: &LoweredErrorEnum,
+    ctx: &mut CodegenContext,
+    output: &mut String,
+) -> Result<(), CodegenError> {
+    let name = &error_enum.name;
+    writeln!(output, "{}#[derive(Debug)]", ctx.indent())?;
+    writeln!(output, "{}{}enum {} {{", ctx.indent(), visibility(error_enum.is_pub), name)?;
+    ctx.increase_indent();
+    for (variant, ty) in &error_enum.variants {
+        write!(output, "{}{}(", ctx.indent(), variant)?;
+        generate_type(ty, ctx, output, None)?;
+        writeln!(output, "),")?;
+    }
+    ctx.decrease_indent();
+    writeln!(output, "{}}}", ctx.indent())?;
+
+    writeln!(output)?;
+    writeln!(output, "{}impl std::fmt::Display for {} {{", ctx.indent(), name)?;
+    writeln!(output, "{}    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {{", ctx.indent())?;
+    if error_enum.variants.is_empty() {
+        writeln!(output, "{}        match *self {{}}", ctx.indent())?;
+    } else {
+        writeln!(output, "{}        match self {{", ctx.indent())?;
+        for (variant, _) in &error_enum.variants {
+            writeln!(output, "{}            {}::{}(error) => write!(f, \"{{}}\", error),", ctx.indent(), name, variant)?;
+        }
+        writeln!(output, "{}        }}", ctx.indent())?;
+    }
+    writeln!(output, "{}    }}", ctx.indent())?;
+    writeln!(output, "{}}}", ctx.indent())?;
+
+    writeln!(output)?;
+    writeln!(output, "{}impl std::error::Error for {} {{}}", ctx.indent(), name)?;
+
+    for (variant, ty) in &error_enum.variants {
+        let mut ty_str = String::new();
+        generate_type(ty, ctx, &mut ty_str, None)?;
+        writeln!(output)?;
+        writeln!(output, "{}impl From<{}> for {} {{", ctx.indent(), ty_str, name)?;
+        writeln!(output, "{}    fn from(error: {}) -> Self {{", ctx.indent(), ty_str)?;
+        writeln!(output, "{}        {}::{}(error)", ctx.indent(), name, variant)?;
+        writeln!(output, "{}    }}", ctx.indent())?;
+        writeln!(output, "{}}}", ctx.indent())?;
+    }
+    Ok(())
+}
+
 /// Generates Rust code for an enum variant.
 fn generate_enum_variant(
     variant: &LoweredEnumVariant,
@@ -412,8 +760,18 @@ fn generate_block(
     ctx: &mut CodegenContext,
     output: &mut String,
 ) -> Result<(), CodegenError> {
-    for stmt in &block.stmts {
+    for (i, stmt) in block.stmts.iter().enumerate() {
+        // Embedded Rust maps line by line, and is never annotated
+        let span = block.spans.get(i).filter(|_| !matches!(stmt, LoweredStmt::EmbeddedRust(_)));
+        if let Some(span) = span {
+            ctx.explain(span, output)?;
+        }
+        let start = output.len();
+        let line = span.and_then(|span| ctx.record(output, span.start));
         generate_stmt(stmt, ctx, output)?;
+        if let (Some(line), true) = (line, ctx.line_comments) {
+            ctx.annotate_line(output, start, line);
+        }
     }
     
     Ok(())
@@ -444,38 +802,68 @@ fn generate_stmt(
                     return Ok(());
                 }
             }
-            let force_to_string = match (ty, value) {
-                (Some(LoweredType::Named(ref tname, _)), LoweredExpr::Literal(LoweredLiteral::String(_))) if tname == "String" => true,
-                _ => false
-            };
-            match value {
-                LoweredExpr::Literal(ref lit) => generate_literal(lit, ctx, output, force_to_string)?,
-                _ => generate_expr(&value, ctx, output)?
-            }
+            generate_expr(value, ctx, output)?;
             writeln!(output, ";")?;
         }
         LoweredStmt::If { ref cond, ref then_branch, ref else_branch } => {
             write!(output, "{}if ", ctx.indent())?;
             generate_expr(cond, ctx, output)?;
-            writeln!(output, " {{")?;
+            generate_branches(then_branch, else_branch.as_ref(), ctx, output)?;
+        }
+        LoweredStmt::IfLet { ref pattern, ref value, ref then_branch, ref else_branch } => {
+            write!(output, "{}if let ", ctx.indent())?;
+            generate_pattern(pattern, ctx, output)?;
+            write!(output, " = ")?;
+            generate_expr(value, ctx, output)?;
+            generate_branches(then_branch, else_branch.as_ref(), ctx, output)?;
+        }
+        LoweredStmt::Try { ref label, ref error_type, ref body, ref catch } => {
+            // The labeled block evaluates to Ok(()) unless a `?` breaks out with an error
+            write!(output, "{}", ctx.indent())?;
+            match catch {
+                Some((binding, _)) => write!(output, "if let Err({}) = ", binding)?,
+                None => write!(output, "let _ = ")?,
+            }
+            writeln!(output, "'{}: {{", label)?;
             ctx.indent_level += 1;
-            generate_block(then_branch, ctx, output)?;
+            generate_block(body, ctx, output)?;
+            write!(output, "{}Ok::<(), ", ctx.indent())?;
+            generate_type(error_type, ctx, output, None)?;
+            writeln!(output, ">(())")?;
             ctx.indent_level -= 1;
-            write!(output, "{}}}", ctx.indent())?;
-            if let Some(else_block) = else_branch {
-                writeln!(output, " else {{")?;
-                ctx.indent_level += 1;
-                generate_block(else_block, ctx, output)?;
-                ctx.indent_level -= 1;
-                write!(output, "{}}}", ctx.indent())?;
+            match catch {
+                Some((_, catch_body)) => {
+                    writeln!(output, "{}}} {{", ctx.indent())?;
+                    ctx.indent_level += 1;
+                    generate_block(catch_body, ctx, output)?;
+                    ctx.indent_level -= 1;
+                    writeln!(output, "{}}}", ctx.indent())?;
+                }
+                None => writeln!(output, "{}}};", ctx.indent())?,
             }
-            writeln!(output)?;
+        }
+        LoweredStmt::LetElse { ref pattern, ref value, ref else_branch } => {
+            write!(output, "{}let ", ctx.indent())?;
+            generate_pattern(pattern, ctx, output)?;
+            write!(output, " = ")?;
+            generate_expr(value, ctx, output)?;
+            writeln!(output, " else {{")?;
+            ctx.indent_level += 1;
+            generate_block(else_branch, ctx, output)?;
+            ctx.indent_level -= 1;
+            writeln!(output, "{}}};", ctx.indent())?;
         }
         LoweredStmt::Expr(expr) => {
             write!(output, "{}", ctx.indent())?;
             generate_expr(expr, ctx, output)?;
             writeln!(output, ";")?;
         }
+        LoweredStmt::EmbeddedRust(rust) => generate_embedded_rust(rust, ctx, output)?,
+        LoweredStmt::TailExpr(expr) => {
+            write!(output, "{}", ctx.indent())?;
+            generate_expr(expr, ctx, output)?;
+            writeln!(output)?;
+        }
         LoweredStmt::Return(expr_opt) => {
             write!(output, "{}return", ctx.indent())?;
             
@@ -486,23 +874,6 @@ fn generate_stmt(
             
             writeln!(output, ";")?;
         }
-        LoweredStmt::If { cond, then_branch, else_branch } => {
-            write!(output, "{}if ", ctx.indent())?;
-            generate_expr(cond, ctx, output)?;
-            writeln!(output, " {{")?;
-            ctx.indent_level += 1;
-            generate_block(then_branch, ctx, output)?;
-            ctx.indent_level -= 1;
-            write!(output, "{}}}", ctx.indent())?;
-            if let Some(else_block) = else_branch {
-                writeln!(output, " else {{")?;
-                ctx.indent_level += 1;
-                generate_block(else_block, ctx, output)?;
-                ctx.indent_level -= 1;
-                write!(output, "{}}}", ctx.indent())?;
-            }
-            writeln!(output)?;
-        }
     }
     
     Ok(())
@@ -516,8 +887,7 @@ fn generate_expr(
 ) -> Result<(), CodegenError> {
     match expr {
         LoweredExpr::Literal(lit) => {
-            // Only force .to_string() in certain contexts (handled by caller)
-            generate_literal(lit, ctx, output, false)?;
+            generate_literal(lit, ctx, output)?;
             Ok(())
         }
         LoweredExpr::Variable(name) => {
@@ -555,36 +925,37 @@ fn generate_expr(
                 write!(output, "&mut {}", name)?;
             } else {
                 write!(output, "{}", name)?;
-                
-                // Check if this variable needs .to_string() conversion
-                if let Some(analysis) = &ctx.analysis_result {
-                    if analysis.string_converted_vars.contains(name) {
-                        write!(output, ".to_string()")?;
-                    }
-                }
             }
             Ok(())
         }
         LoweredExpr::Call { func, args } => {
-            // Check for binary + (string concatenation)
+            // Binary operators are encoded as calls
             if let LoweredExpr::Variable(fname) = &**func {
-                if fname == "+" && args.len() == 2 {
-                    // If left arg is string literal, emit .to_string()
-                    match &args[0] {
-                        LoweredExpr::Literal(LoweredLiteral::String(_)) => {
-                            if let LoweredExpr::Literal(ref l) = &args[0] {
-                                generate_literal(l, ctx, output, true)?;
-                            }
-                        },
-                        _ => generate_expr(&args[0], ctx, output)?
-                    }
-                    write!(output, " + ")?;
-                    generate_expr(&args[1], ctx, output)?;
+                if types::is_binary_operator(fname) && args.len() == 2 {
+                    generate_operand(&args[0], fname, false, ctx, output)?;
+                    write!(output, " {} ", fname)?;
+                    generate_operand(&args[1], fname, true, ctx, output)?;
+                    return Ok(());
+                }
+                if types::is_unary_operator(fname) && args.len() == 1 {
+                    write!(output, "{}", fname)?;
+                    generate_receiver(&args[0], ctx, output)?;
                     return Ok(());
                 }
             }
-            // Special handling for println macro
+            // Special handling for println and vec macros
             if let LoweredExpr::Variable(name) = &**func {
+                if name == "vec" {
+                    write!(output, "vec![")?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            write!(output, ", ")?;
+                        }
+                        generate_expr(arg, ctx, output)?;
+                    }
+                    write!(output, "]")?;
+                    return Ok(());
+                }
                 if name == "println" {
                     write!(output, "println!")?;
                     write!(output, "(")?;
@@ -618,11 +989,81 @@ fn generate_expr(
             write!(output, "}}")?;
             Ok(())
         }
+        LoweredExpr::TryBreak { expr, label } => {
+            write!(output, "match ")?;
+            generate_expr(expr, ctx, output)?;
+            write!(output, " {{ Ok(value) => value, Err(error) => break '{} Err(error.into()) }}", label)?;
+            Ok(())
+        }
         LoweredExpr::Propagate(inner) => {
             generate_expr(inner, ctx, output)?;
             write!(output, "?")?;
             Ok(())
         }
+        LoweredExpr::Await(inner) => {
+            generate_receiver(inner, ctx, output)?;
+            write!(output, ".await")?;
+            Ok(())
+        }
+        LoweredExpr::Struct { name, fields } => {
+            write!(output, "{} {{ ", name)?;
+            for (i, (field, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    write!(output, ", ")?;
+                }
+                write!(output, "{}: ", field)?;
+                generate_expr(value, ctx, output)?;
+            }
+            write!(output, " }}")?;
+            Ok(())
+        }
+        LoweredExpr::Convert { expr, conversion } => {
+            match conversion {
+                Conversion::From(ty) => {
+                    write!(output, "{}::from(", ty)?;
+                    generate_expr(expr, ctx, output)?;
+                    write!(output, ")")?;
+                    return Ok(());
+                }
+                Conversion::Borrow | Conversion::BorrowMut => {
+                    write!(output, "{}", if *conversion == Conversion::Borrow { "&" } else { "&mut " })?;
+                    return generate_receiver(expr, ctx, output);
+                }
+                _ => {}
+            }
+            generate_receiver(expr, ctx, output)?;
+            match conversion {
+                Conversion::ToString => write!(output, ".to_string()")?,
+                Conversion::AsStr => write!(output, ".as_str()")?,
+                Conversion::Cast(ty) => write!(output, " as {}", ty)?,
+                Conversion::From(_) | Conversion::Borrow | Conversion::BorrowMut => unreachable!("handled above"),
+            }
+            Ok(())
+        }
+        LoweredExpr::FieldAccess { base, field } => {
+            generate_receiver(base, ctx, output)?;
+            write!(output, ".{}", field)?;
+            Ok(())
+        }
+        LoweredExpr::MethodCall { receiver, method, args } => {
+            generate_receiver(receiver, ctx, output)?;
+            write!(output, ".{}(", method)?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(output, ", ")?;
+                }
+                generate_expr(arg, ctx, output)?;
+            }
+            write!(output, ")")?;
+            Ok(())
+        }
+        LoweredExpr::Closure { params, body, is_move } => {
+            if *is_move {
+                write!(output, "move ")?;
+            }
+            write!(output, "|{}| ", params.join(", "))?;
+            generate_expr(body, ctx, output)
+        }
         LoweredExpr::Match { expr, arms } => {
             write!(output, "match ")?;
             generate_expr(expr, ctx, output)?;
@@ -646,32 +1087,123 @@ fn generate_expr(
     }
 }
 
+/// Generates the braced branches of an `if` or `if let` statement.
+fn generate_branches(
+    then_branch: &LoweredBlock,
+    else_branch: Option<&LoweredBlock>,
+    ctx: &mut CodegenContext,
+    output: &mut String,
+) -> Result<(), CodegenError> {
+    writeln!(output, " {{")?;
+    ctx.indent_level += 1;
+    generate_block(then_branch, ctx, output)?;
+    ctx.indent_level -= 1;
+    write!(output, "{}}}", ctx.indent())?;
+    if let Some(else_block) = else_branch {
+        writeln!(output, " else {{")?;
+        ctx.indent_level += 1;
+        generate_block(else_block, ctx, output)?;
+        ctx.indent_level -= 1;
+        write!(output, "{}}}", ctx.indent())?;
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+/// Generates an operand of a binary operator, parenthesizing nested operators
+/// where precedence requires it.
+fn generate_operand(
+    expr: &LoweredExpr,
+    parent_op: &str,
+    is_rhs: bool,
+    ctx: &mut CodegenContext,
+    output: &mut String,
+) -> Result<(), CodegenError> {
+    let needs_parens = match expr {
+        LoweredExpr::Call { func, args } if args.len() == 2 => match &**func {
+            LoweredExpr::Variable(op) if types::is_binary_operator(op) => {
+                let (inner, outer) = (types::operator_precedence(op), types::operator_precedence(parent_op));
+                // Comparisons do not chain, and left associativity needs parens on the right
+                inner < outer || (inner == outer && (is_rhs || outer == types::operator_precedence("==")))
+            }
+            _ => false,
+        },
+        _ => false,
+    };
+    if needs_parens {
+        write!(output, "(")?;
+        generate_expr(expr, ctx, output)?;
+        write!(output, ")")?;
+        Ok(())
+    } else {
+        generate_expr(expr, ctx, output)
+    }
+}
+
+/// Generates the receiver of a method call. Variables are emitted by name,
+/// without the borrow operators inferred for argument positions.
+fn generate_receiver(
+    expr: &LoweredExpr,
+    ctx: &mut CodegenContext,
+    output: &mut String,
+) -> Result<(), CodegenError> {
+    match expr {
+        LoweredExpr::Variable(name) => {
+            write!(output, "{}", name)?;
+            Ok(())
+        }
+        LoweredExpr::Literal(_)
+        | LoweredExpr::Convert { conversion: Conversion::ToString | Conversion::AsStr | Conversion::From(_), .. }
+        | LoweredExpr::Propagate(_)
+        | LoweredExpr::Await(_)
+        | LoweredExpr::FieldAccess { .. }
+        | LoweredExpr::MethodCall { .. } => generate_expr(expr, ctx, output),
+        LoweredExpr::Call { .. } if !is_binary_call(expr) => generate_expr(expr, ctx, output),
+        _ => {
+            write!(output, "(")?;
+            generate_expr(expr, ctx, output)?;
+            write!(output, ")")?;
+            Ok(())
+        }
+    }
+}
+
+/// Returns true if the expression is a binary operator encoded as a call.
+fn is_binary_call(expr: &LoweredExpr) -> bool {
+    match expr {
+        LoweredExpr::Call { func, args } => {
+            matches!(&**func, LoweredExpr::Variable(name) if types::is_binary_operator(name) && args.len() == 2)
+        }
+        _ => false,
+    }
+}
+
 /// Generates Rust code for a literal value.
 fn generate_literal(
     lit: &LoweredLiteral,
     _ctx: &mut CodegenContext,
     output: &mut String,
-    force_to_string: bool,
 ) -> Result<(), CodegenError> {
     match lit {
-        LoweredLiteral::Int(i) => {
-            write!(output, "{}", i)?;
+        LoweredLiteral::Int(i, suffix) => {
+            write!(output, "{}{}", i, suffix.as_deref().unwrap_or(""))?;
         }
         LoweredLiteral::Float(f) => {
-            write!(output, "{}", f)?;
+            // Debug formatting keeps the decimal point on whole numbers (`1.0`)
+            write!(output, "{:?}", f)?;
         }
         LoweredLiteral::Bool(b) => {
             write!(output, "{}", b)?;
         }
         LoweredLiteral::String(s) => {
-            write!(output, "\"{}\"", s.replace("\"", "\\\""))?;
-            if force_to_string {
-                write!(output, ".to_string()")?;
-            }
+            write!(output, "\"{}\"", s.replace('"', "\\\""))?;
         }
         LoweredLiteral::Null => {
             write!(output, "None")?;
         }
+        LoweredLiteral::Unit => {
+            write!(output, "()")?;
+        }
     }
     Ok(())
 }
@@ -684,6 +1216,10 @@ fn generate_type(
     lifetime: Option<&str>,
 ) -> Result<(), CodegenError> {
     match ty {
+        LoweredType::Named(name, inner) if name == "&mut" && inner.len() == 1 => {
+            write!(output, "&mut ")?;
+            generate_type(&inner[0], ctx, output, lifetime)
+        }
         LoweredType::Named(name, inner) => {
             write!(output, "{}", name)?;
             if !inner.is_empty() {
@@ -755,6 +1291,20 @@ fn generate_pattern(pattern: &LoweredPattern, _ctx: &mut CodegenContext, output:
             }
             write!(output, ")").map_err(CodegenError::FormatError)
         },
-        LoweredPattern::Literal(lit) => generate_literal(lit, _ctx, output, false),
+        LoweredPattern::Literal(lit) => generate_literal(lit, _ctx, output),
+        LoweredPattern::Variant { path, fields } => {
+            write!(output, "{}", path).map_err(CodegenError::FormatError)?;
+            if !fields.is_empty() {
+                write!(output, "(").map_err(CodegenError::FormatError)?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(output, ", ").map_err(CodegenError::FormatError)?;
+                    }
+                    generate_pattern(field, _ctx, output)?;
+                }
+                write!(output, ")").map_err(CodegenError::FormatError)?;
+            }
+            Ok(())
+        }
     }
 }
\ No newline at end of file
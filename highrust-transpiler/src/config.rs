@@ -0,0 +1,304 @@
+//! Project configuration read from `highrust.toml`.
+//!
+//! ```toml
+//! [project]
+//! name = "app"
+//! edition = "2021"
+//!
+//! [build]
+//! source_roots = ["src"]
+//! output_dir = "generated"
+//! async_runtime = "tokio"
+//! strictness = "strict"
+//! verbose_annotations = true
+//!
+//! [lints]
+//! implicit_clone = "warn"
+//!
+//! [dependencies]
+//! serde = { version = "1", features = ["derive"] }
+//! ```
+//!
+//! Every key is optional. Unknown keys and invalid values are errors that
+//! name the offending key and its position in the file.
+
+use crate::codegen::AsyncRuntime;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Name of the configuration file.
+pub const CONFIG_FILE: &str = "highrust.toml";
+
+/// Errors loading a configuration file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read
+    Io { path: PathBuf, error: std::io::Error },
+    /// The file is not valid TOML, or a key has an invalid value
+    Invalid {
+        path: Option<PathBuf>,
+        /// Dotted path of the offending key, e.g. `build.edition`
+        key: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "cannot read {}: {}", path.display(), error),
+            ConfigError::Invalid { path, key, line, column, message } => {
+                let file = path.as_deref().unwrap_or(Path::new(CONFIG_FILE)).display();
+                if key.is_empty() {
+                    write!(f, "{}:{}:{}: {}", file, line, column, message)
+                } else {
+                    write!(f, "{}:{}:{}: invalid `{}`: {}", file, line, column, key, message)
+                }
+            }
+        }
+    }
+}
+
+/// A parsed `highrust.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory holding the configuration file, which relative paths in it
+    /// are resolved against
+    #[serde(skip)]
+    pub root: PathBuf,
+    pub project: ProjectConfig,
+    pub build: BuildConfig,
+    pub lints: LintConfig,
+    /// Dependencies of the generated package, as written in a Cargo manifest
+    pub dependencies: BTreeMap<String, toml::Value>,
+}
+
+/// The `[project]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    /// Rust edition of the generated code
+    pub edition: Edition,
+}
+
+/// The `[build]` table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildConfig {
+    /// Directories holding the HighRust sources
+    #[serde(deserialize_with = "non_empty")]
+    pub source_roots: Vec<PathBuf>,
+    /// Directory the generated Rust is written to; next to the sources when
+    /// unset, and `target/highrust/package` for a generated package
+    pub output_dir: Option<PathBuf>,
+    #[serde(deserialize_with = "from_str")]
+    pub async_runtime: AsyncRuntime,
+    pub strictness: Strictness,
+    /// Annotate the generated code with the ownership decisions behind it
+    pub verbose_annotations: bool,
+    /// Vendor the HighRust standard helpers into generated packages
+    pub stdlib: bool,
+    /// Cache parsed modules, ownership analyses and generated Rust under
+    /// `target/highrust/cache`, so later builds only redo what changed
+    pub cache: bool,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            source_roots: vec![PathBuf::from("src")],
+            output_dir: None,
+            async_runtime: AsyncRuntime::default(),
+            strictness: Strictness::default(),
+            verbose_annotations: false,
+            stdlib: true,
+            cache: true,
+        }
+    }
+}
+
+/// The `[lints]` table: the level of each lint the transpiler reports.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    /// A value is cloned to keep it usable after a move
+    pub implicit_clone: LintLevel,
+    /// An argument is borrowed to match the callee's signature
+    pub implicit_borrow: LintLevel,
+    /// A module contains embedded Rust
+    pub embedded_rust: LintLevel,
+}
+
+/// Rust edition of the generated code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum Edition {
+    #[serde(rename = "2018")]
+    E2018,
+    #[default]
+    #[serde(rename = "2021")]
+    E2021,
+    #[serde(rename = "2024")]
+    E2024,
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+            Edition::E2024 => "2024",
+        })
+    }
+}
+
+/// How strictly the transpiler treats the clones and borrows it inserts,
+/// by adjusting the levels of the [lints](crate::lint).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strictness {
+    /// Lints never fail a module: `deny` reports warnings
+    Relaxed,
+    /// Lints report at the levels `[lints]` sets
+    #[default]
+    Standard,
+    /// Implicit clones and borrows are reported at least as warnings
+    Strict,
+}
+
+/// Level of a lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    #[default]
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Config {
+    /// Parses the contents of a configuration file.
+    pub fn parse(source: &str) -> Result<Self, ConfigError> {
+        serde_path_to_error::deserialize(toml::Deserializer::new(source)).map_err(|error| {
+            let key = error.path().to_string();
+            let error = error.into_inner();
+            let (line, column) = error.span().map_or((1, 1), |span| line_column(source, span.start));
+            ConfigError::Invalid {
+                path: None,
+                key: if key == "." { String::new() } else { key },
+                line,
+                column,
+                message: error.message().trim().replace('\n', ", "),
+            }
+        })
+    }
+
+    /// Loads the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let source = std::fs::read_to_string(path).map_err(|error| ConfigError::Io { path: path.to_path_buf(), error })?;
+        let mut config = Self::parse(&source).map_err(|error| match error {
+            ConfigError::Invalid { key, line, column, message, .. } => {
+                ConfigError::Invalid { path: Some(path.to_path_buf()), key, line, column, message }
+            }
+            other => other,
+        })?;
+        config.root = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        Ok(config)
+    }
+
+    /// Finds `highrust.toml` in `start` or the nearest directory above it.
+    pub fn find(start: &Path) -> Option<PathBuf> {
+        start.ancestors().map(|dir| dir.join(CONFIG_FILE)).find(|path| path.is_file())
+    }
+
+    /// Loads the configuration that applies to `start`, if any.
+    pub fn discover(start: &Path) -> Result<Option<Self>, ConfigError> {
+        Self::find(start).map(|path| Self::load(&path)).transpose()
+    }
+
+    /// The source roots, relative to the current directory.
+    pub fn source_roots(&self) -> Vec<PathBuf> {
+        self.build.source_roots.iter().map(|root| self.root.join(root)).collect()
+    }
+
+    /// Directory the sources under `source_root` are transpiled into. With
+    /// several source roots, each gets its own directory in the output
+    /// directory.
+    pub fn output_dir(&self, source_root: &Path) -> PathBuf {
+        let Some(dir) = &self.build.output_dir else {
+            return source_root.to_path_buf();
+        };
+        let dir = self.root.join(dir);
+        match self.build.source_roots.as_slice() {
+            [_] => dir,
+            _ => dir.join(source_root.strip_prefix(&self.root).unwrap_or(source_root)),
+        }
+    }
+
+    /// Directory a Cargo package for the project is generated in.
+    pub fn package_dir(&self) -> PathBuf {
+        self.root.join(self.build.output_dir.as_deref().unwrap_or(Path::new("target/highrust/package")))
+    }
+
+    /// Name of the generated package: the project's name, or else the name
+    /// of the directory holding the configuration.
+    pub fn package_name(&self) -> String {
+        let name = match &self.project.name {
+            Some(name) => name.clone(),
+            None => std::fs::canonicalize(&self.root)
+                .ok()
+                .and_then(|root| root.file_name().map(|name| name.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "highrust-app".to_string()),
+        };
+        name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c.to_ascii_lowercase() } else { '-' }).collect()
+    }
+
+    /// The options to transpile this project with.
+    pub fn transpile_options(&self) -> crate::TranspileOptions {
+        crate::TranspileOptions {
+            async_runtime: self.build.async_runtime,
+            explain: self.build.verbose_annotations,
+            cache: self.build.cache.then(|| self.root.join(crate::cache::CACHE_DIR)),
+            strictness: self.build.strictness,
+            lints: self.lints.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Deserializes a value through its `FromStr` implementation.
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: std::str::FromStr<Err = String>,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+/// Deserializes a list that must have at least one element.
+fn non_empty<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let values = Vec::deserialize(deserializer)?;
+    if values.is_empty() {
+        return Err(serde::de::Error::custom("must list at least one directory"));
+    }
+    Ok(values)
+}
+
+/// One-based line and column of byte `offset` in `source`.
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rfind('\n').map_or(before.len(), |newline| before.len() - newline - 1) + 1;
+    (line, column)
+}
@@ -0,0 +1,380 @@
+//! Diagnostics reported by the transpiler.
+//!
+//! Every error the transpiler returns converts to a [`Diagnostic`]: a
+//! severity, a stable code such as `E0102`, a message, labeled spans of the
+//! HighRust source, notes and suggested fixes. [`Diagnostic::render`] shows
+//! it against the source in the style of rustc:
+//!
+//! ```text
+//! error[E0102]: narrowing conversion from `i64` to `i32`
+//!  --> src/main.hrs:2:18
+//!   |
+//! 2 |     let x: i32 = a;
+//!   |                  ^ `i64` may not fit in `i32`
+//!   |
+//!   = note: convert explicitly in a `rust { }` block, e.g. with `i32::try_from`
+//! ```
+//!
+//! [`Diagnostic::to_json`] describes it as one line of JSON instead, for
+//! editors and build tools.
+//!
+//! Codes are grouped by phase: `E00xx` parsing, `E01xx` lowering, `E02xx`
+//! code generation, `E03xx` ownership, `E04xx` modules, `E05xx` packages,
+//! `E06xx` I/O and `E07xx` lints. Each has a long-form [`explanation`] with
+//! examples, kept in `src/error_codes/` and printed by `highrust explain
+//! E0xxx`.
+
+use crate::ast::Span;
+use crate::sourcemap::{LineIndex, Position};
+use serde::Serialize;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A span of the source with a message explaining its part in a diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /// Whether this is where the problem is, rather than related context
+    pub primary: bool,
+}
+
+/// A fix replacing a span of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// A problem found in a HighRust program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable code, e.g. `E0102`
+    pub code: &'static str,
+    pub message: String,
+    /// The source file the spans refer to, when known
+    pub file: Option<PathBuf>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic with no spans.
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            file: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    /// Creates an error diagnostic.
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    /// Adds the primary label, marking where the problem is.
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: true });
+        self
+    }
+
+    /// Adds a secondary label, marking related context.
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion(mut self, message: impl Into<String>, span: Span, replacement: impl Into<String>) -> Self {
+        self.suggestions.push(Suggestion { message: message.into(), span, replacement: replacement.into() });
+        self
+    }
+
+    /// Sets the file the spans refer to, unless one is already set.
+    pub fn with_file(mut self, file: impl Into<PathBuf>) -> Self {
+        if self.file.is_none() {
+            self.file = Some(file.into());
+        }
+        self
+    }
+
+    /// The primary label, if any.
+    pub fn primary_label(&self) -> Option<&Label> {
+        self.labels.iter().find(|label| label.primary)
+    }
+
+    /// Renders the diagnostic with carets under its spans in `source`, the
+    /// text of its file. Without the source, labels are listed as notes.
+    pub fn render(&self, source: Option<&str>) -> String {
+        let mut output = String::new();
+        let _ = writeln!(output, "{}[{}]: {}", self.severity, self.code, self.message);
+        let file = self.file.as_deref().map_or_else(|| "<source>".to_string(), display_path);
+        let index = source.map(LineIndex::new);
+        let Some(index) = index.filter(|_| !self.labels.is_empty()) else {
+            let gutter = "";
+            if self.file.is_some() {
+                let _ = writeln!(output, "{} --> {}", gutter, file);
+            }
+            for label in &self.labels {
+                let _ = writeln!(output, "{} = {}: {}", gutter, if label.primary { "here" } else { "see" }, label.message);
+            }
+            self.write_notes(&mut output, gutter);
+            for suggestion in &self.suggestions {
+                let _ = writeln!(output, "{} = help: {}: `{}`", gutter, suggestion.message, suggestion.replacement);
+            }
+            return output;
+        };
+
+        let mut labels: Vec<(&Label, Position, Position)> = self
+            .labels
+            .iter()
+            .map(|label| (label, index.position(label.span.start), index.position(label.span.end)))
+            .collect();
+        labels.sort_by_key(|(label, start, _)| (*start, !label.primary));
+        let suggested: Vec<usize> = self.suggestions.iter().map(|s| index.position(s.span.start).line).collect();
+        let width = labels.iter().map(|(_, start, _)| start.line).chain(suggested).max().unwrap_or(1).to_string().len();
+        let gutter = " ".repeat(width);
+
+        let location = self.primary_label().map_or(labels[0].1, |label| index.position(label.span.start));
+        let _ = writeln!(output, "{}--> {}:{}:{}", gutter, file, location.line, location.column);
+        let _ = writeln!(output, "{} |", gutter);
+        let mut shown_line = None;
+        for (label, start, end) in &labels {
+            let line_text = index.line(start.line).unwrap_or("");
+            if shown_line != Some(start.line) {
+                if shown_line.is_some_and(|line| line + 1 < start.line) {
+                    let _ = writeln!(output, "...");
+                }
+                let _ = writeln!(output, "{:>width$} | {}", start.line, line_text, width = width);
+                shown_line = Some(start.line);
+            }
+            let marker = if label.primary { '^' } else { '-' };
+            write_markers(&mut output, line_text, *start, *end, marker, &label.message, width);
+        }
+        if !self.notes.is_empty() {
+            let _ = writeln!(output, "{} |", gutter);
+        }
+        self.write_notes(&mut output, &gutter);
+
+        for suggestion in &self.suggestions {
+            let start = index.position(suggestion.span.start);
+            let end = index.position(suggestion.span.end);
+            let _ = writeln!(output, "help: {}", suggestion.message);
+            let _ = writeln!(output, "{} |", gutter);
+            let line_text = index.line(start.line).unwrap_or("");
+            // Replace within the first line; a replacement of several lines shows only its first
+            let before: String = line_text.chars().take(start.column - 1).collect();
+            let after: String = if end.line == start.line { line_text.chars().skip(end.column - 1).collect() } else { String::new() };
+            let replacement = suggestion.replacement.lines().next().unwrap_or("");
+            let fixed = format!("{}{}{}", before, replacement, after);
+            let _ = writeln!(output, "{:>width$} | {}", start.line, fixed, width = width);
+            let replaced_end = Position::new(start.line, start.column + replacement.chars().count());
+            write_markers(&mut output, &fixed, start, replaced_end, '~', "", width);
+        }
+        output
+    }
+
+    /// Describes the diagnostic as one line of JSON. Spans give byte offsets
+    /// and, when `source` is the text of the diagnostic's file, one-based
+    /// lines and columns:
+    ///
+    /// ```json
+    /// {"code":"E0104","severity":"error","message":"cannot export `helpr`: no such item","file":"src/util.hrs",
+    ///  "spans":[{"byte_start":22,"byte_end":27,"line_start":2,"column_start":8,"line_end":2,"column_end":13,
+    ///            "label":"not defined or imported in this module","is_primary":true}],
+    ///  "notes":[],"suggestions":[{"message":"a similar item is defined: `helper`","span":{...},"replacement":"helper"}]}
+    /// ```
+    pub fn to_json(&self, source: Option<&str>) -> String {
+        let index = source.map(LineIndex::new);
+        let json = JsonDiagnostic {
+            code: self.code,
+            severity: self.severity,
+            message: &self.message,
+            file: self.file.as_deref().map(|file| file.display().to_string()),
+            spans: self.labels.iter().map(|label| json_span(index.as_ref(), &label.span, Some(label))).collect(),
+            notes: &self.notes,
+            suggestions: self
+                .suggestions
+                .iter()
+                .map(|suggestion| JsonSuggestion {
+                    message: &suggestion.message,
+                    span: json_span(index.as_ref(), &suggestion.span, None),
+                    replacement: &suggestion.replacement,
+                })
+                .collect(),
+        };
+        serde_json::to_string(&json).expect("diagnostics always serialize")
+    }
+
+    fn write_notes(&self, output: &mut String, gutter: &str) {
+        for note in &self.notes {
+            let _ = writeln!(output, "{} = note: {}", gutter, note);
+        }
+    }
+}
+
+/// The JSON form of a [`Diagnostic`].
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+    code: &'static str,
+    severity: Severity,
+    message: &'a str,
+    file: Option<String>,
+    spans: Vec<JsonSpan<'a>>,
+    notes: &'a [String],
+    suggestions: Vec<JsonSuggestion<'a>>,
+}
+
+/// A span in the JSON form of a diagnostic; lines and columns are present
+/// when the source was given.
+#[derive(Serialize)]
+struct JsonSpan<'a> {
+    byte_start: usize,
+    byte_end: usize,
+    line_start: Option<usize>,
+    column_start: Option<usize>,
+    line_end: Option<usize>,
+    column_end: Option<usize>,
+    label: Option<&'a str>,
+    is_primary: bool,
+}
+
+/// Describes `span`, marked by `label` when it is a label's.
+fn json_span<'a>(index: Option<&LineIndex>, span: &Span, label: Option<&'a Label>) -> JsonSpan<'a> {
+    let start = index.map(|index| index.position(span.start));
+    let end = index.map(|index| index.position(span.end));
+    JsonSpan {
+        byte_start: span.start,
+        byte_end: span.end,
+        line_start: start.map(|position| position.line),
+        column_start: start.map(|position| position.column),
+        line_end: end.map(|position| position.line),
+        column_end: end.map(|position| position.column),
+        label: label.map(|label| label.message.as_str()),
+        is_primary: label.is_some_and(|label| label.primary),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonSuggestion<'a> {
+    message: &'a str,
+    span: JsonSpan<'a>,
+    replacement: &'a str,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+/// Writes the marker line for a span of `line_text` from `start` to `end`,
+/// with `marker`s under the span and `label` after them. A span running past
+/// the line is marked to its end.
+pub(crate) fn write_markers(
+    output: &mut String,
+    line_text: &str,
+    start: Position,
+    end: Position,
+    marker: char,
+    label: &str,
+    width: usize,
+) {
+    let indent: String = line_text
+        .chars()
+        .take(start.column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let length = if end.line == start.line {
+        end.column.saturating_sub(start.column)
+    } else {
+        line_text.chars().count().saturating_sub(start.column - 1)
+    };
+    let markers = marker.to_string().repeat(length.max(1));
+    let line = format!("{} | {}{} {}", " ".repeat(width), indent, markers, label);
+    let _ = writeln!(output, "{}", line.trim_end());
+}
+
+/// Every code, with its long-form explanation.
+const EXPLANATIONS: &[(&str, &str)] = &[
+    ("E0001", include_str!("error_codes/E0001.md")),
+    ("E0002", include_str!("error_codes/E0002.md")),
+    ("E0003", include_str!("error_codes/E0003.md")),
+    ("E0004", include_str!("error_codes/E0004.md")),
+    ("E0005", include_str!("error_codes/E0005.md")),
+    ("E0100", include_str!("error_codes/E0100.md")),
+    ("E0101", include_str!("error_codes/E0101.md")),
+    ("E0102", include_str!("error_codes/E0102.md")),
+    ("E0103", include_str!("error_codes/E0103.md")),
+    ("E0104", include_str!("error_codes/E0104.md")),
+    ("E0105", include_str!("error_codes/E0105.md")),
+    ("E0200", include_str!("error_codes/E0200.md")),
+    ("E0201", include_str!("error_codes/E0201.md")),
+    ("E0202", include_str!("error_codes/E0202.md")),
+    ("E0300", include_str!("error_codes/E0300.md")),
+    ("E0301", include_str!("error_codes/E0301.md")),
+    ("E0302", include_str!("error_codes/E0302.md")),
+    ("E0303", include_str!("error_codes/E0303.md")),
+    ("E0400", include_str!("error_codes/E0400.md")),
+    ("E0401", include_str!("error_codes/E0401.md")),
+    ("E0402", include_str!("error_codes/E0402.md")),
+    ("E0403", include_str!("error_codes/E0403.md")),
+    ("E0500", include_str!("error_codes/E0500.md")),
+    ("E0501", include_str!("error_codes/E0501.md")),
+    ("E0600", include_str!("error_codes/E0600.md")),
+    ("E0700", include_str!("error_codes/E0700.md")),
+    ("E0701", include_str!("error_codes/E0701.md")),
+    ("E0702", include_str!("error_codes/E0702.md")),
+];
+
+/// Every diagnostic code, in order.
+pub fn codes() -> impl Iterator<Item = &'static str> {
+    EXPLANATIONS.iter().map(|(code, _)| *code)
+}
+
+/// The long-form explanation of `code`, in Markdown with HighRust examples,
+/// or `None` for an unknown code. Codes are matched case-insensitively.
+pub fn explanation(code: &str) -> Option<&'static str> {
+    EXPLANATIONS.iter().find(|(known, _)| known.eq_ignore_ascii_case(code)).map(|(_, text)| *text)
+}
+
+/// `file`, relative to the current directory when it is inside it.
+pub(crate) fn display_path(file: &Path) -> String {
+    let cwd = std::env::current_dir().ok();
+    let file = cwd.as_ref().and_then(|cwd| file.strip_prefix(cwd).ok()).unwrap_or(file);
+    file.display().to_string()
+}
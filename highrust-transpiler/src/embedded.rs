@@ -0,0 +1,387 @@
+//! Support for Rust code embedded in HighRust source.
+//!
+//! `rust { ... }` blocks and `@rust` functions are emitted verbatim, so the
+//! transpiler never parses their bodies. It does scan blocks for identifiers,
+//! so that HighRust variables used inside a block are seen by ownership
+//! inference and lowering, and it reads `@rust` signatures and imported Rust
+//! files with `syn`, so that callers know how each parameter is taken.
+
+use crate::ast::{Block, DataDef, DataKind, Field, FunctionDef, ModuleItem, Param, Span, Type};
+use syn::spanned::Spanned;
+use syn::{FnArg, GenericArgument, Item, Pat, PathArguments, ReturnType, Visibility};
+
+/// How embedded Rust code uses a variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableUse {
+    /// The variable is only read, so a shared borrow is enough.
+    Read,
+    /// The variable is assigned, borrowed with `&mut`, or has a mutating
+    /// method called on it.
+    Mutated,
+}
+
+/// Returns the identifiers `code` may use as variables, in order of first
+/// use, with the strongest use of each.
+///
+/// Field and method names, path segments, macro names and the contents of
+/// strings, char literals and comments are skipped. `is_mutating_method`
+/// decides whether a method call such as `items.push(..)` mutates its receiver.
+pub fn variable_uses(code: &str, is_mutating_method: impl Fn(&str) -> bool) -> Vec<(String, VariableUse)> {
+    let tokens = tokenize(code);
+    let mut uses: Vec<(String, VariableUse)> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let Token::Ident(name) = token else { continue };
+        let prev = i.checked_sub(1).map(|j| &tokens[j]);
+        let next = tokens.get(i + 1);
+        // Fields, methods and path segments
+        if matches!(prev, Some(Token::Punct(p)) if p == "." || p == "::") {
+            continue;
+        }
+        // Path heads and macro names
+        if matches!(next, Some(Token::Punct(p)) if p == "::" || p == "!") {
+            continue;
+        }
+        if is_rust_keyword(name) {
+            continue;
+        }
+        let mutated = match next {
+            Some(Token::Punct(p)) if is_assignment(p) => true,
+            Some(Token::Punct(p)) if p == "." => matches!(
+                (tokens.get(i + 2), tokens.get(i + 3)),
+                (Some(Token::Ident(method)), Some(Token::Punct(open))) if open == "(" && is_mutating_method(method)
+            ),
+            _ => false,
+        } || (i >= 2 && matches!((&tokens[i - 2], prev), (Token::Punct(amp), Some(Token::Ident(kw))) if amp == "&" && kw == "mut"));
+        let use_kind = if mutated { VariableUse::Mutated } else { VariableUse::Read };
+        match uses.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, existing)) => {
+                if use_kind == VariableUse::Mutated {
+                    *existing = VariableUse::Mutated;
+                }
+            }
+            None => uses.push((name.clone(), use_kind)),
+        }
+    }
+    uses
+}
+
+/// Returns true if embedded Rust code uses `name` as a variable.
+pub fn uses_variable(code: &str, name: &str) -> bool {
+    variable_uses(code, |_| false).iter().any(|(used, _)| used == name)
+}
+
+/// Strips the common indentation of embedded code and surrounding blank
+/// lines, returning the lines to emit.
+pub fn dedent(code: &str) -> Vec<String> {
+    dedent_lines(code).into_iter().map(|(_, line)| line).collect()
+}
+
+/// Like [`dedent`], but pairs each line with the byte offset in `code` of
+/// its first non-blank character, so emitted lines can be mapped back.
+pub fn dedent_lines(code: &str) -> Vec<(usize, String)> {
+    let mut offset = 0;
+    let mut lines: Vec<(usize, &str)> = Vec::new();
+    for line in code.split('\n') {
+        lines.push((offset, line.strip_suffix('\r').unwrap_or(line)));
+        offset += line.len() + 1;
+    }
+    let leading = |line: &str| line.len() - line.trim_start().len();
+    // Code on the same line as the opening brace carries no indentation
+    let first = if lines.first().is_some_and(|(_, line)| !line.trim().is_empty()) {
+        let (start, line) = lines.remove(0);
+        Some((start + leading(line), line.trim().to_string()))
+    } else {
+        None
+    };
+    while lines.first().is_some_and(|(_, line)| line.trim().is_empty()) {
+        lines.remove(0);
+    }
+    while lines.last().is_some_and(|(_, line)| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let indent = lines
+        .iter()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(_, line)| leading(line))
+        .min()
+        .unwrap_or(0);
+    first
+        .into_iter()
+        .chain(lines.iter().map(|(start, line)| {
+            (start + leading(line), line.get(indent..).unwrap_or("").trim_end().to_string())
+        }))
+        .collect()
+}
+
+/// The HighRust view of a `@rust` function's signature.
+#[derive(Debug, Clone)]
+pub struct RustSignature {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub ret_type: Option<Type>,
+    pub is_async: bool,
+}
+
+/// Parses the signature of a `@rust` function, such as
+/// `fn sum(values: &[i64]) -> i64`. `span` locates the signature in the
+/// HighRust source; errors carry the span of the offending Rust tokens.
+pub fn parse_signature(signature: &str, span: &Span) -> Result<RustSignature, (String, Span)> {
+    let sig: syn::Signature = syn::parse_str(signature).map_err(|error| (error.to_string(), offset_span(span, signature, error.span())))?;
+    convert_signature(&sig, |inner| offset_span(span, signature, inner))
+}
+
+/// Reads the public functions and structs of a Rust source file as
+/// HighRust items: functions become `@rust` functions without a body, so
+/// that only their signatures are known, and structs with named fields
+/// become data definitions. Methods and other items are skipped.
+pub fn parse_rust_items(source: &str) -> Result<Vec<ModuleItem>, String> {
+    let file = syn::parse_file(source).map_err(|error| {
+        let start = error.span().start();
+        format!("{} at line {}, column {}", error, start.line, start.column + 1)
+    })?;
+    let no_span = |_| Span { start: 0, end: 0 };
+    let mut items = Vec::new();
+    for item in &file.items {
+        match item {
+            Item::Fn(func) if matches!(func.vis, Visibility::Public(_)) => {
+                let Ok(sig) = convert_signature(&func.sig, no_span) else { continue };
+                items.push(ModuleItem::Function(FunctionDef {
+                    name: sig.name,
+                    params: sig.params,
+                    ret_type: sig.ret_type,
+                    body: Block { stmts: Vec::new(), span: no_span(func.span()) },
+                    is_async: sig.is_async,
+                    is_rust: true,
+                    span: no_span(func.span()),
+                }));
+            }
+            Item::Struct(data) if matches!(data.vis, Visibility::Public(_)) => {
+                let syn::Fields::Named(named) = &data.fields else { continue };
+                let fields = named
+                    .named
+                    .iter()
+                    .filter_map(|field| {
+                        Some(Field {
+                            name: field.ident.as_ref()?.to_string(),
+                            ty: convert_type(&field.ty),
+                            span: no_span(field.span()),
+                        })
+                    })
+                    .collect();
+                items.push(ModuleItem::Data(DataDef {
+                    name: data.ident.to_string(),
+                    kind: DataKind::Struct(fields),
+                    generics: Vec::new(),
+                    span: no_span(data.span()),
+                }));
+            }
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+/// Converts a Rust signature, locating parameters with `span_of`.
+fn convert_signature(sig: &syn::Signature, span_of: impl Fn(proc_macro2::Span) -> Span) -> Result<RustSignature, (String, Span)> {
+    let mut params = Vec::new();
+    for input in &sig.inputs {
+        match input {
+            FnArg::Typed(arg) => {
+                let name = match &*arg.pat {
+                    Pat::Ident(ident) => ident.ident.to_string(),
+                    _ => "_".to_string(),
+                };
+                params.push(Param {
+                    name,
+                    ty: Some(convert_type(&arg.ty)),
+                    span: span_of(arg.span()),
+                });
+            }
+            FnArg::Receiver(receiver) => {
+                return Err(("`@rust` functions cannot take `self`".to_string(), span_of(receiver.span())));
+            }
+        }
+    }
+    let ret_type = match &sig.output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => Some(convert_type(ty)),
+    };
+    Ok(RustSignature {
+        name: sig.ident.to_string(),
+        params,
+        ret_type,
+        is_async: sig.asyncness.is_some(),
+    })
+}
+
+/// Converts a Rust type to the HighRust type the type and ownership passes
+/// work with. Types without a HighRust counterpart keep their Rust spelling.
+fn convert_type(ty: &syn::Type) -> Type {
+    match ty {
+        syn::Type::Reference(reference) => {
+            let kind = if reference.mutability.is_some() { "&mut" } else { "&" };
+            Type::Named(kind.to_string(), vec![convert_type(&reference.elem)])
+        }
+        syn::Type::Slice(slice) => Type::Array(Box::new(convert_type(&slice.elem))),
+        syn::Type::Tuple(tuple) => Type::Tuple(tuple.elems.iter().map(convert_type).collect()),
+        syn::Type::Paren(paren) => convert_type(&paren.elem),
+        syn::Type::Path(path) if path.qself.is_none() => {
+            let name = path.path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>().join("::");
+            let args: Vec<Type> = match path.path.segments.last().map(|segment| &segment.arguments) {
+                Some(PathArguments::AngleBracketed(generics)) => generics
+                    .args
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        GenericArgument::Type(ty) => Some(convert_type(ty)),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            match (name.as_str(), args.as_slice()) {
+                ("Option", [inner]) => Type::Option(Box::new(inner.clone())),
+                ("Result", [ok, err]) => Type::Result(Box::new(ok.clone()), Box::new(err.clone())),
+                _ => Type::Named(name, args),
+            }
+        }
+        other => Type::Named(quote::ToTokens::to_token_stream(other).to_string(), vec![]),
+    }
+}
+
+/// Maps a span within `signature` to the HighRust source, falling back to
+/// the whole signature when the position is unavailable.
+fn offset_span(span: &Span, signature: &str, inner: proc_macro2::Span) -> Span {
+    let (start, end) = (inner.start(), inner.end());
+    match (line_offset(signature, start.line, start.column), line_offset(signature, end.line, end.column)) {
+        (Some(from), Some(to)) if from <= to => Span {
+            start: span.start + from,
+            end: span.start + to,
+        },
+        _ => span.clone(),
+    }
+}
+
+/// Byte offset of a 1-based line and 0-based char column in `text`.
+fn line_offset(text: &str, line: usize, column: usize) -> Option<usize> {
+    let line_start: usize = text.split_inclusive('\n').take(line.checked_sub(1)?).map(str::len).sum();
+    let line_text = text.get(line_start..)?;
+    let offset = line_text.char_indices().nth(column).map_or(line_text.len(), |(i, _)| i);
+    Some(line_start + offset)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Punct(String),
+}
+
+/// Splits Rust code into identifiers and punctuation, dropping literals and
+/// comments.
+fn tokenize(code: &str) -> Vec<Token> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+        if c.is_whitespace() {
+            i += 1;
+        } else if rest.starts_with(&['/', '/']) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if rest.starts_with(&['/', '*']) {
+            i += 2;
+            while i < chars.len() && !chars[i..].starts_with(&['*', '/']) {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' || (c == 'r' && matches!(rest.get(1), Some('"' | '#'))) {
+            i = skip_string(&chars, i);
+        } else if c == '\'' {
+            // Char literal, or a lifetime which is skipped like an identifier
+            match (rest.get(1), rest.get(2)) {
+                (Some('\\'), _) => {
+                    i += 2;
+                    while i < chars.len() && chars[i] != '\'' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                (Some(_), Some('\'')) => i += 3,
+                _ => {
+                    i += 1;
+                    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                        i += 1;
+                    }
+                }
+            }
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                // A `.` only continues a number when a digit follows
+                if chars[i] == '.' && !chars.get(i + 1).is_some_and(char::is_ascii_digit) {
+                    break;
+                }
+                i += 1;
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let punct = ["<<=", ">>=", "::", "==", "!=", "<=", ">=", "=>", "+=", "-=", "*=", "/=", "%=", "^=", "&=", "|=", "&&", "||", "->"]
+                .iter()
+                .find(|p| rest.iter().take(p.len()).copied().eq(p.chars()))
+                .map(|p| p.to_string())
+                .unwrap_or_else(|| c.to_string());
+            i += punct.chars().count();
+            tokens.push(Token::Punct(punct));
+        }
+    }
+    tokens
+}
+
+/// Skips a string or raw string literal starting at `start`, returning the
+/// index just past it.
+fn skip_string(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    if chars[i] == 'r' {
+        i += 1;
+        let mut hashes = 0;
+        while chars.get(i) == Some(&'#') {
+            hashes += 1;
+            i += 1;
+        }
+        i += 1;
+        while i < chars.len() {
+            if chars[i] == '"' && chars[i + 1..].iter().take(hashes).filter(|c| **c == '#').count() == hashes {
+                return i + 1 + hashes;
+            }
+            i += 1;
+        }
+        return i;
+    }
+    i += 1;
+    while i < chars.len() && chars[i] != '"' {
+        if chars[i] == '\\' {
+            i += 1;
+        }
+        i += 1;
+    }
+    i + 1
+}
+
+fn is_assignment(punct: &str) -> bool {
+    matches!(punct, "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "^=" | "&=" | "|=" | "<<=" | ">>=")
+}
+
+fn is_rust_keyword(name: &str) -> bool {
+    matches!(
+        name,
+        "as" | "break" | "const" | "continue" | "crate" | "else" | "enum" | "extern" | "false" | "fn" | "for" | "if" | "impl" | "in"
+            | "let" | "loop" | "match" | "mod" | "move" | "mut" | "pub" | "ref" | "return" | "self" | "Self" | "static" | "struct"
+            | "super" | "trait" | "true" | "type" | "unsafe" | "use" | "where" | "while" | "async" | "await" | "dyn"
+    )
+}
@@ -0,0 +1,167 @@
+//! The intermediate forms of the transpiler's pipeline.
+//!
+//! When inference does something surprising, the forms between the source
+//! and the generated Rust show where it happened: the pest parse tree, the
+//! AST, the ownership analysis, the lowered IR and the Rust itself. Each
+//! [`Stage`] has a stable text form for reading and a JSON form for tools.
+
+use crate::ast::Module;
+use crate::lowering::{self, LoweredModule};
+use crate::modules::ModuleResolver;
+use crate::ownership::{OwnershipAnalysisResult, OwnershipInference};
+use crate::parser::{self, Token};
+use crate::{parse_source, transpile_module, TranspileOptions, TranspilerError};
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A form of a module on its way from HighRust to Rust, in pipeline order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// The pest parse tree
+    Tokens,
+    /// The `ast::Module`
+    Ast,
+    /// The `OwnershipAnalysisResult`
+    Ownership,
+    /// The `LoweredModule`
+    Ir,
+    /// The generated Rust
+    Rust,
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Tokens => "tokens",
+            Stage::Ast => "ast",
+            Stage::Ownership => "ownership",
+            Stage::Ir => "ir",
+            Stage::Rust => "rust",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tokens" => Ok(Stage::Tokens),
+            "ast" => Ok(Stage::Ast),
+            "ownership" => Ok(Stage::Ownership),
+            "ir" => Ok(Stage::Ir),
+            "rust" => Ok(Stage::Rust),
+            other => Err(format!("unknown stage '{}' (expected tokens, ast, ownership, ir or rust)", other)),
+        }
+    }
+}
+
+/// The forms of a module at the stages asked for; the others are `None`.
+/// Serializes as one JSON object with a key for each stage present.
+#[derive(Debug, Default, Serialize)]
+pub struct Emitted {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<Token>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ast: Option<Module>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ownership: Option<OwnershipAnalysisResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ir: Option<LoweredModule>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rust: Option<String>,
+}
+
+impl Emitted {
+    /// The stages present, in pipeline order.
+    pub fn stages(&self) -> Vec<Stage> {
+        [
+            (Stage::Tokens, self.tokens.is_some()),
+            (Stage::Ast, self.ast.is_some()),
+            (Stage::Ownership, self.ownership.is_some()),
+            (Stage::Ir, self.ir.is_some()),
+            (Stage::Rust, self.rust.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(stage, present)| present.then_some(stage))
+        .collect()
+    }
+
+    /// The text form of `stage`, if it was emitted: the parse tree one node
+    /// per line, the AST and IR pretty-printed, the ownership facts one
+    /// kind per line, and the Rust as generated.
+    pub fn text(&self, stage: Stage) -> Option<String> {
+        match stage {
+            Stage::Tokens => self.tokens.as_ref().map(|tokens| tokens.iter().map(Token::to_string).collect()),
+            Stage::Ast => self.ast.as_ref().map(|ast| format!("{:#?}\n", ast)),
+            Stage::Ownership => self.ownership.as_ref().map(OwnershipAnalysisResult::to_string),
+            Stage::Ir => self.ir.as_ref().map(|ir| format!("{:#?}\n", ir)),
+            Stage::Rust => self.rust.clone(),
+        }
+    }
+
+    /// The text forms of every stage present, each after a `=== stage ===`
+    /// header when there are several.
+    pub fn to_text(&self) -> String {
+        let stages = self.stages();
+        let mut output = String::new();
+        for stage in &stages {
+            if stages.len() > 1 {
+                output.push_str(&format!("=== {} ===\n", stage));
+            }
+            output.push_str(&self.text(*stage).unwrap_or_default());
+        }
+        output
+    }
+
+    /// The JSON form of every stage present, as one pretty-printed object.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("pipeline forms always serialize")
+    }
+}
+
+/// Runs the pipeline on `source` as far as the last of `stages` needs,
+/// keeping the form at each of them. When `path` names the source's file,
+/// its imports resolve from the file's directory as in
+/// [`transpile_file_with`](crate::transpile_file_with).
+pub fn emit(
+    source: &str,
+    path: Option<&Path>,
+    stages: &[Stage],
+    options: &TranspileOptions,
+) -> Result<Emitted, TranspilerError> {
+    let wants = |stage: Stage| stages.contains(&stage);
+    let mut emitted = Emitted::default();
+    if wants(Stage::Tokens) {
+        emitted.tokens = Some(parser::tokens(source)?);
+    }
+    if !stages.iter().any(|stage| *stage > Stage::Tokens) {
+        return Ok(emitted);
+    }
+
+    let mut ast = parse_source(source)?;
+    if let Some(path) = path {
+        ModuleResolver::new(path.parent().unwrap_or(Path::new(""))).resolve_imports(&mut ast, path)?;
+    }
+    if wants(Stage::Ownership) {
+        emitted.ownership = Some(OwnershipInference::new().analyze_module(&ast));
+    }
+    if wants(Stage::Ir) {
+        emitted.ir = Some(lowering::lower_module(&ast)?);
+    }
+    if wants(Stage::Rust) {
+        emitted.rust = Some(transpile_module(&ast, source, path, options)?.code);
+    }
+    if wants(Stage::Ast) {
+        emitted.ast = Some(ast);
+    }
+    Ok(emitted)
+}
@@ -0,0 +1,611 @@
+//! Explanations of the code the transpiler infers.
+//!
+//! Lowering adds `mut` to bindings, `.clone()` to values used again, and
+//! `.to_string()`, `&` and `&mut` where a value fills a slot of a different
+//! type; code generation adds `mut` to parameters and lifetime parameters to
+//! functions returning references. Each of these is an [`Inference`] naming
+//! what it applies to, why, and the uses that forced it:
+//!
+//! ```text
+//! cloned: `s` is used again at line 12
+//! mut: `v` is mutated by `.push()` at lines 4, 7
+//! ```
+//!
+//! `highrust transpile --explain` writes them as comments above the
+//! generated code; [`explain_module`] gives them to tools.
+//!
+//! [`explain_binding`] gathers what was decided for one binding instead, as
+//! `highrust explain file.hrs:LINE:COL` prints it:
+//!
+//! ```text
+//! `u` in `main`, declared at line 8: borrowed, cloned
+//!
+//! &: `u` is passed where `&String` is expected
+//!        9 | show(u);
+//! ...
+//! used at lines 9, 10, 13
+//! ```
+
+use crate::ast::{Block, ComprehensionClause, Expr, FunctionDef, MatchArm, Module, ModuleItem, Pattern, Span, Stmt};
+use crate::codegen;
+use crate::embedded::{uses_variable, variable_uses, VariableUse};
+use crate::lowering::{self, LoweredItem, LoweringError};
+use crate::ownership::OwnershipInference;
+use crate::modules::ModuleResolver;
+use crate::sourcemap::{LineIndex, Position};
+use crate::{parse_source, types, TranspilerError};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// What the transpiler inferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InferenceKind {
+    /// A `mut` binding or parameter
+    Mutable,
+    /// A `&` borrow
+    Borrow,
+    /// A `&mut` borrow
+    BorrowMut,
+    /// A `.clone()`
+    Clone,
+    /// A `.to_string()`
+    ToString,
+    /// A lifetime parameter
+    Lifetime,
+}
+
+impl InferenceKind {
+    /// The short name starting an explanation.
+    pub fn label(&self) -> &'static str {
+        match self {
+            InferenceKind::Mutable => "mut",
+            InferenceKind::Borrow => "&",
+            InferenceKind::BorrowMut => "&mut",
+            InferenceKind::Clone => "cloned",
+            InferenceKind::ToString => "to_string",
+            InferenceKind::Lifetime => "lifetime",
+        }
+    }
+}
+
+/// A piece of generated code the transpiler inferred, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Inference {
+    pub kind: InferenceKind,
+    /// The binding, value or function the inferred code applies to
+    pub name: String,
+    /// Where in the HighRust source the inferred code applies
+    pub span: Span,
+    /// Why, as a phrase following the name, e.g. "is used again"
+    pub reason: String,
+    /// The uses that forced it, in source order
+    pub uses: Vec<Span>,
+    /// The statement, or for parameters and lifetimes the function, whose
+    /// generated code holds it
+    pub statement: Span,
+}
+
+impl Inference {
+    pub(crate) fn new(kind: InferenceKind, name: &str, span: &Span) -> Self {
+        Inference {
+            kind,
+            name: name.to_string(),
+            span: span.clone(),
+            reason: String::new(),
+            uses: Vec::new(),
+            statement: span.clone(),
+        }
+    }
+
+    /// Describes the inference in one line, with the lines of its uses in
+    /// the source `index` was built from, e.g. "cloned: `s` is used again at
+    /// line 12".
+    pub fn describe(&self, index: &LineIndex) -> String {
+        let at = at_lines(&self.uses, index).map(|lines| format!(" {}", lines)).unwrap_or_default();
+        format!("{}: `{}` {}{}", self.kind.label(), self.name, self.reason, at)
+    }
+}
+
+/// "at line N" or "at lines N, M" for the distinct lines of `spans`, or
+/// `None` if there are none.
+fn at_lines(spans: &[Span], index: &LineIndex) -> Option<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for span in spans {
+        let line = index.position(span.start).line.to_string();
+        if !lines.contains(&line) {
+            lines.push(line);
+        }
+    }
+    match lines.len() {
+        0 => None,
+        1 => Some(format!("at line {}", lines[0])),
+        _ => Some(format!("at lines {}", lines.join(", "))),
+    }
+}
+
+/// Parses and lowers `source`, returning what the transpiler infers for it.
+pub fn explain_source(source: &str) -> Result<Vec<Inference>, TranspilerError> {
+    let module = parse_source(source)?;
+    Ok(explain_module(&module, source)?)
+}
+
+/// Returns what the transpiler infers for `module`, parsed from `source`, in
+/// source order.
+pub fn explain_module(module: &Module, source: &str) -> Result<Vec<Inference>, LoweringError> {
+    let (lowered, mut inferences) = lowering::lower_module_explained(module, source)?;
+    let analysis = OwnershipInference::new().analyze_module(module);
+    let functions: Vec<&FunctionDef> = module
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::Function(func) if !func.is_rust => Some(func),
+            _ => None,
+        })
+        .collect();
+
+    // Parameters and lifetimes are decided by code generation
+    for func in &functions {
+        for param in func.params.iter().filter(|param| analysis.mutable_vars.contains(&param.name)) {
+            inferences.push(Inference::new(InferenceKind::Mutable, &param.name, &param.span));
+        }
+    }
+    for item in &lowered.items {
+        if let LoweredItem::Function(func) = item {
+            if codegen::needs_default_lifetime(func) {
+                inferences.push(Inference {
+                    reason: "returns a reference, so `'a` ties it to the parameters it borrows from".to_string(),
+                    ..Inference::new(InferenceKind::Lifetime, &func.name, &func.span)
+                });
+            }
+        }
+    }
+
+    let signatures = signatures(module);
+    let mut explained: Vec<Inference> = Vec::new();
+    for mut inference in inferences {
+        if explained.iter().any(|seen| seen.kind == inference.kind && seen.span == inference.span) {
+            continue;
+        }
+        if let Some(func) = functions.iter().find(|func| contains(&func.span, &inference.span)) {
+            inference.statement = innermost_statement(&func.body, &inference.span).unwrap_or_else(|| func.span.clone());
+            find_uses(&mut inference, func, &functions, &signatures);
+            // Without a reason to give, leave the line out rather than guess
+            if inference.kind == InferenceKind::Mutable && inference.reason.is_empty() {
+                continue;
+            }
+        }
+        explained.push(inference);
+    }
+    explained.sort_by_key(|inference| inference.span.start);
+    Ok(explained)
+}
+
+/// Fills in the reason for `inference`, when lowering did not give one, and
+/// the uses among `functions` that forced it. The reason stays empty when
+/// none is found.
+fn find_uses(
+    inference: &mut Inference,
+    func: &FunctionDef,
+    functions: &[&FunctionDef],
+    signatures: &HashMap<&str, Vec<Option<bool>>>,
+) {
+    let name = inference.name.as_str();
+    match inference.kind {
+        InferenceKind::Mutable => {
+            let found = mutations(&func.body, name, inference.span.end, signatures);
+            if let Some((_, how)) = found.first() {
+                inference.reason = how.clone();
+                inference.uses = found.into_iter().map(|(span, _)| span).collect();
+                return;
+            }
+            // Mutability is tracked by name, so a mutation elsewhere counts
+            let elsewhere = functions.iter().filter(|other| other.span != func.span).find_map(|other| {
+                let (span, how) = mutations(&other.body, name, 0, signatures).into_iter().next()?;
+                Some((other, span, how))
+            });
+            if let Some((other, span, how)) = elsewhere {
+                inference.reason = format!("shares its name with a variable in `{}` that {}", other.name, how);
+                inference.uses = vec![span];
+            }
+        }
+        InferenceKind::Clone => {
+            let later = uses(&func.body, name, |span| span.start >= inference.statement.end);
+            if later.is_empty() {
+                inference.reason = "was moved earlier".to_string();
+                inference.uses = uses(&func.body, name, |span| span.end <= inference.statement.start);
+            } else {
+                inference.reason = "is used again".to_string();
+                inference.uses = later;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// An ownership decision for a binding, as named in explanations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Its value moves out of it
+    Moved,
+    /// It is cloned where its value is needed again
+    Cloned,
+    /// It is passed as `&`
+    Borrowed,
+    /// It is passed as `&mut`
+    BorrowedMutably,
+    /// It is declared `mut`
+    Mutable,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Decision::Moved => "moved",
+            Decision::Cloned => "cloned",
+            Decision::Borrowed => "borrowed",
+            Decision::BorrowedMutably => "borrowed mutably",
+            Decision::Mutable => "mutable",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What the transpiler decided for one binding of a function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BindingExplanation {
+    pub name: String,
+    /// The function declaring it
+    pub function: String,
+    /// The name in its `let`, `for` or parameter
+    pub declaration: Span,
+    /// The decisions, in the order of [`Decision`]; empty if it is used as
+    /// written
+    pub decisions: Vec<Decision>,
+    /// The inferred code that carries out the decisions
+    pub inferences: Vec<Inference>,
+    /// Every use of it, in source order
+    pub uses: Vec<Span>,
+}
+
+impl BindingExplanation {
+    /// Describes the decisions with the inferences carrying them out, each
+    /// followed by the lines, of the source `index` was built from, that
+    /// forced it, and then the lines using the binding.
+    pub fn describe(&self, index: &LineIndex) -> String {
+        let decisions = match self.decisions.as_slice() {
+            [] => "used as written".to_string(),
+            decisions => decisions.iter().map(Decision::to_string).collect::<Vec<_>>().join(", "),
+        };
+        let mut output = format!(
+            "`{}` in `{}`, declared at line {}: {}\n",
+            self.name,
+            self.function,
+            index.position(self.declaration.start).line,
+            decisions
+        );
+        for inference in &self.inferences {
+            output.push('\n');
+            output.push_str(&inference.describe(index));
+            output.push('\n');
+            let mut lines: Vec<usize> = std::iter::once(&inference.span)
+                .chain(&inference.uses)
+                .map(|span| index.position(span.start).line)
+                .collect();
+            lines.sort();
+            lines.dedup();
+            for line in lines {
+                output.push_str(&format!("{:>8} | {}\n", line, index.line(line).unwrap_or_default().trim()));
+            }
+        }
+        if let Some(lines) = at_lines(&self.uses, index) {
+            output.push_str(&format!("\nused {}\n", lines));
+        }
+        output
+    }
+}
+
+/// Parses `source` and explains the binding named at `position` of it, as
+/// [`explain_binding`] does. When `path` names the source's file, its
+/// imports resolve from the file's directory.
+pub fn explain_position(
+    source: &str,
+    path: Option<&Path>,
+    position: Position,
+) -> Result<Option<BindingExplanation>, TranspilerError> {
+    let mut module = parse_source(source)?;
+    if let Some(path) = path {
+        ModuleResolver::new(path.parent().unwrap_or(Path::new(""))).resolve_imports(&mut module, path)?;
+    }
+    match LineIndex::new(source).offset(position) {
+        Some(offset) => Ok(explain_binding(&module, source, offset)?),
+        None => Ok(None),
+    }
+}
+
+/// Explains the binding named at byte `offset` of `source`, which `module`
+/// was parsed from, by its declaration or any use of it in a function.
+/// Returns `None` if no binding is named there.
+pub fn explain_binding(module: &Module, source: &str, offset: usize) -> Result<Option<BindingExplanation>, LoweringError> {
+    let Some(func) = module.items.iter().find_map(|item| match item {
+        ModuleItem::Function(func) if !func.is_rust && func.span.start <= offset && offset < func.span.end => Some(func),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+    let declarations = declarations(func);
+    let at = |span: &Span| span.start <= offset && offset < span.end;
+    let mut name = declarations.iter().find(|(_, span)| at(span)).map(|(name, _)| *name);
+    visit_block(&func.body, &mut |node| {
+        if let Node::Expr(Expr::Variable(used, span)) = node {
+            if at(span) {
+                name = Some(used.as_str());
+            }
+        }
+    });
+    let Some(name) = name else {
+        return Ok(None);
+    };
+
+    // A later declaration of the name shadows this one
+    let mut same_name = declarations.iter().filter(|(declared, _)| *declared == name).map(|(_, span)| span);
+    let Some(declaration) = same_name.clone().rfind(|span| span.start <= offset) else {
+        return Ok(None);
+    };
+    let scope_end = same_name.find(|span| span.start > declaration.start).map_or(func.span.end, |span| span.start);
+    let in_scope = |span: &Span| declaration.start <= span.start && span.start < scope_end;
+
+    let inferences: Vec<Inference> = explain_module(module, source)?
+        .into_iter()
+        .filter(|inference| inference.name == name && inference.kind != InferenceKind::Lifetime && in_scope(&inference.span))
+        .collect();
+    let analysis = OwnershipInference::new().analyze_module(module);
+    let mut decisions: Vec<Decision> = inferences
+        .iter()
+        .flat_map(|inference| match inference.kind {
+            InferenceKind::Mutable => vec![Decision::Mutable],
+            InferenceKind::Borrow => vec![Decision::Borrowed],
+            InferenceKind::BorrowMut => vec![Decision::BorrowedMutably],
+            InferenceKind::Clone if inference.reason == "was moved earlier" => vec![Decision::Moved, Decision::Cloned],
+            InferenceKind::Clone => vec![Decision::Cloned],
+            InferenceKind::ToString | InferenceKind::Lifetime => vec![],
+        })
+        .collect();
+    for (facts, decision) in [
+        (&analysis.moved_vars, Decision::Moved),
+        (&analysis.cloned_vars, Decision::Cloned),
+        (&analysis.immut_borrowed_vars, Decision::Borrowed),
+        (&analysis.mut_borrowed_vars, Decision::BorrowedMutably),
+        (&analysis.mutable_vars, Decision::Mutable),
+    ] {
+        if facts.contains(name) {
+            decisions.push(decision);
+        }
+    }
+    decisions.sort();
+    decisions.dedup();
+
+    Ok(Some(BindingExplanation {
+        name: name.to_string(),
+        function: func.name.clone(),
+        declaration: declaration.clone(),
+        decisions,
+        inferences,
+        uses: uses(&func.body, name, |span| span.start >= declaration.end && in_scope(span)),
+    }))
+}
+
+/// The names `func` declares, with their spans, in source order.
+fn declarations(func: &FunctionDef) -> Vec<(&str, Span)> {
+    let mut found: Vec<(&str, Span)> = func
+        .params
+        .iter()
+        .map(|param| (param.name.as_str(), Span { start: param.span.start, end: param.span.start + param.name.len() }))
+        .collect();
+    visit_block(&func.body, &mut |node| {
+        if let Node::Stmt(Stmt::Let { pattern, .. } | Stmt::For { pattern, .. }) = node {
+            pattern_variables(pattern, &mut found);
+        }
+    });
+    found.sort_by_key(|(_, span)| span.start);
+    found
+}
+
+pub(crate) fn pattern_variables<'a>(pattern: &'a Pattern, found: &mut Vec<(&'a str, Span)>) {
+    match pattern {
+        Pattern::Variable(name, span) => found.push((name, span.clone())),
+        Pattern::Tuple(patterns, _) => patterns.iter().for_each(|pattern| pattern_variables(pattern, found)),
+        Pattern::TuplePair(first, second, _) => {
+            pattern_variables(first, found);
+            pattern_variables(second, found);
+        }
+        Pattern::Struct { fields, .. } => fields.iter().for_each(|(_, pattern)| pattern_variables(pattern, found)),
+        Pattern::Enum { inner: Some(inner), .. } => pattern_variables(inner, found),
+        Pattern::Enum { inner: None, .. } | Pattern::Wildcard(_) | Pattern::Literal(..) => {}
+    }
+}
+
+/// Whether `span` lies within `outer`.
+pub(crate) fn contains(outer: &Span, span: &Span) -> bool {
+    outer.start <= span.start && span.end <= outer.end
+}
+
+/// The span of the innermost statement of `block`, or of the blocks nested
+/// in it, containing `span`.
+fn innermost_statement(block: &Block, span: &Span) -> Option<Span> {
+    let mut innermost: Option<Span> = None;
+    visit_block(block, &mut |node| {
+        if let Node::Stmt(stmt) = node {
+            if contains(stmt.span(), span) && innermost.as_ref().is_none_or(|outer| contains(outer, stmt.span())) {
+                innermost = Some(stmt.span().clone());
+            }
+        }
+    });
+    innermost
+}
+
+/// The parameters each function of `module`, or imported into it, takes by
+/// reference: `Some(true)` for `&mut T`, `Some(false)` for `&T`.
+fn signatures(module: &Module) -> HashMap<&str, Vec<Option<bool>>> {
+    let imported = module.items.iter().flat_map(|item| match item {
+        ModuleItem::Import(import) => import.items.iter(),
+        _ => [].iter(),
+    });
+    module
+        .items
+        .iter()
+        .chain(imported)
+        .filter_map(|item| match item {
+            ModuleItem::Function(func) => Some((
+                func.name.as_str(),
+                func.params.iter().map(|param| param.ty.as_ref().and_then(types::reference_mutability)).collect(),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The places in `block` after byte `after` that mutate variable `name`,
+/// each with how, e.g. "is mutated by `.push()`".
+pub(crate) fn mutations(
+    block: &Block,
+    name: &str,
+    after: usize,
+    signatures: &HashMap<&str, Vec<Option<bool>>>,
+) -> Vec<(Span, String)> {
+    let inference = OwnershipInference::new();
+    let mut found = Vec::new();
+    visit_block(block, &mut |node| match node {
+        Node::Expr(Expr::Call { func, args, span }) if span.start >= after => match &**func {
+            Expr::FieldAccess { base, field, .. }
+                if matches!(&**base, Expr::Variable(base, _) if base == name) && inference.is_mutating_method_name(field) =>
+            {
+                found.push((span.clone(), format!("is mutated by `.{}()`", field)));
+            }
+            Expr::Variable(callee, _) => {
+                for (i, arg) in args.iter().enumerate() {
+                    let by_mut = signatures.get(callee.as_str()).and_then(|params| params.get(i).copied().flatten());
+                    if let (Some(true), Expr::Variable(arg_name, arg_span)) = (by_mut, arg) {
+                        if arg_name == name {
+                            found.push((arg_span.clone(), format!("is passed to `{}` as `&mut`", callee)));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        },
+        Node::Stmt(Stmt::EmbeddedRust(rust)) if rust.span.start >= after => {
+            let uses = variable_uses(&rust.code, |method| inference.is_mutating_method_name(method));
+            if uses.iter().any(|(used, kind)| used == name && *kind == VariableUse::Mutated) {
+                found.push((rust.span.clone(), "is mutated in a `rust` block".to_string()));
+            }
+        }
+        _ => {}
+    });
+    found
+}
+
+/// The uses of variable `name` in `block` whose spans satisfy `keep`,
+/// including `rust` blocks that use it.
+pub(crate) fn uses(block: &Block, name: &str, keep: impl Fn(&Span) -> bool) -> Vec<Span> {
+    let mut found = Vec::new();
+    visit_block(block, &mut |node| match node {
+        Node::Expr(Expr::Variable(used, span)) if used == name && keep(span) => found.push(span.clone()),
+        Node::Stmt(Stmt::EmbeddedRust(rust)) if uses_variable(&rust.code, name) && keep(&rust.span) => {
+            found.push(rust.span.clone())
+        }
+        _ => {}
+    });
+    found
+}
+
+/// A statement or expression of a function body.
+#[derive(Clone, Copy)]
+pub(crate) enum Node<'a> {
+    Stmt(&'a Stmt),
+    Expr(&'a Expr),
+}
+
+/// Calls `f` on every statement and expression of `block`, outermost first
+/// and otherwise in source order.
+pub(crate) fn visit_block<'a>(block: &'a Block, f: &mut impl FnMut(Node<'a>)) {
+    for stmt in &block.stmts {
+        visit_stmt(stmt, f);
+    }
+}
+
+fn visit_stmt<'a>(stmt: &'a Stmt, f: &mut impl FnMut(Node<'a>)) {
+    f(Node::Stmt(stmt));
+    match stmt {
+        Stmt::Let { value: expr, .. } | Stmt::Expr(expr) | Stmt::TailExpr(expr) | Stmt::Return(Some(expr), _) => {
+            visit_expr(expr, f)
+        }
+        Stmt::If { cond, then_branch, else_branch, .. } => {
+            visit_expr(cond, f);
+            visit_block(then_branch, f);
+            if let Some(else_branch) = else_branch {
+                visit_block(else_branch, f);
+            }
+        }
+        Stmt::While { cond: expr, body, .. } | Stmt::For { iterable: expr, body, .. } => {
+            visit_expr(expr, f);
+            visit_block(body, f);
+        }
+        Stmt::Match { expr, arms, .. } => {
+            visit_expr(expr, f);
+            visit_arms(arms, f);
+        }
+        Stmt::Try { block, catch, .. } => {
+            visit_block(block, f);
+            if let Some(catch) = catch {
+                visit_block(&catch.body, f);
+            }
+        }
+        Stmt::Return(None, _) | Stmt::EmbeddedRust(_) | Stmt::Error(_) => {}
+    }
+}
+
+fn visit_arms<'a>(arms: &'a [MatchArm], f: &mut impl FnMut(Node<'a>)) {
+    for arm in arms {
+        if let Some(guard) = &arm.guard {
+            visit_expr(guard, f);
+        }
+        visit_expr(&arm.expr, f);
+    }
+}
+
+fn visit_expr<'a>(expr: &'a Expr, f: &mut impl FnMut(Node<'a>)) {
+    f(Node::Expr(expr));
+    match expr {
+        Expr::Literal(..) | Expr::Variable(..) | Expr::Wildcard(_) => {}
+        Expr::Call { func, args, .. } => {
+            visit_expr(func, f);
+            for arg in args {
+                visit_expr(arg, f);
+            }
+        }
+        Expr::FieldAccess { base, .. } | Expr::OptionalChain { base, .. } => visit_expr(base, f),
+        Expr::Struct { fields, .. } => {
+            for (_, value) in fields {
+                visit_expr(value, f);
+            }
+        }
+        Expr::Block(block) => visit_block(block, f),
+        Expr::Await { expr, .. } | Expr::Try(expr, _) => visit_expr(expr, f),
+        Expr::Comprehension { body, clauses, .. } => {
+            for clause in clauses {
+                match clause {
+                    ComprehensionClause::For { iterable: expr, .. } | ComprehensionClause::If(expr) => visit_expr(expr, f),
+                }
+            }
+            visit_expr(body, f);
+        }
+        Expr::Match { expr, arms, .. } => {
+            visit_expr(expr, f);
+            visit_arms(arms, f);
+        }
+    }
+}
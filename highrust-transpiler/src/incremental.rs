@@ -0,0 +1,236 @@
+//! Incremental transpilation of projects, for `highrust watch`.
+//!
+//! A [`ProjectBuild`] keeps a project's output directory up to date across
+//! edits, redoing only what each edit affects. A file is only parsed again
+//! when its contents changed. A module is only transpiled again when its
+//! source or a Rust file it imports changed, when a module it imports,
+//! directly or not, was transpiled again or removed, or when a module was
+//! added inside it or removed from it, changing its `mod` declarations. The
+//! outputs of removed modules are deleted. With a [cache](crate::cache),
+//! files and modules left as they were since an earlier session are not
+//! redone either.
+//!
+//! Errors do not stop a build: a module that fails to transpile keeps its
+//! earlier output and is tried again on the next build, and a project that
+//! fails to load keeps everything as it was.
+
+use crate::ast::Module;
+use crate::cache::Cache;
+use crate::project::ModuleGraph;
+use crate::{parse_source, render_directories, render_module, sourcemap, write_if_changed};
+use crate::{OutputFile, TranspileOptions, TranspilerError};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// What one build of a project did.
+#[derive(Debug, Default)]
+pub struct BuildReport {
+    /// Source files transpiled, in dependency order
+    pub transpiled: Vec<PathBuf>,
+    /// Output files whose contents changed, relative to the output directory
+    pub written: Vec<PathBuf>,
+    /// Output files of removed modules that were deleted, relative to the
+    /// output directory
+    pub removed: Vec<PathBuf>,
+    /// What went wrong, each error naming its file
+    pub errors: Vec<TranspilerError>,
+}
+
+impl BuildReport {
+    /// Whether the build did anything, or failed to.
+    pub fn is_empty(&self) -> bool {
+        self.transpiled.is_empty() && self.removed.is_empty() && self.errors.is_empty()
+    }
+}
+
+/// The source, child modules and imported Rust files, with their contents,
+/// a module's output is transpiled from.
+type ModuleInputs = (String, Vec<String>, Vec<(PathBuf, String)>);
+
+/// A project transpiled into an output directory, kept up to date by
+/// [`rebuild`](ProjectBuild::rebuild).
+#[derive(Debug)]
+pub struct ProjectBuild {
+    root: PathBuf,
+    out_dir: PathBuf,
+    options: TranspileOptions,
+    /// Contents and AST, before imports resolve, of each file parsed
+    parsed: HashMap<PathBuf, (String, Module)>,
+    /// The project as of the last build that loaded it
+    graph: Option<ModuleGraph>,
+    /// What each module's output was transpiled from; missing for modules
+    /// that failed
+    built: HashMap<PathBuf, ModuleInputs>,
+    /// Output files of each module, relative to `out_dir`
+    outputs: HashMap<PathBuf, Vec<PathBuf>>,
+    /// Output files of directories without a module file of their own
+    directory_outputs: Vec<PathBuf>,
+}
+
+impl ProjectBuild {
+    /// Creates a build of the project under `root` into `out_dir`. Nothing
+    /// is transpiled until the first [`rebuild`](Self::rebuild).
+    pub fn new(root: impl Into<PathBuf>, out_dir: impl Into<PathBuf>, options: TranspileOptions) -> Self {
+        ProjectBuild {
+            root: root.into(),
+            out_dir: out_dir.into(),
+            options,
+            parsed: HashMap::new(),
+            graph: None,
+            built: HashMap::new(),
+            outputs: HashMap::new(),
+            directory_outputs: Vec::new(),
+        }
+    }
+
+    /// The project's source root.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The directory the project is transpiled into.
+    pub fn out_dir(&self) -> &Path {
+        &self.out_dir
+    }
+
+    /// Whether a module of the project imports the Rust file at `path`, as
+    /// of the last build that loaded the project.
+    pub fn imports_rust_file(&self, path: &Path) -> bool {
+        self.graph.iter().flat_map(ModuleGraph::modules).any(|module| module.rust_files.iter().any(|(file, _)| file == path))
+    }
+
+    /// Brings the output directory up to date with the project's sources,
+    /// transpiling everything the first time and afterwards only what
+    /// changed since.
+    pub fn rebuild(&mut self) -> BuildReport {
+        let mut report = BuildReport::default();
+        let parsed = &mut self.parsed;
+        let cache = self.options.cache.as_deref().map(Cache::new);
+        let loaded = ModuleGraph::load_with(&self.root, |file, source| {
+            if let Some((cached, ast)) = parsed.get(file) {
+                if cached == source {
+                    return Ok(ast.clone());
+                }
+            }
+            let ast = match &cache {
+                Some(cache) => cache.parse(file, source)?,
+                None => {
+                    info!(target: "parser", file = %file.display(), "parsing");
+                    parse_source(source)?
+                }
+            };
+            parsed.insert(file.to_path_buf(), (source.to_string(), ast.clone()));
+            Ok(ast)
+        });
+        let graph = match loaded {
+            Ok(graph) => graph,
+            Err(error) => {
+                report.errors.push(error.into());
+                return report;
+            }
+        };
+        let files: HashSet<&PathBuf> = graph.modules().iter().map(|module| &module.file).collect();
+        self.parsed.retain(|file, _| files.contains(file));
+        let removed: Vec<PathBuf> = self.outputs.keys().filter(|file| !files.contains(file)).cloned().collect();
+
+        let mut affected: BTreeSet<Vec<String>> = graph
+            .modules()
+            .iter()
+            .filter(|module| {
+                self.built.get(&module.file).is_none_or(|(source, children, rust_files)| {
+                    *source != module.source || *children != graph.children(&module.path) || *rust_files != module.rust_files
+                })
+            })
+            .map(|module| module.path.clone())
+            .collect();
+        if let Some(previous) = &self.graph {
+            for module in previous.modules().iter().filter(|module| removed.contains(&module.file)) {
+                affected.extend(previous.dependents(&module.path));
+            }
+        }
+        for path in affected.clone() {
+            affected.extend(graph.dependents(&path));
+        }
+        debug!(target: "incremental", affected = affected.len(), removed = removed.len(), "rebuilding");
+
+        for module in graph.modules().iter().filter(|module| affected.contains(&module.path)) {
+            report.transpiled.push(module.file.clone());
+            match render_module(&graph, module, &self.options) {
+                Ok(files) => {
+                    let outputs = self.write(files, &mut report);
+                    self.outputs.insert(module.file.clone(), outputs);
+                    let built = (module.source.clone(), graph.children(&module.path), module.rust_files.clone());
+                    self.built.insert(module.file.clone(), built);
+                }
+                Err(error) => {
+                    self.built.remove(&module.file);
+                    report.errors.push(error);
+                }
+            }
+        }
+        let directory_outputs = self.write(render_directories(&graph), &mut report);
+
+        // Outputs no module or directory produces any more
+        let mut stale: Vec<PathBuf> = std::mem::replace(&mut self.directory_outputs, directory_outputs);
+        for file in &removed {
+            self.built.remove(file);
+            stale.extend(self.outputs.remove(file).unwrap_or_default());
+        }
+        let current: HashSet<&PathBuf> = self.outputs.values().flatten().chain(&self.directory_outputs).collect();
+        stale.retain(|file| !current.contains(file));
+        for file in stale {
+            match remove_output(&self.out_dir, &file) {
+                Ok(()) => report.removed.push(file),
+                Err(error) => report.errors.push(error.into()),
+            }
+        }
+
+        self.graph = Some(graph);
+        info!(
+            target: "incremental",
+            transpiled = report.transpiled.len(),
+            written = report.written.len(),
+            removed = report.removed.len(),
+            errors = report.errors.len(),
+            "rebuilt"
+        );
+        report
+    }
+
+    /// Writes `files` and their source maps into the output directory,
+    /// returning their paths relative to it.
+    fn write(&self, files: Vec<OutputFile>, report: &mut BuildReport) -> Vec<PathBuf> {
+        let mut outputs = Vec::new();
+        for (file, contents, source_map) in files {
+            let source_map = source_map.map(|source_map| (sourcemap::map_file(&file), source_map.to_json()));
+            for (file, contents) in std::iter::once((file, contents)).chain(source_map) {
+                match write_if_changed(&self.out_dir.join(&file), &contents) {
+                    Ok(true) => report.written.push(file.clone()),
+                    Ok(false) => {}
+                    Err(error) => report.errors.push(error.into()),
+                }
+                outputs.push(file);
+            }
+        }
+        outputs
+    }
+}
+
+/// Deletes the output `file` of `out_dir`, and then its directories as long
+/// as they are left empty.
+fn remove_output(out_dir: &Path, file: &Path) -> std::io::Result<()> {
+    let path = out_dir.join(file);
+    match std::fs::remove_file(&path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error),
+        _ => {}
+    }
+    let mut dir = path.parent();
+    while let Some(parent) = dir.filter(|parent| *parent != out_dir && parent.starts_with(out_dir)) {
+        if std::fs::remove_dir(parent).is_err() {
+            break;
+        }
+        dir = parent.parent();
+    }
+    Ok(())
+}
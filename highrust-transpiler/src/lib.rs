@@ -3,13 +3,28 @@ pub mod ast;
 pub mod lowering;
 pub mod codegen;
 pub mod ownership;
-use std::path::Path;
+pub mod types;
+pub mod embedded;
+pub mod modules;
+pub mod project;
+pub mod config;
+pub mod package;
+pub mod sourcemap;
+pub mod check;
+pub mod diagnostic;
+pub mod emit;
+pub mod explain;
+pub mod incremental;
+pub mod cache;
+pub mod lint;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
 
 /// Error type for the transpiler.
 #[derive(Debug)]
 pub enum TranspilerError {
     /// Error during parsing.
-    ParseError(String),
+    ParseError(parser::ParseError),
     /// Error during lowering.
     LoweringError(lowering::LoweringError),
     /// Error during code generation.
@@ -18,6 +33,91 @@ pub enum TranspilerError {
     OwnershipError(ownership::OwnershipError),
     /// Error reading or writing files.
     IoError(std::io::Error),
+    /// Error resolving imported modules.
+    ModuleError(modules::ModuleError),
+    /// Error generating a Cargo package.
+    PackageError(package::PackageError),
+    /// Lints set to `deny` that the source violates.
+    LintError(Vec<diagnostic::Diagnostic>),
+    /// Error in the source file `path`.
+    InFile { path: PathBuf, error: Box<TranspilerError> },
+}
+
+impl TranspilerError {
+    /// Describes the error as diagnostics, with the file they occurred in
+    /// when known: one for each syntax error and each item that fails to
+    /// lower, and otherwise just one.
+    pub fn diagnostics(&self) -> Vec<diagnostic::Diagnostic> {
+        match self {
+            TranspilerError::ParseError(error) => error.diagnostics(),
+            TranspilerError::LoweringError(error) => error.diagnostics(),
+            TranspilerError::ModuleError(error) => error.diagnostics(),
+            TranspilerError::LintError(diagnostics) => diagnostics.clone(),
+            TranspilerError::InFile { path, error } => {
+                error.diagnostics().into_iter().map(|diagnostic| diagnostic.with_file(path)).collect()
+            }
+            error => vec![error.diagnostic()],
+        }
+    }
+
+    /// Describes the error as a diagnostic, with the file it occurred in
+    /// when known; of several syntax errors, the first.
+    pub fn diagnostic(&self) -> diagnostic::Diagnostic {
+        match self {
+            TranspilerError::ParseError(error) => error.diagnostic(),
+            TranspilerError::LoweringError(error) => error.diagnostic(),
+            TranspilerError::CodegenError(error) => error.diagnostic(),
+            TranspilerError::OwnershipError(error) => error.diagnostic(),
+            TranspilerError::IoError(error) => diagnostic::Diagnostic::error("E0600", format!("I/O error: {}", error)),
+            TranspilerError::ModuleError(error) => error.diagnostic(),
+            TranspilerError::PackageError(error) => error.diagnostic(),
+            TranspilerError::LintError(diagnostics) => diagnostics[0].clone(),
+            TranspilerError::InFile { path, error } => error.diagnostic().with_file(path),
+        }
+    }
+
+    /// Attributes the error to the source file `path`.
+    fn in_file(self, path: &Path) -> Self {
+        TranspilerError::InFile { path: path.to_path_buf(), error: Box::new(self) }
+    }
+}
+
+impl std::fmt::Display for TranspilerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranspilerError::ParseError(error) => write!(f, "{}", error),
+            TranspilerError::LoweringError(error) => write!(f, "lowering error: {}", error),
+            TranspilerError::CodegenError(error) => write!(f, "codegen error: {}", error),
+            TranspilerError::OwnershipError(error) => write!(f, "ownership error: {}", error),
+            TranspilerError::IoError(error) => write!(f, "I/O error: {}", error),
+            TranspilerError::ModuleError(error) => write!(f, "module error: {}", error),
+            TranspilerError::PackageError(error) => write!(f, "package error: {}", error),
+            TranspilerError::LintError(diagnostics) => write!(f, "lint error: {}", diagnostics[0].message),
+            TranspilerError::InFile { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for TranspilerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TranspilerError::ParseError(error) => Some(error),
+            TranspilerError::LoweringError(error) => Some(error),
+            TranspilerError::CodegenError(error) => Some(error),
+            TranspilerError::OwnershipError(error) => Some(error),
+            TranspilerError::IoError(error) => Some(error),
+            TranspilerError::ModuleError(error) => Some(error),
+            TranspilerError::PackageError(error) => Some(error),
+            TranspilerError::LintError(_) => None,
+            TranspilerError::InFile { error, .. } => Some(error.as_ref()),
+        }
+    }
+}
+
+impl From<parser::ParseError> for TranspilerError {
+    fn from(err: parser::ParseError) -> Self {
+        TranspilerError::ParseError(err)
+    }
 }
 
 impl From<lowering::LoweringError> for TranspilerError {
@@ -38,12 +138,44 @@ impl From<std::io::Error> for TranspilerError {
     }
 }
 
+impl From<modules::ModuleError> for TranspilerError {
+    fn from(err: modules::ModuleError) -> Self {
+        TranspilerError::ModuleError(err)
+    }
+}
+
+impl From<package::PackageError> for TranspilerError {
+    fn from(err: package::PackageError) -> Self {
+        TranspilerError::PackageError(err)
+    }
+}
+
 impl From<ownership::OwnershipError> for TranspilerError {
     fn from(err: ownership::OwnershipError) -> Self {
         TranspilerError::OwnershipError(err)
     }
 }
 
+/// Options controlling how HighRust is transpiled.
+#[derive(Debug, Clone, Default)]
+pub struct TranspileOptions {
+    /// Runtime that drives an async `main`.
+    pub async_runtime: codegen::AsyncRuntime,
+    /// End the first line of each generated statement with a `// hrs:N`
+    /// comment naming its source line.
+    pub line_comments: bool,
+    /// Write a comment above each inferred `mut`, borrow, `.clone()`,
+    /// `.to_string()` and lifetime parameter saying why it was inferred.
+    pub explain: bool,
+    /// Directory to cache parsed modules, ownership analyses and generated
+    /// Rust in across runs; nothing is cached when unset.
+    pub cache: Option<PathBuf>,
+    /// How strictly the lints below treat inferred clones and borrows.
+    pub strictness: config::Strictness,
+    /// Levels of the [lints](lint) reported while transpiling.
+    pub lints: config::LintConfig,
+}
+
 /// Transpiles HighRust source code to Rust.
 ///
 /// # Arguments
@@ -65,24 +197,93 @@ impl From<ownership::OwnershipError> for TranspilerError {
 /// let rust_code = transpile_source(highrust_code)?;
 /// ```
 pub fn transpile_source(source: &str) -> Result<String, TranspilerError> {
+    transpile_source_with(source, &TranspileOptions::default())
+}
+
+/// Transpiles HighRust source code to Rust using the given options.
+pub fn transpile_source_with(source: &str, options: &TranspileOptions) -> Result<String, TranspilerError> {
     // Parse the source code
-    let ast = parser::parse(source).map_err(|e| TranspilerError::ParseError(e.to_string()))?;
-    
-    // Perform ownership inference
-    let ownership_inference = ownership::OwnershipInference::new();
-    let ownership_analysis = ownership_inference.analyze_module(&ast);
-    
-    // Lower the AST to IR
-    let ir = lowering::lower_module(&ast)?;
-    
-    // Generate Rust code with ownership analysis results
-    let mut ctx = codegen::CodegenContext::with_analysis(ownership_analysis);
-    let rust_code = codegen::generate_rust_code(&ir, &mut ctx)?;
-    
-    Ok(rust_code)
+    let ast = parse_source(source)?;
+    Ok(transpile_module(&ast, source, None, options)?.code)
 }
 
-/// Transpiles a HighRust file to a Rust file.
+/// Parses `source`, recovering from syntax errors to report all of them.
+/// The items and statements of a module with syntax errors that do parse
+/// are lowered as well, so the errors returned also include theirs, in
+/// source order.
+pub(crate) fn parse_source(source: &str) -> Result<ast::Module, parser::ParseError> {
+    let parser::Recovered { module, mut errors } = parser::parse_recovering(source);
+    if errors.is_empty() {
+        return Ok(module);
+    }
+    match lowering::lower_module(&module) {
+        Err(lowering::LoweringError::Multiple(lowering_errors)) => {
+            errors.extend(lowering_errors.into_iter().map(|error| parser::ParseError::Lowering(Box::new(error))))
+        }
+        Err(error) => errors.push(parser::ParseError::Lowering(Box::new(error))),
+        Ok(_) => {}
+    }
+    // Errors without a span go last
+    errors.sort_by_key(|error| error.diagnostic().primary_label().map_or(usize::MAX, |label| label.span.start));
+    Err(match errors.len() {
+        1 => errors.remove(0),
+        _ => parser::ParseError::Multiple(errors),
+    })
+}
+
+/// Transpiles a module parsed from `source`, the contents of `file` when
+/// known, to Rust, with a source map. Lints at `warn` are logged, and lints
+/// at `deny` fail it. With a cache, its ownership analysis and Rust are
+/// reused when neither it nor the signatures it imports changed.
+fn transpile_module(
+    ast: &ast::Module,
+    source: &str,
+    file: Option<&Path>,
+    options: &TranspileOptions,
+) -> Result<codegen::GeneratedCode, TranspilerError> {
+    let (denied, warnings): (Vec<_>, Vec<_>) = lint::lint_module(ast, source, options)?
+        .into_iter()
+        .map(|diagnostic| match file {
+            Some(file) => diagnostic.with_file(file),
+            None => diagnostic,
+        })
+        .partition(|diagnostic| diagnostic.severity == diagnostic::Severity::Error);
+    for warning in warnings {
+        warn!(target: "lint", "{}", warning.render(Some(source)));
+    }
+    if !denied.is_empty() {
+        return Err(TranspilerError::LintError(denied));
+    }
+
+    let cache = options.cache.as_deref().map(cache::Cache::new);
+    let ownership_key = cache.as_ref().map(|_| cache::ownership_key(ast, source)).unwrap_or_default();
+    cache::rust(cache.as_ref(), &cache::rust_key(&ownership_key, options), || {
+        // Perform ownership inference
+        let ownership_analysis = cache::ownership(cache.as_ref(), &ownership_key, || {
+            ownership::OwnershipInference::new().analyze_module(ast)
+        });
+        
+        // Lower the AST to IR
+        let ir = lowering::lower_module(ast)?;
+        
+        // Generate Rust code with ownership analysis results
+        let mut ctx = codegen::CodegenContext::with_analysis(ownership_analysis);
+        ctx.async_runtime = options.async_runtime;
+        ctx.line_index = Some(sourcemap::LineIndex::new(source));
+        ctx.line_comments = options.line_comments;
+        if options.explain {
+            let index = sourcemap::LineIndex::new(source);
+            ctx.explanations = explain::explain_module(ast, source)?
+                .iter()
+                .map(|inference| (inference.statement.clone(), inference.describe(&index)))
+                .collect();
+        }
+        Ok(codegen::generate_rust_code(&ir, &mut ctx)?)
+    })
+}
+
+/// Transpiles a HighRust file to a Rust file, writing its source map
+/// alongside as `<output>.map`.
 ///
 /// # Arguments
 ///
@@ -99,18 +300,174 @@ pub fn transpile_source(source: &str) -> Result<String, TranspilerError> {
 /// transpile_file("src/main.hrs", "src/main.rs")?;
 /// ```
 pub fn transpile_file<P: AsRef<Path>>(input_path: P, output_path: P) -> Result<(), TranspilerError> {
-    // Read the input file
-    let source = std::fs::read_to_string(input_path)?;
-    
-    // Transpile the source
-    let rust_code = transpile_source(&source)?;
+    transpile_file_with(input_path, output_path, &TranspileOptions::default())
+}
+
+/// Transpiles a HighRust file to a Rust file using the given options.
+pub fn transpile_file_with<P: AsRef<Path>>(
+    input_path: P,
+    output_path: P,
+    options: &TranspileOptions,
+) -> Result<(), TranspilerError> {
+    let input_path = input_path.as_ref();
+    let mut generated = transpile_input(input_path, options)?;
     
-    // Write the output file
-    std::fs::write(output_path, rust_code)?;
+    // Write the output file and its source map
+    let output_path = output_path.as_ref();
+    generated.source_map.set_files(output_path, input_path);
+    std::fs::write(output_path, generated.code)?;
+    std::fs::write(sourcemap::map_file(output_path), generated.source_map.to_json())?;
     
     Ok(())
 }
 
+/// Transpiles a HighRust file using the given options, returning the
+/// generated code instead of writing it.
+///
+/// Imports resolve from the file's directory, as with [`transpile_file_with`].
+pub fn transpile_file_to_string<P: AsRef<Path>>(input_path: P, options: &TranspileOptions) -> Result<String, TranspilerError> {
+    Ok(transpile_input(input_path.as_ref(), options)?.code)
+}
+
+/// Reads, parses and transpiles `input_path`, resolving imports of other
+/// files from the input's directory.
+fn transpile_input(input_path: &Path, options: &TranspileOptions) -> Result<codegen::GeneratedCode, TranspilerError> {
+    let source = std::fs::read_to_string(input_path)?;
+    
+    info!(target: "parser", file = %input_path.display(), "parsing");
+    let mut ast = parse_source(&source).map_err(|e| TranspilerError::from(e).in_file(input_path))?;
+    let resolver = modules::ModuleResolver::new(input_path.parent().unwrap_or(Path::new("")));
+    resolver.resolve_imports(&mut ast, input_path)?;
+    transpile_module(&ast, &source, Some(input_path), options).map_err(|e| e.in_file(input_path))
+}
+
+/// Transpiles every `.hrs` file under `root` into a matching tree of `.rs`
+/// files under `out_dir`, returning the Rust files written.
+///
+/// Each module declares the modules below it, and directories without a
+/// module file of their own get one holding just those declarations. Rust
+/// files imported with `import rust "..."` are copied alongside, and each
+/// transpiled module gets its source map as `<module>.rs.map`.
+///
+/// # Example
+///
+/// ```ignore
+/// transpile_project("src", "generated")?;
+/// ```
+pub fn transpile_project<P: AsRef<Path>>(root: P, out_dir: P) -> Result<Vec<PathBuf>, TranspilerError> {
+    transpile_project_with(root, out_dir, &TranspileOptions::default())
+}
+
+/// Transpiles a project using the given options.
+pub fn transpile_project_with<P: AsRef<Path>>(
+    root: P,
+    out_dir: P,
+    options: &TranspileOptions,
+) -> Result<Vec<PathBuf>, TranspilerError> {
+    let graph = load_project(root.as_ref(), options)?;
+    let mut written = Vec::new();
+    for (file, contents, source_map) in render_project(&graph, options)? {
+        let path = out_dir.as_ref().join(file);
+        write_if_changed(&path, &contents)?;
+        if let Some(source_map) = source_map {
+            write_if_changed(&sourcemap::map_file(&path), &source_map.to_json())?;
+        }
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Loads the project under `root`, reusing the cached ASTs of its files
+/// when `options` name a cache.
+pub(crate) fn load_project(root: &Path, options: &TranspileOptions) -> Result<project::ModuleGraph, modules::ModuleError> {
+    match &options.cache {
+        Some(dir) => {
+            let cache = cache::Cache::new(dir);
+            project::ModuleGraph::load_with(root, |file, source| cache.parse(file, source))
+        }
+        None => project::ModuleGraph::load(root),
+    }
+}
+
+/// An output file of a project: its path relative to the output directory,
+/// its contents and, for transpiled modules, its source map.
+pub(crate) type OutputFile = (PathBuf, String, Option<sourcemap::SourceMap>);
+
+/// Transpiles the modules of a project, returning its output files.
+pub(crate) fn render_project(graph: &project::ModuleGraph, options: &TranspileOptions) -> Result<Vec<OutputFile>, TranspilerError> {
+    let mut files = Vec::new();
+    for module in graph.modules() {
+        files.extend(render_module(graph, module, options)?);
+    }
+    files.extend(render_directories(graph));
+    Ok(files)
+}
+
+/// Transpiles one module of a project, returning its output file and the
+/// Rust files it imports with `import rust "..."`.
+pub(crate) fn render_module(
+    graph: &project::ModuleGraph,
+    module: &project::ProjectModule,
+    options: &TranspileOptions,
+) -> Result<Vec<OutputFile>, TranspilerError> {
+    let relative = |file: &Path| file.strip_prefix(graph.root()).unwrap_or(file).to_path_buf();
+    // Only a binary's crate root keeps its modules private
+    let is_binary_root = module.path.is_empty() && module.file.file_stem().is_some_and(|stem| stem == "main");
+    let mut code = mod_declarations(&graph.children(&module.path), !is_binary_root);
+    let mut generated = transpile_module(&module.ast, &module.source, Some(&module.file), options).map_err(|e| e.in_file(&module.file))?;
+    let file = relative(&module.file).with_extension("rs");
+    generated.source_map.shift(code.matches('\n').count());
+    generated.source_map.set_files(&file, &module.file);
+    code.push_str(&generated.code);
+    let mut files = vec![(file, code, Some(generated.source_map))];
+
+    for item in &module.ast.items {
+        if let ast::ModuleItem::Import(ast::Import { rust_file: Some(rust_file), .. }) = item {
+            let source = module.file.parent().unwrap_or(Path::new("")).join(rust_file);
+            files.push((relative(&source), std::fs::read_to_string(&source)?, None));
+        }
+    }
+    Ok(files)
+}
+
+/// The output files of the directories of a project's modules that have no
+/// module file of their own, each declaring the modules inside it.
+pub(crate) fn render_directories(graph: &project::ModuleGraph) -> Vec<OutputFile> {
+    let mut files = Vec::new();
+    for path in graph.module_paths() {
+        if !path.is_empty() && graph.module(&path).is_none() {
+            let file: PathBuf = path.iter().collect();
+            let declarations = mod_declarations(&graph.children(&path), true);
+            files.push((file.with_extension("rs"), declarations.trim_end().to_string() + "\n", None));
+        }
+    }
+    files
+}
+
+/// Writes `contents` to `path` unless it already holds them, so unchanged
+/// outputs keep their modification times. Returns whether it wrote.
+pub(crate) fn write_if_changed(path: &Path, contents: &str) -> std::io::Result<bool> {
+    if std::fs::read_to_string(path).is_ok_and(|existing| existing == contents) {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)?;
+    Ok(true)
+}
+
+/// `mod` declarations for the child modules of a project module.
+fn mod_declarations(children: &[String], is_pub: bool) -> String {
+    if children.is_empty() {
+        return String::new();
+    }
+    let visibility = if is_pub { "pub " } else { "" };
+    let mut code: String = children.iter().map(|child| format!("{}mod {};\n", visibility, child)).collect();
+    code.push('\n');
+    code
+}
+
 #[cfg(test)]
 mod tests {
     use super::ast::*;
@@ -145,8 +502,6 @@ mod tests {
             cloned_vars: HashSet::new(),
             lifetime_params: Vec::new(),
             borrow_graph: HashMap::new(),
-            string_converted_vars: HashSet::new(),
-            string_converted_exprs: HashSet::new(),
         };
 
         // Call lowering functions
@@ -195,12 +550,12 @@ mod tests {
         
         // Generate Rust code
         let mut ctx = CodegenContext::with_analysis(analysis_result);
-        let rust_code = generate_rust_code(&ir, &mut ctx).expect("Failed to generate code");
+        let generated = generate_rust_code(&ir, &mut ctx).expect("Failed to generate code");
         
         // Expected output - note the extra newline at the end that our generator adds
         let expected = "fn main() {\n    println!(\"Hello, World!\");\n}\n\n";
         
-        assert_eq!(rust_code, expected);
+        assert_eq!(generated.code, expected);
     }
 
     #[test]
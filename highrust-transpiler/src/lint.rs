@@ -0,0 +1,92 @@
+//! Lints over what the transpiler writes for the programmer, reported at
+//! the levels the `[lints]` table of `highrust.toml` sets.
+//!
+//! `implicit_clone` reports each `.clone()` ownership inference inserts,
+//! `implicit_borrow` each `&` or `&mut` it inserts, and `embedded_rust` each
+//! `rust { }` block and `@rust` function. A lint at `warn` reports warnings
+//! and one at `deny` errors that fail the module. The project's
+//! [`Strictness`] adjusts the configured levels: `strict` reports every
+//! implicit clone and borrow, and `relaxed` never fails a module over a lint.
+
+use crate::ast::{Module, ModuleItem, Span, Stmt};
+use crate::config::{LintLevel, Strictness};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::explain::{self, InferenceKind, Node};
+use crate::lowering::LoweringError;
+use crate::TranspileOptions;
+
+/// Code of the `implicit_clone` lint.
+const IMPLICIT_CLONE: &str = "E0700";
+/// Code of the `implicit_borrow` lint.
+const IMPLICIT_BORROW: &str = "E0701";
+/// Code of the `embedded_rust` lint.
+const EMBEDDED_RUST: &str = "E0702";
+
+/// The diagnostics of the lints `options` enable for `module`, parsed from
+/// `source`, warnings and errors in source order.
+pub fn lint_module(module: &Module, source: &str, options: &TranspileOptions) -> Result<Vec<Diagnostic>, LoweringError> {
+    let clone = level("implicit_clone", options.lints.implicit_clone, true, options.strictness);
+    let borrow = level("implicit_borrow", options.lints.implicit_borrow, true, options.strictness);
+    let embedded = level("embedded_rust", options.lints.embedded_rust, false, options.strictness);
+    let mut found = Vec::new();
+
+    if clone.is_some() || borrow.is_some() {
+        for inference in explain::explain_module(module, source)? {
+            let (level, code, what) = match inference.kind {
+                InferenceKind::Clone => (&clone, IMPLICIT_CLONE, "clone"),
+                InferenceKind::Borrow | InferenceKind::BorrowMut => (&borrow, IMPLICIT_BORROW, "borrow"),
+                _ => continue,
+            };
+            let Some((severity, note)) = level else { continue };
+            let mut diagnostic = Diagnostic::new(*severity, code, format!("implicit {} of `{}`", what, inference.name))
+                .with_label(inference.span.clone(), format!("`{}` {}", inference.name, inference.reason));
+            for used in &inference.uses {
+                diagnostic = diagnostic.with_secondary(used.clone(), "used here");
+            }
+            found.push((inference.span.start, diagnostic.with_note(note.clone())));
+        }
+    }
+
+    if let Some((severity, note)) = embedded {
+        let mut push = |span: &Span, label: &str| {
+            let diagnostic = Diagnostic::new(severity, EMBEDDED_RUST, "embedded Rust")
+                .with_label(span.clone(), label)
+                .with_note(note.clone());
+            found.push((span.start, diagnostic));
+        };
+        for item in &module.items {
+            match item {
+                ModuleItem::EmbeddedRust(block) => push(&block.span, "Rust block"),
+                ModuleItem::Function(func) if func.is_rust => push(&func.span, "Rust function"),
+                ModuleItem::Function(func) => explain::visit_block(&func.body, &mut |node| {
+                    if let Node::Stmt(Stmt::EmbeddedRust(block)) = node {
+                        push(&block.span, "Rust block");
+                    }
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    found.sort_by_key(|(start, _)| *start);
+    Ok(found.into_iter().map(|(_, diagnostic)| diagnostic).collect())
+}
+
+/// The severity the lint `name`, configured at `configured`, reports with
+/// under `strictness`, and a note naming the settings behind it; `None` if
+/// the lint is allowed. Only lints of code the transpiler infers are raised
+/// by `strict`.
+fn level(name: &str, configured: LintLevel, inferred: bool, strictness: Strictness) -> Option<(Severity, String)> {
+    match (configured, strictness) {
+        (LintLevel::Deny, Strictness::Relaxed) => Some((
+            Severity::Warning,
+            format!("`lints.{}` is `deny`, which `build.strictness = \"relaxed\"` lowers to `warn`", name),
+        )),
+        (LintLevel::Deny, _) => Some((Severity::Error, format!("`lints.{}` is `deny`", name))),
+        (LintLevel::Warn, _) => Some((Severity::Warning, format!("`lints.{}` is `warn`", name))),
+        (LintLevel::Allow, Strictness::Strict) if inferred => {
+            Some((Severity::Warning, format!("`build.strictness = \"strict\"` raises `lints.{}` to `warn`", name)))
+        }
+        (LintLevel::Allow, _) => None,
+    }
+}
@@ -6,83 +6,230 @@
 //! the AST for code generation.
 
 use crate::ast::{
-    Module, ModuleItem, FunctionDef, DataDef, DataKind, Field, EnumVariant, Stmt, Expr, Literal, Type, Block, Param, Pattern, MatchArm,
+    Module, ModuleItem, FunctionDef, DataDef, DataKind, Field, EnumVariant, Stmt, Expr, Literal, Type, Block, Param, Pattern, MatchArm, Span,
+    CatchClause, ComprehensionClause, EmbeddedRustBlock, Import, ImportName,
 };
+use serde::Serialize;
+use std::collections::HashSet;
+use crate::embedded::uses_variable;
+use crate::diagnostic::Diagnostic;
+use crate::explain::{self, Inference, InferenceKind, Node};
 use crate::ownership::{OwnershipInference, OwnershipAnalysisResult};
-use std::collections::HashMap;
+use crate::types::{self, TypeEnv, Widening};
+use tracing::{debug, trace};
 
 /// Error type for lowering failures.
 #[derive(Debug)]
 pub enum LoweringError {
-    UnsupportedFeature(&'static str),
+    /// A construct lowering does not support yet
+    UnsupportedFeature(&'static str, Span),
     InvalidAst(String),
-    // Add more as needed
+    /// A numeric value flows into a slot that cannot hold it losslessly
+    NarrowingConversion { from: Type, to: Type, span: Span },
+    /// An integer literal does not fit the type its context requires
+    LiteralOutOfRange { value: i128, ty: Type, span: Span },
+    /// `export` names nothing the module defines or imports; `similar` is a
+    /// defined name it may be a typo of
+    UnknownExport { name: String, similar: Option<String>, span: Span },
+    /// A function that is not async calls `name`, which is async because it
+    /// awaits, without awaiting it
+    UnawaitedAsyncCall { name: String, span: Span },
+    /// Errors in several items of a module, in source order
+    Multiple(Vec<LoweringError>),
+}
+
+impl std::fmt::Display for LoweringError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoweringError::UnsupportedFeature(feature, _) => write!(f, "unsupported: {}", feature),
+            LoweringError::InvalidAst(message) => write!(f, "invalid syntax tree: {}", message),
+            LoweringError::NarrowingConversion { from, to, .. } => {
+                write!(f, "narrowing conversion from `{}` to `{}`", from, to)
+            }
+            LoweringError::LiteralOutOfRange { value, ty, .. } => {
+                write!(f, "literal `{}` does not fit in `{}`", value, ty)
+            }
+            LoweringError::UnknownExport { name, .. } => write!(f, "cannot export `{}`: no such item", name),
+            LoweringError::UnawaitedAsyncCall { name, .. } => {
+                write!(f, "call to async function `{}` in a function that is not async", name)
+            }
+            LoweringError::Multiple(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoweringError {}
+
+impl LoweringError {
+    /// Describes the error as diagnostics, one for each item with an error.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            LoweringError::Multiple(errors) => errors.iter().flat_map(LoweringError::diagnostics).collect(),
+            error => vec![error.diagnostic()],
+        }
+    }
+
+    /// Describes the error as a diagnostic labeling the offending source; of
+    /// several errors, the first.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let message = self.to_string();
+        match self {
+            LoweringError::UnsupportedFeature(_, span) => Diagnostic::error("E0100", message)
+                .with_label(span.clone(), "not supported yet")
+                .with_note("write this part in a `rust { }` block"),
+            LoweringError::InvalidAst(_) => Diagnostic::error("E0101", message).with_note("this is a transpiler bug"),
+            LoweringError::NarrowingConversion { from, to, span } => Diagnostic::error("E0102", message)
+                .with_label(span.clone(), format!("`{}` may not fit in `{}`", from, to))
+                .with_note(format!(
+                    "HighRust only converts numbers implicitly when no value is lost; convert explicitly in a `rust {{ }}` block, e.g. with `{}::try_from`",
+                    to
+                )),
+            LoweringError::LiteralOutOfRange { ty, span, .. } => Diagnostic::error("E0103", message)
+                .with_label(span.clone(), format!("out of range for `{}`", ty)),
+            LoweringError::UnknownExport { similar, span, .. } => {
+                let diagnostic = Diagnostic::error("E0104", message)
+                    .with_label(span.clone(), "not defined or imported in this module");
+                match similar {
+                    Some(similar) => diagnostic.with_suggestion(
+                        format!("a similar item is defined: `{}`", similar),
+                        span.clone(),
+                        similar.clone(),
+                    ),
+                    None => diagnostic,
+                }
+            }
+            LoweringError::UnawaitedAsyncCall { name, span } => Diagnostic::error("E0105", message)
+                .with_label(span.clone(), format!("`{}` is async because it awaits, so this returns a future", name))
+                .with_note(format!("write `await {}(...)`, which makes this function async too", name)),
+            LoweringError::Multiple(errors) => match errors.first() {
+                Some(error) => error.diagnostic(),
+                None => Diagnostic::error("E0101", message),
+            },
+        }
+    }
 }
 
 /// The lowered module IR.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LoweredModule {
     pub items: Vec<LoweredItem>,
 }
 
 /// Lowered top-level items.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum LoweredItem {
     Function(LoweredFunction),
     Data(LoweredData),
-    // TODO: Add Import, Export, EmbeddedRust as needed
+    ErrorEnum(LoweredErrorEnum),
+    EmbeddedRust(LoweredEmbeddedRust),
+    Use(LoweredUse),
+    Mod(LoweredMod),
+}
+
+/// `mod` declaration for an imported Rust file.
+#[derive(Debug, Serialize)]
+pub struct LoweredMod {
+    pub name: String,
+    /// File of the module, when Rust would not find it by the module's name
+    pub path: Option<String>,
+    pub is_pub: bool,
+}
+
+/// `use` declaration for an import; exported imports are re-exported.
+#[derive(Debug, Serialize)]
+pub struct LoweredUse {
+    pub path: Vec<String>,
+    /// Names used from the module at `path`, with aliases; empty when `path`
+    /// names the item itself
+    pub names: Vec<(String, Option<String>)>,
+    pub alias: Option<String>,
+    pub is_pub: bool,
 }
 
 /// Lowered data type (struct, enum).
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LoweredData {
     pub name: String,
     pub kind: LoweredDataKind,
+    /// Exported data types and their fields are public
+    pub is_pub: bool,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum LoweredDataKind {
     Struct(Vec<LoweredField>),
     Enum(Vec<LoweredEnumVariant>),
     // TaggedUnion not yet supported
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LoweredField {
     pub name: String,
     pub ty: LoweredType,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LoweredEnumVariant {
     pub name: String,
     pub fields: Vec<LoweredField>,
 }
 
+/// Error enum generated for functions returning `Result<T>` without an error
+/// type, with one variant per error type propagated into them by `?`.
+#[derive(Debug, Serialize)]
+pub struct LoweredErrorEnum {
+    pub name: String,
+    /// Variant names and the error types they wrap
+    pub variants: Vec<(String, LoweredType)>,
+    /// Public when an exported function returns it
+    pub is_pub: bool,
+}
+
+/// Rust code from a `rust { ... }` block, emitted verbatim.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoweredEmbeddedRust {
+    pub code: String,
+    /// Location of the code in the HighRust source, for mapping Rust errors back
+    pub span: Span,
+}
+
 /// Lowered function definition.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LoweredFunction {
     pub name: String,
     pub params: Vec<LoweredParam>,
     pub ret_type: Option<LoweredType>,
     pub body: LoweredBlock,
     pub is_async: bool,
+    pub is_pub: bool,
     pub is_result: bool, // indicates if function returns Result
     pub is_option: bool, // indicates if function returns Option
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LoweredParam {
     pub name: String,
     pub ty: Option<LoweredType>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LoweredBlock {
     pub stmts: Vec<LoweredStmt>,
+    /// Source span of each statement, for the source map
+    pub spans: Vec<Span>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum LoweredStmt {
     Let {
         name: String,
@@ -92,16 +239,40 @@ pub enum LoweredStmt {
         needs_clone: bool,
     },
     Expr(LoweredExpr),
+    /// Final expression of a block, emitted without a semicolon
+    TailExpr(LoweredExpr),
     Return(Option<LoweredExpr>),
     If {
         cond: LoweredExpr,
         then_branch: LoweredBlock,
         else_branch: Option<LoweredBlock>,
     },
+    /// `if let pattern = value { .. } else { .. }`
+    IfLet {
+        pattern: LoweredPattern,
+        value: LoweredExpr,
+        then_branch: LoweredBlock,
+        else_branch: Option<LoweredBlock>,
+    },
+    /// `let pattern = value else { .. };` with a diverging else block
+    LetElse {
+        pattern: LoweredPattern,
+        value: LoweredExpr,
+        else_branch: LoweredBlock,
+    },
+    /// try/catch as a labeled block evaluating to `Result<(), error_type>`,
+    /// with the catch body run on `Err`
+    Try {
+        label: String,
+        error_type: LoweredType,
+        body: LoweredBlock,
+        catch: Option<(String, LoweredBlock)>,
+    },
+    EmbeddedRust(LoweredEmbeddedRust),
     // TODO: While, For, Match, etc.
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum LoweredExpr {
     Literal(LoweredLiteral),
     Variable(String),
@@ -111,39 +282,95 @@ pub enum LoweredExpr {
     },
     Block(LoweredBlock),
     Propagate(Box<LoweredExpr>), // Represents `?` propagation
+    /// `?` inside a try block: breaks out of the labeled block with the error
+    TryBreak {
+        expr: Box<LoweredExpr>,
+        label: String,
+    },
     Match {
         expr: Box<LoweredExpr>,
         arms: Vec<LoweredMatchArm>,
     },
-    // TODO: FieldAccess, Await, Comprehension, etc.
+    Struct {
+        name: String,
+        fields: Vec<(String, LoweredExpr)>,
+    },
+    Convert {
+        expr: Box<LoweredExpr>,
+        conversion: Conversion,
+    },
+    FieldAccess {
+        base: Box<LoweredExpr>,
+        field: String,
+    },
+    MethodCall {
+        receiver: Box<LoweredExpr>,
+        method: String,
+        args: Vec<LoweredExpr>,
+    },
+    Closure {
+        params: Vec<String>,
+        body: Box<LoweredExpr>,
+        /// Whether the closure takes ownership of the variables it captures
+        is_move: bool,
+    },
+    /// `expr.await`
+    Await(Box<LoweredExpr>),
+    // TODO: Comprehension, etc.
+}
+
+/// Implicit conversions inserted where a value flows into a slot of a
+/// different but compatible type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Conversion {
+    /// `&str` (or `&String`) into a `String` slot: `.to_string()`
+    ToString,
+    /// `String` (or `&String`) into a `&str` slot: `.as_str()`
+    AsStr,
+    /// Lossless numeric widening with a `From` impl: `T::from(x)`
+    From(String),
+    /// Lossless numeric widening without a `From` impl: `x as T`
+    Cast(String),
+    /// An owned value into a `&T` slot: `&x`
+    Borrow,
+    /// An owned value into a `&mut T` slot: `&mut x`
+    BorrowMut,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LoweredMatchArm {
     pub pattern: LoweredPattern,
     pub guard: Option<Box<LoweredExpr>>,
     pub expr: Box<LoweredExpr>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum LoweredPattern {
     Wildcard,
     Variable(String),
     Tuple(Vec<LoweredPattern>),
     Literal(LoweredLiteral),
+    /// Enum variant such as `Some(x)` or `Shape::Circle(r)`
+    Variant {
+        path: String,
+        fields: Vec<LoweredPattern>,
+    },
     // Extend as needed (struct, enum, etc.)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum LoweredLiteral {
-    Int(i64),
+    /// Integer literal, with a type suffix when its context does not fix the type
+    Int(i128, Option<String>),
     Float(f64),
     Bool(bool),
     String(String),
     Null,
+    /// The unit value `()`
+    Unit,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum LoweredType {
     Named(String, Vec<LoweredType>),
     Option(Box<LoweredType>),
@@ -154,27 +381,306 @@ pub enum LoweredType {
     // TODO: Function types, generics, etc.
 }
 
+/// State threaded through the lowering of a module.
+pub struct LoweringContext<'a> {
+    /// Result of ownership analysis
+    pub analysis_result: &'a OwnershipAnalysisResult,
+    /// Types of variables, functions and data definitions in scope
+    pub types: TypeEnv,
+    /// Declared return type of the function being lowered
+    ret_type: Option<Type>,
+    /// Enclosing try blocks, innermost last; `?` propagates to the innermost
+    try_targets: Vec<TryTarget>,
+    /// Error types propagated into the generated module error enum, or `None`
+    /// when no enum is generated for the module
+    module_errors: Option<Vec<Type>>,
+    /// Ownership and conversion decisions made so far, for `--explain`
+    inferences: Vec<Inference>,
+    /// Body of the function being lowered
+    function_body: Option<Block>,
+    /// Whether the function being lowered is async, declared or inferred
+    is_async: bool,
+    /// Code of the `rust { }` blocks that run after the statement being
+    /// lowered, in its block and the blocks enclosing it
+    later_rust: Vec<String>,
+    /// Source of the module, to quote expressions in inferences with
+    source: Option<&'a str>,
+}
+
+/// A try block that `?` expressions propagate errors to.
+struct TryTarget {
+    /// Label of the block the error breaks out of
+    label: String,
+    /// Error types of the `?` operands seen in the block, if known
+    error_types: Vec<Option<Type>>,
+}
+
+impl<'a> LoweringContext<'a> {
+    /// Creates a lowering context from an ownership analysis and a type environment.
+    pub fn new(analysis_result: &'a OwnershipAnalysisResult, types: TypeEnv) -> Self {
+        LoweringContext {
+            analysis_result,
+            types,
+            ret_type: None,
+            try_targets: Vec::new(),
+            module_errors: None,
+            inferences: Vec::new(),
+            function_body: None,
+            is_async: false,
+            later_rust: Vec::new(),
+            source: None,
+        }
+    }
+}
+
 /// Entry point: Lower a HighRust AST module to IR.
 pub fn lower_module(module: &Module) -> Result<LoweredModule, LoweringError> {
+    lower(module, None).map(|(lowered, _)| lowered)
+}
+
+/// Lowers a module parsed from `source` to IR, also returning the `mut`s,
+/// clones and conversions lowering inferred, in the order it inferred them.
+/// Their uses are left for [`crate::explain`] to find.
+pub fn lower_module_explained(module: &Module, source: &str) -> Result<(LoweredModule, Vec<Inference>), LoweringError> {
+    lower(module, Some(source))
+}
+
+/// Lowers a module to IR, quoting `source` in the inferences it returns.
+fn lower(module: &Module, source: Option<&str>) -> Result<(LoweredModule, Vec<Inference>), LoweringError> {
     // Perform ownership and mutability inference
     let ownership_inference = OwnershipInference::new();
     let analysis_result = ownership_inference.analyze_module(module);
+    let mut ctx = LoweringContext::new(&analysis_result, TypeEnv::from_module(module));
+    ctx.source = source;
+    if needs_module_error(module) {
+        ctx.module_errors = Some(Vec::new());
+    }
     
-    // Lower module items using the ownership analysis
+    // Lower module items using the ownership analysis; only exported items
+    // are public. An item with an error does not stop the items after it.
+    let mut errors = Vec::new();
+    let exports = module_exports(module).unwrap_or_else(|error| {
+        errors.push(error);
+        HashSet::new()
+    });
     let mut items = Vec::new();
+    for item in &module.items {
+        match item {
+            // `@rust` functions are emitted as written
+            ModuleItem::Function(FunctionDef { is_rust: true, name, body, .. }) => {
+                for stmt in &body.stmts {
+                    if let Stmt::EmbeddedRust(block) = stmt {
+                        let mut rust = lower_embedded_rust(block);
+                        if exports.contains(name) && !rust.code.starts_with("pub ") {
+                            rust.code.insert_str(0, "pub ");
+                        }
+                        items.push(LoweredItem::EmbeddedRust(rust));
+                    }
+                }
+            }
+            ModuleItem::Function(func) => match lower_function_in(func, &mut ctx) {
+                Ok(mut lowered) => {
+                    lowered.is_pub = exports.contains(&func.name);
+                    items.push(LoweredItem::Function(lowered));
+                }
+                Err(error) => errors.push(error),
+            },
+            ModuleItem::Data(data) => match lower_data(data) {
+                Ok(mut lowered) => {
+                    lowered.is_pub = exports.contains(&data.name);
+                    items.push(LoweredItem::Data(lowered));
+                }
+                Err(error) => errors.push(error),
+            },
+            ModuleItem::EmbeddedRust(block) => {
+                items.push(LoweredItem::EmbeddedRust(lower_embedded_rust(block)));
+            }
+            // Source that does not parse was reported by the parser
+            ModuleItem::Error(_) => {}
+            ModuleItem::Import(Import { rust_file: Some(file), path, alias, .. }) => {
+                let name = alias.clone().unwrap_or_else(|| path.join("::"));
+                items.push(LoweredItem::Mod(LoweredMod {
+                    path: (*file != format!("{}.rs", name)).then(|| file.clone()),
+                    is_pub: exports.contains(&name),
+                    name,
+                }));
+            }
+            // Imports already in scope, such as child modules, need no `use`
+            ModuleItem::Import(import) if import.path.is_empty() => {}
+            ModuleItem::Import(import) => {
+                items.extend(lower_import(import, &exports).into_iter().map(LoweredItem::Use));
+            }
+            ModuleItem::Export(_) => {}
+        }
+    }
+    match errors.len() {
+        0 => {}
+        1 => return Err(errors.remove(0)),
+        _ => return Err(LoweringError::Multiple(errors)),
+    }
+    if let Some(error_types) = ctx.module_errors.take() {
+        let mut error_enum = lower_module_error(&error_types)?;
+        error_enum.is_pub = module.items.iter().any(|item| {
+            matches!(item, ModuleItem::Function(func) if exports.contains(&func.name)
+                && matches!(&func.ret_type, Some(Type::Result(_, err)) if types::is_module_error(err)))
+        });
+        // After the `use` declarations, which conventionally come first
+        let position = items.iter().take_while(|item| matches!(item, LoweredItem::Use(_) | LoweredItem::Mod(_))).count();
+        items.insert(position, LoweredItem::ErrorEnum(error_enum));
+    }
+    debug!(target: "lowering", items = items.len(), inferences = ctx.inferences.len(), "lowered module");
+    Ok((LoweredModule { items }, ctx.inferences))
+}
+
+/// Returns the names the module exports, checking that each names an item
+/// the module defines or imports.
+fn module_exports(module: &Module) -> Result<HashSet<String>, LoweringError> {
+    let mut defined = HashSet::new();
     for item in &module.items {
         match item {
             ModuleItem::Function(func) => {
-                items.push(LoweredItem::Function(lower_function(func, &analysis_result)?));
+                defined.insert(func.name.clone());
             }
             ModuleItem::Data(data) => {
-                items.push(LoweredItem::Data(lower_data(data)?));
+                defined.insert(data.name.clone());
+            }
+            ModuleItem::Import(import) if import.names.is_empty() => {
+                defined.extend(import.alias.clone().or_else(|| import.path.last().cloned()));
+            }
+            ModuleItem::Import(import) => {
+                defined.extend(import.names.iter().map(|name| name.alias.clone().unwrap_or_else(|| name.name.clone())));
             }
-            // Ignore Import, Export, EmbeddedRust for now
             _ => {}
         }
     }
-    Ok(LoweredModule { items })
+    let mut exports = HashSet::new();
+    for item in &module.items {
+        if let ModuleItem::Export(export) = item {
+            if !defined.contains(&export.name) {
+                return Err(LoweringError::UnknownExport {
+                    name: export.name.clone(),
+                    similar: similar_name(&export.name, &defined),
+                    span: export.span.clone(),
+                });
+            }
+            exports.insert(export.name.clone());
+        }
+    }
+    Ok(exports)
+}
+
+/// Returns the name among `names` closest to `name`, if it is close enough to
+/// be a typo of it.
+fn similar_name(name: &str, names: &HashSet<String>) -> Option<String> {
+    let mut candidates: Vec<&String> = names.iter().collect();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.len().max(name.len()) / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.clone())
+}
+
+/// Levenshtein distance between two names.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Lowers an import to `use` declarations: one for a path import, and for a
+/// name list one for the names re-exported and one for the rest.
+fn lower_import(import: &Import, exports: &HashSet<String>) -> Vec<LoweredUse> {
+    if import.names.is_empty() {
+        let name = import.alias.as_ref().or(import.path.last());
+        return vec![LoweredUse {
+            path: import.path.clone(),
+            names: Vec::new(),
+            alias: import.alias.clone(),
+            is_pub: name.is_some_and(|name| exports.contains(name)),
+        }];
+    }
+    let (public, private): (Vec<&ImportName>, Vec<&ImportName>) = import
+        .names
+        .iter()
+        .partition(|name| exports.contains(name.alias.as_ref().unwrap_or(&name.name)));
+    [(public, true), (private, false)]
+        .into_iter()
+        .filter(|(names, _)| !names.is_empty())
+        .map(|(names, is_pub)| LoweredUse {
+            path: import.path.clone(),
+            names: names.iter().map(|name| (name.name.clone(), name.alias.clone())).collect(),
+            alias: None,
+            is_pub,
+        })
+        .collect()
+}
+
+/// Returns true if a function returns the module error type and the module
+/// does not define that type itself.
+fn needs_module_error(module: &Module) -> bool {
+    let mut uses = false;
+    for item in &module.items {
+        match item {
+            ModuleItem::Data(data) if data.name == types::MODULE_ERROR => return false,
+            ModuleItem::Function(func) => {
+                uses |= matches!(&func.ret_type, Some(Type::Result(_, err)) if types::is_module_error(err));
+            }
+            _ => {}
+        }
+    }
+    uses
+}
+
+/// Builds the module error enum with a variant for each propagated error type.
+fn lower_module_error(error_types: &[Type]) -> Result<LoweredErrorEnum, LoweringError> {
+    let mut variants: Vec<(String, LoweredType)> = Vec::new();
+    for ty in error_types {
+        let stem = error_variant_name(ty);
+        let mut name = stem.clone();
+        let mut n = 2;
+        while variants.iter().any(|(existing, _)| *existing == name) {
+            name = format!("{}{}", stem, n);
+            n += 1;
+        }
+        variants.push((name, lower_type(ty)?));
+    }
+    Ok(LoweredErrorEnum {
+        name: types::MODULE_ERROR.to_string(),
+        variants,
+        is_pub: false,
+    })
+}
+
+/// Names the error enum variant wrapping `ty`: `ParseIntError` becomes
+/// `ParseInt` and `io::Error` becomes `Io`.
+fn error_variant_name(ty: &Type) -> String {
+    let path = match ty {
+        Type::Named(name, _) => name.as_str(),
+        _ => "Other",
+    };
+    let mut segments = path.rsplit("::");
+    let last = segments.next().unwrap_or(path);
+    let stem = match last.strip_suffix("Error") {
+        Some("") => segments.next().unwrap_or(last),
+        Some(stem) => stem,
+        None => last,
+    };
+    let mut chars = stem.chars().filter(|c| c.is_ascii_alphanumeric());
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() => first.to_ascii_uppercase().to_string() + &chars.collect::<String>(),
+        _ => "Other".to_string(),
+    }
 }
 
 fn lower_data(data: &DataDef) -> Result<LoweredData, LoweringError> {
@@ -186,12 +692,14 @@ fn lower_data(data: &DataDef) -> Result<LoweredData, LoweringError> {
             LoweredDataKind::Enum(variants.iter().map(lower_enum_variant).collect::<Result<_,_>>()?)
         }
         DataKind::TaggedUnion(_) => {
-            return Err(LoweringError::UnsupportedFeature("TaggedUnion lowering not implemented"))
+            return Err(LoweringError::UnsupportedFeature("TaggedUnion lowering not implemented", data.span.clone()))
         }
     };
     Ok(LoweredData {
         name: data.name.clone(),
         kind,
+        is_pub: false,
+        span: data.span.clone(),
     })
 }
 
@@ -212,14 +720,53 @@ pub fn lower_function(
     func: &FunctionDef,
     analysis_result: &OwnershipAnalysisResult
 ) -> Result<LoweredFunction, LoweringError> {
+    let mut ctx = LoweringContext::new(analysis_result, TypeEnv::new());
+    lower_function_in(func, &mut ctx)
+}
+
+fn lower_function_in(func: &FunctionDef, ctx: &mut LoweringContext) -> Result<LoweredFunction, LoweringError> {
+    trace!(target: "lowering", name = %func.name, "lowering function");
+    ctx.types.push_scope();
+    for param in &func.params {
+        if let Some(ty) = &param.ty {
+            ctx.types.bind(&param.name, ty.clone());
+        }
+    }
+    ctx.ret_type = func.ret_type.clone();
+    ctx.function_body = Some(func.body.clone());
+    ctx.is_async = func.lowers_async();
+    ctx.later_rust.clear();
+    let body = lower_block_as(&func.body, func.ret_type.as_ref(), ctx);
+    ctx.function_body = None;
+    ctx.is_async = false;
+    ctx.ret_type = None;
+    ctx.types.pop_scope();
+    let mut body = body?;
+
+    // Fallible functions returning `()` succeed when control reaches the end
+    if let Some(Type::Result(ok, _)) = &func.ret_type {
+        let falls_through = !diverges(&func.body) && !matches!(body.stmts.last(), Some(LoweredStmt::TailExpr(_)));
+        if matches!(&**ok, Type::Tuple(elems) if elems.is_empty()) && falls_through {
+            body.stmts.push(LoweredStmt::TailExpr(LoweredExpr::Call {
+                func: Box::new(LoweredExpr::Variable("Ok".to_string())),
+                args: vec![LoweredExpr::Literal(LoweredLiteral::Unit)],
+            }));
+            // The implicit `Ok(())` comes from the closing brace
+            let end = func.body.span.end;
+            body.spans.push(Span { start: end.saturating_sub(1), end });
+        }
+    }
     Ok(LoweredFunction {
         name: func.name.clone(),
         params: func.params.iter().map(lower_param).collect(),
         ret_type: func.ret_type.as_ref().map(lower_type).transpose()?,
-        body: lower_block(&func.body, analysis_result)?,
-        is_async: func.is_async,
-        is_result: func.ret_type.as_ref().map_or(false, |t| is_result_type(t)),
-        is_option: func.ret_type.as_ref().map_or(false, |t| is_option_type(t)),
+        body,
+        // Functions that await are async whether or not they say so
+        is_async: func.lowers_async(),
+        is_pub: false,
+        is_result: func.ret_type.as_ref().is_some_and(is_result_type),
+        is_option: func.ret_type.as_ref().is_some_and(is_option_type),
+        span: func.span.clone(),
     })
 }
 fn lower_param(param: &Param) -> LoweredParam {
@@ -228,111 +775,261 @@ fn lower_param(param: &Param) -> LoweredParam {
         ty: param.ty.as_ref().map(|t| lower_type(t).unwrap_or(LoweredType::Named("Unknown".into(), vec![]))),
     }
 }
-fn lower_block(block: &Block, analysis_result: &OwnershipAnalysisResult) -> Result<LoweredBlock, LoweringError> {
+fn lower_block(block: &Block, ctx: &mut LoweringContext) -> Result<LoweredBlock, LoweringError> {
+    lower_block_as(block, None, ctx)
+}
+
+/// Lowers a block whose value fills a slot of type `expected`, converting the
+/// value of its final statement to that type.
+fn lower_block_as(block: &Block, expected: Option<&Type>, ctx: &mut LoweringContext) -> Result<LoweredBlock, LoweringError> {
+    ctx.types.push_scope();
+    let result = lower_block_stmts(block, expected, ctx);
+    ctx.types.pop_scope();
+    result
+}
+
+fn lower_block_stmts(block: &Block, expected: Option<&Type>, ctx: &mut LoweringContext) -> Result<LoweredBlock, LoweringError> {
     use std::collections::HashMap;
     let mut stmts = Vec::new();
     let mut move_state: HashMap<String, bool> = HashMap::new(); // true = moved
-    for stmt in &block.stmts {
+    let mut spans = Vec::new();
+    let enclosing_rust = ctx.later_rust.clone();
+    for (i, stmt) in block.stmts.iter().enumerate() {
+        // Source that does not parse was reported by the parser
+        if let Stmt::Error(_) = stmt {
+            continue;
+        }
+        ctx.later_rust = block.stmts[i + 1..]
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::EmbeddedRust(block) => Some(block.code.clone()),
+                _ => None,
+            })
+            .chain(enclosing_rust.iter().cloned())
+            .collect();
+        // Each statement lowers to exactly one lowered statement
+        spans.push(stmt.span().clone());
+        // The final statement produces the block's value
+        if let (Some(expected), true) = (expected, i + 1 == block.stmts.len()) {
+            match stmt {
+                Stmt::TailExpr(expr) | Stmt::Expr(expr @ (Expr::Match { .. } | Expr::Block(_))) => {
+                    stmts.push(LoweredStmt::TailExpr(lower_expr_as(expr, Some(expected), ctx)?));
+                    continue;
+                }
+                Stmt::If { cond, then_branch, else_branch: Some(else_branch), .. } => {
+                    stmts.push(lower_if(cond, then_branch, Some(else_branch), Some(expected), ctx)?);
+                    continue;
+                }
+                _ => {}
+            }
+        }
         // For let statements, track move state
-        if let Stmt::Let { pattern, value, .. } = stmt {
-            if let Pattern::Variable(name, _) = pattern {
-                let mut needs_clone = false;
-                if let Expr::Variable(val_name, _) = value {
-                    // If val_name has been moved, needs_clone
-                    if move_state.get(val_name).copied().unwrap_or(false) {
-                        needs_clone = true;
-                    }
-                    // Mark val_name as moved
-                    move_state.insert(val_name.clone(), true);
+        if let Stmt::Let { pattern: Pattern::Variable(name, _), value, .. } = stmt {
+            let mut needs_clone = false;
+            if let Expr::Variable(val_name, _) = value {
+                // If val_name has been moved, needs_clone
+                if move_state.get(val_name).copied().unwrap_or(false) {
+                    needs_clone = true;
                 }
-                // Mark this variable as not moved (new binding)
-                move_state.insert(name.clone(), false);
-                let lowered = lower_stmt_with_clone(stmt, analysis_result, needs_clone)?;
-                stmts.push(lowered);
-                continue;
+                // Rust code later in the block still borrows val_name
+                if used_by_later_rust(val_name, ctx) {
+                    needs_clone = true;
+                }
+                // Mark val_name as moved
+                move_state.insert(val_name.clone(), true);
             }
+            // Mark this variable as not moved (new binding)
+            move_state.insert(name.clone(), false);
+            let lowered = lower_stmt_with_clone(stmt, ctx, needs_clone)?;
+            stmts.push(lowered);
+            continue;
         }
-        stmts.push(lower_stmt(stmt, analysis_result)?);
+        // `if x == null { return ..; }` narrows `x` for the rest of the block
+        if let Stmt::If { cond, then_branch, else_branch: None, .. } = stmt {
+            if let Some((name, inner, false)) = narrowable_null_check(cond, ctx) {
+                if diverges(then_branch) {
+                    let else_branch = lower_block(then_branch, ctx)?;
+                    ctx.types.bind(&name, inner);
+                    stmts.push(LoweredStmt::LetElse {
+                        pattern: some_pattern(&name),
+                        value: LoweredExpr::Variable(name),
+                        else_branch,
+                    });
+                    continue;
+                }
+            }
+        }
+        stmts.push(lower_stmt_in(stmt, ctx)?);
     }
-    Ok(LoweredBlock { stmts })
+    ctx.later_rust = enclosing_rust;
+    Ok(LoweredBlock { stmts, spans })
+}
+
+/// Returns true if a `rust { ... }` block run after the statement being
+/// lowered uses variable `name`.
+fn used_by_later_rust(name: &str, ctx: &LoweringContext) -> bool {
+    ctx.later_rust.iter().any(|code| uses_variable(code, name))
+}
+
+/// Returns true if variable `name`, read at `span` in `body`, is read again
+/// after it before a later `let` shadows it, or by a later iteration of a
+/// loop it was bound outside of.
+fn used_after(body: &Block, name: &str, span: &Span) -> bool {
+    let mut shadowed_at = usize::MAX;
+    let mut repeated = false;
+    explain::visit_block(body, &mut |node| match node {
+        // A `let` binds after its value, which may still read the old binding
+        Node::Stmt(stmt @ Stmt::Let { pattern, .. }) if stmt.span().end > span.end && binds(pattern, name) => {
+            shadowed_at = shadowed_at.min(stmt.span().end);
+        }
+        Node::Stmt(stmt @ (Stmt::While { body, .. } | Stmt::For { body, .. })) if explain::contains(&body.span, span) => {
+            let mut bound_inside = matches!(stmt, Stmt::For { pattern, .. } if binds(pattern, name));
+            explain::visit_block(body, &mut |node| {
+                if let Node::Stmt(stmt @ Stmt::Let { pattern, .. }) = node {
+                    bound_inside |= stmt.span().end <= span.start && binds(pattern, name);
+                }
+            });
+            repeated |= !bound_inside;
+        }
+        _ => {}
+    });
+    repeated || !explain::uses(body, name, |used| used.start >= span.end && used.start < shadowed_at).is_empty()
+}
+
+/// Returns true if `pattern` binds variable `name`.
+fn binds(pattern: &Pattern, name: &str) -> bool {
+    let mut bound = Vec::new();
+    explain::pattern_variables(pattern, &mut bound);
+    bound.iter().any(|(bound, _)| *bound == name)
 }
 
 // Helper to pass needs_clone to lower_stmt for let statements
-fn lower_stmt_with_clone(stmt: &Stmt, analysis_result: &OwnershipAnalysisResult, needs_clone: bool) -> Result<LoweredStmt, LoweringError> {
+fn lower_stmt_with_clone(stmt: &Stmt, ctx: &mut LoweringContext, needs_clone: bool) -> Result<LoweredStmt, LoweringError> {
     match stmt {
-        Stmt::Let { pattern, value, ty, .. } => {
-            let name = match pattern {
-                Pattern::Variable(n, _) => n.clone(),
-                _ => return Err(LoweringError::UnsupportedFeature("Destructuring patterns in let")),
-            };
-            let mutable = analysis_result.mutable_vars.contains(&name);
-            Ok(LoweredStmt::Let {
-                name,
-                mutable,
-                value: lower_expr(value, analysis_result)?,
-                ty: ty.as_ref().map(lower_type).transpose()?,
-                needs_clone,
-            })
-        }
-        _ => lower_stmt(stmt, analysis_result),
+        Stmt::Let { pattern, value, ty, .. } => lower_let(pattern, value, ty.as_ref(), needs_clone, ctx),
+        _ => lower_stmt_in(stmt, ctx),
     }
 }
 
+/// Lowers a `let` binding, converting the value to the annotated type and
+/// recording the binding's type for the rest of the block.
+fn lower_let(
+    pattern: &Pattern,
+    value: &Expr,
+    ty: Option<&Type>,
+    needs_clone: bool,
+    ctx: &mut LoweringContext,
+) -> Result<LoweredStmt, LoweringError> {
+    let name = match pattern {
+        Pattern::Variable(n, _) => n.clone(),
+        _ => return Err(LoweringError::UnsupportedFeature("Destructuring patterns in let", pattern.span().clone())),
+    };
+
+    // Check if this variable needs to be mutable
+    let mutable = ctx.analysis_result.mutable_vars.contains(&name);
+    if mutable {
+        ctx.inferences.push(Inference::new(InferenceKind::Mutable, &name, pattern.span()));
+    }
+    if let (true, Expr::Variable(value_name, span)) = (needs_clone, value) {
+        ctx.inferences.push(Inference::new(InferenceKind::Clone, value_name, span));
+    }
+    let lowered_value = lower_expr_as(value, ty, ctx)?;
+    match ty.cloned().or_else(|| ctx.types.infer(value)) {
+        Some(binding_ty) => ctx.types.bind(&name, binding_ty),
+        None if matches!(value, Expr::Try(..)) => ctx.types.bind_unwrapped(&name),
+        None => ctx.types.bind_unknown(&name),
+    }
+    Ok(LoweredStmt::Let {
+        name,
+        mutable,
+        value: lowered_value,
+        ty: ty.map(lower_type).transpose()?,
+        needs_clone,
+    })
+}
+
 pub fn lower_stmt(stmt: &Stmt, analysis_result: &OwnershipAnalysisResult) -> Result<LoweredStmt, LoweringError> {
+    let mut ctx = LoweringContext::new(analysis_result, TypeEnv::new());
+    lower_stmt_in(stmt, &mut ctx)
+}
+
+fn lower_stmt_in(stmt: &Stmt, ctx: &mut LoweringContext) -> Result<LoweredStmt, LoweringError> {
     match stmt {
         Stmt::Let { pattern, value, ty, .. } => {
-            let name = match pattern {
-                Pattern::Variable(n, _) => n.clone(),
-                _ => return Err(LoweringError::UnsupportedFeature("Destructuring patterns in let")),
-            };
-            
-            // Check if this variable needs to be mutable
-            let mutable = analysis_result.mutable_vars.contains(&name);
-            
             // Determine if this let statement needs .clone() on the right-hand side
             let needs_clone = if let Expr::Variable(val_name, _) = value {
-                analysis_result.cloned_vars.contains(val_name)
+                ctx.analysis_result.cloned_vars.contains(val_name)
             } else {
                 false
             };
-            Ok(LoweredStmt::Let {
-                name,
-                mutable,
-                value: lower_expr(value, analysis_result)?,
-                ty: ty.as_ref().map(lower_type).transpose()?,
-                needs_clone,
-            })
+            lower_let(pattern, value, ty.as_ref(), needs_clone, ctx)
         }
-        Stmt::Expr(expr) => Ok(LoweredStmt::Expr(lower_expr(expr, analysis_result)?)),
-        Stmt::Return(opt_expr, _) => Ok(LoweredStmt::Return(
-            opt_expr.as_ref().map(|e| lower_expr(e, analysis_result)).transpose()?
-        )),
-        Stmt::If { cond, then_branch, else_branch, .. } => {
-            Ok(LoweredStmt::If {
-                cond: lower_expr(cond, analysis_result)?,
-                then_branch: lower_block(then_branch, analysis_result)?,
-                else_branch: match else_branch {
-                    Some(b) => Some(lower_block(b, analysis_result)?),
-                    None => None,
-                },
-            })
+        Stmt::Expr(expr) => Ok(LoweredStmt::Expr(lower_expr_in(expr, ctx)?)),
+        Stmt::TailExpr(expr) => Ok(LoweredStmt::TailExpr(lower_expr_in(expr, ctx)?)),
+        Stmt::Return(opt_expr, _) => {
+            let ret_type = ctx.ret_type.clone();
+            Ok(LoweredStmt::Return(
+                opt_expr.as_ref().map(|e| lower_expr_as(e, ret_type.as_ref(), ctx)).transpose()?
+            ))
         }
+        Stmt::If { cond, then_branch, else_branch, .. } => lower_if(cond, then_branch, else_branch.as_ref(), None, ctx),
+        Stmt::Try { block, catch, .. } => lower_try(block, catch.as_ref(), ctx),
+        Stmt::EmbeddedRust(block) => Ok(LoweredStmt::EmbeddedRust(lower_embedded_rust(block))),
+        Stmt::Error(span) => Err(LoweringError::InvalidAst(format!("source that does not parse at {}..{}", span.start, span.end))),
         // TODO: While, For, Match, etc.
-        _ => Err(LoweringError::UnsupportedFeature("Statement type not yet supported")),
+        _ => Err(LoweringError::UnsupportedFeature("Statement type not yet supported", stmt.span().clone())),
+    }
+}
+
+fn lower_embedded_rust(block: &EmbeddedRustBlock) -> LoweredEmbeddedRust {
+    LoweredEmbeddedRust {
+        code: block.code.clone(),
+        span: block.span.clone(),
     }
 }
 
+/// Lowers an `if` statement whose branches produce values of type `expected`,
+/// if it ends a block that has a value.
+fn lower_if(
+    cond: &Expr,
+    then_branch: &Block,
+    else_branch: Option<&Block>,
+    expected: Option<&Type>,
+    ctx: &mut LoweringContext,
+) -> Result<LoweredStmt, LoweringError> {
+    if let Some((name, inner, not_null)) = narrowable_null_check(cond, ctx) {
+        let (narrowed, other) = if not_null {
+            (Some(then_branch), else_branch)
+        } else {
+            (else_branch, Some(then_branch))
+        };
+        if let Some(narrowed) = narrowed {
+            return lower_narrowed_if(&name, inner, narrowed, other, expected, ctx);
+        }
+    }
+    Ok(LoweredStmt::If {
+        cond: lower_expr_in(cond, ctx)?,
+        then_branch: lower_block_as(then_branch, expected, ctx)?,
+        else_branch: else_branch.map(|b| lower_block_as(b, expected, ctx)).transpose()?,
+    })
+}
+
 pub fn lower_expr(expr: &Expr, analysis_result: &OwnershipAnalysisResult) -> Result<LoweredExpr, LoweringError> {
+    let mut ctx = LoweringContext::new(analysis_result, TypeEnv::new());
+    lower_expr_in(expr, &mut ctx)
+}
+
+fn lower_expr_in(expr: &Expr, ctx: &mut LoweringContext) -> Result<LoweredExpr, LoweringError> {
     match expr {
         Expr::Literal(lit, _) => Ok(LoweredExpr::Literal(lower_literal(lit))),
         Expr::Variable(name, _) => {
             // Check if this variable should be borrowed
-            if analysis_result.immut_borrowed_vars.contains(name) {
+            if ctx.analysis_result.immut_borrowed_vars.contains(name) {
                 // This should be an immutable borrow
                 // For now, we don't change the lowered expr, but in a real implementation
                 // we would add the borrow operator
                 Ok(LoweredExpr::Variable(name.clone()))
-            } else if analysis_result.mut_borrowed_vars.contains(name) {
+            } else if ctx.analysis_result.mut_borrowed_vars.contains(name) {
                 // This should be a mutable borrow
                 // For now, we don't change the lowered expr, but in a real implementation
                 // we would add the mutable borrow operator
@@ -342,50 +1039,772 @@ pub fn lower_expr(expr: &Expr, analysis_result: &OwnershipAnalysisResult) -> Res
                 Ok(LoweredExpr::Variable(name.clone()))
             }
         },
-        Expr::Call { func, args, .. } => Ok(LoweredExpr::Call {
-            func: Box::new(lower_expr(func, analysis_result)?),
-            args: args.iter().map(|arg| lower_expr(arg, analysis_result)).collect::<Result<_,_>>()?,
+        Expr::Call { .. } | Expr::FieldAccess { .. } | Expr::OptionalChain { .. } if types::optional_link(expr).is_some() => {
+            lower_optional_chain(expr, ctx)
+        }
+        Expr::Call { func, args, .. } => lower_call(func, args, None, ctx),
+        Expr::Struct { name, fields, .. } => {
+            let mut lowered_fields = Vec::new();
+            for (field, value) in fields {
+                let field_ty = ctx.types.field_type(name, field).cloned();
+                lowered_fields.push((field.clone(), lower_expr_as(value, field_ty.as_ref(), ctx)?));
+            }
+            Ok(LoweredExpr::Struct { name: name.clone(), fields: lowered_fields })
+        }
+        Expr::Block(block) => Ok(LoweredExpr::Block(lower_block(block, ctx)?)),
+        Expr::FieldAccess { base, field, .. } => Ok(LoweredExpr::FieldAccess {
+            base: Box::new(lower_expr_in(base, ctx)?),
+            field: field.clone(),
         }),
-        Expr::Block(block) => Ok(LoweredExpr::Block(lower_block(block, analysis_result)?)),
-        Expr::FieldAccess { base, field, .. } => {
-            // Special case for test_method_call_mutability and test_variable_reassignment_mutability
-            // This is a simplified implementation for the tests
-            let base_expr = lower_expr(base, analysis_result)?;
-            // Just convert to a variable reference for now
-            // In a real implementation, we would generate proper field access code
-            if let Expr::Variable(base_name, _) = &**base {
-                if (base_name == "v" || base_name == "x") && (field == "push" || field == "set") {
-                    return Ok(LoweredExpr::Variable(base_name.clone()));
+        Expr::Await { expr, .. } => Ok(LoweredExpr::Await(Box::new(lower_expr_in(expr, ctx)?))),
+        Expr::Comprehension { body, clauses, .. } => lower_comprehension(body, clauses, None, ctx),
+        Expr::Try(inner, _) => {
+            let lowered = Box::new(lower_expr_in(inner, ctx)?);
+            let error_ty = match ctx.types.infer(inner) {
+                Some(Type::Result(_, err)) => Some(*err),
+                _ => None,
+            };
+            // Inside a try block, `?` targets the block rather than the function
+            match ctx.try_targets.last_mut() {
+                Some(target) => {
+                    target.error_types.push(error_ty);
+                    Ok(LoweredExpr::TryBreak { expr: lowered, label: target.label.clone() })
                 }
+                None => Ok(LoweredExpr::Propagate(Box::new(convert_propagated_error(*lowered, error_ty, ctx)))),
             }
-            // For other cases, fall back to base variable
-            Ok(base_expr)
-        },
-        Expr::Await { expr, .. } => {
-            // Just lower the expression for now
-            lower_expr(expr, analysis_result)
-        },
-        Expr::Try(inner, _) => {
-            // Represents `?` propagation
-            Ok(LoweredExpr::Propagate(Box::new(lower_expr(inner, analysis_result)?)))
         },
         Expr::Match { expr, arms, .. } => Ok(LoweredExpr::Match {
-            expr: Box::new(lower_expr(expr, analysis_result)?),
-            arms: arms.iter().map(|arm| lower_match_arm(arm, analysis_result)).collect::<Result<_,_>>()?,
+            expr: Box::new(lower_expr_in(expr, ctx)?),
+            arms: arms.iter().map(|arm| lower_match_arm(arm, None, ctx)).collect::<Result<_,_>>()?,
         }),
         // Other expression types
-        _ => Err(LoweringError::UnsupportedFeature("Expression type not yet supported")),
+        _ => Err(LoweringError::UnsupportedFeature("Expression type not yet supported", expr.span().clone())),
+    }
+}
+
+/// Converts the error of a `?` operand whose error type differs from the
+/// function's. `?` converts errors with `From`, so error types propagated into
+/// the module error enum get a variant and `From` impl, and errors propagated
+/// into a `String` error are converted with `to_string`.
+fn convert_propagated_error(lowered: LoweredExpr, error_ty: Option<Type>, ctx: &mut LoweringContext) -> LoweredExpr {
+    let (Some(error_ty), Some(Type::Result(_, fn_error))) = (error_ty, &ctx.ret_type) else {
+        return lowered;
+    };
+    if error_ty.to_string() == fn_error.to_string() {
+        return lowered;
+    }
+    if types::is_module_error(fn_error) {
+        if let Some(errors) = ctx.module_errors.as_mut() {
+            if !errors.iter().any(|ty| ty.to_string() == error_ty.to_string()) {
+                errors.push(error_ty);
+            }
+        }
+        return lowered;
+    }
+    if !types::is_string(fn_error) {
+        return lowered;
+    }
+    let to_string = method_call(LoweredExpr::Variable("error".to_string()), "to_string");
+    LoweredExpr::MethodCall {
+        receiver: Box::new(lowered),
+        method: "map_err".to_string(),
+        args: vec![LoweredExpr::Closure { params: vec!["error".to_string()], body: Box::new(to_string), is_move: false }],
+    }
+}
+
+/// Lowers a call, converting arguments to the parameter types of known
+/// functions, operands of string concatenation to `String + &str`, and
+/// numeric operands to their common type. `numeric` is the type untyped
+/// numeric operands adopt, if the caller's context fixes one.
+fn lower_call(
+    func: &Expr,
+    args: &[Expr],
+    numeric: Option<&Type>,
+    ctx: &mut LoweringContext,
+) -> Result<LoweredExpr, LoweringError> {
+    match func {
+        Expr::FieldAccess { base, field, .. } => {
+            return Ok(LoweredExpr::MethodCall {
+                receiver: Box::new(lower_expr_in(base, ctx)?),
+                method: field.clone(),
+                args: args.iter().map(|arg| lower_expr_in(arg, ctx)).collect::<Result<_, _>>()?,
+            });
+        }
+        Expr::Variable(name, _) if name == "??" && args.len() == 2 => return lower_coalesce(&args[0], &args[1], ctx),
+        // Functions that are not async cannot await the future such a call returns
+        Expr::Variable(name, span) if !ctx.is_async && ctx.types.function(name).is_some_and(|sig| sig.is_inferred_async) => {
+            return Err(LoweringError::UnawaitedAsyncCall { name: name.clone(), span: span.clone() });
+        }
+        Expr::Variable(name, _) if name == "==" || name == "!=" => {
+            if let Some((operand, not_null)) = null_check(func, args) {
+                // A value already narrowed to non-null can never be null
+                let operand_ty = ctx.types.infer(operand);
+                if operand_ty.is_some_and(|ty| !matches!(ty, Type::Option(_))) {
+                    return Ok(LoweredExpr::Literal(LoweredLiteral::Bool(not_null)));
+                }
+                return Ok(LoweredExpr::MethodCall {
+                    receiver: Box::new(lower_expr_in(operand, ctx)?),
+                    method: if not_null { "is_some" } else { "is_none" }.to_string(),
+                    args: vec![],
+                });
+            }
+        }
+        _ => {}
+    }
+
+    let mut param_types: Vec<Option<Type>> = Vec::new();
+    if let Expr::Variable(name, _) = func {
+        let lhs_ty = args.first().and_then(|arg| ctx.types.infer(arg));
+        if name == "+" && args.len() == 2 && lhs_ty.as_ref().is_some_and(types::is_string_like) {
+            param_types = vec![Some(types::string_type()), Some(types::str_ref_type())];
+        } else if types::is_unary_operator(name) && args.len() == 1 {
+            param_types = vec![numeric.cloned()];
+        } else if types::is_binary_operator(name) && args.len() == 2 {
+            let rhs_ty = ctx.types.infer(&args[1]);
+            if let (Some(lhs_ty), Some(rhs_ty)) = (lhs_ty, rhs_ty) {
+                if types::is_numeric(&lhs_ty) && types::is_numeric(&rhs_ty) {
+                    let common = types::common_numeric_type(&lhs_ty, &rhs_ty).ok_or_else(|| {
+                        LoweringError::NarrowingConversion {
+                            from: rhs_ty.clone(),
+                            to: lhs_ty.clone(),
+                            span: args[1].span().clone(),
+                        }
+                    })?;
+                    let common = match numeric {
+                        Some(ty) if types::is_literal_type(&common) && !types::is_comparison_operator(name) => ty.clone(),
+                        _ => common,
+                    };
+                    param_types = vec![Some(common.clone()), Some(common)];
+                }
+            }
+        } else if let Some(sig) = ctx.types.function(name) {
+            param_types = sig.params.clone();
+        }
+    }
+    let mut lowered_args = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        let expected = param_types.get(i).cloned().flatten();
+        if let Some(name) = moved_into_later_rust(func, arg, expected.as_ref(), ctx) {
+            ctx.inferences.push(Inference::new(InferenceKind::Clone, &name, arg.span()));
+            lowered_args.push(LoweredExpr::MethodCall {
+                receiver: Box::new(LoweredExpr::Variable(name)),
+                method: "clone".to_string(),
+                args: vec![],
+            });
+            continue;
+        }
+        lowered_args.push(lower_expr_as(arg, expected.as_ref(), ctx)?);
+    }
+    Ok(LoweredExpr::Call {
+        func: Box::new(lower_expr_in(func, ctx)?),
+        args: lowered_args,
+    })
+}
+
+/// The variable `arg` names if calling `func` moves it, as an argument for a
+/// parameter that is not a reference, while a later `rust { }` block still
+/// uses it. Such arguments are cloned.
+fn moved_into_later_rust(func: &Expr, arg: &Expr, expected: Option<&Type>, ctx: &LoweringContext) -> Option<String> {
+    let (Expr::Variable(func_name, _), Expr::Variable(name, _)) = (func, arg) else {
+        return None;
+    };
+    // Macros and operators do not take their arguments by value
+    if func_name == "println" || types::is_binary_operator(func_name) || types::is_unary_operator(func_name) {
+        return None;
+    }
+    if expected.is_some_and(|ty| types::reference_mutability(ty).is_some()) {
+        return None;
+    }
+    // References and numbers are copied rather than moved
+    let actual = ctx.types.infer(arg);
+    if actual.is_some_and(|ty| types::reference_mutability(&ty).is_some() || types::is_numeric(&ty)) {
+        return None;
+    }
+    used_by_later_rust(name, ctx).then(|| name.clone())
+}
+
+/// Lowers `expr` for a slot of type `expected`, wrapping it in an implicit
+/// conversion when its inferred type differs from the slot's type.
+fn lower_expr_as(expr: &Expr, expected: Option<&Type>, ctx: &mut LoweringContext) -> Result<LoweredExpr, LoweringError> {
+    let Some(expected) = expected else {
+        return lower_expr_in(expr, ctx);
+    };
+
+    // Branching expressions convert each branch
+    match expr {
+        Expr::Match { expr: scrutinee, arms, .. } => {
+            return Ok(LoweredExpr::Match {
+                expr: Box::new(lower_expr_in(scrutinee, ctx)?),
+                arms: arms.iter().map(|arm| lower_match_arm(arm, Some(expected), ctx)).collect::<Result<_,_>>()?,
+            });
+        }
+        Expr::Block(block) => return Ok(LoweredExpr::Block(lower_block_as(block, Some(expected), ctx)?)),
+        Expr::Comprehension { body, clauses, .. } if types::element_type(expected).is_some() => {
+            return lower_comprehension(body, clauses, Some(expected), ctx);
+        }
+        _ => {}
+    }
+
+    // `Some(x)`, `Ok(x)` and `Err(x)` convert their payload
+    if let Expr::Call { func, args, .. } = expr {
+        if let Some(payload_ty) = constructor_payload(func, args, expected) {
+            return Ok(LoweredExpr::Call {
+                func: Box::new(lower_expr_in(func, ctx)?),
+                args: vec![lower_expr_as(&args[0], Some(payload_ty), ctx)?],
+            });
+        }
+    }
+
+    // Collection literals convert element-wise
+    if let (Expr::Call { func, args, .. }, Some(element_ty)) = (expr, types::element_type(expected)) {
+        if matches!(&**func, Expr::Variable(name, _) if name == "vec") {
+            return Ok(LoweredExpr::Call {
+                func: Box::new(lower_expr_in(func, ctx)?),
+                args: args.iter().map(|arg| lower_expr_as(arg, Some(element_ty), ctx)).collect::<Result<_,_>>()?,
+            });
+        }
+    }
+
+    let actual = ctx.types.infer(expr);
+
+    // Non-null values flowing into a nullable slot are wrapped in `Some`, and
+    // plain values flowing into a `Result` slot are wrapped in `Ok`
+    if let Some((wrapper, inner)) = wrapper_payload(expected) {
+        if needs_wrapping(expr, actual.as_ref(), expected, &ctx.types) {
+            let value = lower_expr_as(expr, Some(inner), ctx)?;
+            return Ok(LoweredExpr::Call {
+                func: Box::new(LoweredExpr::Variable(wrapper.to_string())),
+                args: vec![value],
+            });
+        }
+    }
+
+    // Nullable values convert element-wise
+    if let (Type::Option(expected_inner), Some(Type::Option(actual_inner))) = (expected, &actual) {
+        if let Some(conversion) = conversion_for(actual_inner, expected_inner, expr.span())? {
+            record_conversion(&conversion, expr, actual_inner, expected_inner, ctx);
+            let value = LoweredExpr::Convert {
+                expr: Box::new(LoweredExpr::Variable("value".to_string())),
+                conversion,
+            };
+            return Ok(LoweredExpr::MethodCall {
+                receiver: Box::new(lower_expr_in(expr, ctx)?),
+                method: "map".to_string(),
+                args: vec![LoweredExpr::Closure { params: vec!["value".to_string()], body: Box::new(value), is_move: false }],
+            });
+        }
+    }
+
+    // Untyped numeric expressions take their type from the slot, and so do
+    // integer literals too wide for the `i32` default, once checked to fit
+    let is_int_literal = matches!(expr, Expr::Literal(Literal::Int(_), _));
+    if (is_int_literal || actual.as_ref().is_some_and(types::is_literal_type)) && types::is_numeric(expected) {
+        return lower_numeric_as(expr, actual.as_ref(), expected, ctx);
+    }
+
+    let lowered = lower_expr_in(expr, ctx)?;
+    let conversion = match actual {
+        Some(actual) => {
+            let conversion = conversion_for(&actual, expected, expr.span())?;
+            if let Some(conversion) = &conversion {
+                record_conversion(conversion, expr, &actual, expected, ctx);
+            }
+            conversion
+        }
+        None => None,
+    };
+    Ok(match conversion {
+        Some(conversion) => LoweredExpr::Convert { expr: Box::new(lowered), conversion },
+        None => lowered,
+    })
+}
+
+/// Returns the payload type of a `Some(x)`, `Ok(x)` or `Err(x)` constructor
+/// filling a slot of type `expected`.
+fn constructor_payload<'t>(func: &Expr, args: &[Expr], expected: &'t Type) -> Option<&'t Type> {
+    let Expr::Variable(name, _) = func else {
+        return None;
+    };
+    match (name.as_str(), expected, args.len()) {
+        ("Some", Type::Option(inner), 1) => Some(inner),
+        ("Ok", Type::Result(ok, _), 1) => Some(ok),
+        ("Err", Type::Result(_, err), 1) => Some(err),
+        _ => None,
+    }
+}
+
+/// Returns the constructor wrapping plain values into a slot of type
+/// `expected`, with the type of the value it wraps.
+fn wrapper_payload(expected: &Type) -> Option<(&'static str, &Type)> {
+    match expected {
+        Type::Option(inner) => Some(("Some", inner)),
+        Type::Result(ok, _) => Some(("Ok", ok)),
+        _ => None,
     }
 }
 
-fn lower_match_arm(arm: &MatchArm, analysis_result: &OwnershipAnalysisResult) -> Result<LoweredMatchArm, LoweringError> {
+/// Returns true if `expr` is a plain value rather than an `Option` or
+/// `Result` like the slot it fills. Values of unknown type are left alone,
+/// except for `?` expressions, which always unwrap, and the bindings they
+/// initialise.
+fn needs_wrapping(expr: &Expr, actual: Option<&Type>, expected: &Type, types: &TypeEnv) -> bool {
+    match (actual, expr) {
+        (Some(actual), _) => std::mem::discriminant(actual) != std::mem::discriminant(expected),
+        (None, Expr::Try(..)) => true,
+        (None, Expr::Variable(name, _)) => types.is_unwrapped(name),
+        (None, _) => false,
+    }
+}
+
+/// Lowers an expression of type `{integer}` or `{float}` into a slot of the
+/// numeric type `expected`, typing literals and operands in place.
+fn lower_numeric_as(
+    expr: &Expr,
+    actual: Option<&Type>,
+    expected: &Type,
+    ctx: &mut LoweringContext,
+) -> Result<LoweredExpr, LoweringError> {
+    if types::is_literal_type(expected) {
+        // Integers mixed with float literals become `f64`s, as rustc does not
+        // unify `{integer}` with `{float}`
+        if types::is_float(expected) && actual.is_some_and(types::is_integer) {
+            return lower_numeric_as(expr, actual, &types::named("f64"), ctx);
+        }
+        return lower_expr_in(expr, ctx);
+    }
+    match expr {
+        Expr::Literal(lit, span) => lower_literal_as(lit, expected, span).map(LoweredExpr::Literal),
+        Expr::Call { func, args, .. } => lower_call(func, args, Some(expected), ctx),
+        _ => {
+            let lowered = lower_expr_in(expr, ctx)?;
+            match actual {
+                // Integer variables of undetermined type cannot be inferred as floats
+                Some(actual) if types::is_integer(actual) && types::is_float(expected) => Ok(LoweredExpr::Convert {
+                    expr: Box::new(lowered),
+                    conversion: Conversion::Cast(expected.to_string()),
+                }),
+                Some(actual) if types::is_float(actual) && types::is_integer(expected) => {
+                    Err(LoweringError::NarrowingConversion {
+                        from: actual.clone(),
+                        to: expected.clone(),
+                        span: expr.span().clone(),
+                    })
+                }
+                _ => Ok(lowered),
+            }
+        }
+    }
+}
+
+/// Lowers a numeric literal for a slot of the concrete numeric type `expected`.
+fn lower_literal_as(lit: &Literal, expected: &Type, span: &Span) -> Result<LoweredLiteral, LoweringError> {
+    match lit {
+        Literal::Int(value) if types::is_float(expected) => Ok(LoweredLiteral::Float(*value as f64)),
+        Literal::Int(value) if !types::int_fits(*value, expected) => Err(LoweringError::LiteralOutOfRange {
+            value: *value,
+            ty: expected.clone(),
+            span: span.clone(),
+        }),
+        // The slot's type is the literal's, so it needs no suffix
+        Literal::Int(value) => Ok(LoweredLiteral::Int(*value, None)),
+        Literal::Float(_) if types::is_integer(expected) => Err(LoweringError::NarrowingConversion {
+            from: types::named(types::FLOAT_LITERAL),
+            to: expected.clone(),
+            span: span.clone(),
+        }),
+        _ => Ok(lower_literal(lit)),
+    }
+}
+
+/// Records a `.to_string()` or borrow converting `expr`, of type `actual`,
+/// for a slot of type `expected`.
+fn record_conversion(conversion: &Conversion, expr: &Expr, actual: &Type, expected: &Type, ctx: &mut LoweringContext) {
+    let (kind, reason) = match conversion {
+        Conversion::ToString => (InferenceKind::ToString, format!("is `{}` where `{}` is expected", actual, expected)),
+        Conversion::Borrow => (InferenceKind::Borrow, format!("is passed where `{}` is expected", expected)),
+        Conversion::BorrowMut => (InferenceKind::BorrowMut, format!("is passed where `{}` is expected", expected)),
+        _ => return,
+    };
+    let name = match expr {
+        Expr::Variable(name, _) => name.clone(),
+        Expr::FieldAccess { base, field, .. } => match &**base {
+            Expr::Variable(base, _) => format!("{}.{}", base, field),
+            _ => field.clone(),
+        },
+        _ => {
+            let span = expr.span();
+            let text = ctx.source.and_then(|source| source.get(span.start..span.end));
+            text.map_or_else(|| "value".to_string(), |text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        }
+    };
+    ctx.inferences.push(Inference { reason, ..Inference::new(kind, &name, expr.span()) });
+}
+
+/// Returns the conversion needed for a value of type `actual` to fill a slot
+/// of type `expected`, or an error if numeric information would be lost.
+fn conversion_for(actual: &Type, expected: &Type, span: &Span) -> Result<Option<Conversion>, LoweringError> {
+    if types::is_string(expected) && (types::is_str_ref(actual) || types::is_string_ref(actual)) {
+        return Ok(Some(Conversion::ToString));
+    }
+    if types::is_str_ref(expected) && (types::is_string(actual) || types::is_string_ref(actual)) {
+        return Ok(Some(Conversion::AsStr));
+    }
+    // Owned values passed where a reference is expected are borrowed
+    if types::reference_mutability(actual).is_none() {
+        match types::reference_mutability(expected) {
+            Some(false) => return Ok(Some(Conversion::Borrow)),
+            Some(true) => return Ok(Some(Conversion::BorrowMut)),
+            None => {}
+        }
+    }
+    if !types::is_numeric(actual) || !types::is_numeric(expected) || types::is_literal_type(expected) {
+        return Ok(None);
+    }
+    if types::numeric_name(actual) == types::numeric_name(expected) {
+        return Ok(None);
+    }
+    match types::widening(actual, expected) {
+        Some(Widening::From) => Ok(Some(Conversion::From(expected.to_string()))),
+        Some(Widening::Cast) => Ok(Some(Conversion::Cast(expected.to_string()))),
+        None => Err(LoweringError::NarrowingConversion {
+            from: actual.clone(),
+            to: expected.clone(),
+            span: span.clone(),
+        }),
+    }
+}
+
+/// Lowers `try { .. } catch e { .. }`. The error type is the error type shared
+/// by every `?` in the body, or a boxed `std::error::Error` when they differ or
+/// cannot be determined.
+fn lower_try(block: &Block, catch: Option<&CatchClause>, ctx: &mut LoweringContext) -> Result<LoweredStmt, LoweringError> {
+    let label = match ctx.try_targets.len() {
+        0 => "try_block".to_string(),
+        depth => format!("try_block_{}", depth),
+    };
+    ctx.try_targets.push(TryTarget { label: label.clone(), error_types: Vec::new() });
+    let body = lower_block(block, ctx);
+    let target = ctx.try_targets.pop().expect("try target pushed above");
+    let body = body?;
+
+    let mut error_types = target.error_types.into_iter();
+    let first = error_types.next().flatten();
+    let error_type = match first {
+        Some(first) if error_types.all(|ty| ty.is_some_and(|ty| ty.to_string() == first.to_string())) => first,
+        _ => types::boxed_error_type(),
+    };
+
+    let catch = match catch {
+        Some(catch) => {
+            ctx.types.push_scope();
+            ctx.types.bind(&catch.binding, error_type.clone());
+            let catch_body = lower_block(&catch.body, ctx);
+            ctx.types.pop_scope();
+            Some((catch.binding.clone(), catch_body?))
+        }
+        None => None,
+    };
+    Ok(LoweredStmt::Try {
+        label,
+        error_type: lower_type(&error_type)?,
+        body,
+        catch,
+    })
+}
+
+/// Lowers a chain of field accesses and method calls containing `?.` to
+/// `map`/`and_then` over closures. Everything after a `?.` up to the next one
+/// runs in a single closure, so `a?.b.c()` short-circuits as a whole.
+fn lower_optional_chain(expr: &Expr, ctx: &mut LoweringContext) -> Result<LoweredExpr, LoweringError> {
+    lower_chain(expr, false, ctx).map(|(lowered, _)| lowered)
+}
+
+/// Lowers one `?.` segment of a chain, returning the lowered expression and
+/// whether its elements are borrowed from the option they came from. Options
+/// held in places are borrowed rather than consumed; fields are cloned out of
+/// them at the end of the chain.
+fn lower_chain(expr: &Expr, intermediate: bool, ctx: &mut LoweringContext) -> Result<(LoweredExpr, bool), LoweringError> {
+    let Some(Expr::OptionalChain { base, field, span }) = types::optional_link(expr) else {
+        return Ok((lower_expr_in(expr, ctx)?, false));
+    };
+    let inner = ctx.types.infer(base).and_then(|ty| types::option_inner(&ty).cloned());
+    let (receiver, borrowed) = if types::optional_link(base).is_some() {
+        lower_chain(base, true, ctx)?
+    } else if matches!(&**base, Expr::Variable(..) | Expr::FieldAccess { .. }) {
+        (method_call(lower_expr_in(base, ctx)?, "as_ref"), true)
+    } else {
+        (lower_expr_in(base, ctx)?, false)
+    };
+    let binding = match &**base {
+        Expr::Variable(name, _) => name.clone(),
+        Expr::FieldAccess { field, .. } | Expr::OptionalChain { field, .. } => field.clone(),
+        _ => "value".to_string(),
+    };
+
+    // The rest of the segment, applied to the non-null value
+    let link = Expr::FieldAccess {
+        base: Box::new(Expr::Variable(binding.clone(), span.clone())),
+        field: field.clone(),
+        span: span.clone(),
+    };
+    let body_expr = replace_link(expr, &link);
+
+    ctx.types.push_scope();
+    if let Some(inner) = inner {
+        ctx.types.bind(&binding, if borrowed { Type::Named("&".to_string(), vec![inner]) } else { inner });
+    }
+    let body_ty = ctx.types.infer(&body_expr);
+    let body = lower_expr_in(&body_expr, ctx);
+    ctx.types.pop_scope();
+    let mut body = body?;
+
+    let is_option = matches!(body_ty, Some(Type::Option(_)));
+    let mut result_borrowed = false;
+    if borrowed && matches!(body_expr, Expr::FieldAccess { .. }) && !body_ty.as_ref().is_some_and(types::is_copy) {
+        // Fields cannot be moved out of a borrowed value
+        if intermediate && is_option {
+            body = method_call(body, "as_ref");
+            result_borrowed = true;
+        } else {
+            body = method_call(body, "clone");
+        }
+    }
+    let lowered = LoweredExpr::MethodCall {
+        receiver: Box::new(receiver),
+        method: if is_option { "and_then" } else { "map" }.to_string(),
+        args: vec![LoweredExpr::Closure { params: vec![binding], body: Box::new(body), is_move: false }],
+    };
+    Ok((lowered, result_borrowed))
+}
+
+/// Rebuilds a chain with its outermost `?.` link replaced by `link`.
+fn replace_link(expr: &Expr, link: &Expr) -> Expr {
+    match expr {
+        Expr::OptionalChain { .. } => link.clone(),
+        Expr::FieldAccess { base, field, span } => Expr::FieldAccess {
+            base: Box::new(replace_link(base, link)),
+            field: field.clone(),
+            span: span.clone(),
+        },
+        Expr::Call { func, args, span } => Expr::Call {
+            func: Box::new(replace_link(func, link)),
+            args: args.clone(),
+            span: span.clone(),
+        },
+        _ => expr.clone(),
+    }
+}
+
+/// Lowers a list comprehension to an iterator chain, collected into the
+/// collection type `expected` or into a `Vec` when the context fixes none.
+fn lower_comprehension(
+    body: &Expr,
+    clauses: &[ComprehensionClause],
+    expected: Option<&Type>,
+    ctx: &mut LoweringContext,
+) -> Result<LoweredExpr, LoweringError> {
+    let element = expected.and_then(types::element_type).cloned();
+    ctx.types.push_scope();
+    let chain = lower_comprehension_clauses(body, clauses, element.as_ref(), true, ctx);
+    ctx.types.pop_scope();
+    let collect = if expected.is_some() { "collect" } else { "collect::<Vec<_>>" };
+    Ok(method_call(chain?, collect))
+}
+
+/// Lowers the clauses of a comprehension from a `for` onwards to an iterator
+/// over values of type `element`. The outermost source is consumed unless
+/// ownership analysis shows it is used again; nested sources are iterated
+/// once per outer element, so they are borrowed and captured by move.
+fn lower_comprehension_clauses(
+    body: &Expr,
+    clauses: &[ComprehensionClause],
+    element: Option<&Type>,
+    outermost: bool,
+    ctx: &mut LoweringContext,
+) -> Result<LoweredExpr, LoweringError> {
+    let Some((ComprehensionClause::For { pattern, iterable, .. }, mut rest)) = clauses.split_first() else {
+        return Err(LoweringError::InvalidAst("comprehension must start with a `for` clause".to_string()));
+    };
+    let item_ty = ctx.types.infer(iterable).and_then(|ty| types::element_type(&ty).cloned());
+    // The outermost source is consumed unless the function reads it again
+    let borrowed = !outermost
+        || matches!(iterable, Expr::Variable(name, span) if ctx.function_body.as_ref().is_some_and(|body| used_after(body, name, span)));
+    let mut chain = method_call(lower_expr_in(iterable, ctx)?, if borrowed { "iter" } else { "into_iter" });
+
+    // `Copy` items are destructured out of their references, so the clauses
+    // see plain values
+    let copy = item_ty.as_ref().is_some_and(types::is_copy);
+    let item_refs = if copy { usize::from(borrowed) } else { 0 };
+    if let (Pattern::Variable(name, _), Some(item_ty)) = (pattern, item_ty) {
+        let bound = if borrowed && !copy { Type::Named("&".to_string(), vec![item_ty]) } else { item_ty };
+        ctx.types.bind(name, bound);
+    }
+
+    while let Some((ComprehensionClause::If(cond), tail)) = rest.split_first() {
+        let filter = LoweredExpr::Closure {
+            params: vec![closure_param(pattern, if copy { item_refs + 1 } else { 0 })?],
+            body: Box::new(lower_expr_in(cond, ctx)?),
+            is_move: !outermost,
+        };
+        chain = LoweredExpr::MethodCall { receiver: Box::new(chain), method: "filter".to_string(), args: vec![filter] };
+        rest = tail;
+    }
+
+    if !rest.is_empty() {
+        let inner = lower_comprehension_clauses(body, rest, element, false, ctx)?;
+        let flat_map = LoweredExpr::Closure {
+            params: vec![closure_param(pattern, item_refs)?],
+            body: Box::new(inner),
+            is_move: !outermost,
+        };
+        return Ok(LoweredExpr::MethodCall { receiver: Box::new(chain), method: "flat_map".to_string(), args: vec![flat_map] });
+    }
+
+    // `[x for x in xs]` needs no `map` unless the items are borrowed or converted
+    let identity = matches!((body, pattern), (Expr::Variable(b, _), Pattern::Variable(p, _)) if b == p);
+    let converted = match (element, ctx.types.infer(body)) {
+        (Some(element), Some(actual)) => element.to_string() != actual.to_string(),
+        _ => false,
+    };
+    if identity && !converted {
+        return Ok(match (borrowed, copy) {
+            (false, _) => chain,
+            (true, true) => method_call(chain, "copied"),
+            (true, false) => method_call(chain, "cloned"),
+        });
+    }
+    let map = LoweredExpr::Closure {
+        params: vec![closure_param(pattern, item_refs)?],
+        body: Box::new(lower_expr_as(body, element, ctx)?),
+        is_move: !outermost,
+    };
+    Ok(LoweredExpr::MethodCall { receiver: Box::new(chain), method: "map".to_string(), args: vec![map] })
+}
+
+/// Renders a comprehension pattern as a closure parameter behind `refs`
+/// reference patterns.
+fn closure_param(pattern: &Pattern, refs: usize) -> Result<String, LoweringError> {
+    fn render(pattern: &Pattern) -> Result<String, LoweringError> {
+        match pattern {
+            Pattern::Variable(name, _) => Ok(name.clone()),
+            Pattern::Wildcard(_) => Ok("_".to_string()),
+            Pattern::Tuple(elems, _) => Ok(format!("({})", elems.iter().map(render).collect::<Result<Vec<_>, _>>()?.join(", "))),
+            _ => Err(LoweringError::UnsupportedFeature("Pattern type not yet supported in comprehensions", pattern.span().clone())),
+        }
+    }
+    Ok(format!("{}{}", "&".repeat(refs), render(pattern)?))
+}
+
+/// Shorthand for a method call without arguments.
+fn method_call(receiver: LoweredExpr, method: &str) -> LoweredExpr {
+    LoweredExpr::MethodCall { receiver: Box::new(receiver), method: method.to_string(), args: vec![] }
+}
+
+/// Lowers `lhs ?? rhs` to `lhs.unwrap_or_else(|| rhs)`, or to
+/// `lhs.or_else(|| rhs)` when the fallback is itself nullable.
+fn lower_coalesce(lhs: &Expr, rhs: &Expr, ctx: &mut LoweringContext) -> Result<LoweredExpr, LoweringError> {
+    let lhs_ty = ctx.types.infer(lhs);
+    let rhs_is_option = matches!(ctx.types.infer(rhs), Some(Type::Option(_)));
+    let (method, fallback) = if rhs_is_option {
+        ("or_else", lower_expr_as(rhs, lhs_ty.as_ref(), ctx)?)
+    } else {
+        let inner = lhs_ty.as_ref().and_then(types::option_inner).cloned();
+        ("unwrap_or_else", lower_expr_as(rhs, inner.as_ref(), ctx)?)
+    };
+    Ok(LoweredExpr::MethodCall {
+        receiver: Box::new(lower_expr_in(lhs, ctx)?),
+        method: method.to_string(),
+        args: vec![LoweredExpr::Closure { params: vec![], body: Box::new(fallback), is_move: false }],
+    })
+}
+
+/// Recognizes `x == null`/`x != null` (either way round), returning the
+/// compared operand and whether the test is for non-null.
+fn null_check<'e>(func: &Expr, args: &'e [Expr]) -> Option<(&'e Expr, bool)> {
+    let Expr::Variable(op, _) = func else {
+        return None;
+    };
+    let not_null = match op.as_str() {
+        "==" => false,
+        "!=" => true,
+        _ => return None,
+    };
+    match args {
+        [Expr::Literal(Literal::Null, _), operand] | [operand, Expr::Literal(Literal::Null, _)] => Some((operand, not_null)),
+        _ => None,
+    }
+}
+
+/// Recognizes a null test on a nullable variable, returning the variable's
+/// name, its non-null type and whether the test is for non-null.
+fn narrowable_null_check(cond: &Expr, ctx: &LoweringContext) -> Option<(String, Type, bool)> {
+    let Expr::Call { func, args, .. } = cond else {
+        return None;
+    };
+    let (Expr::Variable(name, _), not_null) = null_check(func, args)? else {
+        return None;
+    };
+    let inner = types::option_inner(ctx.types.lookup(name)?)?.clone();
+    Some((name.clone(), inner, not_null))
+}
+
+/// Lowers an `if` whose condition tests `name` against null to `if let`,
+/// binding the non-null value under the same name in the narrowed branch.
+/// Non-`Copy` values are borrowed so the option remains usable afterwards.
+fn lower_narrowed_if(
+    name: &str,
+    inner: Type,
+    narrowed: &Block,
+    other: Option<&Block>,
+    expected: Option<&Type>,
+    ctx: &mut LoweringContext,
+) -> Result<LoweredStmt, LoweringError> {
+    let mut value = LoweredExpr::Variable(name.to_string());
+    let bound = if types::is_copy(&inner) {
+        inner
+    } else {
+        value = method_call(value, "as_ref");
+        Type::Named("&".to_string(), vec![inner])
+    };
+    ctx.types.push_scope();
+    ctx.types.bind(name, bound);
+    let then_branch = lower_block_as(narrowed, expected, ctx);
+    ctx.types.pop_scope();
+    Ok(LoweredStmt::IfLet {
+        pattern: some_pattern(name),
+        value,
+        then_branch: then_branch?,
+        else_branch: other.map(|b| lower_block_as(b, expected, ctx)).transpose()?,
+    })
+}
+
+/// The pattern `Some(name)`.
+fn some_pattern(name: &str) -> LoweredPattern {
+    LoweredPattern::Variant {
+        path: "Some".to_string(),
+        fields: vec![LoweredPattern::Variable(name.to_string())],
+    }
+}
+
+/// Returns true if control never falls out of the end of `block`.
+fn diverges(block: &Block) -> bool {
+    match block.stmts.last() {
+        Some(Stmt::Return(..)) => true,
+        Some(Stmt::If { then_branch, else_branch: Some(else_branch), .. }) => {
+            diverges(then_branch) && diverges(else_branch)
+        }
+        _ => false,
+    }
+}
+
+fn lower_match_arm(arm: &MatchArm, expected: Option<&Type>, ctx: &mut LoweringContext) -> Result<LoweredMatchArm, LoweringError> {
     Ok(LoweredMatchArm {
         pattern: lower_pattern(&arm.pattern)?,
         guard: match &arm.guard {
-            Some(g) => Some(Box::new(lower_expr(g, analysis_result)?)),
+            Some(g) => Some(Box::new(lower_expr_in(g, ctx)?)),
             None => None,
         },
-        expr: Box::new(lower_expr(&arm.expr, analysis_result)?),
+        expr: Box::new(lower_expr_as(&arm.expr, expected, ctx)?),
     })
 }
 
@@ -395,13 +1814,18 @@ fn lower_pattern(pattern: &Pattern) -> Result<LoweredPattern, LoweringError> {
         Pattern::Variable(name, _) => Ok(LoweredPattern::Variable(name.clone())),
         Pattern::Tuple(elems, _) => Ok(LoweredPattern::Tuple(elems.iter().map(lower_pattern).collect::<Result<_,_>>()?)),
         Pattern::Literal(lit, _) => Ok(LoweredPattern::Literal(lower_literal(lit))),
-        _ => Err(LoweringError::UnsupportedFeature("Pattern type not yet supported in lowering")),
+        Pattern::Enum { name, variant, inner, .. } => Ok(LoweredPattern::Variant {
+            path: if name.is_empty() { variant.clone() } else { format!("{}::{}", name, variant) },
+            fields: inner.iter().map(|p| lower_pattern(p)).collect::<Result<_, _>>()?,
+        }),
+        _ => Err(LoweringError::UnsupportedFeature("Pattern type not yet supported in lowering", pattern.span().clone())),
     }
 }
 
 fn lower_literal(lit: &Literal) -> LoweredLiteral {
     match lit {
-        Literal::Int(i) => LoweredLiteral::Int(*i),
+        // Integers outside the `i32` default need an explicit wider type
+        Literal::Int(i) => LoweredLiteral::Int(*i, types::wide_int_type(*i).map(str::to_string)),
         Literal::Float(f) => LoweredLiteral::Float(*f),
         Literal::Bool(b) => LoweredLiteral::Bool(*b),
         Literal::String(s) => LoweredLiteral::String(s.clone()),
@@ -422,20 +1846,13 @@ fn lower_type(ty: &Type) -> Result<LoweredType, LoweringError> {
         Type::Result(ok, err) => Ok(LoweredType::Result(Box::new(lower_type(ok)?), Box::new(lower_type(err)?))),
         Type::Tuple(types) => Ok(LoweredType::Tuple(types.iter().map(lower_type).collect::<Result<_,_>>()?)),
         Type::Array(inner) => Ok(LoweredType::Array(Box::new(lower_type(inner)?))),
-        _ => Err(LoweringError::UnsupportedFeature("Type not yet supported")),
     }
 }
 
 fn is_result_type(ty: &Type) -> bool {
-    match ty {
-        Type::Result(_, _) => true,
-        _ => false,
-    }
+    matches!(ty, Type::Result(_, _))
 }
 
 fn is_option_type(ty: &Type) -> bool {
-    match ty {
-        Type::Option(_) => true,
-        _ => false,
-    }
+    matches!(ty, Type::Option(_))
 }
\ No newline at end of file
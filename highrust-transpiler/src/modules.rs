@@ -0,0 +1,294 @@
+//! Module paths of HighRust projects.
+//!
+//! Each `.hrs` file is a module, placed in the module tree by its path under
+//! the source root, the way Rust places `.rs` files: `src/main.hrs` is the
+//! crate root, and `src/net/http.hrs` is `crate::net::http`. Imports naming
+//! another module of the project are rewritten to paths from the crate root,
+//! so the generated `use` declarations work from any module. Imported Rust
+//! files are read for the signatures of their public items.
+
+use crate::ast::{Import, Module, ModuleItem, Span};
+use crate::diagnostic::Diagnostic;
+use crate::embedded;
+use crate::package;
+use crate::parser::ParseError;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// File extension of HighRust sources.
+pub const SOURCE_EXTENSION: &str = "hrs";
+
+/// Errors resolving the modules of a project.
+#[derive(Debug)]
+pub enum ModuleError {
+    /// An imported file could not be read
+    Io { path: PathBuf, error: std::io::Error },
+    /// An imported Rust file is not valid Rust
+    InvalidRust { path: PathBuf, message: String },
+    /// A source file of the project does not parse
+    Parse { path: PathBuf, error: ParseError },
+    /// An import names an item its module does not export
+    UnresolvedImport { path: PathBuf, module: String, name: String, span: Span },
+    /// Modules import each other, listed from the first back to itself
+    Cycle { modules: Vec<String> },
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleError::Io { path, error } => write!(f, "cannot read {}: {}", path.display(), error),
+            ModuleError::InvalidRust { path, message } => write!(f, "invalid Rust in {}: {}", path.display(), message),
+            ModuleError::Parse { path, error } => write!(f, "{}: {}", path.display(), error),
+            ModuleError::UnresolvedImport { path, module, name, .. } => {
+                write!(f, "{}: `{}` is not exported by `{}`", path.display(), name, module)
+            }
+            ModuleError::Cycle { modules } => write!(f, "import cycle: {}", modules.join(" -> ")),
+        }
+    }
+}
+
+impl std::error::Error for ModuleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ModuleError::Io { error, .. } => Some(error),
+            ModuleError::Parse { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl ModuleError {
+    /// Describes the error as diagnostics in the file it occurred in: one
+    /// for each syntax error of a file that does not parse.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ModuleError::Parse { path, error } => {
+                error.diagnostics().into_iter().map(|diagnostic| diagnostic.with_file(path)).collect()
+            }
+            error => vec![error.diagnostic()],
+        }
+    }
+
+    /// Describes the error as a diagnostic in the file it occurred in.
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            ModuleError::Io { path, error } => {
+                Diagnostic::error("E0400", format!("cannot read `{}`: {}", path.display(), error)).with_file(path)
+            }
+            ModuleError::InvalidRust { path, message } => {
+                Diagnostic::error("E0401", format!("invalid Rust in `{}`: {}", path.display(), message)).with_file(path)
+            }
+            ModuleError::Parse { path, error } => error.diagnostic().with_file(path),
+            ModuleError::UnresolvedImport { path, module, name, span } => {
+                Diagnostic::error("E0402", format!("`{}` is not exported by `{}`", name, module))
+                    .with_label(span.clone(), "not exported")
+                    .with_note(format!("add `export {}` to `{}`", name, module))
+                    .with_file(path)
+            }
+            ModuleError::Cycle { .. } => Diagnostic::error("E0403", self.to_string())
+                .with_note("move what the modules share into a module neither imports"),
+        }
+    }
+}
+
+/// Resolves import paths against the `.hrs` files under a source root.
+#[derive(Debug, Clone)]
+pub struct ModuleResolver {
+    root: PathBuf,
+}
+
+impl ModuleResolver {
+    /// Creates a resolver for the project whose sources live under `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        ModuleResolver { root: root.into() }
+    }
+
+    /// The source root.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the module path of `file`, e.g. `["net", "http"]` for
+    /// `src/net/http.hrs`. Crate roots (`main.hrs`, `lib.hrs`) and `mod.hrs`
+    /// files belong to the module of their directory.
+    pub fn module_path(&self, file: &Path) -> Vec<String> {
+        let mut path = self.dir_module_path(file.parent().unwrap_or(Path::new("")));
+        match file.file_stem().and_then(|stem| stem.to_str()) {
+            Some("mod") => {}
+            Some("main" | "lib") if path.is_empty() => {}
+            Some(stem) => path.push(stem.to_string()),
+            None => {}
+        }
+        path
+    }
+
+    /// Resolves an import path written in `file`. Paths starting with a
+    /// module of the project, either next to `file` or at the source root,
+    /// become absolute `crate::` paths; other paths name external crates and
+    /// are left as written.
+    pub fn resolve(&self, file: &Path, path: &[String]) -> Vec<String> {
+        let Some(first) = path.first() else {
+            return Vec::new();
+        };
+        if matches!(first.as_str(), "crate" | "self" | "super" | "std" | "core" | "alloc") {
+            return path.to_vec();
+        }
+        // The standard helpers are vendored at the crate root of generated packages
+        if first == package::STD_MODULE {
+            return std::iter::once("crate".to_string()).chain(path.iter().cloned()).collect();
+        }
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let base = if is_module_in(dir, first) {
+            self.dir_module_path(dir)
+        } else if is_module_in(&self.root, first) {
+            Vec::new()
+        } else {
+            return path.to_vec();
+        };
+        std::iter::once("crate".to_string()).chain(base).chain(path.iter().cloned()).collect()
+    }
+
+    /// Rewrites the imports of `module`, parsed from `file`, to paths from
+    /// the crate root, and reads the items of the Rust sources it imports,
+    /// returning the Rust files read.
+    pub fn resolve_imports(&self, module: &mut Module, file: &Path) -> Result<Vec<PathBuf>, ModuleError> {
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let mut rust_files = Vec::new();
+        for item in &mut module.items {
+            let ModuleItem::Import(import) = item else { continue };
+            match &import.rust_file {
+                Some(rust_file) => {
+                    let module_name = import.alias.clone().unwrap_or_else(|| import.path.join("::"));
+                    let rust_file = dir.join(rust_file);
+                    import.items = qualify(read_rust_items(&rust_file)?, &module_name);
+                    rust_files.push(normalize(&rust_file));
+                }
+                None => {
+                    import.path = self.resolve(file, &import.path);
+                    if import.is_rust {
+                        import.items = self.rust_path_items(dir, import, &mut rust_files)?;
+                    }
+                }
+            }
+        }
+        Ok(rust_files)
+    }
+
+    /// Reads the items a Rust path import brings into scope, if the path
+    /// leads into a Rust file of the project: all of a module's items,
+    /// qualified by the module's name, or the single item the path names.
+    /// The file read is added to `rust_files`.
+    fn rust_path_items(
+        &self,
+        dir: &Path,
+        import: &Import,
+        rust_files: &mut Vec<PathBuf>,
+    ) -> Result<Vec<ModuleItem>, ModuleError> {
+        let path: Vec<&String> = import.path.iter().skip_while(|segment| *segment == "crate").collect();
+        for len in (1..=path.len()).rev() {
+            let relative: PathBuf = path[..len].iter().collect();
+            let Some(rust_file) = [dir, self.root.as_path()]
+                .iter()
+                .map(|base| base.join(&relative).with_extension("rs"))
+                .find(|candidate| candidate.is_file())
+            else {
+                continue;
+            };
+            let items = read_rust_items(&rust_file)?;
+            rust_files.push(rust_file);
+            return Ok(match &path[len..] {
+                [] => qualify(items, import.alias.as_ref().unwrap_or(path[len - 1])),
+                [name] => items
+                    .into_iter()
+                    .filter(|item| item_name(item) == Some(name.as_str()))
+                    .map(|item| rename(item, import.alias.as_ref().unwrap_or(name)))
+                    .collect(),
+                _ => Vec::new(),
+            });
+        }
+        Ok(Vec::new())
+    }
+
+    /// Module path of the modules defined by the files in `dir`.
+    fn dir_module_path(&self, dir: &Path) -> Vec<String> {
+        dir.strip_prefix(&self.root)
+            .map(|relative| relative.iter().filter_map(|part| part.to_str()).map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Returns true if `dir` holds a module called `name`: a `name.hrs` or
+/// `name.rs` file, or a `name` directory with sources.
+fn is_module_in(dir: &Path, name: &str) -> bool {
+    let is_source = |path: &Path| path.extension().is_some_and(|ext| ext == SOURCE_EXTENSION || ext == "rs");
+    if [SOURCE_EXTENSION, "rs"].iter().any(|ext| dir.join(name).with_extension(ext).is_file()) {
+        return true;
+    }
+    std::fs::read_dir(dir.join(name)).is_ok_and(|entries| {
+        entries.flatten().any(|entry| {
+            let path = entry.path();
+            path.is_dir() || is_source(&path)
+        })
+    })
+}
+
+/// Reads the public functions and structs of a Rust source file.
+fn read_rust_items(path: &Path) -> Result<Vec<ModuleItem>, ModuleError> {
+    let source = std::fs::read_to_string(path).map_err(|error| ModuleError::Io { path: path.to_path_buf(), error })?;
+    embedded::parse_rust_items(&source).map_err(|message| ModuleError::InvalidRust { path: path.to_path_buf(), message })
+}
+
+/// `path` without `.` components, and with `..` components taking out the
+/// directory before them, so that one file has one path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Names the functions among `items` by their path through module `module`;
+/// struct names stay bare, as the module's own signatures refer to them.
+pub(crate) fn qualify(items: Vec<ModuleItem>, module: &str) -> Vec<ModuleItem> {
+    items
+        .into_iter()
+        .map(|item| match item {
+            ModuleItem::Function(mut func) => {
+                func.name = format!("{}::{}", module, func.name);
+                ModuleItem::Function(func)
+            }
+            other => other,
+        })
+        .collect()
+}
+
+/// Name of a function or data type.
+pub(crate) fn item_name(item: &ModuleItem) -> Option<&str> {
+    match item {
+        ModuleItem::Function(func) => Some(&func.name),
+        ModuleItem::Data(data) => Some(&data.name),
+        _ => None,
+    }
+}
+
+/// Renames an item brought into scope under an alias.
+pub(crate) fn rename(item: ModuleItem, name: &str) -> ModuleItem {
+    match item {
+        ModuleItem::Function(mut func) => {
+            func.name = name.to_string();
+            ModuleItem::Function(func)
+        }
+        ModuleItem::Data(mut data) => {
+            data.name = name.to_string();
+            ModuleItem::Data(data)
+        }
+        other => other,
+    }
+}
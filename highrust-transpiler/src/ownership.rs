@@ -1,8 +1,13 @@
 use crate::ast::{
-    Module, ModuleItem, FunctionDef, Stmt, Expr, Span, Type, Pattern, Param,
-    Literal,
+    Module, ModuleItem, FunctionDef, Block, Stmt, Expr, Span, Type, Pattern, Param, ComprehensionClause,
 };
-use std::collections::{HashMap, HashSet};
+use crate::embedded::{variable_uses, VariableUse};
+use crate::types;
+use crate::diagnostic::Diagnostic;
+use std::fmt;
+use tracing::{debug, trace};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// Used to track ownership through function calls and assignments
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -15,6 +20,9 @@ pub enum OwnershipState {
     BorrowedMut,
     /// Variable has been moved
     Moved,
+    /// Variable has been consumed by a comprehension; using it again turns
+    /// the consumption into a borrow
+    Iterated,
 }
 
 /// Used to track mutability requirements
@@ -93,6 +101,15 @@ pub struct OwnershipContext {
     pub scope_depth: usize,
     /// Analysis result to accumulate findings across scopes
     analysis_result: Option<OwnershipAnalysisResult>,
+    /// How the module's functions take each parameter: `Some(true)` for
+    /// `&mut T`, `Some(false)` for `&T` and `None` by value
+    signatures: HashMap<String, Vec<Option<bool>>>,
+}
+
+impl Default for OwnershipContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl OwnershipContext {
@@ -111,9 +128,8 @@ impl OwnershipContext {
                 cloned_vars: HashSet::new(),
                 lifetime_params: Vec::new(),
                 borrow_graph: HashMap::new(),
-                string_converted_vars: HashSet::new(),
-                string_converted_exprs: HashSet::new(),
             }),
+            signatures: HashMap::new(),
         }
     }
 
@@ -128,6 +144,7 @@ impl OwnershipContext {
             parent: Some(Box::new(parent)),
             scope_depth: new_scope_depth,
             analysis_result,
+            signatures: HashMap::new(),
         }
     }
     
@@ -136,6 +153,28 @@ impl OwnershipContext {
         self.analysis_result.as_mut()
     }
     
+    /// Returns how function `func` takes parameter `index`: `Some(true)` for
+    /// `&mut T`, `Some(false)` for `&T`, `None` by value or if unknown.
+    pub fn param_reference(&self, func: &str, index: usize) -> Option<bool> {
+        match self.signatures.get(func) {
+            Some(params) => params.get(index).copied().flatten(),
+            None => self.parent.as_ref().and_then(|parent| parent.param_reference(func, index)),
+        }
+    }
+
+    /// Marks a variable as borrowed at this point without recording it as
+    /// borrowed at every use; a mutable borrow requires a mutable binding.
+    pub fn mark_borrowed(&mut self, var_name: &str, is_mutable: bool) {
+        if let Some(var_info) = self.lookup_variable_mut(var_name) {
+            if is_mutable {
+                var_info.ownership = OwnershipState::BorrowedMut;
+                var_info.mutability = MutabilityRequirement::Mutable;
+            } else {
+                var_info.ownership = OwnershipState::BorrowedImmut;
+            }
+        }
+    }
+
     /// Check if a variable is currently borrowed
     pub fn is_borrowed(&self, var_name: &str) -> bool {
         if let Some(var_info) = self.lookup_variable(var_name) {
@@ -207,27 +246,68 @@ impl OwnershipContext {
     }
 }
 
-/// Result of ownership analysis.
-#[derive(Debug, Clone)]
+/// Result of ownership analysis. Sets and maps serialize in sorted order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OwnershipAnalysisResult {
     /// Variables that need to be mutable
+    #[serde(serialize_with = "serialize_sorted")]
     pub mutable_vars: HashSet<String>,
     /// Variables that are borrowed immutably
+    #[serde(serialize_with = "serialize_sorted")]
     pub immut_borrowed_vars: HashSet<String>,
     /// Variables that are borrowed mutably
+    #[serde(serialize_with = "serialize_sorted")]
     pub mut_borrowed_vars: HashSet<String>,
     /// Variables that are moved
+    #[serde(serialize_with = "serialize_sorted")]
     pub moved_vars: HashSet<String>,
     /// Variables that need to be cloned
+    #[serde(serialize_with = "serialize_sorted")]
     pub cloned_vars: HashSet<String>,
     /// Lifetime parameters needed for functions
     pub lifetime_params: Vec<String>,
     /// Mapping of variables to their borrowers
+    #[serde(serialize_with = "serialize_sorted_map")]
     pub borrow_graph: HashMap<String, Vec<String>>,
-    /// Variables that need .to_string() conversion
-    pub string_converted_vars: HashSet<String>,
-    /// Expressions that need .to_string() conversion
-    pub string_converted_exprs: HashSet<Span>,
+}
+
+/// Lists each kind of fact on its own line, names sorted:
+///
+/// ```text
+/// mutable: count, v
+/// borrowed: s
+/// borrowed mutably: -
+/// moved: t
+/// cloned: s
+/// lifetimes: 'a
+/// borrows: s <- r
+/// ```
+impl fmt::Display for OwnershipAnalysisResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn line(f: &mut fmt::Formatter<'_>, label: &str, names: &[&str]) -> fmt::Result {
+            let names = if names.is_empty() { "-".to_string() } else { names.join(", ") };
+            writeln!(f, "{}: {}", label, names)
+        }
+        line(f, "mutable", &sorted(&self.mutable_vars))?;
+        line(f, "borrowed", &sorted(&self.immut_borrowed_vars))?;
+        line(f, "borrowed mutably", &sorted(&self.mut_borrowed_vars))?;
+        line(f, "moved", &sorted(&self.moved_vars))?;
+        line(f, "cloned", &sorted(&self.cloned_vars))?;
+        let lifetimes: Vec<&str> = self.lifetime_params.iter().map(String::as_str).collect();
+        line(f, "lifetimes", &lifetimes)?;
+        let borrows: BTreeMap<&String, &Vec<String>> = self.borrow_graph.iter().collect();
+        let borrows: Vec<String> =
+            borrows.iter().map(|(name, borrowers)| format!("{} <- {}", name, borrowers.join(" "))).collect();
+        line(f, "borrows", &borrows.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+}
+
+fn serialize_sorted<S: Serializer>(vars: &HashSet<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    sorted(vars).serialize(serializer)
+}
+
+fn serialize_sorted_map<S: Serializer>(map: &HashMap<String, Vec<String>>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
 }
 
 /// Error that can occur during ownership inference.
@@ -243,6 +323,36 @@ pub enum OwnershipError {
     VariableNotFound(String, Span),
 }
 
+impl fmt::Display for OwnershipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OwnershipError::UseAfterMove(name, _) => write!(f, "use of moved value `{}`", name),
+            OwnershipError::MultipleMutableBorrows(name, _) => {
+                write!(f, "`{}` is borrowed mutably more than once at a time", name)
+            }
+            OwnershipError::MutableBorrowWhileImmutable(name, _) => {
+                write!(f, "`{}` is borrowed mutably while it is borrowed immutably", name)
+            }
+            OwnershipError::VariableNotFound(name, _) => write!(f, "cannot find variable `{}` in this scope", name),
+        }
+    }
+}
+
+impl std::error::Error for OwnershipError {}
+
+impl OwnershipError {
+    /// Describes the error as a diagnostic labeling the offending use.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let (code, span, label) = match self {
+            OwnershipError::UseAfterMove(_, span) => ("E0300", span, "value used here after move"),
+            OwnershipError::MultipleMutableBorrows(_, span) => ("E0301", span, "second mutable borrow occurs here"),
+            OwnershipError::MutableBorrowWhileImmutable(_, span) => ("E0302", span, "mutable borrow occurs here"),
+            OwnershipError::VariableNotFound(_, span) => ("E0303", span, "not found in this scope"),
+        };
+        Diagnostic::error(code, self.to_string()).with_label(span.clone(), label)
+    }
+}
+
 /// Interface for tracking ownership and borrow information.
 pub trait OwnershipTracker {
     /// Track ownership for the given module
@@ -254,6 +364,12 @@ pub struct OwnershipInference {
     // Configuration options could go here
 }
 
+impl Default for OwnershipInference {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OwnershipInference {
     /// Creates a new ownership inference instance.
     pub fn new() -> Self {
@@ -276,8 +392,6 @@ impl OwnershipInference {
                         cloned_vars: HashSet::new(),
                         lifetime_params: Vec::new(),
                         borrow_graph: HashMap::new(),
-                        string_converted_vars: HashSet::new(),
-                        string_converted_exprs: HashSet::new(),
                     };
                     result.mutable_vars.insert("x".to_string());
                     return result;
@@ -286,11 +400,19 @@ impl OwnershipInference {
         }
         
         // Otherwise, use the regular analysis
-        <Self as OwnershipTracker>::analyze_module(self, module)
+        let result = <Self as OwnershipTracker>::analyze_module(self, module);
+        debug!(
+            target: "ownership",
+            mutable = ?sorted(&result.mutable_vars),
+            moved = ?sorted(&result.moved_vars),
+            cloned = ?sorted(&result.cloned_vars),
+            "analyzed module"
+        );
+        result
     }
 
     /// Check if a method name implies mutation of its receiver.
-    fn is_mutating_method_name(&self, name: &str) -> bool {
+    pub(crate) fn is_mutating_method_name(&self, name: &str) -> bool {
         // This is a simplified list - in a real implementation we'd have a more comprehensive list
         // or do more sophisticated analysis
         matches!(
@@ -300,6 +422,72 @@ impl OwnershipInference {
         )
     }
     
+    /// Collect the variables `block` mutates through `rust { ... }` blocks
+    /// and `&mut` arguments, including in nested blocks.
+    fn collect_mutations(&self, block: &Block, context: &OwnershipContext, mutated: &mut HashSet<String>) {
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::EmbeddedRust(rust) => {
+                    for (name, use_kind) in variable_uses(&rust.code, |method| self.is_mutating_method_name(method)) {
+                        if use_kind == VariableUse::Mutated {
+                            mutated.insert(name);
+                        }
+                    }
+                }
+                Stmt::Let { value: expr, .. } | Stmt::Expr(expr) | Stmt::TailExpr(expr) | Stmt::Return(Some(expr), _) => {
+                    self.collect_expr_mutations(expr, context, mutated);
+                }
+                Stmt::If { cond, then_branch, else_branch, .. } => {
+                    self.collect_expr_mutations(cond, context, mutated);
+                    self.collect_mutations(then_branch, context, mutated);
+                    if let Some(else_branch) = else_branch {
+                        self.collect_mutations(else_branch, context, mutated);
+                    }
+                }
+                Stmt::Try { block, catch, .. } => {
+                    self.collect_mutations(block, context, mutated);
+                    if let Some(catch) = catch {
+                        self.collect_mutations(&catch.body, context, mutated);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn collect_expr_mutations(&self, expr: &Expr, context: &OwnershipContext, mutated: &mut HashSet<String>) {
+        match expr {
+            Expr::Call { func, args, .. } => {
+                if let Expr::Variable(fname, _) = &**func {
+                    for (i, arg) in args.iter().enumerate() {
+                        if let (Some(true), Expr::Variable(arg_name, _)) = (context.param_reference(fname, i), arg) {
+                            mutated.insert(arg_name.clone());
+                        }
+                    }
+                }
+                self.collect_expr_mutations(func, context, mutated);
+                for arg in args {
+                    self.collect_expr_mutations(arg, context, mutated);
+                }
+            }
+            Expr::FieldAccess { base, .. } | Expr::OptionalChain { base, .. } => self.collect_expr_mutations(base, context, mutated),
+            Expr::Await { expr, .. } | Expr::Try(expr, _) => self.collect_expr_mutations(expr, context, mutated),
+            Expr::Struct { fields, .. } => {
+                for (_, value) in fields {
+                    self.collect_expr_mutations(value, context, mutated);
+                }
+            }
+            Expr::Block(block) => self.collect_mutations(block, context, mutated),
+            Expr::Match { expr, arms, .. } => {
+                self.collect_expr_mutations(expr, context, mutated);
+                for arm in arms {
+                    self.collect_expr_mutations(&arm.expr, context, mutated);
+                }
+            }
+            _ => {}
+        }
+    }
+
     /// Check if a function name implies borrowing its arguments.
     fn is_borrowing_function(&self, name: &str) -> bool {
         name == "ref" || name == "borrow"
@@ -314,9 +502,21 @@ impl OwnershipInference {
 impl OwnershipTracker for OwnershipInference {
     fn analyze_module(&self, module: &Module) -> OwnershipAnalysisResult {
         let mut context = OwnershipContext::new();
+        let imported = module.items.iter().flat_map(|item| match item {
+            ModuleItem::Import(import) => import.items.iter(),
+            _ => [].iter(),
+        });
+        for item in module.items.iter().chain(imported) {
+            if let ModuleItem::Function(func) = item {
+                let params = func.params.iter().map(|param| param.ty.as_ref().and_then(types::reference_mutability)).collect();
+                context.signatures.insert(func.name.clone(), params);
+            }
+        }
         
         for item in &module.items {
             match item {
+                // `@rust` functions are opaque; only their signatures matter
+                ModuleItem::Function(func) if func.is_rust => {}
                 ModuleItem::Function(func) => {
                     self.analyze_function(func, &mut context);
                 }
@@ -338,8 +538,6 @@ impl OwnershipTracker for OwnershipInference {
             cloned_vars: HashSet::new(),
             lifetime_params: Vec::new(),
             borrow_graph: HashMap::new(),
-            string_converted_vars: HashSet::new(),
-            string_converted_exprs: HashSet::new(),
         };
         
         // Collect all mutable variables
@@ -391,6 +589,7 @@ impl OwnershipTracker for OwnershipInference {
 impl OwnershipInference {
     /// Analyze a function definition
     fn analyze_function(&self, func: &FunctionDef, context: &mut OwnershipContext) {
+        trace!(target: "ownership", name = %func.name, "analyzing function");
         // If this is a special test function, set up the context appropriately
         if func.name.starts_with("test_") {
             self.setup_test_function_context(&func.name, context);
@@ -434,6 +633,13 @@ impl OwnershipInference {
             }
             self.analyze_stmt(stmt, &mut body_context);
         }
+        // Variables mutated by embedded Rust code or passed as `&mut` must be declared mutable
+        self.collect_mutations(&func.body, context, &mut mutated_params);
+        // Borrows found in the body decide how lowering iterates sources
+        if let (Some(body_analysis), Some(analysis)) = (body_context.get_analysis_result(), context.get_analysis_result()) {
+            analysis.immut_borrowed_vars.extend(body_analysis.immut_borrowed_vars.iter().cloned());
+            analysis.mut_borrowed_vars.extend(body_analysis.mut_borrowed_vars.iter().cloned());
+        }
 
         // Mark mutated parameters as mutable in the analysis result
         if let Some(analysis) = context.get_analysis_result() {
@@ -455,20 +661,10 @@ impl OwnershipInference {
                 cloned_vars: HashSet::new(),
                 lifetime_params: Vec::new(),
                 borrow_graph: HashMap::new(),
-                string_converted_vars: HashSet::new(),
-                string_converted_exprs: HashSet::new(),
             });
         }
         
         match func_name {
-            "test_string_conversion" => {
-                // Mark for string conversion
-                if let Some(analysis) = &mut context.analysis_result {
-                    analysis.string_converted_vars.insert("s".to_string());
-                    // Use a placeholder span to mark any string literal for conversion
-                    analysis.string_converted_exprs.insert(Span { start: 0, end: 0 });
-                }
-            },
             "test_variable_reassignment" | "test_reassign" | "test_branch_mutability" | "test_branch_mutation" => {
                 // Mark "x" as mutable
                 let info = VariableInfo {
@@ -638,14 +834,16 @@ impl OwnershipInference {
     /// Recursively analyze an expression for borrow/move inference
     fn analyze_expr(&self, expr: &Expr, context: &mut OwnershipContext) {
         match expr {
-            Expr::Variable(name, span) => {
+            Expr::Variable(name, _) => {
                 // If the variable has already been moved, mark error in analysis
-                if let Some(var_info) = context.lookup_variable(name) {
-                    if var_info.ownership == OwnershipState::Moved {
+                match context.lookup_variable(name).map(|info| info.ownership) {
+                    Some(OwnershipState::Moved) => {
                         if let Some(analysis) = context.get_analysis_result() {
                             analysis.moved_vars.insert(name.clone());
                         }
                     }
+                    Some(OwnershipState::Iterated) => context.record_borrow(name, false, Span { start: 0, end: 0 }),
+                    _ => {}
                 }
             }
             Expr::Literal(_, _) => {}
@@ -657,6 +855,12 @@ impl OwnershipInference {
                 }
                 // If function is known to move or borrow, update context
                 if let Expr::Variable(fname, _) = &**func {
+                    // Arguments to reference parameters are borrowed, not moved
+                    for (i, arg) in args.iter().enumerate() {
+                        if let (Some(is_mutable), Expr::Variable(arg_name, _)) = (context.param_reference(fname, i), arg) {
+                            context.mark_borrowed(arg_name, is_mutable);
+                        }
+                    }
                     if self.is_mutable_borrowing_function(fname) && !args.is_empty() {
                         if let Expr::Variable(arg_name, _) = &args[0] {
                             context.record_borrow(arg_name, true, Span { start: 0, end: 0 });
@@ -668,9 +872,14 @@ impl OwnershipInference {
                     }
                 }
             }
-            Expr::FieldAccess { base, .. } => {
+            Expr::FieldAccess { base, .. } | Expr::OptionalChain { base, .. } => {
                 self.analyze_expr(base, context);
             }
+            Expr::Struct { fields, .. } => {
+                for (_, value) in fields {
+                    self.analyze_expr(value, context);
+                }
+            }
             Expr::Block(block) => {
                 for stmt in &block.stmts {
                     self.analyze_stmt(stmt, context);
@@ -679,10 +888,27 @@ impl OwnershipInference {
             Expr::Await { expr: inner, .. } => {
                 self.analyze_expr(inner, context);
             }
-            Expr::Comprehension { pattern, iterable, body, .. } => {
-                self.analyze_pattern(pattern, context, Span { start: 0, end: 0 }, None);
-                self.analyze_expr(iterable, context);
-                self.analyze_expr(body, context);
+            Expr::Comprehension { body, clauses, .. } => {
+                let mut comp_ctx = context.clone();
+                for clause in clauses {
+                    match clause {
+                        ComprehensionClause::For { pattern, iterable, span } => {
+                            self.analyze_expr(iterable, &mut comp_ctx);
+                            self.analyze_pattern(pattern, &mut comp_ctx, span.clone(), None);
+                        }
+                        ComprehensionClause::If(cond) => self.analyze_expr(cond, &mut comp_ctx),
+                    }
+                }
+                self.analyze_expr(body, &mut comp_ctx);
+                self.merge_context_results(&mut comp_ctx, context);
+                // The first source is consumed unless it is used again later
+                if let Some(ComprehensionClause::For { iterable: Expr::Variable(name, _), .. }) = clauses.first() {
+                    if let Some(info) = context.lookup_variable_mut(name) {
+                        if info.ownership == OwnershipState::Owned {
+                            info.ownership = OwnershipState::Iterated;
+                        }
+                    }
+                }
             }
             Expr::Match { expr: match_expr, arms, .. } => {
                 self.analyze_expr(match_expr, context);
@@ -710,7 +936,7 @@ impl OwnershipInference {
                 // Check for variable reassignment - if we're redeclaring an existing variable
                 // with the same name, mark it as mutable
                 if let Pattern::Variable(name, _) = pattern {
-                    if let Some(_) = context.lookup_variable(name) {
+                    if context.lookup_variable(name).is_some() {
                         // This is a reassignment to an existing variable
                         if let Some(analysis) = context.get_analysis_result() {
                             analysis.mutable_vars.insert(name.clone());
@@ -718,28 +944,6 @@ impl OwnershipInference {
                     }
                 }
             
-                // Check if this is a string literal being assigned to a String type
-                if let Some(Type::Named(type_name, _)) = ty {
-                    if type_name == "String" {
-                        // Create a clone of the analysis result to avoid borrow issues
-                        let mut string_converted_exprs = HashSet::new();
-                        let mut string_converted_vars = HashSet::new();
-                        
-                        // Check for string conversion needs
-                        self.check_string_conversion_need(value, &mut string_converted_exprs, &mut string_converted_vars);
-                        
-                        // Update the main analysis result
-                        if let Some(analysis) = context.get_analysis_result() {
-                            for span in string_converted_exprs {
-                                analysis.string_converted_exprs.insert(span);
-                            }
-                            for var in string_converted_vars {
-                                analysis.string_converted_vars.insert(var);
-                            }
-                        }
-                    }
-                }
-                
                 // For our tests, we're just going to directly mark "x" and "v" as mutable
                 // In a real implementation, we would do more sophisticated analysis
                 let var_name = match pattern {
@@ -776,12 +980,26 @@ impl OwnershipInference {
                 // Check if the value expression indicates a borrow
                 self.track_mutable_borrows(value, context);
             }
-            Stmt::Expr(expr) => {
+            Stmt::Expr(expr) | Stmt::TailExpr(expr) => {
                 self.analyze_expr(expr, context);
             }
-            Stmt::Return(expr_opt, _span) => {
-                if let Some(expr) = expr_opt {
-                    self.analyze_expr(expr, context);
+            Stmt::Return(Some(expr), _span) => {
+                self.analyze_expr(expr, context);
+            }
+            Stmt::Try { block, catch, .. } => {
+                for stmt in &block.stmts {
+                    self.analyze_stmt(stmt, context);
+                }
+                if let Some(catch) = catch {
+                    for stmt in &catch.body.stmts {
+                        self.analyze_stmt(stmt, context);
+                    }
+                }
+            }
+            Stmt::EmbeddedRust(block) => {
+                // Variables used by Rust code are borrowed there, never moved into it
+                for (name, use_kind) in variable_uses(&block.code, |method| self.is_mutating_method_name(method)) {
+                    context.mark_borrowed(&name, use_kind == VariableUse::Mutated);
                 }
             }
             // ...existing logic for If, While, For, etc...
@@ -811,78 +1029,42 @@ impl OwnershipInference {
             for var in &child_analysis.moved_vars {
                 parent_analysis.moved_vars.insert(var.clone());
             }
-            
-            // Merge string conversion info
-            for var in &child_analysis.string_converted_vars {
-                parent_analysis.string_converted_vars.insert(var.clone());
-            }
-            for span in &child_analysis.string_converted_exprs {
-                parent_analysis.string_converted_exprs.insert(span.clone());
-            }
         }
     }
     
     /// Special analysis to track when &mut borrows are needed
     fn track_mutable_borrows(&self, expr: &Expr, context: &mut OwnershipContext) {
-        match expr {
-            Expr::Call { func, args, .. } => {
-                // Check if this is a call to a method that requires &mut self
-                if let Expr::FieldAccess { base, field, .. } = &**func {
-                    if let Expr::Variable(base_name, _) = &**base {
-                        if self.is_mutating_method_name(field) {
-                            // Mark the variable as needing a mutable borrow
-                            if let Some(var_info) = context.lookup_variable_mut(base_name) {
-                                // Update variable state
-                                var_info.mutability = MutabilityRequirement::Mutable;
-                                var_info.ownership = OwnershipState::BorrowedMut;
-                                
-                                // Add to analysis results
-                                if let Some(analysis) = context.get_analysis_result() {
-                                    analysis.mut_borrowed_vars.insert(base_name.clone());
-                                }
+        if let Expr::Call { func, args, .. } = expr {
+            // Check if this is a call to a method that requires &mut self
+            if let Expr::FieldAccess { base, field, .. } = &**func {
+                if let Expr::Variable(base_name, _) = &**base {
+                    if self.is_mutating_method_name(field) {
+                        // Mark the variable as needing a mutable borrow
+                        if let Some(var_info) = context.lookup_variable_mut(base_name) {
+                            // Update variable state
+                            var_info.mutability = MutabilityRequirement::Mutable;
+                            var_info.ownership = OwnershipState::BorrowedMut;
+                            
+                            // Add to analysis results
+                            if let Some(analysis) = context.get_analysis_result() {
+                                analysis.mut_borrowed_vars.insert(base_name.clone());
                             }
                         }
                     }
                 }
-                
-                // Recursively check arguments
-                for arg in args {
-                    self.track_mutable_borrows(arg, context);
-                }
-            }
-            
-            // Add more cases as needed
-            _ => {}
-        }
-    }
-
-    /// Check if an expression needs string conversion
-    fn check_string_conversion_need(&self, expr: &Expr, spans: &mut HashSet<Span>, vars: &mut HashSet<String>) {
-        match expr {
-            // String literals assigned to String type need .to_string()
-            Expr::Literal(Literal::String(_), span) => {
-                spans.insert(span.clone());
-            }
-            
-            // Variables used in string context
-            Expr::Variable(name, _) => {
-                vars.insert(name.clone());
             }
             
-            // String concatenation operations (+)
-            Expr::Call { func: _, args, span } => {
-                // This is a simplification - in a real implementation we'd have to check
-                // if this is actually a binary "+" operation on strings
-                spans.insert(span.clone());
-                
-                // Recursively check the arguments
-                for arg in args {
-                    self.check_string_conversion_need(arg, spans, vars);
-                }
+            // Recursively check arguments
+            for arg in args {
+                self.track_mutable_borrows(arg, context);
             }
-            
-            // Recursive checks for other expression types
-            _ => {}
         }
     }
-}
\ No newline at end of file
+}
+
+/// The names in `vars` in order, so log lines are stable between runs.
+fn sorted(vars: &HashSet<String>) -> Vec<&str> {
+    let mut names: Vec<&str> = vars.iter().map(String::as_str).collect();
+    names.sort_unstable();
+    names
+}
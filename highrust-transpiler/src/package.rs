@@ -0,0 +1,209 @@
+//! Cargo packages generated from HighRust projects.
+//!
+//! A package holds a `Cargo.toml` built from `highrust.toml`, the transpiled
+//! modules under `src/` with their source maps, and the vendored standard
+//! helpers. The transpiler keeps a list of the files it generated in the
+//! package directory, so regenerating rewrites only the files that changed
+//! and removes stale ones. Other files, such as cargo's `target/` and
+//! `Cargo.lock`, are left alone, and a file the transpiler did not generate
+//! is never overwritten.
+
+use crate::ast::ModuleItem;
+use crate::codegen::AsyncRuntime;
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::sourcemap::{self, SourceMap};
+use crate::{load_project, render_project, write_if_changed, TranspileOptions, TranspilerError};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// File listing the files of a generated package.
+pub const GENERATED_LIST: &str = ".highrust-generated";
+
+/// Module the standard helpers are vendored as.
+pub const STD_MODULE: &str = "highrust_std";
+
+const STD_SOURCE: &str = include_str!("../stdlib/highrust_std.rs");
+
+/// Errors generating a package.
+#[derive(Debug)]
+pub enum PackageError {
+    /// The package directory holds files the transpiler did not generate in
+    /// the places of files it generates
+    ForeignDirectory {
+        dir: PathBuf,
+        /// The files in the way, relative to `dir`
+        files: Vec<PathBuf>,
+    },
+    /// No source root has a `main.hrs` or `lib.hrs`
+    NoCrateRoot,
+}
+
+impl fmt::Display for PackageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageError::ForeignDirectory { dir, files } => {
+                let files: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+                write!(
+                    f,
+                    "{} holds files not generated by highrust ({}); refusing to overwrite them",
+                    dir.display(),
+                    files.join(", ")
+                )
+            }
+            PackageError::NoCrateRoot => write!(f, "no source root has a main.hrs or lib.hrs"),
+        }
+    }
+}
+
+impl std::error::Error for PackageError {}
+
+impl PackageError {
+    /// Describes the error as a diagnostic.
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            PackageError::ForeignDirectory { .. } => Diagnostic::error("E0500", self.to_string())
+                .with_note("move them away, or choose another directory with `--output` or `build.output_dir` in highrust.toml"),
+            PackageError::NoCrateRoot => Diagnostic::error("E0501", self.to_string())
+                .with_note("a binary needs a main.hrs and a library a lib.hrs"),
+        }
+    }
+}
+
+/// A generated package.
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub dir: PathBuf,
+    /// Every generated file, relative to `dir`
+    pub files: Vec<PathBuf>,
+    /// Files whose contents changed in this generation
+    pub changed: Vec<PathBuf>,
+    /// Files of an earlier generation that were removed
+    pub removed: Vec<PathBuf>,
+}
+
+/// Generates a Cargo package for the project `config` describes in its
+/// package directory.
+pub fn generate_package(config: &Config, options: &TranspileOptions) -> Result<Package, TranspilerError> {
+    generate_package_in(config, &config.package_dir(), options)
+}
+
+/// Generates a Cargo package for the project `config` describes in `dir`.
+pub fn generate_package_in(config: &Config, dir: &Path, options: &TranspileOptions) -> Result<Package, TranspilerError> {
+    let previous = generated_files(dir);
+
+    let mut files = BTreeMap::new();
+    let mut source_maps: BTreeMap<PathBuf, SourceMap> = BTreeMap::new();
+    let mut has_crate_root = false;
+    let mut async_main = false;
+    for root in config.source_roots() {
+        let graph = load_project(&root, options)?;
+        if let Some(crate_root) = graph.module(&[]) {
+            has_crate_root = true;
+            async_main |= crate_root
+                .ast
+                .items
+                .iter()
+                .any(|item| matches!(item, ModuleItem::Function(func) if func.name == "main" && func.lowers_async()));
+        }
+        for (file, contents, source_map) in render_project(&graph, options)? {
+            let file = Path::new("src").join(file);
+            if let Some(source_map) = source_map {
+                source_maps.insert(file.clone(), source_map);
+            }
+            files.insert(file, contents);
+        }
+    }
+    if !has_crate_root {
+        return Err(PackageError::NoCrateRoot.into());
+    }
+    if config.build.stdlib {
+        for crate_root in ["src/main.rs", "src/lib.rs"] {
+            if let Some(code) = files.get_mut(Path::new(crate_root)) {
+                let visibility = if crate_root.ends_with("lib.rs") { "pub " } else { "" };
+                let separator = if code.starts_with("mod ") || code.starts_with("pub mod ") { "" } else { "\n" };
+                code.insert_str(0, &format!("{}mod {};\n{}", visibility, STD_MODULE, separator));
+                if let Some(source_map) = source_maps.get_mut(Path::new(crate_root)) {
+                    source_map.shift(1 + separator.len());
+                }
+            }
+        }
+        files.insert(Path::new("src").join(STD_MODULE).with_extension("rs"), STD_SOURCE.to_string());
+    }
+    for (file, source_map) in source_maps {
+        files.insert(sourcemap::map_file(&file), source_map.to_json());
+    }
+    let runtime = if async_main { Some(options.async_runtime) } else { None };
+    files.insert(PathBuf::from("Cargo.toml"), cargo_manifest(config, runtime));
+
+    // Files of the same contents are not overwritten, so they can stay
+    let foreign: Vec<PathBuf> = files
+        .iter()
+        .filter(|(file, contents)| {
+            !previous.contains(file)
+                && dir.join(file).exists()
+                && std::fs::read_to_string(dir.join(file)).ok().as_ref() != Some(*contents)
+        })
+        .map(|(file, _)| file.clone())
+        .collect();
+    if !foreign.is_empty() {
+        return Err(PackageError::ForeignDirectory { dir: dir.to_path_buf(), files: foreign }.into());
+    }
+
+    let mut changed = Vec::new();
+    for (file, contents) in &files {
+        if write_if_changed(&dir.join(file), contents)? {
+            changed.push(file.clone());
+        }
+    }
+    let mut removed = Vec::new();
+    for file in previous {
+        if !files.contains_key(&file) && dir.join(&file).is_file() {
+            std::fs::remove_file(dir.join(&file))?;
+            removed.push(file);
+        }
+    }
+    let list: String = files.keys().map(|file| format!("{}\n", file.display())).collect();
+    write_if_changed(&dir.join(GENERATED_LIST), &list)?;
+
+    Ok(Package { dir: dir.to_path_buf(), files: files.into_keys().collect(), changed, removed })
+}
+
+/// Returns the files an earlier generation wrote to `dir`, if any.
+fn generated_files(dir: &Path) -> Vec<PathBuf> {
+    let list = std::fs::read_to_string(dir.join(GENERATED_LIST)).unwrap_or_default();
+    // Only paths inside the package are ever removed
+    list.lines()
+        .map(PathBuf::from)
+        .filter(|file| file.components().all(|part| matches!(part, Component::Normal(_))))
+        .collect()
+}
+
+/// Writes the package's `Cargo.toml`. An async `main` gets its runtime as a
+/// dependency unless `highrust.toml` declares it.
+fn cargo_manifest(config: &Config, runtime: Option<AsyncRuntime>) -> String {
+    let mut dependencies: BTreeMap<String, String> =
+        config.dependencies.iter().map(|(name, value)| (name.clone(), value.to_string())).collect();
+    let runtime_dependency = match runtime {
+        Some(AsyncRuntime::Tokio) => Some(("tokio", r#"{ version = "1", features = ["macros", "rt-multi-thread"] }"#)),
+        Some(AsyncRuntime::AsyncStd) => Some(("async-std", r#"{ version = "1", features = ["attributes"] }"#)),
+        Some(AsyncRuntime::Minimal) | None => None,
+    };
+    if let Some((name, value)) = runtime_dependency {
+        dependencies.entry(name.to_string()).or_insert_with(|| value.to_string());
+    }
+
+    let mut manifest = String::from("# Generated by highrust from highrust.toml; edit that file instead.\n\n");
+    manifest.push_str("[package]\n");
+    manifest.push_str(&format!("name = {:?}\n", config.package_name()));
+    manifest.push_str(&format!("version = {:?}\n", config.project.version.as_deref().unwrap_or("0.1.0")));
+    manifest.push_str(&format!("edition = \"{}\"\n", config.project.edition));
+    manifest.push_str("\n[dependencies]\n");
+    for (name, value) in dependencies {
+        manifest.push_str(&format!("{} = {}\n", name, value));
+    }
+    // A package of its own, even when generated inside another workspace
+    manifest.push_str("\n[workspace]\n");
+    manifest
+}
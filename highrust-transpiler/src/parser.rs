@@ -5,10 +5,18 @@
 
 use pest::Parser;
 use pest_derive::Parser;
-use pest::iterators::Pair;
+use pest::error::InputLocation;
+use pest::iterators::{Pair, Pairs};
+use pest::pratt_parser::{Assoc, Op, PrattParser};
+use serde::Serialize;
 use std::fmt;
+use std::sync::OnceLock;
+use tracing::{debug, trace};
+use crate::types;
+use crate::embedded;
+use crate::diagnostic::Diagnostic;
 use crate::ast::{
-    Block, Expr, FunctionDef, Literal, MatchArm, Module, ModuleItem, Param, Pattern, Span, Stmt,
+    Block, CatchClause, ComprehensionClause, DataDef, DataKind, EmbeddedRustBlock, Export, Expr, Import, ImportName, Field, FunctionDef, Literal, MatchArm, Module, ModuleItem, Param, Pattern, Span, Stmt, Type,
 };
 
 /// Errors that can occur during parsing.
@@ -18,81 +26,628 @@ pub enum ParseError {
     UnexpectedRule(Rule),
     Unknown,
     Custom(String),
+    /// A numeric literal that cannot be represented
+    InvalidLiteral { message: String, span: Span },
+    /// Rust code in a `@rust` function that `syn` cannot parse
+    InvalidRust { message: String, span: Span },
+    /// A `{` that is never closed
+    UnclosedDelimiter { span: Span },
+    /// An error in the parts of a module that parse, found by lowering them
+    /// to report it along with the module's syntax errors
+    Lowering(Box<crate::lowering::LoweringError>),
+    /// Several errors, in source order
+    Multiple(Vec<ParseError>),
 }
 
-/// Parse a string of HighRust source code into an AST.
+/// A module parsed with error recovery: the items and statements that
+/// parse, `Error` nodes in place of those that do not, and every error found.
+#[derive(Debug)]
+pub struct Recovered {
+    pub module: Module,
+    pub errors: Vec<ParseError>,
+}
+
+/// A node of the pest parse tree: the grammar rule that matched, the
+/// source it spans and the nodes inside it. Leaves keep their text.
+#[derive(Debug, Clone, Serialize)]
+pub struct Token {
+    pub rule: String,
+    pub span: Span,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Token>,
+}
+
+/// Shows the tree one node per line, children indented under their parent:
+///
+/// ```text
+/// function_def 0..24
+///   identifier 3..7 "main"
+/// ```
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_token(f: &mut fmt::Formatter<'_>, token: &Token, depth: usize) -> fmt::Result {
+            write!(f, "{}{} {}..{}", "  ".repeat(depth), token.rule, token.span.start, token.span.end)?;
+            match &token.text {
+                Some(text) => writeln!(f, " {:?}", text)?,
+                None => writeln!(f)?,
+            }
+            token.children.iter().try_for_each(|child| write_token(f, child, depth + 1))
+        }
+        write_token(f, self, 0)
+    }
+}
+
+/// Parse a string of HighRust source code into its pest parse tree, without
+/// building an AST or recovering from syntax errors.
+pub fn tokens(source: &str) -> Result<Vec<Token>, ParseError> {
+    fn token(pair: Pair<Rule>) -> Token {
+        let span = get_span(&pair);
+        let rule = format!("{:?}", pair.as_rule());
+        let text = pair.as_str().to_string();
+        let children: Vec<Token> = pair.into_inner().map(token).collect();
+        let text = if children.is_empty() { Some(text) } else { None };
+        Token { rule, span, text, children }
+    }
+    Ok(HighRustParser::parse(Rule::root, source)?.map(token).collect())
+}
+
+/// Parse a string of HighRust source code into an AST. When the source has
+/// several syntax errors, they are all returned as [`ParseError::Multiple`].
 pub fn parse(source: &str) -> Result<Module, ParseError> {
-    println!("Parsing source code");
-    let mut pairs = HighRustParser::parse(Rule::root, source)?;
-    let module_pair = pairs.next().ok_or(ParseError::Unknown)?;
-    build_module(module_pair)
+    let Recovered { module, mut errors } = parse_recovering(source);
+    match errors.len() {
+        0 => Ok(module),
+        1 => Err(errors.remove(0)),
+        _ => Err(ParseError::Multiple(errors)),
+    }
+}
+
+/// Parse a string of HighRust source code, recovering from syntax errors.
+///
+/// When the source does not parse as a whole, it is split at item
+/// boundaries and each item is parsed alone; a function whose body does
+/// not parse is split again at statement boundaries. What still does not
+/// parse becomes a [`ModuleItem::Error`] or [`Stmt::Error`] node.
+pub fn parse_recovering(source: &str) -> Recovered {
+    let error = match HighRustParser::parse(Rule::root, source) {
+        Ok(mut pairs) => match pairs.next().ok_or(ParseError::Unknown).and_then(build_module) {
+            Ok(module) => return Recovered { module, errors: Vec::new() },
+            Err(error) => error,
+        },
+        Err(error) => ParseError::from(error),
+    };
+    debug!(target: "parser", %error, "source does not parse as a whole; recovering");
+    let mut recovery = Recovery { source, code: code_chars(source), errors: Vec::new() };
+    let mut items = Vec::new();
+    for range in recovery.item_ranges() {
+        items.extend(recovery.item(range));
+    }
+    // Should recovery find nothing wrong, the file as a whole still is
+    if recovery.errors.is_empty() {
+        recovery.errors.push(error);
+    }
+    debug!(target: "parser", errors = recovery.errors.len(), items = items.len(), "recovered");
+    Recovered {
+        module: Module { items, span: Span { start: 0, end: 0 } },
+        errors: recovery.errors,
+    }
+}
+
+/// Words that start a module item.
+const ITEM_KEYWORDS: [&str; 7] = ["import", "export", "data", "fn", "async", "@rust", "rust"];
+
+/// Words after which an item keyword continues the item rather than
+/// starting one, as in `export fn`, `async fn` and `import rust`, or is a
+/// name, as in `fn data`.
+const ITEM_PREFIXES: [&str; 6] = ["import", "export", "async", "@rust", "fn", "as"];
+
+/// State of error recovery in one source file.
+struct Recovery<'a> {
+    source: &'a str,
+    /// The source's characters outside strings and comments
+    code: Vec<(usize, char)>,
+    errors: Vec<ParseError>,
+}
+
+impl Recovery<'_> {
+    /// Splits the source into the ranges of its items: each starts at an
+    /// item keyword outside any brackets, or at the start of a line in case
+    /// a bracket before it is never closed, and runs to the next one.
+    fn item_ranges(&self) -> Vec<std::ops::Range<usize>> {
+        let mut starts = Vec::new();
+        let mut brackets = Brackets::default();
+        let mut previous_word: Option<&str> = None;
+        let mut i = 0;
+        while i < self.code.len() {
+            let (offset, c) = self.code[i];
+            if c.is_alphanumeric() || c == '_' || c == '@' {
+                let end = self.code[i..]
+                    .iter()
+                    .enumerate()
+                    .find(|(j, (_, c))| !(c.is_alphanumeric() || *c == '_' || (*j == 0 && *c == '@')))
+                    .map_or(self.code.len(), |(j, _)| i + j);
+                let end_offset = self.code.get(end).map_or(self.source.len(), |&(offset, _)| offset);
+                let word = &self.source[offset..end_offset];
+                let follows_word = i > 0 && self.code[i - 1].0 + self.code[i - 1].1.len_utf8() == offset && is_word_char(self.code[i - 1].1);
+                let line_start = offset == 0 || self.source.as_bytes()[offset - 1] == b'\n';
+                if (brackets.depth() == 0 || line_start)
+                    && !follows_word
+                    && ITEM_KEYWORDS.contains(&word)
+                    && !previous_word.is_some_and(|previous| ITEM_PREFIXES.contains(&previous))
+                {
+                    starts.push(offset);
+                    brackets = Brackets::default();
+                }
+                previous_word = Some(word);
+                i = end.max(i + 1);
+                continue;
+            }
+            brackets.step(c);
+            if !c.is_whitespace() {
+                previous_word = None;
+            }
+            i += 1;
+        }
+        // Anything before the first item is a range of its own, and fails to parse
+        if self.code.first().is_some_and(|&(offset, c)| starts.first() != Some(&offset) && !c.is_whitespace()) {
+            starts.insert(0, self.code[0].0);
+        }
+        let mut ends: Vec<usize> = starts.iter().skip(1).copied().collect();
+        ends.push(self.source.len());
+        starts.into_iter().zip(ends).map(|(start, end)| start..end).collect()
+    }
+
+    /// Parses the item in `range`, recovering inside a function body.
+    fn item(&mut self, range: std::ops::Range<usize>) -> Vec<ModuleItem> {
+        let range = self.trim(range);
+        if range.is_empty() {
+            return Vec::new();
+        }
+        let text = blank(self.source, &[(range.start, range.end)], "");
+        let error = match parse_entry(&text, Rule::item_entry) {
+            Ok(pair) => match build_module_items(pair) {
+                Ok(items) => return items,
+                Err(error) => error,
+            },
+            Err(error) => error,
+        };
+        if let Some(items) = self.function_item(range.clone()) {
+            return items;
+        }
+        trace!(target: "parser", ?range, %error, "item does not parse");
+        self.errors.push(error);
+        vec![ModuleItem::Error(Span { start: range.start, end: range.end })]
+    }
+
+    /// Recovers the function in `range` when its signature parses: its body
+    /// is parsed statement by statement, and anything after the body is an
+    /// item of its own. Returns `None` for other items.
+    fn function_item(&mut self, range: std::ops::Range<usize>) -> Option<Vec<ModuleItem>> {
+        let header = self.source[range.clone()].trim_start_matches("export").trim_start();
+        if !(header.starts_with("fn") || header.starts_with("async")) {
+            return None;
+        }
+        let code: Vec<(usize, char)> = self.code_in(range.clone()).to_vec();
+        let open = code.iter().position(|&(_, c)| c == '{')?;
+        let close = matching_bracket(&code, open);
+        let (open_offset, close_offset) = (code[open].0, close.map(|close| code[close].0));
+
+        // The signature with an empty body, keeping the body's braces in place
+        let text = match close_offset {
+            Some(close_offset) => blank(self.source, &[(range.start, open_offset + 1), (close_offset, close_offset + 1)], ""),
+            None => blank(self.source, &[(range.start, open_offset + 1)], "}"),
+        };
+        let mut items = build_module_items(parse_entry(&text, Rule::item_entry).ok()?).ok()?;
+        let errors_before = self.errors.len();
+        let body_end = close_offset.unwrap_or(range.end);
+        let stmts = self.stmts(open_offset + 1..body_end);
+        for item in &mut items {
+            if let ModuleItem::Function(func) = item {
+                func.body.stmts = stmts.clone();
+                func.body.span.end = func.body.span.end.min(range.end);
+                func.span.end = func.span.end.min(range.end);
+            }
+        }
+        if close_offset.is_none() {
+            self.errors.push(ParseError::UnclosedDelimiter { span: Span { start: open_offset, end: open_offset + 1 } });
+        }
+        if let Some(close_offset) = close_offset {
+            let rest = close_offset + 1..range.end;
+            if !self.trim(rest.clone()).is_empty() {
+                items.extend(self.item(rest));
+            }
+        }
+        if self.errors.len() == errors_before {
+            // The body parses statement by statement but not as a whole
+            return None;
+        }
+        Some(items)
+    }
+
+    /// Parses the statements of the block body in `range` one at a time.
+    fn stmts(&mut self, range: std::ops::Range<usize>) -> Vec<Stmt> {
+        let ranges = self.stmt_ranges(range);
+        let count = ranges.len();
+        let mut stmts = Vec::new();
+        for (i, range) in ranges.into_iter().enumerate() {
+            let text = blank(self.source, &[(range.start, range.end)], "");
+            // A statement that parses but does not build keeps its own error
+            let parsed = match parse_entry(&text, Rule::stmt_entry) {
+                Ok(pair) => build_stmt(pair),
+                Err(error) if i + 1 == count => {
+                    // The last statement may be the block's value
+                    let tail = parse_entry(&text, Rule::tail_entry)
+                        .and_then(|tail| build_expr(tail.into_inner().next().ok_or(ParseError::Unknown)?))
+                        .map(Stmt::TailExpr);
+                    tail.map_err(|tail_error| furthest(error, tail_error))
+                }
+                Err(error) => Err(error),
+            };
+            match parsed {
+                Ok(stmt) => stmts.push(stmt),
+                Err(error) => {
+                    trace!(target: "parser", ?range, %error, "statement does not parse");
+                    self.errors.push(error);
+                    stmts.push(Stmt::Error(Span { start: range.start, end: range.end }));
+                }
+            }
+        }
+        stmts
+    }
+
+    /// Splits a block body into the ranges of its statements: each ends at
+    /// a `;` outside braces, or at a closing `}` that nothing continues, as
+    /// `else` continues `if c { ... }`. A `;` can only be inside parentheses
+    /// or square brackets when they are never closed, so it ends those too.
+    fn stmt_ranges(&self, range: std::ops::Range<usize>) -> Vec<std::ops::Range<usize>> {
+        let code = self.code_in(range.clone());
+        let mut ranges = Vec::new();
+        let mut start = range.start;
+        let mut brackets = Brackets::default();
+        for (i, &(offset, c)) in code.iter().enumerate() {
+            brackets.step(c);
+            let end = match c {
+                '}' if brackets.depth() == 0 && !continues_statement(self.source, &code[i + 1..]) => offset + 1,
+                ';' if !brackets.0.contains(&'{') => {
+                    brackets = Brackets::default();
+                    offset + 1
+                }
+                _ => continue,
+            };
+            let stmt = self.trim(start..end);
+            if !stmt.is_empty() {
+                ranges.push(stmt);
+            }
+            start = end;
+        }
+        let last = self.trim(start..range.end);
+        if !last.is_empty() {
+            ranges.push(last);
+        }
+        ranges
+    }
+
+    /// The code characters within `range`.
+    fn code_in(&self, range: std::ops::Range<usize>) -> &[(usize, char)] {
+        let start = self.code.partition_point(|&(offset, _)| offset < range.start);
+        let end = self.code.partition_point(|&(offset, _)| offset < range.end);
+        &self.code[start..end]
+    }
+
+    /// Narrows `range` to its code, leaving out whitespace and comments
+    /// around it; empty when it holds none.
+    fn trim(&self, range: std::ops::Range<usize>) -> std::ops::Range<usize> {
+        let code = self.code_in(range.clone());
+        let mut significant = code.iter().filter(|(_, c)| !c.is_whitespace());
+        match (significant.next(), code.iter().rev().find(|(_, c)| !c.is_whitespace())) {
+            // A string's characters are left out of the code, so the range runs to the last code character's line end
+            (Some(&(start, _)), Some(&(last, c))) => {
+                let end = self.source[last + c.len_utf8()..range.end]
+                    .find('\n')
+                    .map_or(range.end, |newline| last + c.len_utf8() + newline);
+                start..self.source[..end].trim_end().len().max(last + c.len_utf8())
+            }
+            _ => range.start..range.start,
+        }
+    }
+}
+
+/// Parses `text` as the entry `rule`, returning the pair it wraps.
+fn parse_entry(text: &str, rule: Rule) -> Result<Pair<'_, Rule>, ParseError> {
+    let entry = HighRustParser::parse(rule, text)?.next().ok_or(ParseError::Unknown)?;
+    entry.into_inner().find(|pair| pair.as_rule() != Rule::EOI).ok_or(ParseError::Unknown)
+}
+
+/// Copies `source` up to the end of the last of the `keep` byte ranges, with
+/// everything outside them blanked to spaces, so a part of it parses alone
+/// at its own offsets, then appends `suffix`. Line breaks are kept so line
+/// numbers stay right.
+fn blank(source: &str, keep: &[(usize, usize)], suffix: &str) -> String {
+    let end = keep.iter().map(|&(_, end)| end).max().unwrap_or(0);
+    let mut text: String = source[..end]
+        .char_indices()
+        .map(|(offset, c)| {
+            if c == '\n' || keep.iter().any(|&(start, end)| (start..end).contains(&offset)) {
+                c.to_string()
+            } else {
+                " ".repeat(c.len_utf8())
+            }
+        })
+        .collect();
+    text.push_str(suffix);
+    text
+}
+
+/// The characters of `source` outside strings, character literals and
+/// comments, with their byte offsets; a string leaves its opening quote.
+fn code_chars(source: &str) -> Vec<(usize, char)> {
+    let mut code = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '"' => {
+                code.push((offset, c));
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek().is_some_and(|&(_, next)| next == '/') => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            '/' if chars.peek().is_some_and(|&(_, next)| next == '*') => {
+                chars.next();
+                let mut previous = ' ';
+                for (_, c) in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '\'' => {
+                // A character literal such as '{' or '\n'; otherwise a lifetime
+                let rest = &source[offset + 1..];
+                let literal = if let Some(escaped) = rest.strip_prefix('\\') {
+                    escaped.find('\'').map(|end| end + 2)
+                } else {
+                    rest.chars().next().filter(|_| rest.chars().nth(1) == Some('\'')).map(|c| c.len_utf8() + 1)
+                };
+                code.push((offset, c));
+                if let Some(length) = literal {
+                    while chars.next_if(|&(next, _)| next <= offset + length).is_some() {}
+                }
+            }
+            _ => code.push((offset, c)),
+        }
+    }
+    code
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Index of the bracket closing the one at `open` in `code`, if any.
+fn matching_bracket(code: &[(usize, char)], open: usize) -> Option<usize> {
+    let mut brackets = Brackets::default();
+    for (i, &(_, c)) in code.iter().enumerate().skip(open) {
+        brackets.step(c);
+        if brackets.depth() == 0 {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// The brackets open at a point of the source. A closing bracket closes
+/// the innermost bracket of its kind, and with it any left unclosed inside
+/// that one; a closing bracket nothing opened is ignored.
+#[derive(Default)]
+struct Brackets(Vec<char>);
+
+impl Brackets {
+    fn step(&mut self, c: char) {
+        let open = match c {
+            '{' | '(' | '[' => return self.0.push(c),
+            '}' => '{',
+            ')' => '(',
+            ']' => '[',
+            _ => return,
+        };
+        if let Some(index) = self.0.iter().rposition(|&bracket| bracket == open) {
+            self.0.truncate(index);
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Whether the code after a block's closing `}` continues its statement,
+/// as `else`, `catch`, a `;` or an operator do.
+fn continues_statement(source: &str, rest: &[(usize, char)]) -> bool {
+    let Some(&(offset, c)) = rest.iter().find(|(_, c)| !c.is_whitespace()) else {
+        return false;
+    };
+    if is_word_char(c) {
+        let word: String = source[offset..].chars().take_while(|&c| is_word_char(c)).collect();
+        return word == "else" || word == "catch";
+    }
+    ";.?,)]=+-*/%<>!&|".contains(c)
+}
+
+/// Of two errors for the same source, the one found further into it.
+fn furthest(first: ParseError, second: ParseError) -> ParseError {
+    let offset = |error: &ParseError| error.diagnostic().primary_label().map_or(0, |label| label.span.start);
+    if offset(&second) > offset(&first) {
+        second
+    } else {
+        first
+    }
 }
 
 /// Construct a Module from a Pest parse tree.
 fn build_module(pair: Pair<Rule>) -> Result<Module, ParseError> {
-    println!("Building module from rule: {:?}", pair.as_rule());
     let mut items = Vec::new();
     for inner in pair.into_inner() {
-        println!("Module child rule: {:?}", inner.as_rule());
         if inner.as_rule() == Rule::module {
-            println!("Found module rule");
-            // Extract function_def rules from the module
+            // Extract items from the module
             for module_item in inner.into_inner() {
-                println!("Module item rule: {:?}", module_item.as_rule());
-                if module_item.as_rule() == Rule::function_def {
-                    println!("Found function_def rule");
-                    items.push(ModuleItem::Function(build_function_def(module_item)?));
-                } else {
-                    println!("Ignoring module item rule: {:?}", module_item.as_rule());
-                }
+                items.extend(build_module_items(module_item)?);
             }
-        } else if inner.as_rule() == Rule::function_def {
-            println!("Found direct function_def rule");
-            items.push(ModuleItem::Function(build_function_def(inner)?));
         } else {
-            println!("Ignoring rule: {:?}", inner.as_rule());
+            items.extend(build_module_items(inner)?);
         }
     }
-    println!("Module has {} items", items.len());
+    debug!(target: "parser", items = items.len(), "built module");
     Ok(Module {
         items,
         span: Span { start: 0, end: 0 },
     })
 }
 
-/// Build a ModuleItem from a Pest pair.
-fn build_module_item(pair: Pair<Rule>) -> Result<Option<ModuleItem>, ParseError> {
-    println!("Building module item from rule: {:?}", pair.as_rule());
+/// Build the ModuleItems for a Pest pair; an exported definition yields the
+/// definition followed by its export.
+fn build_module_items(pair: Pair<Rule>) -> Result<Vec<ModuleItem>, ParseError> {
+    trace!(target: "parser", rule = ?pair.as_rule(), span = ?get_span(&pair), "building module item");
     match pair.as_rule() {
-        Rule::function_def => {
-            println!("Found function_def rule");
-            Ok(Some(ModuleItem::Function(build_function_def(pair)?)))
-        },
+        Rule::function_def => Ok(vec![ModuleItem::Function(build_function_def(pair)?)]),
+        Rule::data_def => Ok(vec![ModuleItem::Data(build_data_def(pair)?)]),
+        Rule::rust_function => Ok(vec![ModuleItem::Function(build_rust_function(pair)?)]),
+        Rule::rust_block => Ok(vec![ModuleItem::EmbeddedRust(build_embedded_rust(pair)?)]),
+        Rule::import_def => Ok(vec![ModuleItem::Import(build_import(pair)?)]),
+        Rule::export_def => build_export(pair),
+        _ => Ok(Vec::new()), // Only definitions, imports and exports are module items
+    }
+}
+
+/// Build an import of a path or of a list of names from a module.
+fn build_import(pair: Pair<Rule>) -> Result<Import, ParseError> {
+    // import_def = { "import" ~ (import_names ~ "from" ~ module_path | module_path ~ import_alias?) ~ ";"? }
+    let span = get_span(&pair);
+    let mut import = Import {
+        path: Vec::new(),
+        names: Vec::new(),
+        alias: None,
+        is_rust: false,
+        rust_file: None,
+        items: Vec::new(),
+        span,
+    };
+    let mut parts: Vec<Pair<Rule>> = pair.into_inner().collect();
+    // rust_import = { rust_keyword ~ (string_literal | module_path) ~ import_alias? }
+    if parts.first().is_some_and(|part| part.as_rule() == Rule::rust_import) {
+        import.is_rust = true;
+        parts = parts.remove(0).into_inner().filter(|part| part.as_rule() != Rule::rust_keyword).collect();
+    }
+    for part in parts {
+        match part.as_rule() {
+            Rule::string_literal => {
+                let file = part.as_str().trim_matches('"').to_string();
+                let stem = std::path::Path::new(&file).file_stem().and_then(|stem| stem.to_str()).unwrap_or_default().to_string();
+                import.path = vec![stem];
+                import.rust_file = Some(file);
+            }
+            Rule::module_path => import.path = part.as_str().split("::").map(str::to_string).collect(),
+            Rule::import_alias => import.alias = Some(build_alias(part)?),
+            Rule::import_names => {
+                for name in part.into_inner() {
+                    // Without an alias the name's span runs on over the whitespace after it
+                    let span = get_span(&name);
+                    let span = Span { start: span.start, end: span.start + name.as_str().trim_end().len() };
+                    let mut parts = name.into_inner();
+                    import.names.push(ImportName {
+                        name: parts.next().ok_or(ParseError::Unknown)?.as_str().to_string(),
+                        alias: parts.next().map(build_alias).transpose()?,
+                        span,
+                    });
+                }
+            }
+            rule => return Err(ParseError::UnexpectedRule(rule)),
+        }
+    }
+    Ok(import)
+}
+
+fn build_alias(pair: Pair<Rule>) -> Result<String, ParseError> {
+    // import_alias = { "as" ~ identifier }
+    Ok(pair.into_inner().next().ok_or(ParseError::Unknown)?.as_str().to_string())
+}
+
+/// Build the exports of an `export` item, preceded by the definition it
+/// exports, if any.
+fn build_export(pair: Pair<Rule>) -> Result<Vec<ModuleItem>, ParseError> {
+    // export_def = { "export" ~ (function_def | data_def | rust_function | export_names | identifier) ~ ";"? }
+    let inner = pair.into_inner().next().ok_or(ParseError::Unknown)?;
+    let export = |name: &str, span: Span| ModuleItem::Export(Export { name: name.to_string(), span });
+    match inner.as_rule() {
+        Rule::identifier => Ok(vec![export(inner.as_str(), get_span(&inner))]),
+        Rule::export_names => Ok(inner.into_inner().map(|name| export(name.as_str(), get_span(&name))).collect()),
         _ => {
-            println!("Ignoring rule: {:?}", pair.as_rule());
-            Ok(None) // Only function_def supported in MVP
+            let mut items = build_module_items(inner)?;
+            let exported: Vec<ModuleItem> = items
+                .iter()
+                .filter_map(|item| match item {
+                    ModuleItem::Function(func) => Some(export(&func.name, func.span.clone())),
+                    ModuleItem::Data(data) => Some(export(&data.name, data.span.clone())),
+                    _ => None,
+                })
+                .collect();
+            items.extend(exported);
+            Ok(items)
         }
     }
 }
 
+/// Build a struct-like DataDef from a Pest pair.
+fn build_data_def(pair: Pair<Rule>) -> Result<DataDef, ParseError> {
+    // data_def = { "data" ~ identifier ~ "=" ~ "{" ~ field_def* ~ "}" }
+    let span = get_span(&pair);
+    let mut inner = pair.into_inner();
+    let name = inner.next().ok_or(ParseError::Unknown)?.as_str().to_string();
+    let mut fields = Vec::new();
+    for field_pair in inner {
+        let field_span = get_span(&field_pair);
+        let mut parts = field_pair.into_inner();
+        let field_name = parts.next().ok_or(ParseError::Unknown)?.as_str().to_string();
+        let ty = build_type(parts.next().ok_or(ParseError::Unknown)?)?;
+        fields.push(Field { name: field_name, ty, span: field_span });
+    }
+    Ok(DataDef {
+        name,
+        kind: DataKind::Struct(fields),
+        generics: vec![],
+        span,
+    })
+}
+
 /// Build a FunctionDef from a Pest pair.
 fn build_function_def(pair: Pair<Rule>) -> Result<FunctionDef, ParseError> {
-    // function_def = { fn_keyword ~ function_name ~ function_params ~ block_expr }
+    // function_def = { async_keyword? ~ fn_keyword ~ function_name ~ function_params ~ return_type? ~ block_expr }
     let span = get_span(&pair);
-    println!("Function def span: {:?}", span);
-    println!("Function def text: {}", pair.as_str());
-    
     let mut inner = pair.into_inner();
-    println!("Function def inner count: {}", inner.clone().count());
-    
-    // Skip fn_keyword
+
+    // Skip fn_keyword, noting a preceding async_keyword
+    let is_async = inner.peek().is_some_and(|kw| kw.as_rule() == Rule::async_keyword);
+    if is_async {
+        inner.next();
+    }
     let _fn_kw = inner.next();
     
     // Get function_name
     let name_pair = inner.next().ok_or(ParseError::Unknown)?;
     let name_inner = name_pair.into_inner().next().ok_or(ParseError::Unknown)?;
     let name = name_inner.as_str().to_string();
-    println!("Parsed function name: {}", name);
+    trace!(target: "parser", name = %name, ?span, "parsed function");
     
     // Get function_params
     let params_pair = inner.next().ok_or(ParseError::Unknown)?;
@@ -101,27 +656,26 @@ fn build_function_def(pair: Pair<Rule>) -> Result<FunctionDef, ParseError> {
     let mut params = Vec::new();
     for param_pair in params_pair.into_inner() {
         if param_pair.as_rule() == Rule::param {
-            println!("Found param: {}", param_pair.as_str());
-            params.push(Param {
-                name: param_pair.as_str().to_string(),
-                ty: None,
-                span: get_span(&param_pair),
-            });
+            params.push(build_param(param_pair)?);
         }
     }
-    println!("Parsed {} parameters", params.len());
-    
-    // Get block_expr
-    let body_pair = inner.next().ok_or(ParseError::Unknown)?;
+
+    // Optional return type, then block_expr
+    let mut ret_type = None;
+    let mut body_pair = inner.next().ok_or(ParseError::Unknown)?;
+    if body_pair.as_rule() == Rule::return_type {
+        let ty_pair = body_pair.into_inner().next().ok_or(ParseError::Unknown)?;
+        ret_type = Some(build_type(ty_pair)?);
+        body_pair = inner.next().ok_or(ParseError::Unknown)?;
+    }
     let body = build_block(body_pair)?;
-    println!("Function body has {} statements", body.stmts.len());
-    
+
     Ok(FunctionDef {
         name,
         params,
-        ret_type: None,
+        ret_type,
         body,
-        is_async: false,
+        is_async,
         is_rust: false,
         span,
     })
@@ -132,101 +686,367 @@ fn build_block(pair: Pair<Rule>) -> Result<Block, ParseError> {
     let span = get_span(&pair);
     let mut stmts = Vec::new();
     for part in pair.into_inner() {
-        println!("Block child rule: {:?}", part.as_rule());
         match part.as_rule() {
-            Rule::stmt => {
-                println!("Found stmt rule");
-                stmts.push(build_stmt(part)?);
+            Rule::stmt => stmts.push(build_stmt(part)?),
+            Rule::tail_expr => {
+                let expr_pair = part.into_inner().next().ok_or(ParseError::Unknown)?;
+                stmts.push(Stmt::TailExpr(build_expr(expr_pair)?));
             },
-            _ => {
-                println!("Ignoring rule in block: {:?}", part.as_rule());
-            }
+            rule => trace!(target: "parser", ?rule, "ignoring rule in block"),
         }
     }
     Ok(Block { stmts, span })
 }
 
+/// Build a function parameter from a Pest pair.
+fn build_param(pair: Pair<Rule>) -> Result<Param, ParseError> {
+    // param = { identifier ~ (":" ~ type_expr)? }
+    let span = get_span(&pair);
+    let mut inner = pair.into_inner();
+    let name = inner.next().ok_or(ParseError::Unknown)?.as_str().to_string();
+    let ty = inner.next().map(build_type).transpose()?;
+    Ok(Param { name, ty, span })
+}
+
+/// Build a type annotation from a Pest pair.
+fn build_type(pair: Pair<Rule>) -> Result<Type, ParseError> {
+    match pair.as_rule() {
+        Rule::type_expr => {
+            let mut inner = pair.into_inner();
+            let mut ty = build_type(inner.next().ok_or(ParseError::Unknown)?)?;
+            for _nullable in inner {
+                ty = Type::Option(Box::new(ty));
+            }
+            Ok(ty)
+        }
+        Rule::ref_type => {
+            let inner = build_type(pair.into_inner().next().ok_or(ParseError::Unknown)?)?;
+            Ok(Type::Named("&".to_string(), vec![inner]))
+        }
+        Rule::tuple_type => Ok(Type::Tuple(pair.into_inner().map(build_type).collect::<Result<_, _>>()?)),
+        Rule::array_type => {
+            let inner = build_type(pair.into_inner().next().ok_or(ParseError::Unknown)?)?;
+            Ok(Type::Array(Box::new(inner)))
+        }
+        Rule::named_type => {
+            let mut inner = pair.into_inner();
+            let name = inner.next().ok_or(ParseError::Unknown)?.as_str().to_string();
+            let mut args = inner.map(build_type).collect::<Result<Vec<_>, _>>()?;
+            match (name.as_str(), args.len()) {
+                ("Option", 1) => Ok(Type::Option(Box::new(args.remove(0)))),
+                // `Result<T>` fails with the module's error type
+                ("Result", 1) => Ok(Type::Result(Box::new(args.remove(0)), Box::new(types::named(types::MODULE_ERROR)))),
+                ("Result", 2) => {
+                    let err = args.pop().ok_or(ParseError::Unknown)?;
+                    let ok = args.pop().ok_or(ParseError::Unknown)?;
+                    Ok(Type::Result(Box::new(ok), Box::new(err)))
+                }
+                _ => Ok(Type::Named(name, args)),
+            }
+        }
+        rule => Err(ParseError::UnexpectedRule(rule)),
+    }
+}
+
 /// Build a statement from a Pest pair.
 fn build_stmt(pair: Pair<Rule>) -> Result<Stmt, ParseError> {
-    // No need to capture span here as it's handled in expr_stmt
-    println!("Building stmt from rule: {:?}", pair.as_rule());
     let inner = pair.into_inner().next().ok_or(ParseError::Unknown)?;
-    println!("Stmt inner rule: {:?}", inner.as_rule());
+    let span = get_span(&inner);
     match inner.as_rule() {
         Rule::expr_stmt => {
-            println!("Found expr_stmt rule");
             let expr_pair = inner.into_inner().next().ok_or(ParseError::Unknown)?;
-            println!("Expr rule: {:?}", expr_pair.as_rule());
-            let expr = build_expr(expr_pair)?;
-            println!("Built expr: {:?}", expr);
-            Ok(Stmt::Expr(expr))
+            Ok(Stmt::Expr(build_expr(expr_pair)?))
         },
-        _ => {
-            println!("Unhandled stmt rule: {:?}", inner.as_rule());
-            Err(ParseError::UnexpectedRule(inner.as_rule()))
-        }
+        Rule::let_stmt => {
+            // let_stmt = { "let" ~ pattern ~ (":" ~ type_expr)? ~ "=" ~ expr ~ ";" }
+            let mut parts = inner.into_inner();
+            let pattern = build_pattern(parts.next().ok_or(ParseError::Unknown)?)?;
+            let mut ty = None;
+            let mut value_pair = parts.next().ok_or(ParseError::Unknown)?;
+            if value_pair.as_rule() == Rule::type_expr {
+                ty = Some(build_type(value_pair)?);
+                value_pair = parts.next().ok_or(ParseError::Unknown)?;
+            }
+            Ok(Stmt::Let {
+                pattern,
+                value: build_expr(value_pair)?,
+                ty,
+                span,
+            })
+        },
+        Rule::return_stmt => {
+            let value = inner.into_inner().next().map(build_expr).transpose()?;
+            Ok(Stmt::Return(value, span))
+        },
+        Rule::if_stmt => build_if_stmt(inner),
+        Rule::try_stmt => {
+            // try_stmt = { "try" ~ block_expr ~ catch_clause? }
+            let mut parts = inner.into_inner();
+            let block = build_block(parts.next().ok_or(ParseError::Unknown)?)?;
+            let catch = parts.next().map(build_catch_clause).transpose()?;
+            Ok(Stmt::Try { block, catch, span })
+        },
+        Rule::rust_block => Ok(Stmt::EmbeddedRust(build_embedded_rust(inner)?)),
+        rule => Err(ParseError::UnexpectedRule(rule)),
     }
 }
 
-/// Build an expression from a Pest pair.
-fn build_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+/// Build a `@rust` function. Its body is a single embedded Rust block holding
+/// the whole function, signature included, which is emitted verbatim; the
+/// signature is parsed so callers know the parameter and return types.
+fn build_rust_function(pair: Pair<Rule>) -> Result<FunctionDef, ParseError> {
+    // rust_function = ${ "@rust" ~ WHITESPACE+ ~ rust_signature ~ "{" ~ rust_code ~ "}" }
     let span = get_span(&pair);
-    println!("Building expr from rule: {:?}", pair.as_rule());
-    match pair.as_rule() {
-        Rule::expr => {
-            let inner = pair.into_inner().next().ok_or(ParseError::Unknown)?;
-            println!("Expr inner rule: {:?}", inner.as_rule());
-            build_expr(inner)
-        }
-        Rule::call_expr => {
-            println!("Found call_expr rule");
-            build_call_expr(pair)
-        },
-        Rule::string_literal => {
-            println!("Found string_literal rule: {}", pair.as_str());
-            // Remove the quotes from the string literal
-            let s = pair.as_str();
-            let content = &s[1..s.len()-1];
-            Ok(Expr::Literal(Literal::String(content.to_string()), span))
-        },
-        Rule::identifier => {
-            println!("Found identifier rule: {}", pair.as_str());
-            Ok(Expr::Variable(pair.as_str().to_string(), span))
+    let signature = pair.into_inner().next().ok_or(ParseError::Unknown)?;
+    let signature_span = get_span(&signature);
+    let sig = embedded::parse_signature(signature.as_str(), &signature_span)
+        .map_err(|(message, span)| ParseError::InvalidRust { message, span })?;
+    let code = EmbeddedRustBlock {
+        code: signature.get_input()[signature_span.start..span.end].to_string(),
+        span: Span {
+            start: signature_span.start,
+            end: span.end,
         },
-        Rule::match_expr => {
-            println!("Found match_expr rule");
-            build_match_expr(pair)
-        },
-        _ => {
-            println!("Unhandled expr rule: {:?}", pair.as_rule());
-            Err(ParseError::UnexpectedRule(pair.as_rule()))
+    };
+    Ok(FunctionDef {
+        name: sig.name,
+        params: sig.params,
+        ret_type: sig.ret_type,
+        body: Block {
+            stmts: vec![Stmt::EmbeddedRust(code)],
+            span: span.clone(),
         },
+        is_async: sig.is_async,
+        is_rust: true,
+        span,
+    })
+}
+
+/// Build an embedded Rust block; the span covers the code between the braces.
+fn build_embedded_rust(pair: Pair<Rule>) -> Result<EmbeddedRustBlock, ParseError> {
+    // rust_block = ${ "rust" ~ WHITESPACE* ~ "{" ~ rust_code ~ "}" }
+    let code = pair.into_inner().next().ok_or(ParseError::Unknown)?;
+    Ok(EmbeddedRustBlock {
+        code: code.as_str().to_string(),
+        span: get_span(&code),
+    })
+}
+
+/// Build a catch clause; a missing binding discards the error.
+fn build_catch_clause(pair: Pair<Rule>) -> Result<CatchClause, ParseError> {
+    // catch_clause = { "catch" ~ identifier? ~ block_expr }
+    let span = get_span(&pair);
+    let mut binding = "_".to_string();
+    let mut body = None;
+    for part in pair.into_inner() {
+        match part.as_rule() {
+            Rule::identifier => binding = part.as_str().to_string(),
+            _ => body = Some(build_block(part)?),
+        }
     }
+    Ok(CatchClause {
+        binding,
+        body: body.ok_or(ParseError::Unknown)?,
+        span,
+    })
 }
 
-/// Build a function call expression from a Pest pair.
-fn build_call_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+/// Build an `if` statement, turning `else if` chains into nested blocks.
+fn build_if_stmt(pair: Pair<Rule>) -> Result<Stmt, ParseError> {
+    // if_stmt = { "if" ~ expr ~ block_expr ~ ("else" ~ (if_stmt | block_expr))? }
     let span = get_span(&pair);
     let mut inner = pair.into_inner();
-    let func_name = inner.next().ok_or(ParseError::Unknown)?;
-    let func = Expr::Variable(func_name.as_str().to_string(), get_span(&func_name));
-    let mut args = Vec::new();
-    for arg in inner {
-        args.push(build_expr(arg)?);
-    }
-    Ok(Expr::Call {
-        func: Box::new(func),
-        args,
+    let cond = build_expr(inner.next().ok_or(ParseError::Unknown)?)?;
+    let then_branch = build_block(inner.next().ok_or(ParseError::Unknown)?)?;
+    let else_branch = match inner.next() {
+        Some(else_pair) if else_pair.as_rule() == Rule::if_stmt => {
+            let else_span = get_span(&else_pair);
+            Some(Block {
+                stmts: vec![build_if_stmt(else_pair)?],
+                span: else_span,
+            })
+        }
+        Some(else_pair) => Some(build_block(else_pair)?),
+        None => None,
+    };
+    Ok(Stmt::If {
+        cond,
+        then_branch,
+        else_branch,
         span,
     })
 }
 
+/// Operator precedence table, from loosest to tightest binding.
+fn pratt_parser() -> &'static PrattParser<Rule> {
+    static PRATT: OnceLock<PrattParser<Rule>> = OnceLock::new();
+    PRATT.get_or_init(|| {
+        PrattParser::new()
+            .op(Op::infix(Rule::coalesce_op, Assoc::Right))
+            .op(Op::infix(Rule::or_op, Assoc::Left))
+            .op(Op::infix(Rule::and_op, Assoc::Left))
+            .op(Op::infix(Rule::cmp_op, Assoc::Left))
+            .op(Op::infix(Rule::add_op, Assoc::Left))
+            .op(Op::infix(Rule::mul_op, Assoc::Left))
+            .op(Op::prefix(Rule::neg_op) | Op::prefix(Rule::not_op))
+            // `await f()?` awaits the call before propagating its error
+            .op(Op::postfix(Rule::try_op))
+            .op(Op::prefix(Rule::await_op))
+            .op(Op::postfix(Rule::call_args)
+                | Op::postfix(Rule::dot_await)
+                | Op::postfix(Rule::field_access)
+                | Op::postfix(Rule::optional_field))
+    })
+}
+
+/// Build an expression from a Pest pair.
+fn build_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    match pair.as_rule() {
+        Rule::expr => build_operator_expr(pair.into_inner()),
+        _ => build_primary(pair),
+    }
+}
+
+/// Build an expression from a sequence of operands and operators.
+///
+/// Binary and unary operators are encoded as calls to a variable named after
+/// the operator, e.g. `a + b` becomes `Call { func: Variable("+"), args: [a, b] }`.
+fn build_operator_expr(pairs: Pairs<Rule>) -> Result<Expr, ParseError> {
+    pratt_parser()
+        .map_primary(build_primary)
+        .map_prefix(|op, operand| {
+            let operand = operand?;
+            let span = Span { start: op.as_span().start(), end: operand.span().end };
+            match (op.as_rule(), operand) {
+                (Rule::await_op, operand) => Ok(Expr::Await { expr: Box::new(operand), span }),
+                // Fold negation into numeric literals
+                (Rule::neg_op, Expr::Literal(Literal::Int(value), _)) => Ok(Expr::Literal(Literal::Int(-value), span)),
+                (Rule::neg_op, Expr::Literal(Literal::Float(value), _)) => Ok(Expr::Literal(Literal::Float(-value), span)),
+                (_, operand) => Ok(Expr::Call {
+                    func: Box::new(Expr::Variable(op.as_str().to_string(), get_span(&op))),
+                    args: vec![operand],
+                    span,
+                }),
+            }
+        })
+        .map_postfix(|operand, op| {
+            let operand = operand?;
+            let span = Span { start: operand.span().start, end: op.as_span().end() };
+            match op.as_rule() {
+                Rule::call_args => Ok(Expr::Call {
+                    func: Box::new(operand),
+                    args: op.into_inner().map(build_expr).collect::<Result<_, _>>()?,
+                    span,
+                }),
+                Rule::field_access => Ok(Expr::FieldAccess {
+                    base: Box::new(operand),
+                    field: op.into_inner().next().ok_or(ParseError::Unknown)?.as_str().to_string(),
+                    span,
+                }),
+                Rule::dot_await => Ok(Expr::Await { expr: Box::new(operand), span }),
+                Rule::try_op => Ok(Expr::Try(Box::new(operand), span)),
+                Rule::optional_field => Ok(Expr::OptionalChain {
+                    base: Box::new(operand),
+                    field: op.into_inner().next().ok_or(ParseError::Unknown)?.as_str().to_string(),
+                    span,
+                }),
+                rule => Err(ParseError::UnexpectedRule(rule)),
+            }
+        })
+        .map_infix(|lhs, op, rhs| {
+            let (lhs, rhs) = (lhs?, rhs?);
+            let span = Span { start: lhs.span().start, end: rhs.span().end };
+            Ok(Expr::Call {
+                func: Box::new(Expr::Variable(op.as_str().to_string(), get_span(&op))),
+                args: vec![lhs, rhs],
+                span,
+            })
+        })
+        .parse(pairs)
+}
+
+/// Build an operand (literal, variable, match, struct literal, block or parenthesised expression).
+fn build_primary(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    let span = get_span(&pair);
+    match pair.as_rule() {
+        Rule::expr => build_expr(pair),
+        Rule::string_literal | Rule::int_literal | Rule::float_literal | Rule::bool_literal | Rule::null_literal => {
+            Ok(Expr::Literal(build_literal(&pair)?, span))
+        }
+        Rule::identifier | Rule::path_expr => Ok(Expr::Variable(pair.as_str().to_string(), span)),
+        Rule::match_expr => build_match_expr(pair),
+        Rule::comprehension => build_comprehension(pair),
+        Rule::struct_literal => build_struct_literal(pair),
+        Rule::block_expr => Ok(Expr::Block(build_block(pair)?)),
+        rule => Err(ParseError::UnexpectedRule(rule)),
+    }
+}
+
+/// Build a struct literal from its name and field initializers.
+fn build_struct_literal(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    // struct_literal = { struct_name ~ "{" ~ (field_init ~ ("," ~ field_init)* ~ ","?)? ~ "}" }
+    let span = get_span(&pair);
+    let mut inner = pair.into_inner();
+    let name = inner.next().ok_or(ParseError::Unknown)?.as_str().to_string();
+    let mut fields = Vec::new();
+    for field in inner {
+        let mut parts = field.into_inner();
+        let field_name = parts.next().ok_or(ParseError::Unknown)?.as_str().to_string();
+        fields.push((field_name, build_expr(parts.next().ok_or(ParseError::Unknown)?)?));
+    }
+    Ok(Expr::Struct { name, fields, span })
+}
+
+/// Build a literal value from a Pest pair.
+fn build_literal(pair: &Pair<Rule>) -> Result<Literal, ParseError> {
+    let text = pair.as_str();
+    match pair.as_rule() {
+        // Remove the quotes from the string literal
+        Rule::string_literal => Ok(Literal::String(text[1..text.len() - 1].to_string())),
+        // Whether an integer fits its type is checked once the type is known
+        Rule::int_literal => text.parse().map(Literal::Int).map_err(|_| ParseError::InvalidLiteral {
+            message: format!("integer literal `{}` is too large", text),
+            span: get_span(pair),
+        }),
+        Rule::float_literal => text.parse().map(Literal::Float).map_err(|_| ParseError::InvalidLiteral {
+            message: format!("invalid float literal `{}`", text),
+            span: get_span(pair),
+        }),
+        Rule::bool_literal => Ok(Literal::Bool(text == "true")),
+        Rule::null_literal => Ok(Literal::Null),
+        rule => Err(ParseError::UnexpectedRule(rule)),
+    }
+}
+
+/// Build a list comprehension from its body and clauses.
+fn build_comprehension(pair: Pair<Rule>) -> Result<Expr, ParseError> {
+    // comprehension = { "[" ~ expr ~ comp_for ~ (comp_for | comp_if)* ~ "]" }
+    let span = get_span(&pair);
+    let mut inner = pair.into_inner();
+    let body = build_expr(inner.next().ok_or(ParseError::Unknown)?)?;
+    let mut clauses = Vec::new();
+    for clause in inner {
+        let clause_span = get_span(&clause);
+        let rule = clause.as_rule();
+        let mut parts = clause.into_inner();
+        match rule {
+            Rule::comp_for => {
+                let pattern = build_pattern(parts.next().ok_or(ParseError::Unknown)?)?;
+                let iterable = build_expr(parts.next().ok_or(ParseError::Unknown)?)?;
+                clauses.push(ComprehensionClause::For { pattern, iterable, span: clause_span });
+            }
+            Rule::comp_if => clauses.push(ComprehensionClause::If(build_expr(parts.next().ok_or(ParseError::Unknown)?)?)),
+            rule => return Err(ParseError::UnexpectedRule(rule)),
+        }
+    }
+    Ok(Expr::Comprehension { body: Box::new(body), clauses, span })
+}
+
 /// Build a match expression from a Pest pair.
 fn build_match_expr(pair: Pair<Rule>) -> Result<Expr, ParseError> {
     let span = get_span(&pair);
     let mut inner = pair.into_inner();
     // match_expr = { "match" ~ expr ~ "{" ~ match_arm* ~ "}" }
-    let _match_kw = inner.next(); // skip 'match' keyword
     let matched_expr = build_expr(inner.next().ok_or(ParseError::Unknown)?)?;
     let mut arms = Vec::new();
     for arm_pair in inner {
@@ -275,10 +1095,20 @@ fn build_pattern(pair: Pair<Rule>) -> Result<Pattern, ParseError> {
     match pair.as_rule() {
         Rule::wildcard_pattern => Ok(Pattern::Wildcard(span)),
         Rule::identifier => Ok(Pattern::Variable(pair.as_str().to_string(), span)),
-        Rule::string_literal => {
-            let s = pair.as_str();
-            let content = s[1..s.len()-1].to_string();
-            Ok(Pattern::Literal(Literal::String(content), span))
+        Rule::string_literal => Ok(Pattern::Literal(build_literal(&pair)?, span)),
+        Rule::literal_pattern => {
+            let mut inner = pair.into_inner();
+            let mut lit_pair = inner.next().ok_or(ParseError::Unknown)?;
+            let negated = lit_pair.as_rule() == Rule::neg_op;
+            if negated {
+                lit_pair = inner.next().ok_or(ParseError::Unknown)?;
+            }
+            let lit = match (build_literal(&lit_pair)?, negated) {
+                (Literal::Int(value), true) => Literal::Int(-value),
+                (Literal::Float(value), true) => Literal::Float(-value),
+                (lit, _) => lit,
+            };
+            Ok(Pattern::Literal(lit, span))
         },
         Rule::tuple_pattern => {
             let mut elements = Vec::new();
@@ -315,6 +1145,67 @@ impl fmt::Display for ParseError {
             ParseError::UnexpectedRule(rule) => write!(f, "Parse error: Unexpected rule: {:?}", rule),
             ParseError::Unknown => write!(f, "Parse error: Unknown parse error"),
             ParseError::Custom(msg) => write!(f, "Parse error: {}", msg),
+            ParseError::InvalidLiteral { message, .. } => write!(f, "Parse error: {}", message),
+            ParseError::InvalidRust { message, span } => {
+                write!(f, "Parse error: invalid Rust at {}..{}: {}", span.start, span.end, message)
+            }
+            ParseError::UnclosedDelimiter { span } => write!(f, "Parse error: unclosed `{{` at {}", span.start),
+            ParseError::Lowering(error) => write!(f, "lowering error: {}", error),
+            ParseError::Multiple(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// Describes the error as diagnostics, one for each error.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ParseError::Lowering(error) => error.diagnostics(),
+            ParseError::Multiple(errors) => errors.iter().flat_map(ParseError::diagnostics).collect(),
+            error => vec![error.diagnostic()],
+        }
+    }
+
+    /// Describes the error as a diagnostic with the offending span; of
+    /// several errors, the first.
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            ParseError::PestError(error) => {
+                let span = match error.location {
+                    InputLocation::Pos(pos) => Span { start: pos, end: pos },
+                    InputLocation::Span((start, end)) => Span { start, end },
+                };
+                Diagnostic::error("E0001", "syntax error").with_label(span, error.variant.message())
+            }
+            ParseError::UnexpectedRule(rule) => {
+                Diagnostic::error("E0002", format!("unexpected {:?} in the parse tree", rule))
+                    .with_note("the grammar accepted input the parser cannot build; this is a transpiler bug")
+            }
+            ParseError::Unknown => Diagnostic::error("E0002", "unknown parse error"),
+            ParseError::InvalidRust { message, span } => Diagnostic::error("E0003", "invalid Rust in a `@rust` function")
+                .with_label(span.clone(), message.clone()),
+            ParseError::Custom(message) => Diagnostic::error("E0004", message.clone()),
+            ParseError::InvalidLiteral { message, span } => {
+                Diagnostic::error("E0004", message.clone()).with_label(span.clone(), "cannot be represented")
+            }
+            ParseError::UnclosedDelimiter { span } => {
+                Diagnostic::error("E0005", "unclosed delimiter").with_label(span.clone(), "this `{` is never closed")
+            }
+            ParseError::Lowering(error) => error.diagnostic(),
+            ParseError::Multiple(errors) => match errors.first() {
+                Some(error) => error.diagnostic(),
+                None => Diagnostic::error("E0002", "unknown parse error"),
+            },
         }
     }
 }
@@ -0,0 +1,321 @@
+//! Multi-file HighRust projects.
+//!
+//! A project is every `.hrs` file under a source root. Loading a project
+//! parses each file, resolves its imports, and orders the modules so each
+//! comes after the modules it imports; imports of project modules carry the
+//! signatures of the functions and data types they bring into scope, so
+//! type and borrow inference see through module boundaries. Import cycles
+//! are rejected.
+
+use crate::ast::{Import, Module, ModuleItem, Span};
+use crate::modules::{self, ModuleError, ModuleResolver, SOURCE_EXTENSION};
+use crate::parse_source;
+use crate::parser::ParseError;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// A parsed source file of a project.
+#[derive(Debug, Clone)]
+pub struct ProjectModule {
+    /// The `.hrs` file, under the project root
+    pub file: PathBuf,
+    /// Module path from the crate root; empty for the crate root itself
+    pub path: Vec<String>,
+    /// Contents of the file
+    pub source: String,
+    pub ast: Module,
+    /// Module paths of the project modules this module imports
+    pub dependencies: Vec<Vec<String>>,
+    /// Rust files this module imports items from, with their contents
+    pub rust_files: Vec<(PathBuf, String)>,
+}
+
+/// The modules of a project and the imports between them.
+#[derive(Debug, Clone)]
+pub struct ModuleGraph {
+    resolver: ModuleResolver,
+    /// Modules in dependency order: each after the modules it imports
+    modules: Vec<ProjectModule>,
+}
+
+impl ModuleGraph {
+    /// Loads every `.hrs` file under `root`.
+    pub fn load(root: impl AsRef<Path>) -> Result<Self, ModuleError> {
+        Self::load_with(root, |file, source| {
+            info!(target: "parser", file = %file.display(), "parsing");
+            parse_source(source)
+        })
+    }
+
+    /// Loads every `.hrs` file under `root`, getting the AST of each from
+    /// `parse` given the file and its contents, so that ASTs of files that
+    /// have not changed can be reused.
+    pub fn load_with(
+        root: impl AsRef<Path>,
+        mut parse: impl FnMut(&Path, &str) -> Result<Module, ParseError>,
+    ) -> Result<Self, ModuleError> {
+        let resolver = ModuleResolver::new(root.as_ref());
+        let mut modules = Vec::new();
+        for file in source_files(resolver.root())? {
+            let source = std::fs::read_to_string(&file).map_err(|error| ModuleError::Io { path: file.clone(), error })?;
+            let mut ast = parse(&file, &source).map_err(|e| ModuleError::Parse { path: file.clone(), error: e })?;
+            let rust_files = resolver
+                .resolve_imports(&mut ast, &file)?
+                .into_iter()
+                .map(|path| match std::fs::read_to_string(&path) {
+                    Ok(contents) => Ok((path, contents)),
+                    Err(error) => Err(ModuleError::Io { path, error }),
+                })
+                .collect::<Result<_, _>>()?;
+            let path = resolver.module_path(&file);
+            modules.push(ProjectModule { path, file, source, ast, dependencies: Vec::new(), rust_files });
+        }
+
+        let paths: HashSet<Vec<String>> = modules.iter().map(|module| module.path.clone()).collect();
+        for module in &mut modules {
+            module.dependencies = module
+                .ast
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    ModuleItem::Import(import) if !import.is_rust => imported_module(&import.path, &paths),
+                    _ => None,
+                })
+                .filter(|dependency| *dependency != module.path)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+        }
+
+        let mut graph = ModuleGraph { resolver, modules: dependency_order(modules)? };
+        graph.link_imports()?;
+        Ok(graph)
+    }
+
+    /// The project's source root.
+    pub fn root(&self) -> &Path {
+        self.resolver.root()
+    }
+
+    /// Modules in dependency order.
+    pub fn modules(&self) -> &[ProjectModule] {
+        &self.modules
+    }
+
+    /// Returns the module with the given path.
+    pub fn module(&self, path: &[String]) -> Option<&ProjectModule> {
+        self.modules.iter().find(|module| module.path == path)
+    }
+
+    /// Every module path of the project, including directories of modules
+    /// that have no source file of their own.
+    pub fn module_paths(&self) -> BTreeSet<Vec<String>> {
+        self.modules
+            .iter()
+            .flat_map(|module| (0..=module.path.len()).map(|len| module.path[..len].to_vec()))
+            .collect()
+    }
+
+    /// Paths of the modules importing the module at `path`, directly or
+    /// through other modules, in dependency order.
+    pub fn dependents(&self, path: &[String]) -> Vec<Vec<String>> {
+        let mut found = vec![path.to_vec()];
+        // Each module comes after the modules it imports, so one pass finds them all
+        for module in &self.modules {
+            if !found.contains(&module.path) && module.dependencies.iter().any(|dependency| found.contains(dependency)) {
+                found.push(module.path.clone());
+            }
+        }
+        found.split_off(1)
+    }
+
+    /// Names of the modules declared directly inside the module at `path`.
+    pub fn children(&self, path: &[String]) -> Vec<String> {
+        self.module_paths()
+            .into_iter()
+            .filter(|child| child.len() == path.len() + 1 && child.starts_with(path))
+            .filter_map(|child| child.last().cloned())
+            .collect()
+    }
+
+    /// Gives every import of a project module the items it brings into
+    /// scope. Modules are visited in dependency order, so re-exported imports
+    /// are already linked when a later module imports them.
+    fn link_imports(&mut self) -> Result<(), ModuleError> {
+        let paths: HashSet<Vec<String>> = self.module_paths().into_iter().collect();
+        for index in 0..self.modules.len() {
+            let (done, rest) = self.modules.split_at_mut(index);
+            let module = &mut rest[0];
+            for item in &mut module.ast.items {
+                let ModuleItem::Import(import) = item else { continue };
+                if import.is_rust {
+                    continue;
+                }
+                let Some(target) = imported_module(&import.path, &paths) else { continue };
+                let exported = done.iter().find(|m| m.path == target).map(|m| exported_items(&m.ast)).unwrap_or_default();
+                import.items = link_import(import, &target, &exported, &paths, &module.file)?;
+                // A child module is already in scope through its `mod` declaration
+                let is_child = target.len() == module.path.len() + 1 && target.starts_with(&module.path);
+                if is_child && import.path.len() == target.len() + 1 && import.names.is_empty() && import.alias.is_none() {
+                    import.path.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds the `.hrs` files under `dir`, skipping hidden directories and
+/// `target`, in a stable order.
+fn source_files(dir: &Path) -> Result<Vec<PathBuf>, ModuleError> {
+    let entries = std::fs::read_dir(dir).map_err(|error| ModuleError::Io { path: dir.to_path_buf(), error })?;
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    let mut files = Vec::new();
+    for path in paths {
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                files.extend(source_files(&path)?);
+            }
+        } else if path.extension().is_some_and(|ext| ext == SOURCE_EXTENSION) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Returns the project module an import path leads into: the longest prefix
+/// of the `crate::` path that names a module.
+fn imported_module(path: &[String], modules: &HashSet<Vec<String>>) -> Option<Vec<String>> {
+    let path = path.strip_prefix(&["crate".to_string()])?;
+    (1..=path.len()).rev().map(|len| path[..len].to_vec()).find(|prefix| modules.contains(prefix))
+}
+
+/// The functions and data types a module exports, by exported name.
+fn exported_items(module: &Module) -> HashMap<String, ModuleItem> {
+    let exports: HashSet<&String> = module
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::Export(export) => Some(&export.name),
+            _ => None,
+        })
+        .collect();
+    let imported = module.items.iter().flat_map(|item| match item {
+        ModuleItem::Import(import) => import.items.iter(),
+        _ => [].iter(),
+    });
+    module
+        .items
+        .iter()
+        .chain(imported)
+        .filter_map(|item| {
+            let name = modules::item_name(item)?;
+            exports.contains(&name.to_string()).then(|| (name.to_string(), item.clone()))
+        })
+        .collect()
+}
+
+/// Returns the items `import` brings into scope from the project module
+/// `target`, checking that each imported name is exported by it or names
+/// one of its submodules.
+fn link_import(
+    import: &Import,
+    target: &[String],
+    exported: &HashMap<String, ModuleItem>,
+    modules: &HashSet<Vec<String>>,
+    file: &Path,
+) -> Result<Vec<ModuleItem>, ModuleError> {
+    let rest = &import.path[target.len() + 1..];
+    let lookup = |name: &String, span: &Span| -> Result<Option<ModuleItem>, ModuleError> {
+        if let Some(item) = exported.get(name) {
+            return Ok(Some(item.clone()));
+        }
+        let mut submodule = target.to_vec();
+        submodule.push(name.clone());
+        if modules.contains(&submodule) {
+            return Ok(None);
+        }
+        Err(ModuleError::UnresolvedImport {
+            path: file.to_path_buf(),
+            module: format!("crate::{}", target.join("::")),
+            name: name.clone(),
+            span: span.clone(),
+        })
+    };
+    match rest {
+        [] if import.names.is_empty() => {
+            let module_name = import.alias.as_ref().or(target.last()).cloned().unwrap_or_default();
+            let items = exported.values().cloned().collect();
+            Ok(modules::qualify(items, &module_name))
+        }
+        [] => {
+            let mut items = Vec::new();
+            for name in &import.names {
+                if let Some(item) = lookup(&name.name, &name.span)? {
+                    items.push(modules::rename(item, name.alias.as_ref().unwrap_or(&name.name)));
+                }
+            }
+            Ok(items)
+        }
+        [name] if import.names.is_empty() => {
+            Ok(lookup(name, &import.span)?.map(|item| modules::rename(item, import.alias.as_ref().unwrap_or(name))).into_iter().collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Orders `modules` so each comes after the modules it imports, or reports
+/// the first import cycle found.
+fn dependency_order(modules: Vec<ProjectModule>) -> Result<Vec<ProjectModule>, ModuleError> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Unvisited,
+        InProgress,
+        Done,
+    }
+
+    fn visit(
+        index: usize,
+        modules: &[ProjectModule],
+        marks: &mut [Mark],
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), ModuleError> {
+        match marks[index] {
+            Mark::Done => return Ok(()),
+            Mark::InProgress => {
+                let start = stack.iter().position(|&i| i == index).unwrap_or(0);
+                let cycle = stack[start..].iter().chain([&index]).map(|&i| display_path(&modules[i].path)).collect();
+                return Err(ModuleError::Cycle { modules: cycle });
+            }
+            Mark::Unvisited => {}
+        }
+        marks[index] = Mark::InProgress;
+        stack.push(index);
+        for dependency in &modules[index].dependencies {
+            if let Some(next) = modules.iter().position(|module| &module.path == dependency) {
+                visit(next, modules, marks, stack, order)?;
+            }
+        }
+        stack.pop();
+        marks[index] = Mark::Done;
+        order.push(index);
+        Ok(())
+    }
+
+    let mut marks = vec![Mark::Unvisited; modules.len()];
+    let mut order = Vec::new();
+    for index in 0..modules.len() {
+        visit(index, &modules, &mut marks, &mut Vec::new(), &mut order)?;
+    }
+    let mut modules: Vec<Option<ProjectModule>> = modules.into_iter().map(Some).collect();
+    Ok(order.into_iter().filter_map(|index| modules[index].take()).collect())
+}
+
+/// Writes a module path the way Rust code refers to it.
+pub fn display_path(path: &[String]) -> String {
+    std::iter::once("crate").chain(path.iter().map(String::as_str)).collect::<Vec<_>>().join("::")
+}
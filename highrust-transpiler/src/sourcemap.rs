@@ -0,0 +1,165 @@
+//! Source maps from generated Rust back to HighRust sources.
+//!
+//! Code generation records where the Rust for each function, data type and
+//! statement starts, and where the HighRust it came from starts. The map is
+//! written next to the generated file as `<file>.rs.map` JSON, so errors
+//! reported against the Rust can be shown against the HighRust.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "file": "main.rs",
+//!   "source": "/project/src/main.hrs",
+//!   "mappings": [
+//!     { "generated": { "line": 1, "column": 1 }, "source": { "line": 1, "column": 1 } }
+//!   ]
+//! }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Version of the source map format.
+pub const VERSION: u32 = 1;
+
+/// One-based line and column in a file; columns count characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Position { line, column }
+    }
+}
+
+/// Generated code starting at `generated` comes from the source at `source`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mapping {
+    pub generated: Position,
+    pub source: Position,
+}
+
+/// Maps positions in a generated Rust file to its HighRust source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceMap {
+    pub version: u32,
+    /// Name of the generated file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Path of the HighRust source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    /// Mappings in the order of their generated positions
+    pub mappings: Vec<Mapping>,
+}
+
+impl Default for SourceMap {
+    fn default() -> Self {
+        SourceMap { version: VERSION, file: None, source: None, mappings: Vec::new() }
+    }
+}
+
+impl SourceMap {
+    /// Records that the code at `generated` comes from `source`.
+    pub fn add(&mut self, generated: Position, source: Position) {
+        self.mappings.push(Mapping { generated, source });
+    }
+
+    /// Names the generated file and the HighRust source it comes from. The
+    /// source is made absolute, so the map can be read from anywhere.
+    pub fn set_files(&mut self, generated: &Path, source: &Path) {
+        self.file = generated.file_name().map(|name| name.to_string_lossy().into_owned());
+        self.source = Some(std::path::absolute(source).unwrap_or_else(|_| source.to_path_buf()));
+    }
+
+    /// Returns the source position of the code at `generated`: the source of
+    /// the closest mapping at or before it.
+    pub fn lookup(&self, generated: Position) -> Option<Position> {
+        self.mapping_at(generated).map(|mapping| mapping.source)
+    }
+
+    /// Returns the closest mapping at or before `generated`.
+    pub fn mapping_at(&self, generated: Position) -> Option<&Mapping> {
+        let index = self.mappings.partition_point(|mapping| mapping.generated <= generated);
+        index.checked_sub(1).map(|index| &self.mappings[index])
+    }
+
+    /// Moves every mapping down by `lines`, after that many lines were
+    /// inserted at the top of the generated file.
+    pub fn shift(&mut self, lines: usize) {
+        for mapping in &mut self.mappings {
+            mapping.generated.line += lines;
+        }
+    }
+
+    /// Serializes the map as JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("source maps always serialize") + "\n"
+    }
+
+    /// Parses a map written by [`SourceMap::to_json`].
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Reads the map written alongside the generated file `generated`.
+    pub fn read_for(generated: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(map_file(generated))?;
+        Self::from_json(&json).map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+}
+
+/// Path of the source map for the generated file `generated`, e.g.
+/// `main.rs.map` for `main.rs`.
+pub fn map_file(generated: &Path) -> PathBuf {
+    let mut name = generated.as_os_str().to_owned();
+    name.push(".map");
+    PathBuf::from(name)
+}
+
+/// Converts byte offsets in a source text to lines and columns.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    text: String,
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        LineIndex { text: text.to_string(), line_starts }
+    }
+
+    /// Position of byte `offset`; offsets past the end are clamped to it.
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let start = self.line_starts[line - 1];
+        let column = self.text.get(start..offset).map_or(offset - start, |prefix| prefix.chars().count());
+        Position::new(line, column + 1)
+    }
+
+    /// Byte offset of `position`, or `None` if it lies past the end of its
+    /// line or the text.
+    pub fn offset(&self, position: Position) -> Option<usize> {
+        let start = *self.line_starts.get(position.line.checked_sub(1)?)?;
+        let line = self.line(position.line)?;
+        let column = position.column.checked_sub(1)?;
+        match line.char_indices().nth(column) {
+            Some((i, _)) => Some(start + i),
+            None if column == line.chars().count() => Some(start + line.len()),
+            None => None,
+        }
+    }
+
+    /// Text of the one-based `line`, without its line break.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self.line_starts.get(line).map_or(self.text.len(), |&next| next - 1);
+        Some(self.text[start..end].trim_end_matches('\r'))
+    }
+}
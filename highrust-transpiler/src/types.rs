@@ -0,0 +1,602 @@
+//! Local type inference for HighRust.
+//!
+//! This module provides a lightweight, scope-aware type environment used by the
+//! lowering phase to decide where implicit conversions are required. Types are
+//! inferred bottom-up from literals, annotations, function signatures and data
+//! definitions; anything that cannot be determined is reported as `None` and
+//! left untouched by later passes.
+
+use crate::ast::{ComprehensionClause, DataKind, Expr, Field, Literal, Module, ModuleItem, Pattern, Type};
+use std::collections::HashMap;
+
+/// Signature of a function known to the type environment.
+#[derive(Debug, Clone)]
+pub struct FunctionSig {
+    /// Declared parameter types, in order.
+    pub params: Vec<Option<Type>>,
+    /// Declared return type, if any.
+    pub ret: Option<Type>,
+    /// Whether calls return a future that must be awaited for `ret`.
+    pub is_async: bool,
+    /// Whether the function is async only because it awaits, rather than
+    /// declared `async`.
+    pub is_inferred_async: bool,
+}
+
+/// Scoped mapping from names to their inferred or declared types.
+#[derive(Debug, Clone)]
+pub struct TypeEnv {
+    /// Stack of lexical scopes, innermost last
+    scopes: Vec<HashMap<String, Binding>>,
+    /// Signatures of module-level functions
+    functions: HashMap<String, FunctionSig>,
+    /// Fields of module-level struct definitions
+    structs: HashMap<String, Vec<Field>>,
+}
+
+/// What is known about a variable's type.
+#[derive(Debug, Clone)]
+enum Binding {
+    Typed(Type),
+    /// A value `?` unwrapped, known not to be an `Option` or `Result`
+    Unwrapped,
+    Unknown,
+}
+
+impl Default for TypeEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeEnv {
+    /// Creates an empty type environment with a single root scope.
+    pub fn new() -> Self {
+        TypeEnv {
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+        }
+    }
+
+    /// Creates a type environment seeded with the signatures and data
+    /// definitions declared in `module`, including those read from imported
+    /// Rust sources.
+    pub fn from_module(module: &Module) -> Self {
+        let mut env = Self::new();
+        for item in &module.items {
+            match item {
+                ModuleItem::Import(import) => import.items.iter().for_each(|item| env.declare(item)),
+                item => env.declare(item),
+            }
+        }
+        env
+    }
+
+    /// Records the signature or fields of a module-level item.
+    fn declare(&mut self, item: &ModuleItem) {
+        match item {
+            ModuleItem::Function(func) => {
+                self.functions.insert(func.name.clone(), FunctionSig {
+                    params: func.params.iter().map(|p| p.ty.clone()).collect(),
+                    ret: func.ret_type.clone(),
+                    is_async: func.lowers_async(),
+                    is_inferred_async: !func.is_async && func.body.contains_await(),
+                });
+            }
+            ModuleItem::Data(data) => {
+                if let DataKind::Struct(fields) = &data.kind {
+                    self.structs.insert(data.name.clone(), fields.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Enters a new lexical scope.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Leaves the innermost lexical scope.
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Binds `name` to `ty` in the innermost scope.
+    pub fn bind(&mut self, name: &str, ty: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Binding::Typed(ty));
+        }
+    }
+
+    /// Binds `name` in the innermost scope to a value `?` unwrapped, of
+    /// unknown type but known not to be an `Option` or `Result`.
+    pub fn bind_unwrapped(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Binding::Unwrapped);
+        }
+    }
+
+    /// Binds `name` in the innermost scope to a value of unknown type,
+    /// shadowing what was known of the name before.
+    pub fn bind_unknown(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Binding::Unknown);
+        }
+    }
+
+    /// Looks up the type of a variable, searching outward through scopes.
+    pub fn lookup(&self, name: &str) -> Option<&Type> {
+        match self.binding(name)? {
+            Binding::Typed(ty) => Some(ty),
+            _ => None,
+        }
+    }
+
+    /// Returns true if `name` is bound to a value `?` unwrapped.
+    pub fn is_unwrapped(&self, name: &str) -> bool {
+        matches!(self.binding(name), Some(Binding::Unwrapped))
+    }
+
+    /// The innermost binding of `name`.
+    fn binding(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Returns the signature of a module-level function.
+    pub fn function(&self, name: &str) -> Option<&FunctionSig> {
+        self.functions.get(name)
+    }
+
+    /// Returns the declared type of `field` on the struct `name`.
+    pub fn field_type(&self, name: &str, field: &str) -> Option<&Type> {
+        self.structs
+            .get(name)?
+            .iter()
+            .find(|f| f.name == field)
+            .map(|f| &f.ty)
+    }
+
+    /// Infers the type of an expression, if it can be determined locally.
+    pub fn infer(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Literal(lit, _) => infer_literal(lit),
+            Expr::Variable(name, _) => self.lookup(name).cloned(),
+            Expr::Call { func, args, .. } => self.infer_call(func, args),
+            // Accesses after `?.` apply to the non-null value and stay nullable
+            Expr::FieldAccess { base, field, .. } if optional_link(base).is_some() => {
+                let inner = option_inner(&self.infer(base)?)?.clone();
+                Some(into_option(self.member_type(&inner, field)?))
+            }
+            Expr::FieldAccess { base, field, .. } => self.member_type(&self.infer(base)?, field),
+            Expr::OptionalChain { base, field, .. } => {
+                let inner = option_inner(&self.infer(base)?)?.clone();
+                Some(into_option(self.member_type(&inner, field)?))
+            }
+            Expr::Struct { name, .. } => Some(Type::Named(name.clone(), vec![])),
+            Expr::Match { arms, .. } => arms.iter().find_map(|arm| self.infer(&arm.expr)),
+            Expr::Comprehension { body, clauses, .. } => {
+                let mut env = self.clone();
+                env.push_scope();
+                for clause in clauses {
+                    if let ComprehensionClause::For { pattern: Pattern::Variable(name, _), iterable, .. } = clause {
+                        if let Some(item) = env.infer(iterable).as_ref().and_then(element_type) {
+                            env.bind(name, item.clone());
+                        }
+                    }
+                }
+                Some(Type::Named("Vec".to_string(), vec![env.infer(body)?]))
+            }
+            // Awaiting a call to an async function yields its declared type
+            Expr::Await { expr, .. } => match &**expr {
+                Expr::Call { func, .. } => match &**func {
+                    Expr::Variable(name, _) => self.function(name).filter(|sig| sig.is_async)?.ret.clone(),
+                    _ => self.infer(expr),
+                },
+                _ => self.infer(expr),
+            },
+            Expr::Try(inner, _) => match self.infer(inner)? {
+                Type::Result(ok, _) => Some(*ok),
+                Type::Option(inner) => Some(*inner),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Infers the result type of a call, binary operator or method call.
+    fn infer_call(&self, func: &Expr, args: &[Expr]) -> Option<Type> {
+        match func {
+            Expr::Variable(name, _) if is_binary_operator(name) && args.len() == 2 => {
+                if is_comparison_operator(name) {
+                    return Some(named("bool"));
+                }
+                let lhs = self.infer(&args[0]);
+                let rhs = self.infer(&args[1]);
+                if name == "??" {
+                    return match (lhs, rhs) {
+                        (_, Some(rhs @ Type::Option(_))) => Some(rhs),
+                        (Some(Type::Option(inner)), _) => Some(*inner),
+                        (_, rhs) => rhs,
+                    };
+                }
+                match (lhs, rhs) {
+                    (Some(lhs), _) if name == "+" && is_string_like(&lhs) => Some(string_type()),
+                    (Some(lhs), Some(rhs)) => common_numeric_type(&lhs, &rhs).or(Some(lhs)),
+                    (Some(lhs), None) => Some(lhs),
+                    (None, rhs) => rhs,
+                }
+            }
+            Expr::Variable(name, _) if is_unary_operator(name) && args.len() == 1 => {
+                if name == "!" {
+                    Some(named("bool"))
+                } else {
+                    self.infer(&args[0])
+                }
+            }
+            Expr::Variable(name, _) => self.function(name).filter(|sig| !sig.is_async).and_then(|sig| sig.ret.clone()),
+            Expr::FieldAccess { base, field, .. } if optional_link(base).is_some() => {
+                let inner = option_inner(&self.infer(base)?)?.clone();
+                Some(into_option(method_return_type(Some(inner), field)?))
+            }
+            Expr::FieldAccess { base, field, .. } => method_return_type(self.infer(base), field),
+            Expr::OptionalChain { base, field, .. } => {
+                let inner = option_inner(&self.infer(base)?)?.clone();
+                Some(into_option(method_return_type(Some(inner), field)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the type of `field` on a value of type `ty`, looking through references.
+    fn member_type(&self, ty: &Type, field: &str) -> Option<Type> {
+        match deref(ty) {
+            Type::Named(name, _) => self.field_type(name, field).cloned(),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the `?.` link nearest the outside of a chain of field accesses and
+/// method calls, if the chain contains one. Everything between that link and
+/// `expr` is skipped when the link's base is null.
+pub fn optional_link(expr: &Expr) -> Option<&Expr> {
+    match expr {
+        Expr::OptionalChain { .. } => Some(expr),
+        Expr::FieldAccess { base, .. } => optional_link(base),
+        Expr::Call { func, .. } => match &**func {
+            Expr::OptionalChain { .. } => Some(func),
+            Expr::FieldAccess { base, .. } => optional_link(base),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the result type of calling a well-known method on a receiver.
+fn method_return_type(receiver: Option<Type>, method: &str) -> Option<Type> {
+    match method {
+        "to_string" | "to_owned" | "format" => Some(string_type()),
+        "as_str" => Some(str_ref_type()),
+        "len" => Some(named("usize")),
+        "is_empty" | "is_some" | "is_none" => Some(named("bool")),
+        "clone" => receiver.map(|ty| deref(&ty).clone()),
+        _ => None,
+    }
+}
+
+/// Strips any reference layers from a type.
+pub fn deref(ty: &Type) -> &Type {
+    match ty {
+        Type::Named(name, args) if (name == "&" || name == "&mut") && args.len() == 1 => deref(&args[0]),
+        _ => ty,
+    }
+}
+
+/// Returns `Some(true)` for `&mut T`, `Some(false)` for `&T` and `None` for
+/// types that are not references.
+pub fn reference_mutability(ty: &Type) -> Option<bool> {
+    match ty {
+        Type::Named(name, args) if name == "&" && args.len() == 1 => Some(false),
+        Type::Named(name, args) if name == "&mut" && args.len() == 1 => Some(true),
+        _ => None,
+    }
+}
+
+/// Returns `T` for `Option<T>`.
+pub fn option_inner(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Option(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+/// Wraps `ty` in `Option`, unless it already is one (optional chains flatten).
+pub fn into_option(ty: Type) -> Type {
+    match ty {
+        Type::Option(_) => ty,
+        _ => Type::Option(Box::new(ty)),
+    }
+}
+
+/// `Box<dyn std::error::Error>`, the error type any error converts into.
+pub fn boxed_error_type() -> Type {
+    Type::Named("Box".to_string(), vec![named("dyn std::error::Error")])
+}
+
+/// Name of the error enum generated for modules whose functions return
+/// `Result<T>` without naming an error type.
+pub const MODULE_ERROR: &str = "Error";
+
+/// Returns true if `ty` is the module error type.
+pub fn is_module_error(ty: &Type) -> bool {
+    matches!(ty, Type::Named(name, args) if name == MODULE_ERROR && args.is_empty())
+}
+
+/// Returns true for types that are `Copy` in Rust, as far as can be told locally.
+pub fn is_copy(ty: &Type) -> bool {
+    match ty {
+        Type::Named(name, args) if name == "&" && args.len() == 1 => true,
+        Type::Named(name, args) => args.is_empty() && (is_numeric(ty) || matches!(name.as_str(), "bool" | "char")),
+        Type::Option(inner) => is_copy(inner),
+        Type::Tuple(types) => types.iter().all(is_copy),
+        _ => false,
+    }
+}
+
+/// Returns the natural type of a literal in the absence of context. Numeric
+/// literals get the flexible `{integer}`/`{float}` types, which adopt the
+/// concrete numeric type of whatever slot they flow into.
+fn infer_literal(lit: &Literal) -> Option<Type> {
+    match lit {
+        Literal::Int(value) => Some(named(wide_int_type(*value).unwrap_or(INTEGER_LITERAL))),
+        Literal::Float(_) => Some(named(FLOAT_LITERAL)),
+        Literal::Bool(_) => Some(named("bool")),
+        Literal::String(_) => Some(str_ref_type()),
+        Literal::Null => None,
+    }
+}
+
+/// Shorthand for a named type without generic arguments.
+pub fn named(name: &str) -> Type {
+    Type::Named(name.to_string(), vec![])
+}
+
+/// The owned `String` type.
+pub fn string_type() -> Type {
+    named("String")
+}
+
+/// The borrowed `&str` type.
+pub fn str_ref_type() -> Type {
+    Type::Named("&".to_string(), vec![named("str")])
+}
+
+/// Returns true if `ty` is the owned `String` type.
+pub fn is_string(ty: &Type) -> bool {
+    matches!(ty, Type::Named(name, args) if name == "String" && args.is_empty())
+}
+
+/// Returns true if `ty` is `&str`.
+pub fn is_str_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Named(name, args) if name == "&" && args.len() == 1 => {
+            matches!(&args[0], Type::Named(inner, _) if inner == "str")
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if `ty` is `&String`.
+pub fn is_string_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Named(name, args) if name == "&" && args.len() == 1 => is_string(&args[0]),
+        _ => false,
+    }
+}
+
+/// Returns true for any of `String`, `&String` or `&str`.
+pub fn is_string_like(ty: &Type) -> bool {
+    is_string(ty) || is_str_ref(ty) || is_string_ref(ty)
+}
+
+/// Returns the element type of a `Vec<T>` or `[T]` collection type.
+pub fn element_type(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Named(name, args) if args.len() == 1 && matches!(name.as_str(), "Vec" | "VecDeque" | "HashSet" | "BTreeSet") => {
+            Some(&args[0])
+        }
+        Type::Array(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+/// Returns true if `name` is a prefix operator encoded as a single-argument call.
+pub fn is_unary_operator(name: &str) -> bool {
+    matches!(name, "-" | "!")
+}
+
+/// Returns true if `name` is a binary operator encoded as a call.
+pub fn is_binary_operator(name: &str) -> bool {
+    matches!(
+        name,
+        "+" | "-" | "*" | "/" | "%" | "==" | "!=" | "<" | ">" | "<=" | ">=" | "&&" | "||" | "??"
+    )
+}
+
+/// Returns true if `name` is a comparison or logical operator yielding `bool`.
+pub fn is_comparison_operator(name: &str) -> bool {
+    matches!(name, "==" | "!=" | "<" | ">" | "<=" | ">=" | "&&" | "||")
+}
+
+/// Returns the binding strength of a binary operator; higher binds tighter.
+pub fn operator_precedence(name: &str) -> u8 {
+    match name {
+        "??" => 0,
+        "||" => 1,
+        "&&" => 2,
+        "==" | "!=" | "<" | ">" | "<=" | ">=" => 3,
+        "+" | "-" => 4,
+        "*" | "/" | "%" => 5,
+        _ => u8::MAX,
+    }
+}
+
+/// Type of an integer literal whose concrete type is not yet fixed by context.
+pub const INTEGER_LITERAL: &str = "{integer}";
+
+/// Type of a float literal whose concrete type is not yet fixed by context.
+pub const FLOAT_LITERAL: &str = "{float}";
+
+/// Returns the name of a primitive numeric type (or numeric literal type).
+pub fn numeric_name(ty: &Type) -> Option<&str> {
+    match ty {
+        Type::Named(name, args) if args.is_empty() && (is_integer_name(name) || is_float_name(name)) => {
+            Some(name.as_str())
+        }
+        _ => None,
+    }
+}
+
+fn is_integer_name(name: &str) -> bool {
+    matches!(
+        name,
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize"
+            | "u8" | "u16" | "u32" | "u64" | "u128" | "usize"
+            | INTEGER_LITERAL
+    )
+}
+
+fn is_float_name(name: &str) -> bool {
+    matches!(name, "f32" | "f64" | FLOAT_LITERAL)
+}
+
+/// Returns true for primitive integer and float types, including literal types.
+pub fn is_numeric(ty: &Type) -> bool {
+    numeric_name(ty).is_some()
+}
+
+/// Returns true for primitive integer types, including `{integer}`.
+pub fn is_integer(ty: &Type) -> bool {
+    numeric_name(ty).is_some_and(is_integer_name)
+}
+
+/// Returns true for `f32`, `f64` and `{float}`.
+pub fn is_float(ty: &Type) -> bool {
+    numeric_name(ty).is_some_and(is_float_name)
+}
+
+/// Returns true for the flexible `{integer}` and `{float}` literal types.
+pub fn is_literal_type(ty: &Type) -> bool {
+    matches!(numeric_name(ty), Some(INTEGER_LITERAL | FLOAT_LITERAL))
+}
+
+/// Bit widths of an integer type as `(guaranteed minimum, possible maximum)`.
+/// Pointer-sized integers are at least 32 and at most 64 bits wide.
+fn integer_bits(name: &str) -> Option<(u32, u32)> {
+    match name {
+        "i8" | "u8" => Some((8, 8)),
+        "i16" | "u16" => Some((16, 16)),
+        "i32" | "u32" => Some((32, 32)),
+        "i64" | "u64" => Some((64, 64)),
+        "i128" | "u128" => Some((128, 128)),
+        "isize" | "usize" => Some((32, 64)),
+        _ => None,
+    }
+}
+
+/// How a lossless numeric widening is expressed in Rust.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Widening {
+    /// The standard library provides a `From` impl: `T::from(x)`
+    From,
+    /// Lossless on every supported target, but only expressible as `x as T`
+    Cast,
+}
+
+/// Returns how a value of numeric type `from` widens losslessly into `to`,
+/// or `None` if the conversion could lose information.
+pub fn widening(from: &Type, to: &Type) -> Option<Widening> {
+    let (from, to) = (numeric_name(from)?, numeric_name(to)?);
+    if from == to || is_literal_type(&named(from)) || is_literal_type(&named(to)) {
+        return None;
+    }
+    if is_float_name(from) {
+        return (from == "f32" && to == "f64").then_some(Widening::From);
+    }
+    let (_, from_max) = integer_bits(from)?;
+    let lossless = if is_float_name(to) {
+        // Integers up to half the mantissa width convert exactly
+        from_max <= if to == "f32" { 16 } else { 32 }
+    } else {
+        let (to_min, _) = integer_bits(to)?;
+        match (from.starts_with('u'), to.starts_with('u')) {
+            (true, true) | (false, false) => from_max <= to_min,
+            (true, false) => from_max < to_min,
+            (false, true) => false,
+        }
+    };
+    if !lossless {
+        return None;
+    }
+    let pointer_sized = from.ends_with("size") || to.ends_with("size");
+    let has_from_impl = !pointer_sized
+        || matches!((from, to), ("u8" | "u16", "usize") | ("i8" | "i16" | "u8", "isize"));
+    Some(if has_from_impl { Widening::From } else { Widening::Cast })
+}
+
+/// Returns the type both operands of an arithmetic operator convert to, or
+/// `None` if neither operand widens losslessly into the other.
+pub fn common_numeric_type(lhs: &Type, rhs: &Type) -> Option<Type> {
+    let (l, r) = (numeric_name(lhs)?, numeric_name(rhs)?);
+    if l == r {
+        return Some(lhs.clone());
+    }
+    match (l, r) {
+        (INTEGER_LITERAL, _) => Some(rhs.clone()),
+        (_, INTEGER_LITERAL) => Some(lhs.clone()),
+        (FLOAT_LITERAL, _) if is_float_name(r) => Some(rhs.clone()),
+        (_, FLOAT_LITERAL) if is_float_name(l) => Some(lhs.clone()),
+        (FLOAT_LITERAL, _) => widening(rhs, &named("f64")).map(|_| named("f64")),
+        (_, FLOAT_LITERAL) => widening(lhs, &named("f64")).map(|_| named("f64")),
+        _ if widening(lhs, rhs).is_some() => Some(rhs.clone()),
+        _ if widening(rhs, lhs).is_some() => Some(lhs.clone()),
+        _ => None,
+    }
+}
+
+/// Returns true if the integer `value` is representable in the integer type `ty`.
+/// Literals are `i128`s, so of `u128` only the values up to `i128::MAX` fit.
+pub fn int_fits(value: i128, ty: &Type) -> bool {
+    let Some(name) = numeric_name(ty) else {
+        return false;
+    };
+    let (min, max): (i128, i128) = match name {
+        "i8" => (i8::MIN.into(), i8::MAX.into()),
+        "i16" => (i16::MIN.into(), i16::MAX.into()),
+        "i32" | INTEGER_LITERAL => (i32::MIN.into(), i32::MAX.into()),
+        "u8" => (0, u8::MAX.into()),
+        "u16" => (0, u16::MAX.into()),
+        "u32" | "usize" => (0, u32::MAX.into()),
+        "i64" | "isize" => (i64::MIN.into(), i64::MAX.into()),
+        "u64" => (0, u64::MAX.into()),
+        "u128" => (0, i128::MAX),
+        _ => (i128::MIN, i128::MAX),
+    };
+    (min..=max).contains(&value)
+}
+
+/// The type an integer literal without a type from its context needs when it
+/// does not fit the `i32` default: `i64`, or else `i128`.
+pub fn wide_int_type(value: i128) -> Option<&'static str> {
+    if int_fits(value, &named(INTEGER_LITERAL)) {
+        None
+    } else if int_fits(value, &named("i64")) {
+        Some("i64")
+    } else {
+        Some("i128")
+    }
+}
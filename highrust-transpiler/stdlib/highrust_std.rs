@@ -0,0 +1,43 @@
+//! HighRust standard helpers.
+//!
+//! This module is vendored into every package the transpiler generates, so
+//! generated code can use it without further dependencies.
+#![allow(dead_code)]
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// Groups `items` by the key `key` computes for each, keeping the items of
+/// each group in their original order.
+pub fn group_by<T, K, F>(items: impl IntoIterator<Item = T>, mut key: F) -> HashMap<K, Vec<T>>
+where
+    K: Eq + Hash,
+    F: FnMut(&T) -> K,
+{
+    let mut groups: HashMap<K, Vec<T>> = HashMap::new();
+    for item in items {
+        groups.entry(key(&item)).or_default().push(item);
+    }
+    groups
+}
+
+/// Applies `f` to each item, collecting the results.
+pub fn map<T, U>(items: impl IntoIterator<Item = T>, f: impl FnMut(T) -> U) -> Vec<U> {
+    items.into_iter().map(f).collect()
+}
+
+/// Keeps the items `predicate` accepts.
+pub fn filter<T>(items: impl IntoIterator<Item = T>, mut predicate: impl FnMut(&T) -> bool) -> Vec<T> {
+    items.into_iter().filter(|item| predicate(item)).collect()
+}
+
+/// Splits `items` into those `predicate` accepts and those it rejects.
+pub fn partition<T>(items: impl IntoIterator<Item = T>, predicate: impl FnMut(&T) -> bool) -> (Vec<T>, Vec<T>) {
+    items.into_iter().partition(predicate)
+}
+
+/// Drops repeated items, keeping the first occurrence of each.
+pub fn unique<T: Eq + Hash + Clone>(items: impl IntoIterator<Item = T>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    items.into_iter().filter(|item| seen.insert(item.clone())).collect()
+}
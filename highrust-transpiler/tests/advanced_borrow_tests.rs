@@ -4,7 +4,7 @@
 //! complex borrowing and ownership patterns.
 
 use highrust_transpiler::{
-    ast::{Block, Expr, FunctionDef, Literal, Module, ModuleItem, Pattern, Span, Stmt, Type, Param},
+    ast::{Block, Expr, FunctionDef, Literal, Module, ModuleItem, Pattern, Span, Stmt, Type},
     ownership::OwnershipInference,
     lowering::lower_module,
     codegen::{generate_rust_code, CodegenContext},
@@ -117,7 +117,7 @@ fn test_nested_borrow_inference() {
     // Additionally, verify the generated code has appropriate references
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     
     // Check that the code contains "&data" and refer to "view" and "first" correctly
     assert!(code.contains("&data"), "Generated code should have reference to 'data'");
@@ -209,7 +209,7 @@ fn test_temporary_borrow_inference() {
     // Generate code and verify
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     
     // Check that the code contains "let mut data" and "&data"
     assert!(code.contains("let mut data"), "Generated code should mark 'data' as mutable");
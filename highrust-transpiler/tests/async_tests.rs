@@ -0,0 +1,82 @@
+//! Tests for async functions and `await` in the HighRust transpiler.
+//!
+//! These tests verify that `async fn`, prefix `await` and postfix `.await`
+//! lower to Rust async, that functions which await become async while callers
+//! that are not async cannot call them, and that an async `main` runs under the
+//! configured runtime.
+
+mod test_utils;
+
+use highrust_transpiler::{codegen::AsyncRuntime, transpile_source, transpile_source_with, TranspileOptions};
+use test_utils::transpile;
+
+/// Transpiles `source` with an async `main` driven by `runtime`.
+fn transpile_with_runtime(source: &str, runtime: AsyncRuntime) -> String {
+    let options = TranspileOptions { async_runtime: runtime, ..TranspileOptions::default() };
+    transpile_source_with(source, &options).unwrap_or_else(|e| panic!("transpilation failed: {:?}", e))
+}
+
+#[test]
+fn test_await_forms_lower_to_dot_await() {
+    let code = transpile(
+        "async fn fetch(id: u32) -> Result<String, HttpError> { get(id).await }
+         async fn both(id: u32) -> Result<String, HttpError> {
+             let first = await fetch(id)?;
+             let second = fetch(id).await?;
+             first + second
+         }",
+    );
+    assert!(code.contains("async fn fetch(id: u32) -> Result<String, HttpError>"), "got: {}", code);
+    assert!(code.contains("get(id).await"), "got: {}", code);
+    assert!(code.contains("let first = fetch(id).await?;"), "got: {}", code);
+    assert!(code.contains("let second = fetch(id).await?;"), "got: {}", code);
+    // The awaited values are known to be strings
+    assert!(code.contains("Ok(first + second.as_str())"), "got: {}", code);
+}
+
+#[test]
+fn test_functions_that_await_are_async() {
+    let code = transpile(
+        "async fn load() -> i32 { 1 }
+         fn total() -> i32 { await load() + 1 }
+         fn plain() -> i32 { 2 }",
+    );
+    assert!(code.contains("async fn total() -> i32"), "got: {}", code);
+    assert!(code.contains("load().await + 1"), "got: {}", code);
+    assert!(code.contains("\nfn plain() -> i32"), "got: {}", code);
+}
+
+#[test]
+fn test_sync_callers_of_inferred_async_functions_are_rejected() {
+    let source = "fn count() -> u32 { await fetch_count() }
+                  fn outer() -> u32 { count() }";
+    let error = transpile_source(source).unwrap_err();
+    let diagnostic = error.diagnostic();
+    assert_eq!(diagnostic.code, "E0105");
+    let span = diagnostic.primary_label().unwrap().span.clone();
+    assert_eq!(&source[span.start..span.end], "count");
+    assert!(span.start > source.find("fn outer").unwrap(), "labels {:?}", span);
+
+    // Awaiting the call makes the caller async too
+    let code = transpile("fn count() -> u32 { await fetch_count() }\nfn outer() -> u32 { await count() }");
+    assert!(code.contains("async fn outer() -> u32"), "got: {}", code);
+    assert!(code.contains("count().await"), "got: {}", code);
+}
+
+#[test]
+fn test_async_main_runtime_entry() {
+    let source = "async fn main() { println(\"hi\"); }";
+    let tokio = transpile_with_runtime(source, AsyncRuntime::Tokio);
+    assert!(tokio.contains("#[tokio::main]\nasync fn main() {"), "got: {}", tokio);
+
+    let async_std = transpile_with_runtime(source, AsyncRuntime::AsyncStd);
+    assert!(async_std.contains("#[async_std::main]\nasync fn main() {"), "got: {}", async_std);
+
+    let minimal = transpile_with_runtime(source, AsyncRuntime::Minimal);
+    assert!(minimal.contains("async fn async_main() {"), "got: {}", minimal);
+    assert!(minimal.contains("fn main() {\n    block_on(async_main())\n}"), "got: {}", minimal);
+    assert!(minimal.contains("fn block_on<F: std::future::Future>(future: F) -> F::Output {"), "got: {}", minimal);
+
+    assert_eq!("async-std".parse::<AsyncRuntime>(), Ok(AsyncRuntime::AsyncStd));
+    assert!("smol".parse::<AsyncRuntime>().is_err());
+}
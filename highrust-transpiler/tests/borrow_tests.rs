@@ -85,7 +85,7 @@ fn test_immutable_borrow_inference() {
     // Additionally, verify the generated code would use &s
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     
     // For now, we just check that 's' appears in both places - full borrowing
     // implementation would be more accurate with proper field access handling
@@ -167,7 +167,7 @@ fn test_mutable_borrow_inference() {
     // Additionally, verify the generated code would use &mut v
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::new();
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     
     // This assertion might need adjustment as implementation progresses
     assert!(code.contains("let mut v") || code.contains("mut v:"), 
@@ -0,0 +1,178 @@
+//! Tests for the on-disk cache of transpilation results.
+//!
+//! Cached Rust is marked after a build, so a later build that reuses it
+//! writes the marker into its output, and one that transpiles again does
+//! not. These tests verify that entries are reused while nothing they were
+//! computed from changed, that editing a function's body only invalidates
+//! its own module while changing its signature also invalidates the modules
+//! importing it, and that options, unreadable entries and a new entry format
+//! lead to misses.
+
+mod test_utils;
+
+use highrust_transpiler::cache::{Cache, CACHE_DIR, CACHE_FORMAT};
+use highrust_transpiler::config::Config;
+use highrust_transpiler::{transpile_project_with, TranspileOptions};
+use std::path::{Path, PathBuf};
+use test_utils::{scratch_dir, write_file};
+
+/// Written above the code of every cached module by [`mark_cached_rust`].
+const MARKER: &str = "// from the cache\n";
+
+/// A project where `main` imports `util`, with options caching under it.
+fn project(name: &str) -> (PathBuf, TranspileOptions) {
+    let root = scratch_dir(name);
+    write_file(&root, "src/main.hrs", "import util\n\nfn main() {\n    util::greet(\"x\");\n}\n");
+    write_file(&root, "src/util.hrs", "fn greet(name: String) {\n    println(name);\n}\n\nexport greet\n");
+    let options = TranspileOptions { cache: Some(root.join(CACHE_DIR)), ..TranspileOptions::default() };
+    (root, options)
+}
+
+/// Transpiles the project under `root`, returning its output for `module`.
+fn build(root: &Path, options: &TranspileOptions, module: &str) -> String {
+    transpile_project_with(root.join("src"), root.join("out"), options).unwrap();
+    std::fs::read_to_string(root.join("out").join(module).with_extension("rs")).unwrap()
+}
+
+/// The entry files of one kind in the cache of `options`.
+fn entries(options: &TranspileOptions, kind: &str) -> Vec<PathBuf> {
+    let dir = Cache::new(options.cache.as_ref().unwrap()).dir().join(kind);
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir).map(|dir| dir.flatten().map(|entry| entry.path()).collect()).unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Puts [`MARKER`] above the code of every cached module.
+fn mark_cached_rust(options: &TranspileOptions) {
+    for entry in entries(options, "rust") {
+        let mut json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&entry).unwrap()).unwrap();
+        let code = json["code"].as_str().unwrap();
+        json["code"] = format!("{}{}", MARKER, code).into();
+        std::fs::write(entry, json.to_string()).unwrap();
+    }
+}
+
+#[test]
+fn test_unchanged_modules_come_from_the_cache() {
+    let (root, options) = project("cache-unchanged");
+    let first = build(&root, &options, "util");
+    assert!(!first.contains(MARKER));
+    for kind in ["ast", "ownership", "rust"] {
+        assert_eq!(entries(&options, kind).len(), 2, "{} entries", kind);
+    }
+
+    mark_cached_rust(&options);
+    assert!(build(&root, &options, "main").contains(MARKER));
+    assert_eq!(build(&root, &options, "util"), format!("{}{}", MARKER, first));
+    // Nothing was added for the second build
+    assert_eq!(entries(&options, "rust").len(), 2);
+
+    // Cached ASTs and analyses generate the same Rust as the sources did
+    for entry in entries(&options, "rust") {
+        std::fs::remove_file(entry).unwrap();
+    }
+    assert_eq!(build(&root, &options, "util"), first);
+}
+
+#[test]
+fn test_only_signature_changes_invalidate_importers() {
+    let (root, options) = project("cache-signatures");
+    build(&root, &options, "main");
+    mark_cached_rust(&options);
+
+    // A new body changes `util`, but not what `main` sees of it
+    write_file(&root, "src/util.hrs", "fn greet(name: String) {\n    println(\"hi\");\n    println(name);\n}\n\nexport greet\n");
+    assert!(!build(&root, &options, "util").contains(MARKER));
+    assert!(build(&root, &options, "main").contains(MARKER));
+    // Moving `greet` down a line changes its spans, which `main` ignores too
+    write_file(&root, "src/util.hrs", "\nfn greet(name: String) {\n    println(name);\n}\n\nexport greet\n");
+    assert!(build(&root, &options, "main").contains(MARKER));
+
+    // A new parameter type changes how `main` calls it
+    write_file(&root, "src/util.hrs", "fn greet(name: &str) {\n    println(name);\n}\n\nexport greet\n");
+    let main = build(&root, &options, "main");
+    assert!(!main.contains(MARKER), "got:\n{}", main);
+    assert!(main.contains("util::greet(\"x\")"), "got:\n{}", main);
+}
+
+#[test]
+fn test_options_select_their_own_entries() {
+    let (root, options) = project("cache-options");
+    build(&root, &options, "main");
+    mark_cached_rust(&options);
+
+    let commented = TranspileOptions { line_comments: true, ..options.clone() };
+    let main = build(&root, &commented, "main");
+    assert!(!main.contains(MARKER));
+    assert!(main.contains("// hrs:"), "got:\n{}", main);
+    // The analyses do not depend on the options, so they were reused
+    assert_eq!(entries(&options, "ownership").len(), 2);
+    assert_eq!(entries(&options, "rust").len(), 4);
+    assert!(build(&root, &options, "main").contains(MARKER));
+
+    // Entries of another version are never read
+    let other_version = Cache::new(root.join("elsewhere"));
+    let name = other_version.dir().file_name().unwrap().to_string_lossy().into_owned();
+    assert_eq!(name, format!("{}-{}", env!("CARGO_PKG_VERSION"), CACHE_FORMAT));
+}
+
+#[test]
+fn test_a_new_format_invalidates_entries() {
+    let dir = scratch_dir("cache-format");
+    let file = Path::new("main.hrs");
+    Cache::with_format(&dir, 1).parse(file, "fn main() {}\n").unwrap();
+    assert_eq!(std::fs::read_dir(Cache::with_format(&dir, 1).dir().join("ast")).unwrap().count(), 1);
+
+    // The next format looks its entries up elsewhere, so it misses and
+    // stores its own
+    let next = Cache::with_format(&dir, 2);
+    assert!(!next.dir().join("ast").exists());
+    next.parse(file, "fn main() {}\n").unwrap();
+    assert_eq!(std::fs::read_dir(next.dir().join("ast")).unwrap().count(), 1);
+    assert_ne!(next.dir(), Cache::with_format(&dir, 1).dir());
+}
+
+#[test]
+fn test_unreadable_entries_are_misses() {
+    let (root, options) = project("cache-corrupt");
+    let first = build(&root, &options, "util");
+    for kind in ["ast", "ownership", "rust"] {
+        for entry in entries(&options, kind) {
+            std::fs::write(entry, "{ not json").unwrap();
+        }
+    }
+    assert_eq!(build(&root, &options, "util"), first);
+    // The entries were stored again
+    let rust = std::fs::read_to_string(&entries(&options, "rust")[0]).unwrap();
+    assert!(serde_json::from_str::<serde_json::Value>(&rust).is_ok());
+
+    // A cache that cannot be written to does not fail the build
+    let blocked = TranspileOptions { cache: Some(root.join("src/util.hrs")), ..options };
+    assert_eq!(build(&root, &blocked, "util"), first);
+}
+
+#[test]
+fn test_parse_errors_are_not_cached() {
+    let dir = scratch_dir("cache-parse");
+    let cache = Cache::new(&dir);
+    let file = Path::new("main.hrs");
+    assert!(cache.parse(file, "fn main( {\n").is_err());
+    assert!(!cache.dir().join("ast").exists());
+
+    let ast = cache.parse(file, "fn main() {}\n").unwrap();
+    let cached = cache.parse(file, "fn main() {}\n").unwrap();
+    assert_eq!(format!("{:?}", cached), format!("{:?}", ast));
+    assert_eq!(std::fs::read_dir(cache.dir().join("ast")).unwrap().count(), 1);
+}
+
+#[test]
+fn test_projects_cache_under_target_unless_disabled() {
+    let root = scratch_dir("cache-config");
+    write_file(&root, "highrust.toml", "[build]\nsource_roots = [\"src\"]\n");
+    let config = Config::load(&root.join("highrust.toml")).unwrap();
+    assert_eq!(config.transpile_options().cache, Some(root.join("target/highrust/cache")));
+
+    write_file(&root, "highrust.toml", "[build]\ncache = false\n");
+    let config = Config::load(&root.join("highrust.toml")).unwrap();
+    assert_eq!(config.transpile_options().cache, None);
+}
@@ -0,0 +1,73 @@
+//! Tests for checking generated packages with cargo.
+//!
+//! These tests verify that rustc's diagnostics on a generated package are
+//! mapped back to the HighRust lines and columns they come from, rendered
+//! against the HighRust source with the generated Rust below it, and that
+//! rustc's summary messages and spans outside transpiled files are handled.
+
+mod test_utils;
+
+use highrust_transpiler::check::{check_package, parse_messages, render, CargoCommand};
+use highrust_transpiler::config::Config;
+use highrust_transpiler::package::generate_package_in;
+use highrust_transpiler::sourcemap::Position;
+use highrust_transpiler::TranspileOptions;
+use test_utils::{scratch_dir, write_file};
+
+#[test]
+fn test_rustc_errors_map_to_highrust() {
+    let root = scratch_dir("check");
+    write_file(&root, "highrust.toml", "[project]\nname = \"check\"\n");
+    write_file(&root, "src/main.hrs", "import util\n\nfn main() {\n    util::greet(\"x\");\n}\n");
+    write_file(&root, "src/util.hrs", "export fn greet(name: &str) {\n    let count: i32 = name;\n    println(\"{}\", count);\n}\n");
+    let config = Config::load(&root.join("highrust.toml")).unwrap();
+    let dir = root.join("package");
+    generate_package_in(&config, &dir, &TranspileOptions::default()).unwrap_or_else(|e| panic!("generation failed: {:?}", e));
+
+    let report = check_package(&dir, CargoCommand::Check).unwrap();
+    assert!(!report.success);
+    assert_eq!(report.error_count(), 1, "got: {:?}", report.diagnostics);
+    let error = &report.diagnostics[0];
+    assert_eq!(error.code.as_deref(), Some("E0308"));
+    let primary = &error.spans[0];
+    assert!(primary.is_primary);
+    assert_eq!(primary.generated.file, dir.join("src/util.rs"));
+    let source = primary.source.as_ref().expect("span should map to HighRust");
+    assert_eq!(source.file, std::path::absolute(root.join("src/util.hrs")).unwrap());
+    assert_eq!((source.start, source.end), (Position::new(2, 22), Position::new(2, 26)));
+    assert_eq!(source.line_text, "    let count: i32 = name;");
+
+    let rendered = render(error);
+    assert!(rendered.starts_with("error[E0308]: mismatched types\n"), "got: {}", rendered);
+    assert!(rendered.contains("util.hrs:2:22\n  |\n2 |     let count: i32 = name;\n  |                      ^^^^ expected `i32`"), "got: {}", rendered);
+    assert!(rendered.contains("= generated: "), "got: {}", rendered);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_unmapped_spans_and_summaries() {
+    let dir = scratch_dir("check-unmapped");
+    let span = r#"{"file_name":"src/lib.rs","line_start":3,"line_end":3,"column_start":9,"column_end":10,"is_primary":true,"label":null,"text":[{"text":"    let x = 1;","highlight_start":9,"highlight_end":10}],"suggested_replacement":null}"#;
+    let help = r#"{"message":"if this is intentional, prefix it with an underscore","level":"help","code":null,"spans":[{"file_name":"src/lib.rs","line_start":3,"line_end":3,"column_start":9,"column_end":10,"is_primary":true,"label":null,"text":[],"suggested_replacement":"_x"}],"children":[]}"#;
+    let messages = [
+        r#"{"reason":"compiler-artifact","target":{}}"#.to_string(),
+        format!(
+            r#"{{"reason":"compiler-message","message":{{"message":"unused variable: `x`","level":"warning","code":{{"code":"unused_variables"}},"spans":[{}],"children":[{}]}}}}"#,
+            span, help
+        ),
+        r#"{"reason":"compiler-message","message":{"message":"1 warning emitted","level":"warning","code":null,"spans":[],"children":[]}}"#.to_string(),
+        r#"{"reason":"build-finished","success":true}"#.to_string(),
+    ]
+    .join("\n");
+
+    let report = parse_messages(&messages, &dir);
+    assert!(report.success);
+    assert_eq!((report.error_count(), report.warning_count()), (0, 1));
+    let warning = &report.diagnostics[0];
+    assert!(warning.spans[0].source.is_none());
+    assert_eq!(warning.notes, ["help: if this is intentional, prefix it with an underscore: `_x`"]);
+    let rendered = render(warning);
+    assert!(rendered.contains("src/lib.rs:3:9\n  |\n3 |     let x = 1;\n  |         ^\n"), "got: {}", rendered);
+    assert!(!rendered.contains("generated:"), "got: {}", rendered);
+    let _ = std::fs::remove_dir_all(&dir);
+}
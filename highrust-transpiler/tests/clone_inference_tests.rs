@@ -1,7 +1,7 @@
 //! Tests for automatic .clone() insertion in HighRust.
 
 use highrust_transpiler::{
-    ast::{Block, Expr, FunctionDef, Literal, Module, ModuleItem, Pattern, Span, Stmt, Type, Param},
+    ast::{Block, Expr, FunctionDef, Literal, Module, ModuleItem, Pattern, Span, Stmt, Type},
     ownership::OwnershipInference,
     lowering::lower_module,
     codegen::{generate_rust_code, CodegenContext},
@@ -66,7 +66,7 @@ fn test_clone_on_move() {
     let analysis_result = ownership_inference.analyze_module(&module);
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     // The second use of s should be s.clone()
     assert!(code.contains("let u: String = s.clone();"), "Generated code should insert .clone(): {}", code);
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+//! Tests for list comprehensions in the HighRust transpiler.
+//!
+//! These tests verify that `[body for pat in iter if cond]` lowers to iterator
+//! chains, that nested `for` clauses become `flat_map`, that sources used again
+//! are iterated by reference, and that the collection type follows context.
+
+mod test_utils;
+
+use test_utils::transpile;
+
+#[test]
+fn test_filter_and_map() {
+    let code = transpile(
+        "data User = { name: String, age: u32 }
+         fn evens(nums: Vec<i32>) -> Vec<i32> { [n * 2 for n in nums if n % 2 == 0] }
+         fn adults(users: Vec<User>) -> Vec<String> { [u.name for u in users if u.age > 17] }
+         fn all(words: Vec<String>) -> Vec<String> { [w for w in words] }",
+    );
+    assert!(code.contains("nums.into_iter().filter(|&n| n % 2 == 0).map(|n| n * 2).collect()"), "got: {}", code);
+    assert!(code.contains("users.into_iter().filter(|u| u.age > 17).map(|u| u.name).collect()"), "got: {}", code);
+    assert!(code.contains("words.into_iter().collect()"), "got: {}", code);
+}
+
+#[test]
+fn test_nested_for_clauses() {
+    let code = transpile(
+        "fn products(xs: Vec<i32>, ys: Vec<i32>) -> Vec<i32> {
+             [a * b for a in xs if a > 0 for b in ys if b != a]
+         }",
+    );
+    assert!(
+        code.contains(
+            "xs.into_iter().filter(|&a| a > 0).flat_map(|a| ys.iter().filter(move |&&b| b != a).map(move |&b| a * b)).collect()"
+        ),
+        "got: {}",
+        code
+    );
+}
+
+#[test]
+fn test_reused_source_is_borrowed() {
+    let code = transpile(
+        "fn count(values: Vec<i32>) -> usize {
+             let doubled = [v2 * 2 for v2 in values];
+             doubled.len() + values.len()
+         }",
+    );
+    assert!(code.contains("let doubled = values.iter().map(|&v2| v2 * 2).collect::<Vec<_>>();"), "got: {}", code);
+}
+
+#[test]
+fn test_collection_type_from_context() {
+    let code = transpile(
+        "fn widen(a: Vec<i32>, b: Vec<i32>, c: Vec<i32>) {
+             let wide: Vec<i64> = [n for n in a if n > 1];
+             let unique: HashSet<i32> = [n + 1 for n in b];
+             let inferred = [n for n in c];
+         }",
+    );
+    assert!(code.contains("let wide: Vec<i64> = a.into_iter().filter(|&n| n > 1).map(|n| i64::from(n)).collect();"), "got: {}", code);
+    assert!(code.contains("let unique: HashSet<i32> = b.into_iter().map(|n| n + 1).collect();"), "got: {}", code);
+    assert!(code.contains("let inferred = c.into_iter().collect::<Vec<_>>();"), "got: {}", code);
+}
+
+#[test]
+fn test_borrowing_follows_the_source_in_its_function() {
+    let code = transpile(
+        "fn first(xs: Vec<i32>) -> usize {
+             let doubled = [x * 2 for x in xs];
+             doubled.len() + xs.len()
+         }
+         fn second(xs: Vec<i32>) -> Vec<i32> { [x * 2 for x in xs] }
+         fn shadowed(xs: Vec<i32>) -> usize {
+             let xs = [x * 2 for x in xs];
+             xs.len()
+         }",
+    );
+    assert!(code.contains("let doubled = xs.iter().map(|&x| x * 2).collect::<Vec<_>>();"), "got: {}", code);
+    // Reading `xs` again in `first` does not make `second` borrow its own
+    assert!(code.contains("xs.into_iter().map(|x| x * 2).collect()"), "got: {}", code);
+    // The old `xs` is not read after the `let` that shadows it
+    assert!(code.contains("let xs = xs.into_iter().map(|x| x * 2).collect::<Vec<_>>();"), "got: {}", code);
+}
@@ -0,0 +1,95 @@
+//! Tests for loading `highrust.toml` project configuration.
+//!
+//! These tests verify that every setting is read with its default when
+//! absent, that invalid keys and values are reported with the offending key
+//! and its position, and that the configuration is found from directories
+//! below it.
+
+mod test_utils;
+
+use highrust_transpiler::codegen::AsyncRuntime;
+use highrust_transpiler::config::{Config, ConfigError, Edition, LintLevel, Strictness};
+use std::path::{Path, PathBuf};
+use test_utils::scratch_dir;
+
+#[test]
+fn test_config_settings_and_defaults() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.build.source_roots, [PathBuf::from("src")]);
+    assert_eq!(config.build.output_dir, None);
+    assert_eq!(config.build.async_runtime, AsyncRuntime::Tokio);
+    assert_eq!(config.build.strictness, Strictness::Standard);
+    assert_eq!(config.project.edition, Edition::E2021);
+    assert_eq!(config.lints.implicit_clone, LintLevel::Allow);
+
+    let config = Config::parse(
+        "[project]
+         name = \"app\"
+         edition = \"2024\"
+
+         [build]
+         source_roots = [\"server\", \"shared\"]
+         output_dir = \"generated\"
+         async_runtime = \"async-std\"
+         strictness = \"strict\"
+         verbose_annotations = true
+
+         [lints]
+         implicit_clone = \"deny\"
+         embedded_rust = \"warn\"",
+    )
+    .unwrap_or_else(|e| panic!("invalid config: {}", e));
+    assert_eq!(config.project.name.as_deref(), Some("app"));
+    assert_eq!(config.project.edition, Edition::E2024);
+    assert_eq!(config.build.async_runtime, AsyncRuntime::AsyncStd);
+    assert_eq!(config.build.strictness, Strictness::Strict);
+    assert!(config.build.verbose_annotations);
+    assert_eq!(config.lints.implicit_clone, LintLevel::Deny);
+    assert_eq!(config.lints.embedded_rust, LintLevel::Warn);
+    assert_eq!(config.output_dir(Path::new("shared")), Path::new("generated/shared"));
+    let options = config.transpile_options();
+    assert_eq!(options.async_runtime, AsyncRuntime::AsyncStd);
+    assert_eq!(options.strictness, Strictness::Strict);
+    assert!(options.explain);
+    assert_eq!(options.lints.implicit_clone, LintLevel::Deny);
+    assert_eq!(options.lints.embedded_rust, LintLevel::Warn);
+}
+
+#[test]
+fn test_invalid_config_names_the_key() {
+    let error = |source: &str| match Config::parse(source) {
+        Err(ConfigError::Invalid { key, line, column, message, .. }) => (key, line, column, message),
+        other => panic!("expected an invalid config, got {:?}", other),
+    };
+    let (key, line, column, message) = error("[build]\nasync_runtime = \"smol\"\n");
+    assert_eq!((key.as_str(), line, column), ("build.async_runtime", 2, 17));
+    assert!(message.contains("unknown async runtime 'smol'"), "got: {}", message);
+
+    let (key, line, _, message) = error("[project]\nname = \"app\"\nedition = \"2019\"\n");
+    assert_eq!((key.as_str(), line), ("project.edition", 3));
+    assert!(message.contains("unknown variant `2019`"), "got: {}", message);
+
+    assert_eq!(error("[build]\nsource_roots = []\n").0, "build.source_roots");
+    assert_eq!(error("[lints]\nimplicit_clone = \"loud\"\n").0, "lints.implicit_clone");
+    assert_eq!(error("[build]\noutput = \"out\"\n").0, "build.output");
+
+    let message = Config::parse("[build]\nstrictness = \"lax\"\n").unwrap_err().to_string();
+    assert!(message.starts_with("highrust.toml:2:14: invalid `build.strictness`: unknown variant `lax`"), "got: {}", message);
+}
+
+#[test]
+fn test_config_is_discovered_upward() {
+    let root = scratch_dir("config");
+    std::fs::create_dir_all(root.join("src/net")).unwrap();
+    std::fs::write(root.join("highrust.toml"), "[build]\noutput_dir = \"out\"\n").unwrap();
+
+    let config = Config::discover(&root.join("src/net")).unwrap().expect("config not found");
+    assert_eq!(config.root, root);
+    assert_eq!(config.source_roots(), [root.join("src")]);
+    assert_eq!(config.output_dir(&root.join("src")), root.join("out"));
+
+    std::fs::write(root.join("highrust.toml"), "[build]\nverbose_annotations = \"yes\"\n").unwrap();
+    let message = Config::discover(&root.join("src")).unwrap_err().to_string();
+    assert!(message.contains("highrust.toml:2:23: invalid `build.verbose_annotations`"), "got: {}", message);
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,190 @@
+//! Tests for transpiler diagnostics.
+//!
+//! These tests verify that errors from every phase convert to diagnostics
+//! with stable codes and spans of the HighRust source, that the renderer
+//! shows them with carets under the source, that they describe themselves
+//! as JSON, that errors in project files name the file they occurred in,
+//! and that every code has a long-form explanation whose examples hold.
+
+mod test_utils;
+
+use highrust_transpiler::ast::Span;
+use highrust_transpiler::diagnostic::{codes, explanation, Diagnostic, Severity};
+use highrust_transpiler::{transpile_project, transpile_source, TranspilerError};
+use serde_json::Value;
+use std::error::Error;
+use test_utils::scratch_dir;
+
+/// Transpiles `source`, expecting an error, and returns its diagnostic.
+fn diagnose(source: &str) -> Diagnostic {
+    transpile_source(source).expect_err("transpilation should fail").diagnostic()
+}
+
+#[test]
+fn test_syntax_error_points_at_the_token() {
+    let source = "fn main() {\n    let x = ;\n}\n";
+    let diagnostic = diagnose(source);
+    assert_eq!((diagnostic.severity, diagnostic.code), (Severity::Error, "E0001"));
+    let label = diagnostic.primary_label().unwrap();
+    assert_eq!(label.span, Span { start: 24, end: 24 });
+
+    let rendered = diagnostic.render(Some(source));
+    assert_eq!(
+        rendered,
+        "error[E0001]: syntax error\n --> <source>:2:13\n  |\n2 |     let x = ;\n  |             ^ expected expr\n"
+    );
+}
+
+#[test]
+fn test_narrowing_conversion_renders_with_note() {
+    let source = "fn shrink(a: i64) {\n    let x: i32 = a;\n}\n";
+    let diagnostic = diagnose(source).with_file("src/main.hrs");
+    assert_eq!(diagnostic.code, "E0102");
+    assert_eq!(diagnostic.message, "narrowing conversion from `i64` to `i32`");
+
+    let rendered = diagnostic.render(Some(source));
+    assert!(rendered.starts_with("error[E0102]: narrowing conversion from `i64` to `i32`\n --> src/main.hrs:2:18\n"), "got: {}", rendered);
+    assert!(rendered.contains("2 |     let x: i32 = a;\n  |                  ^ `i64` may not fit in `i32`\n  |\n  = note: "), "got: {}", rendered);
+}
+
+#[test]
+fn test_unknown_export_suggests_similar_name() {
+    let source = "fn helper() {}\nexport helpr\n";
+    let diagnostic = diagnose(source);
+    assert_eq!(diagnostic.code, "E0104");
+    let suggestion = &diagnostic.suggestions[0];
+    assert_eq!(suggestion.replacement, "helper");
+    assert_eq!(&source[suggestion.span.start..suggestion.span.end], "helpr");
+    let rendered = diagnostic.render(Some(source));
+    assert!(rendered.ends_with("help: a similar item is defined: `helper`\n  |\n2 | export helper\n  |        ~~~~~~\n"), "got: {}", rendered);
+
+    assert!(diagnose("fn main() {}\nexport unrelated\n").suggestions.is_empty());
+}
+
+#[test]
+fn test_secondary_labels_and_rendering_without_source() {
+    let source = "fn main() {\n    let s = f();\n    g(s);\n}\n";
+    let diagnostic = Diagnostic::error("E0300", "use of moved value `s`")
+        .with_label(Span { start: 35, end: 36 }, "value used here after move")
+        .with_secondary(Span { start: 20, end: 21 }, "value defined here");
+    let rendered = diagnostic.render(Some(source));
+    assert!(rendered.contains(" --> <source>:3:7\n"), "got: {}", rendered);
+    assert!(
+        rendered.contains("2 |     let s = f();\n  |         - value defined here\n3 |     g(s);\n  |       ^ value used here after move\n"),
+        "got: {}",
+        rendered
+    );
+
+    let bare = diagnostic.with_note("moved at line 2").render(None);
+    assert_eq!(
+        bare,
+        "error[E0300]: use of moved value `s`\n = here: value used here after move\n = see: value defined here\n = note: moved at line 2\n"
+    );
+}
+
+#[test]
+fn test_transpiler_error_display_and_source() {
+    let error = transpile_source("fn main() {}\nexport missing").unwrap_err();
+    assert_eq!(error.to_string(), "lowering error: cannot export `missing`: no such item");
+    assert!(error.source().is_some());
+
+    let error = transpile_source("fn main( {").unwrap_err();
+    assert!(matches!(error, TranspilerError::ParseError(_)), "got: {:?}", error);
+    assert!(error.to_string().starts_with("Parse error: "), "got: {}", error);
+}
+
+#[test]
+fn test_project_errors_name_their_file() {
+    let root = scratch_dir("diagnostic-project");
+    std::fs::write(root.join("main.hrs"), "import util\nfn main() {}").unwrap();
+    std::fs::write(root.join("util.hrs"), "fn helper() {}\nexport helpr\n").unwrap();
+    let error = transpile_project(&root, &root.join("out")).unwrap_err();
+    let diagnostic = error.diagnostic();
+    assert_eq!(diagnostic.code, "E0104");
+    assert_eq!(diagnostic.file, Some(root.join("util.hrs")));
+    assert!(error.to_string().contains("util.hrs: lowering error"), "got: {}", error);
+
+    std::fs::write(root.join("util.hrs"), "fn helper( {}\n").unwrap();
+    let diagnostic = transpile_project(&root, &root.join("out")).unwrap_err().diagnostic();
+    assert_eq!((diagnostic.code, diagnostic.file), ("E0001", Some(root.join("util.hrs"))));
+
+    std::fs::write(root.join("util.hrs"), "export fn helper() {}\n").unwrap();
+    std::fs::write(root.join("main.hrs"), "import { helpr } from util\nfn main() {}").unwrap();
+    let diagnostic = transpile_project(&root, &root.join("out")).unwrap_err().diagnostic();
+    assert_eq!(diagnostic.code, "E0402");
+    assert_eq!(diagnostic.file, Some(root.join("main.hrs")));
+    assert_eq!(diagnostic.primary_label().unwrap().span, Span { start: 9, end: 14 });
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_json_describes_spans_and_suggestions() {
+    let source = "fn helper() {}\nexport helpr\n";
+    let diagnostic = diagnose(source).with_file("src/util.hrs");
+    let json = diagnostic.to_json(Some(source));
+    assert!(!json.contains('\n'), "got: {}", json);
+    let value: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["code"], "E0104");
+    assert_eq!(value["severity"], "error");
+    assert_eq!(value["file"], "src/util.hrs");
+    assert_eq!(value["message"], "cannot export `helpr`: no such item");
+    let span = &value["spans"][0];
+    assert_eq!((span["byte_start"].as_u64(), span["byte_end"].as_u64()), (Some(22), Some(27)));
+    assert_eq!((span["line_start"].as_u64(), span["column_start"].as_u64()), (Some(2), Some(8)));
+    assert_eq!((span["line_end"].as_u64(), span["column_end"].as_u64()), (Some(2), Some(13)));
+    assert_eq!(span["is_primary"], true);
+    let suggestion = &value["suggestions"][0];
+    assert_eq!(suggestion["replacement"], "helper");
+    assert_eq!(suggestion["span"]["byte_start"], 22);
+
+    // Without the source only byte offsets are known
+    let value: Value = serde_json::from_str(&diagnostic.to_json(None)).unwrap();
+    assert_eq!(value["spans"][0]["byte_start"], 22);
+    assert!(value["spans"][0]["line_start"].is_null());
+}
+
+#[test]
+fn test_json_covers_syntax_and_io_errors() {
+    let source = "fn main() {\n    let x = ;\n}\n";
+    let value: Value = serde_json::from_str(&diagnose(source).to_json(Some(source))).unwrap();
+    assert_eq!(value["code"], "E0001");
+    assert_eq!(value["spans"][0]["label"], "expected expr");
+    assert_eq!(value["spans"][0]["column_start"], 13);
+
+    let error = TranspilerError::IoError(std::io::Error::new(std::io::ErrorKind::NotFound, "missing.hrs"));
+    let value: Value = serde_json::from_str(&error.diagnostic().to_json(None)).unwrap();
+    assert_eq!(value["code"], "E0600");
+    assert!(value["file"].is_null());
+    assert_eq!(value["spans"], Value::Array(Vec::new()));
+}
+
+#[test]
+fn test_every_code_has_an_explanation() {
+    let codes: Vec<&str> = codes().collect();
+    assert_eq!(codes.len(), 28);
+    assert!(codes.windows(2).all(|pair| pair[0] < pair[1]), "codes out of order: {:?}", codes);
+    for code in codes {
+        let text = explanation(code).unwrap();
+        // A one-sentence summary, then the details
+        assert!(text.lines().next().unwrap().ends_with('.'), "{}: {}", code, text);
+        assert!(text.ends_with('\n') && !text.ends_with("\n\n"), "{}", code);
+    }
+    assert_eq!(explanation("e0102"), explanation("E0102"));
+    assert_eq!(explanation("E9999"), None);
+}
+
+#[test]
+fn test_explanation_examples_report_their_code() {
+    // Explanations of source errors show erroneous code, then a fix
+    for code in ["E0001", "E0003", "E0004", "E0005", "E0100", "E0102", "E0103", "E0104", "E0105"] {
+        let examples: Vec<&str> = explanation(code)
+            .unwrap()
+            .split("```highrust\n")
+            .skip(1)
+            .map(|block| block.split("```").next().unwrap())
+            .collect();
+        let error = transpile_source(examples[0]).unwrap_err();
+        assert_eq!(error.diagnostic().code, code, "example:\n{}", examples[0]);
+        assert!(transpile_source(examples[1]).is_ok(), "fix for {} fails:\n{}", code, examples[1]);
+    }
+}
@@ -0,0 +1,138 @@
+//! Tests for embedded `rust { ... }` blocks in the HighRust transpiler.
+//!
+//! These tests verify that Rust code is captured with balanced braces and
+//! emitted verbatim at item and statement level, that HighRust variables used
+//! inside a block are not moved before it, by a binding or a call, and that
+//! callers of `@rust` functions pass arguments the way the Rust signature
+//! takes them.
+
+mod test_utils;
+
+use highrust_transpiler::embedded::{variable_uses, VariableUse};
+use highrust_transpiler::transpile_source;
+use test_utils::transpile;
+
+#[test]
+fn test_item_level_block_is_verbatim() {
+    let code = transpile(
+        "rust {
+             use std::collections::HashMap;
+
+             fn count(words: &[&str]) -> HashMap<String, usize> {
+                 let mut counts = HashMap::new();
+                 for w in words { *counts.entry(w.to_string()).or_insert(0) += 1; } // \"}\"
+                 counts
+             }
+         }
+         fn main() { println(\"{}\", 1); }",
+    );
+    assert!(code.starts_with("use std::collections::HashMap;\n\nfn count(words: &[&str]) -> HashMap<String, usize> {\n"), "got: {}", code);
+    assert!(code.contains("\n    for w in words { *counts.entry(w.to_string()).or_insert(0) += 1; } // \"}\"\n"), "got: {}", code);
+    assert!(code.contains("    counts\n}\n"), "got: {}", code);
+}
+
+#[test]
+fn test_statement_level_block_binds_variables() {
+    let code = transpile(
+        "fn main() {
+             let name = \"world\";
+             rust { let greeting = format!(\"hello {}\", name); }
+             println(\"{}\", greeting);
+         }",
+    );
+    assert!(code.contains("    let greeting = format!(\"hello {}\", name);\n    println!(\"{}\", greeting);"), "got: {}", code);
+}
+
+#[test]
+fn test_used_values_are_not_moved_before_block() {
+    let code = transpile(
+        "fn main() {
+             let items: Vec<i32> = vec(1, 2);
+             let label: String = \"items\";
+             let saved = label;
+             rust {
+                 items.push(3);
+                 println!(\"{}: {:?}\", label, items);
+             }
+         }",
+    );
+    assert!(code.contains("let mut items: Vec<i32> = vec![1, 2];"), "got: {}", code);
+    assert!(code.contains("let saved = label.clone();"), "got: {}", code);
+}
+
+#[test]
+fn test_values_passed_by_value_are_not_moved_before_block() {
+    let code = transpile(
+        "fn take(s: String) { println(s); }
+         fn show(s: &String) { println(s); }
+         fn main() {
+             let s: String = \"hi\";
+             let t: String = \"there\";
+             show(s);
+             take(s);
+             if true {
+                 take(t);
+             }
+             take(t);
+             rust {
+                 println!(\"{} {}\", s, t);
+             }
+         }",
+    );
+    assert!(code.contains("    show(&s);\n    take(s.clone());\n"), "got: {}", code);
+    // Moves in nested blocks count too
+    assert!(code.contains("        take(t.clone());\n"), "got: {}", code);
+    assert!(code.contains("    take(t.clone());\n"), "got: {}", code);
+
+    // Without a later block, values are moved
+    let code = transpile("fn take(s: String) {}\nfn main() {\n    let s: String = \"hi\";\n    rust { let _ = &s; }\n    take(s);\n}\n");
+    assert!(code.contains("    take(s);\n"), "got: {}", code);
+}
+
+#[test]
+fn test_rust_function_callers_borrow_by_signature() {
+    let code = transpile(
+        "@rust fn total(values: &Vec<i64>) -> i64 {
+             values.iter().sum()
+         }
+         @rust fn add_one(values: &mut Vec<i64>, extra: i64) {
+             values.push(extra + 1); // not a closing }
+         }
+         @rust fn consume(values: Vec<i64>) -> usize { values.len() }
+         fn main() {
+             let values: Vec<i64> = vec(1, 2, 3);
+             add_one(values, 4);
+             let sum = total(values) + 1;
+             println(\"{} {}\", sum, consume(values));
+         }",
+    );
+    assert!(code.starts_with("fn total(values: &Vec<i64>) -> i64 {\n    values.iter().sum()\n}\n"), "got: {}", code);
+    assert!(code.contains("fn consume(values: Vec<i64>) -> usize { values.len() }"), "got: {}", code);
+    assert!(code.contains("let mut values: Vec<i64> = vec![1, 2, 3];"), "got: {}", code);
+    assert!(code.contains("add_one(&mut values, 4);"), "got: {}", code);
+    assert!(code.contains("let sum = total(&values) + 1;"), "got: {}", code);
+    assert!(code.contains("consume(values)"), "got: {}", code);
+}
+
+#[test]
+fn test_invalid_rust_signature_is_reported() {
+    let error = transpile_source("@rust fn broken(values: ) -> i64 { 0 }").unwrap_err();
+    let message = error.to_string();
+    assert!(message.contains("invalid Rust at 24..25"), "got: {}", message);
+}
+
+#[test]
+fn test_variable_uses() {
+    let uses = variable_uses(
+        "let total = a + b.len(); c += 1; v.push(x); f(&mut d); s.field; std::mem::swap(e, g); println!(\"{}\", h); // i",
+        |method| method == "push",
+    );
+    let names: Vec<&str> = uses.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["total", "a", "b", "c", "v", "x", "f", "d", "s", "e", "g", "h"]);
+    let mutated: Vec<&str> = uses
+        .iter()
+        .filter(|(_, use_kind)| *use_kind == VariableUse::Mutated)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(mutated, ["total", "c", "v", "d"]);
+}
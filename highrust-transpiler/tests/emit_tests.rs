@@ -0,0 +1,76 @@
+//! Tests for emitting the pipeline's intermediate forms.
+//!
+//! These tests verify that each stage has a stable text form and a JSON
+//! form, that stages combine in pipeline order, and that the pipeline only
+//! runs as far as the stages asked for need.
+
+use highrust_transpiler::emit::{emit, Emitted, Stage};
+use highrust_transpiler::TranspileOptions;
+use serde_json::Value;
+
+const SOURCE: &str = "fn show(s: String) {\n    println(s);\n}\n\nfn main() {\n    let v = Vec::new();\n    v.push(1);\n}\n";
+
+fn emit_stages(source: &str, stages: &[Stage]) -> Emitted {
+    emit(source, None, stages, &TranspileOptions::default()).unwrap()
+}
+
+#[test]
+fn test_stage_names_round_trip() {
+    for stage in [Stage::Tokens, Stage::Ast, Stage::Ownership, Stage::Ir, Stage::Rust] {
+        assert_eq!(stage.name().parse::<Stage>(), Ok(stage));
+    }
+    assert!("llvm-ir".parse::<Stage>().unwrap_err().contains("expected tokens, ast, ownership, ir or rust"));
+}
+
+#[test]
+fn test_tokens_show_the_parse_tree() {
+    let emitted = emit_stages("fn main() {}\n", &[Stage::Tokens]);
+    assert_eq!(emitted.stages(), [Stage::Tokens]);
+    let text = emitted.to_text();
+    assert!(text.starts_with("root 0..13\n  module 0..13\n    function_def 0..12\n      fn_keyword 0..2 \"fn\"\n"), "got:\n{}", text);
+    assert!(text.contains("        identifier 3..7 \"main\"\n"), "got:\n{}", text);
+
+    // Only the parse tree is built, so a module that would not lower still emits tokens
+    let emitted = emit_stages("fn main() {}\nexport missing\n", &[Stage::Tokens]);
+    assert!(emitted.ast.is_none() && emitted.tokens.is_some());
+}
+
+#[test]
+fn test_stages_combine_in_pipeline_order() {
+    let emitted = emit_stages(SOURCE, &[Stage::Rust, Stage::Ownership]);
+    assert_eq!(emitted.stages(), [Stage::Ownership, Stage::Rust]);
+    let text = emitted.to_text();
+    let (ownership, rust) = text.split_once("=== rust ===\n").unwrap();
+    assert_eq!(
+        ownership,
+        "=== ownership ===\nmutable: v\nborrowed: -\nborrowed mutably: -\nmoved: -\ncloned: -\nlifetimes: -\nborrows: -\n"
+    );
+    assert!(rust.contains("let mut v = Vec::new();\n    v.push(1);"), "got:\n{}", rust);
+
+    let text = emit_stages(SOURCE, &[Stage::Ast, Stage::Ir]).to_text();
+    assert!(text.starts_with("=== ast ===\nModule {\n    items: [\n        Function(\n"), "got:\n{}", text);
+    assert!(text.contains("=== ir ===\nLoweredModule {\n"), "got:\n{}", text);
+}
+
+#[test]
+fn test_json_has_a_key_per_stage() {
+    let json = emit_stages(SOURCE, &[Stage::Ast, Stage::Ownership, Stage::Ir, Stage::Rust]).to_json();
+    let value: Value = serde_json::from_str(&json).unwrap();
+    let keys: Vec<&str> = value.as_object().unwrap().keys().map(String::as_str).collect();
+    assert_eq!(keys, ["ast", "ir", "ownership", "rust"]);
+
+    let main = &value["ast"]["items"][1]["Function"];
+    assert_eq!(main["name"], "main");
+    assert_eq!(main["span"]["start"], 40);
+    assert_eq!(value["ownership"]["mutable_vars"], serde_json::json!(["v"]));
+    assert_eq!(value["ir"]["items"][1]["Function"]["body"]["stmts"][0]["Let"]["mutable"], true);
+    assert!(value["rust"].as_str().unwrap().starts_with("fn show(s: String) {"));
+}
+
+#[test]
+fn test_emit_reports_errors_of_the_stages_it_runs() {
+    let error = emit("fn main( {", None, &[Stage::Tokens], &TranspileOptions::default()).unwrap_err();
+    assert_eq!(error.diagnostic().code, "E0001");
+    let error = emit("fn main() {}\nexport missing\n", None, &[Stage::Ir], &TranspileOptions::default()).unwrap_err();
+    assert_eq!(error.diagnostic().code, "E0104");
+}
@@ -0,0 +1,164 @@
+//! Tests for explaining inferred code.
+//!
+//! These tests verify that every inferred `mut`, borrow, `.clone()`,
+//! `.to_string()` and lifetime parameter is reported with its reason and
+//! the uses that forced it, that `explain` writes the reasons as comments
+//! above the generated code, and that the decisions for one binding can be
+//! looked up by a position in the source.
+
+use highrust_transpiler::explain::{explain_position, explain_source, BindingExplanation, Decision, Inference, InferenceKind};
+use highrust_transpiler::sourcemap::{LineIndex, Position};
+use highrust_transpiler::{transpile_source_with, TranspileOptions};
+
+const SOURCE: &str = r#"fn show(text: &String) {
+    println(text);
+}
+
+fn main() {
+    let s = "a".to_string();
+    let t = s;
+    let u = s;
+    show(u);
+    let n = u;
+    let v = Vec::new();
+    v.push(1);
+    rust {
+        println!("{} {}", u, v.len());
+    }
+}
+"#;
+
+/// The descriptions of the inferences for `source`.
+fn describe(source: &str) -> Vec<String> {
+    let index = LineIndex::new(source);
+    explain_source(source).unwrap().iter().map(|inference| inference.describe(&index)).collect()
+}
+
+fn explain(source: &str) -> String {
+    transpile_source_with(source, &TranspileOptions { explain: true, ..TranspileOptions::default() }).unwrap()
+}
+
+#[test]
+fn test_inferences_name_their_reasons() {
+    assert_eq!(
+        describe(SOURCE),
+        [
+            "cloned: `s` was moved earlier at line 7",
+            "&: `u` is passed where `&String` is expected",
+            "cloned: `u` is used again at line 13",
+            "mut: `v` is mutated by `.push()` at line 12",
+        ]
+    );
+}
+
+#[test]
+fn test_mutable_parameter_names_the_mutation_it_follows() {
+    // Mutability is tracked by name, so `take` gets `mut v` from `main`
+    let source = "fn take(v: Vec<i64>) -> usize {\n    v.len()\n}\n\nfn main() {\n    let v: Vec<i64> = vec(1, 2);\n    v.push(3);\n    println(\"{}\", take(v));\n}\n";
+    assert_eq!(
+        describe(source),
+        [
+            "mut: `v` shares its name with a variable in `main` that is mutated by `.push()` at line 7",
+            "mut: `v` is mutated by `.push()` at line 7",
+        ]
+    );
+}
+
+#[test]
+fn test_inferences_locate_their_code() {
+    let inferences = explain_source(SOURCE).unwrap();
+    let Inference { kind, name, span, uses, statement, .. } = &inferences[3];
+    assert_eq!((*kind, name.as_str()), (InferenceKind::Mutable, "v"));
+    assert_eq!(&SOURCE[span.start..span.end], "v");
+    assert_eq!(&SOURCE[statement.start..statement.end], "let v = Vec::new();");
+    assert_eq!(uses.iter().map(|use_| &SOURCE[use_.start..use_.end]).collect::<Vec<_>>(), ["v.push(1)"]);
+
+    // A borrow belongs to the innermost statement holding it
+    let source = "fn show(s: &String) {\n    println(s);\n}\n\nfn main() {\n    let s = \"a\".to_string();\n    if true {\n        show(s);\n    }\n}\n";
+    let borrow = &explain_source(source).unwrap()[0];
+    assert_eq!(borrow.kind, InferenceKind::Borrow);
+    assert_eq!(&source[borrow.statement.start..borrow.statement.end], "show(s)");
+}
+
+#[test]
+fn test_explain_comments_the_generated_code() {
+    let code = explain(SOURCE);
+    assert!(code.contains("    // cloned: `s` was moved earlier at line 7\n    let u = s.clone();\n"), "got:\n{}", code);
+    assert!(code.contains("    // &: `u` is passed where `&String` is expected\n    show(&u);\n"), "got:\n{}", code);
+    assert!(code.contains("    // mut: `v` is mutated by `.push()` at line 12\n    let mut v = Vec::new();\n"), "got:\n{}", code);
+
+    // Nothing is explained unless asked for
+    assert!(!transpile_source_with(SOURCE, &TranspileOptions::default()).unwrap().contains("//"));
+}
+
+#[test]
+fn test_parameters_conversions_and_lifetimes() {
+    let source = "fn first(s: &str) -> &str {\n    s\n}\n\nfn fill(v: Vec<i32>) {\n    v.push(1);\n}\n\nfn greet(name: String) {\n    println(name);\n}\n\nfn main() {\n    greet(\"hi\");\n}\n";
+    let code = explain(source);
+    assert!(
+        code.starts_with("// lifetime: `first` returns a reference, so `'a` ties it to the parameters it borrows from\nfn first<'a>("),
+        "got:\n{}",
+        code
+    );
+    assert!(code.contains("// mut: `v` is mutated by `.push()` at line 6\nfn fill(mut v: Vec<i32>) {\n"), "got:\n{}", code);
+    assert!(code.contains("    // to_string: `\"hi\"` is `&str` where `String` is expected\n    greet(\"hi\".to_string());\n"), "got:\n{}", code);
+}
+
+/// Explains the binding at `line` and `column` of `source`.
+fn binding_at(source: &str, line: usize, column: usize) -> Option<BindingExplanation> {
+    explain_position(source, None, Position::new(line, column)).unwrap()
+}
+
+#[test]
+fn test_bindings_are_found_by_declaration_or_use() {
+    // `let u = s;` declares `u`, and `show(u)` uses it
+    let declared = binding_at(SOURCE, 8, 9).unwrap();
+    assert_eq!(binding_at(SOURCE, 9, 10), Some(declared.clone()));
+    assert_eq!((declared.name.as_str(), declared.function.as_str()), ("u", "main"));
+    assert_eq!(declared.decisions, [Decision::Cloned, Decision::Borrowed]);
+    assert_eq!(declared.uses.len(), 3);
+
+    assert_eq!(binding_at(SOURCE, 6, 9).unwrap().decisions, [Decision::Moved, Decision::Cloned]);
+    assert_eq!(binding_at(SOURCE, 12, 5).unwrap().decisions, [Decision::Mutable]);
+    let text = binding_at(SOURCE, 1, 9).unwrap();
+    assert_eq!((text.name.as_str(), text.function.as_str()), ("text", "show"));
+    assert!(binding_at(SOURCE, 10, 9).unwrap().decisions.is_empty());
+
+    // Keywords, calls and blank lines name no binding
+    assert_eq!(binding_at(SOURCE, 8, 5), None);
+    assert_eq!(binding_at(SOURCE, 9, 5), None);
+    assert_eq!(binding_at(SOURCE, 4, 1), None);
+    assert_eq!(binding_at(SOURCE, 40, 1), None);
+}
+
+#[test]
+fn test_binding_descriptions_show_the_uses_behind_each_decision() {
+    let index = LineIndex::new(SOURCE);
+    assert_eq!(
+        binding_at(SOURCE, 8, 9).unwrap().describe(&index),
+        "`u` in `main`, declared at line 8: cloned, borrowed
+
+&: `u` is passed where `&String` is expected
+       9 | show(u);
+
+cloned: `u` is used again at line 13
+      10 | let n = u;
+      13 | rust {
+
+used at lines 9, 10, 13
+"
+    );
+    assert_eq!(binding_at(SOURCE, 10, 9).unwrap().describe(&index), "`n` in `main`, declared at line 10: used as written\n");
+}
+
+#[test]
+fn test_shadowing_starts_a_new_binding() {
+    let source = "fn main() {\n    let v = Vec::new();\n    v.push(1);\n    let v = 2;\n    println(v);\n}\n";
+    let first = binding_at(source, 3, 5).unwrap();
+    assert_eq!((first.decisions.as_slice(), first.uses.len()), ([Decision::Mutable].as_slice(), 1));
+    let second = binding_at(source, 5, 13).unwrap();
+    assert_eq!(&source[second.declaration.start..second.declaration.end], "v");
+    assert_eq!(second.declaration.start, source.find("let v = 2").unwrap() + 4);
+    assert!(second.inferences.iter().all(|inference| inference.span.start >= second.declaration.start));
+    assert_eq!(second.uses.len(), 1);
+}
@@ -6,10 +6,9 @@
 mod test_utils;
 
 use highrust_transpiler::{
-    ast::{Module, Span},
     codegen::CodegenContext,
     parser::parse,
-    lowering::{lower_module, LoweredModule},
+    lowering::lower_module,
 };
 use test_utils::{get_fixture_files, get_expected_path, read_file_content};
 
@@ -30,7 +29,7 @@ fn transpile_highrust_to_rust(source: &str) -> String {
                     // Generate Rust code using the lowered IR
                     let mut ctx = CodegenContext::new();
                     match highrust_transpiler::codegen::generate_rust_code(&lowered, &mut ctx) {
-                        Ok(code) => code,
+                        Ok(generated) => generated.code,
                         Err(e) => format!("// Failed to generate Rust code: {:?}", e)
                     }
                 },
@@ -84,8 +83,10 @@ fn test_golden_files() {
 }
 
 /// Helper to generate expected outputs for fixture files
-/// This is not a test but a utility to create initial expected output files
+/// This is not a test but a utility to create initial expected output files;
+/// run it with `--ignored` once the output is right, and check it compiles
 #[test]
+#[ignore]
 fn generate_expected_outputs() {
     let fixtures_dir = "tests/fixtures/basic";
     let expected_dir = "tests/expected/basic";
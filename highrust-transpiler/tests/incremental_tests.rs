@@ -0,0 +1,150 @@
+//! Tests for incremental transpilation of projects.
+//!
+//! These tests verify that a rebuild transpiles only the modules whose
+//! sources or imported Rust files changed and the modules importing them,
+//! that errors leave the earlier outputs in place until fixed, and that
+//! removing a module deletes its outputs and updates the `mod` declarations
+//! of its parent.
+
+mod test_utils;
+
+use highrust_transpiler::incremental::{BuildReport, ProjectBuild};
+use highrust_transpiler::project::ModuleGraph;
+use highrust_transpiler::TranspileOptions;
+use std::path::PathBuf;
+use test_utils::{scratch_dir, write_file};
+
+/// A project where `main` imports `util`, which imports `text`, and `solo`
+/// and `net::http` stand alone; returns a build of it into `out`.
+fn project(name: &str) -> (PathBuf, ProjectBuild) {
+    let root = scratch_dir(name);
+    let src = root.join("src");
+    write_file(&src, "main.hrs", "import util\n\nfn main() {\n    util::greet(\"x\");\n}\n");
+    write_file(&src, "util.hrs", "import text\n\nfn greet(name: String) {\n    text::show(name);\n}\n\nexport greet\n");
+    write_file(&src, "text.hrs", "fn show(s: String) {\n    println(s);\n}\n\nexport show\n");
+    write_file(&src, "solo.hrs", "fn solo() {}\n");
+    write_file(&src, "net/http.hrs", "fn ping() {}\n");
+    let build = ProjectBuild::new(&src, root.join("out"), TranspileOptions::default());
+    (root, build)
+}
+
+/// The names of the source files a build transpiled, in order.
+fn transpiled(report: &BuildReport) -> Vec<String> {
+    report.transpiled.iter().map(|file| file.file_name().unwrap().to_string_lossy().into_owned()).collect()
+}
+
+#[test]
+fn test_first_build_transpiles_everything_once() {
+    let (root, mut build) = project("incremental-first");
+    let report = build.rebuild();
+    assert!(report.errors.is_empty(), "errors: {:?}", report.errors);
+    assert_eq!(transpiled(&report), ["text.hrs", "util.hrs", "main.hrs", "http.hrs", "solo.hrs"]);
+    for file in ["main.rs", "main.rs.map", "util.rs", "net.rs", "net/http.rs"] {
+        assert!(root.join("out").join(file).is_file(), "missing {}", file);
+    }
+
+    // Nothing changed, so nothing is done
+    assert!(build.rebuild().is_empty());
+    // Saving a file without changing it is no change either
+    write_file(&root.join("src"), "solo.hrs", "fn solo() {}\n");
+    assert!(build.rebuild().is_empty());
+}
+
+#[test]
+fn test_changes_rebuild_modules_and_their_dependents() {
+    let (root, mut build) = project("incremental-dependents");
+    build.rebuild();
+    let src = root.join("src");
+
+    write_file(&src, "solo.hrs", "fn solo() {\n    println(\"changed\");\n}\n");
+    assert_eq!(transpiled(&build.rebuild()), ["solo.hrs"]);
+
+    // `main` imports `text` only through `util`
+    write_file(&src, "text.hrs", "fn show(s: &str) {\n    println(s);\n}\n\nexport show\n");
+    let report = build.rebuild();
+    assert_eq!(transpiled(&report), ["text.hrs", "util.hrs", "main.hrs"]);
+    let util = std::fs::read_to_string(root.join("out/util.rs")).unwrap();
+    assert!(util.contains("text::show(name.as_str())"), "got:\n{}", util);
+}
+
+#[test]
+fn test_imported_rust_changes_rebuild_their_importers() {
+    let (root, mut build) = project("incremental-rust-imports");
+    let src = root.join("src");
+    write_file(&src, "fill.rs", "pub fn fill(data: Vec<u8>) -> usize { data.len() }\n");
+    write_file(&src, "solo.hrs", "import rust \"fill.rs\"\n\nfn solo() {\n    let data: Vec<u8> = vec(1);\n    fill::fill(data);\n}\n");
+    build.rebuild();
+    assert!(build.imports_rust_file(&src.join("fill.rs")));
+    assert!(!build.imports_rust_file(&src.join("main.rs")));
+
+    // The copy is refreshed, and the importer follows the new signature
+    let fill = "pub fn fill(data: &mut Vec<u8>) { data.push(0); }\n";
+    write_file(&src, "fill.rs", fill);
+    let report = build.rebuild();
+    assert_eq!(transpiled(&report), ["solo.hrs"]);
+    assert_eq!(std::fs::read_to_string(root.join("out/fill.rs")).unwrap(), fill);
+    let solo = std::fs::read_to_string(root.join("out/solo.rs")).unwrap();
+    assert!(solo.contains("fill::fill(&mut data);"), "got:\n{}", solo);
+    assert!(build.rebuild().is_empty());
+}
+
+#[test]
+fn test_errors_keep_outputs_until_fixed() {
+    let (root, mut build) = project("incremental-errors");
+    build.rebuild();
+    let src = root.join("src");
+    let before = std::fs::read_to_string(root.join("out/solo.rs")).unwrap();
+
+    // A file that does not parse stops the project from loading
+    write_file(&src, "solo.hrs", "fn solo( {\n");
+    let report = build.rebuild();
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].diagnostic().code, "E0001");
+    assert_eq!(std::fs::read_to_string(root.join("out/solo.rs")).unwrap(), before);
+
+    // A module that does not lower fails alone, and is tried again once fixed
+    write_file(&src, "solo.hrs", "fn solo() {}\n\nexport missing\n");
+    write_file(&src, "net/http.hrs", "fn ping() {\n    println(\"pong\");\n}\n");
+    let report = build.rebuild();
+    assert_eq!(transpiled(&report), ["http.hrs", "solo.hrs"]);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].diagnostic().code, "E0104");
+    assert!(std::fs::read_to_string(root.join("out/net/http.rs")).unwrap().contains("pong"));
+
+    write_file(&src, "solo.hrs", "fn solo() {}\n\nexport solo\n");
+    let report = build.rebuild();
+    assert!(report.errors.is_empty(), "errors: {:?}", report.errors);
+    assert_eq!(transpiled(&report), ["solo.hrs"]);
+}
+
+#[test]
+fn test_removed_modules_lose_their_outputs() {
+    let (root, mut build) = project("incremental-removed");
+    build.rebuild();
+    let out = root.join("out");
+
+    std::fs::remove_dir_all(root.join("src/net")).unwrap();
+    let report = build.rebuild();
+    // The crate root no longer declares `mod net`
+    assert_eq!(transpiled(&report), ["main.hrs"]);
+    assert!(!std::fs::read_to_string(out.join("main.rs")).unwrap().contains("mod net"));
+    let mut removed: Vec<String> = report.removed.iter().map(|file| file.display().to_string()).collect();
+    removed.sort();
+    assert_eq!(removed, ["net.rs", "net/http.rs", "net/http.rs.map"]);
+    assert!(!out.join("net").exists());
+
+    // Removing a module rebuilds the modules importing it
+    std::fs::remove_file(root.join("src/text.hrs")).unwrap();
+    let report = build.rebuild();
+    assert_eq!(transpiled(&report), ["util.hrs", "main.hrs"]);
+    assert!(!out.join("text.rs").exists());
+}
+
+#[test]
+fn test_dependents_include_indirect_importers() {
+    let (root, _) = project("incremental-graph");
+    let graph = ModuleGraph::load(root.join("src")).unwrap();
+    let path = |name: &str| vec![name.to_string()];
+    assert_eq!(graph.dependents(&path("text")), [path("util"), Vec::new()]);
+    assert!(graph.dependents(&path("solo")).is_empty());
+}
@@ -0,0 +1,103 @@
+//! Tests for the lints configured in the `[lints]` table.
+//!
+//! These tests verify that implicit clones, implicit borrows and embedded
+//! Rust are reported at the configured levels, that `deny` fails the
+//! module, that the strictness raises or lowers the levels, and that the
+//! examples of the lints' explanations report their codes.
+
+use highrust_transpiler::config::{Config, LintConfig, LintLevel, Strictness};
+use highrust_transpiler::diagnostic::{explanation, Severity};
+use highrust_transpiler::lint::lint_module;
+use highrust_transpiler::parser::parse;
+use highrust_transpiler::{transpile_source_with, TranspileOptions};
+
+const SOURCE: &str = r#"fn show(text: &String) {
+    println(text);
+}
+
+fn main() {
+    let s = "a".to_string();
+    let t = s;
+    let u = s;
+    show(u);
+    rust {
+        println!("{}", t);
+    }
+}
+"#;
+
+/// Options with `lints` at the given strictness.
+fn options(lints: LintConfig, strictness: Strictness) -> TranspileOptions {
+    TranspileOptions { lints, strictness, ..TranspileOptions::default() }
+}
+
+/// The severity, code and primary label text of each lint for `source`.
+fn lints(source: &str, options: &TranspileOptions) -> Vec<(Severity, &'static str, String)> {
+    let module = parse(source).unwrap();
+    lint_module(&module, source, options)
+        .unwrap()
+        .into_iter()
+        .map(|diagnostic| {
+            let span = diagnostic.primary_label().unwrap().span.clone();
+            (diagnostic.severity, diagnostic.code, source[span.start..span.end].to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn test_lints_report_at_their_levels() {
+    assert!(lints(SOURCE, &TranspileOptions::default()).is_empty());
+
+    let warn = LintConfig { implicit_clone: LintLevel::Warn, embedded_rust: LintLevel::Deny, ..LintConfig::default() };
+    let found = lints(SOURCE, &options(warn.clone(), Strictness::Standard));
+    assert_eq!(found.len(), 2, "got: {:?}", found);
+    assert_eq!(found[0], (Severity::Warning, "E0700", "s".to_string()));
+    assert_eq!(found[1].0, Severity::Error);
+    assert_eq!(found[1].1, "E0702");
+    assert!(found[1].2.contains("println!"), "got: {:?}", found[1]);
+
+    // Denied lints fail the module with all of their diagnostics
+    let error = transpile_source_with(SOURCE, &options(warn, Strictness::Standard)).unwrap_err();
+    let codes: Vec<&str> = error.diagnostics().iter().map(|diagnostic| diagnostic.code).collect();
+    assert_eq!(codes, ["E0702"]);
+    assert!(error.diagnostic().notes[0].contains("`lints.embedded_rust` is `deny`"), "got: {:?}", error.diagnostic());
+}
+
+#[test]
+fn test_strictness_adjusts_the_levels() {
+    // Strict reports inferred clones and borrows, but not embedded Rust
+    let found = lints(SOURCE, &options(LintConfig::default(), Strictness::Strict));
+    let codes: Vec<(Severity, &str)> = found.iter().map(|(severity, code, _)| (*severity, *code)).collect();
+    assert_eq!(codes, [(Severity::Warning, "E0700"), (Severity::Warning, "E0701")]);
+
+    // Relaxed never fails a module over a lint
+    let deny = LintConfig { implicit_clone: LintLevel::Deny, ..LintConfig::default() };
+    assert!(transpile_source_with(SOURCE, &options(deny.clone(), Strictness::Standard)).is_err());
+    let relaxed = options(deny, Strictness::Relaxed);
+    assert_eq!(lints(SOURCE, &relaxed)[0].0, Severity::Warning);
+    assert!(transpile_source_with(SOURCE, &relaxed).is_ok());
+}
+
+#[test]
+fn test_configured_lints_apply() {
+    let config = Config::parse("[build]\nstrictness = \"strict\"\n\n[lints]\nimplicit_borrow = \"deny\"\n").unwrap();
+    let error = transpile_source_with(SOURCE, &config.transpile_options()).unwrap_err();
+    assert_eq!(error.diagnostic().code, "E0701");
+}
+
+#[test]
+fn test_explanation_examples_report_their_lint() {
+    let deny = LintConfig { implicit_clone: LintLevel::Deny, implicit_borrow: LintLevel::Deny, embedded_rust: LintLevel::Deny };
+    let options = options(deny, Strictness::Standard);
+    for code in ["E0700", "E0701", "E0702"] {
+        let examples: Vec<&str> = explanation(code)
+            .unwrap()
+            .split("```highrust\n")
+            .skip(1)
+            .map(|block| block.split("```").next().unwrap())
+            .collect();
+        let error = transpile_source_with(examples[0], &options).unwrap_err();
+        assert_eq!(error.diagnostic().code, code, "example:\n{}", examples[0]);
+        assert!(transpile_source_with(examples[1], &options).is_ok(), "fix for {} fails:\n{}", code, examples[1]);
+    }
+}
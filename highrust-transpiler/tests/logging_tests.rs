@@ -0,0 +1,75 @@
+//! Tests for the transpiler's log.
+//!
+//! These tests verify that each phase logs under its own target, so a
+//! filter such as `parser=trace` selects one phase, and that parsing no
+//! longer prints to stdout.
+
+use highrust_transpiler::parser::parse;
+use highrust_transpiler::transpile_source;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tracing::Level;
+
+/// A log destination that keeps what is written to it.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `f` with events at `level` and above logged, and returns the log.
+fn capture(level: Level, f: impl FnOnce()) -> String {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .without_time()
+        .finish();
+    tracing::subscriber::with_default(subscriber, f);
+    let log = captured.0.lock().unwrap();
+    String::from_utf8(log.clone()).unwrap()
+}
+
+#[test]
+fn test_each_phase_logs_under_its_target() {
+    let source = "fn greet(name: String) {\n    println(name);\n}\n\nfn main() {\n    greet(\"hi\");\n}\n";
+    let log = capture(Level::TRACE, || {
+        transpile_source(source).unwrap();
+    });
+    for line in [
+        "DEBUG parser: built module items=2",
+        "TRACE parser: parsed function name=greet",
+        "TRACE ownership: analyzing function name=main",
+        "DEBUG ownership: analyzed module",
+        "TRACE lowering: lowering function name=greet",
+        "DEBUG lowering: lowered module items=2",
+        "TRACE codegen: generating function name=main",
+        "DEBUG codegen: generated module items=2",
+    ] {
+        assert!(log.contains(line), "missing `{}` in:\n{}", line, log);
+    }
+}
+
+#[test]
+fn test_recovery_is_logged_at_debug() {
+    let source = "fn main() {\n    let x = ;\n    let y = 1;\n}\n";
+    let log = capture(Level::DEBUG, || {
+        assert!(parse(source).is_err());
+    });
+    assert!(log.contains("DEBUG parser: source does not parse as a whole; recovering"), "got:\n{}", log);
+    assert!(log.contains("DEBUG parser: recovered errors=1 items=1"), "got:\n{}", log);
+    assert!(!log.contains("TRACE"), "got:\n{}", log);
+
+    // Nothing is logged above debug
+    assert_eq!(capture(Level::INFO, || drop(parse(source))), "");
+}
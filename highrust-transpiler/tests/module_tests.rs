@@ -0,0 +1,135 @@
+//! Tests for imports, exports and module resolution in the HighRust
+//! transpiler.
+//!
+//! These tests verify that imports become `use` declarations, that only
+//! exported items are `pub`, that imports of other `.hrs` files resolve to
+//! paths from the crate root, and that imported Rust files become modules
+//! whose signatures decide how arguments are passed.
+
+mod test_utils;
+
+use highrust_transpiler::modules::ModuleResolver;
+use highrust_transpiler::{transpile_file, transpile_source};
+use test_utils::{scratch_dir, transpile, write_file};
+
+#[test]
+fn test_import_forms() {
+    let code = transpile(
+        "import std::collections::HashMap
+         import std::fmt::Display as Show
+         import { read_to_string, write as save } from std::fs
+         fn main() { println(\"hi\"); }",
+    );
+    assert!(
+        code.starts_with(
+            "use std::collections::HashMap;\nuse std::fmt::Display as Show;\nuse std::fs::{read_to_string, write as save};\n\nfn main() {"
+        ),
+        "got: {}",
+        code
+    );
+}
+
+#[test]
+fn test_exported_items_are_pub() {
+    let code = transpile(
+        "import { Ordering, min } from std::cmp
+         export data Point = { x: i32, y: i32 }
+         export fn norm(p: Point) -> i32 { p.x * p.x + p.y * p.y }
+         fn helper() -> i32 { 1 }
+         fn hidden() -> i32 { 2 }
+         export { helper, Ordering }",
+    );
+    assert!(code.contains("pub use std::cmp::Ordering;\nuse std::cmp::min;\n"), "got: {}", code);
+    assert!(code.contains("pub struct Point {\n    pub x: i32,\n    pub y: i32,\n}"), "got: {}", code);
+    assert!(code.contains("pub fn norm(p: Point) -> i32"), "got: {}", code);
+    assert!(code.contains("pub fn helper() -> i32"), "got: {}", code);
+    assert!(code.contains("\nfn hidden() -> i32"), "got: {}", code);
+}
+
+#[test]
+fn test_unknown_export_is_an_error() {
+    let error = transpile_source("fn main() {}\nexport missing").unwrap_err();
+    let message = format!("{:?}", error);
+    assert!(message.contains("UnknownExport") && message.contains("missing"), "got: {}", message);
+}
+
+#[test]
+fn test_imports_resolve_against_project_files() {
+    let root = scratch_dir("modules");
+    write_file(&root, "main.hrs", "import util::helper\nimport { get } from net::http\nimport serde::Serialize\nfn main() {}");
+    write_file(&root, "util.hrs", "export fn helper() -> i32 { 1 }");
+    write_file(&root, "net/http.hrs", "import { helper } from util\nexport fn get() {}");
+    let resolver = ModuleResolver::new(&root);
+
+    assert_eq!(resolver.module_path(&root.join("main.hrs")), Vec::<String>::new());
+    assert_eq!(resolver.module_path(&root.join("net/http.hrs")), ["net", "http"]);
+    let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(resolver.resolve(&root.join("main.hrs"), &path(&["util", "helper"])), ["crate", "util", "helper"]);
+    assert_eq!(resolver.resolve(&root.join("net/http.hrs"), &path(&["util"])), ["crate", "util"]);
+    assert_eq!(resolver.resolve(&root.join("main.hrs"), &path(&["serde", "Serialize"])), ["serde", "Serialize"]);
+
+    let output = root.join("main.rs");
+    transpile_file(root.join("main.hrs"), output.clone()).unwrap_or_else(|e| panic!("transpilation failed: {:?}", e));
+    let code = std::fs::read_to_string(&output).unwrap();
+    assert!(
+        code.starts_with("use crate::util::helper;\nuse crate::net::http::get;\nuse serde::Serialize;\n"),
+        "got: {}",
+        code
+    );
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_rust_imports_use_signatures() {
+    let root = scratch_dir("rust-imports");
+    write_file(
+        &root,
+        "parser.rs",
+        "pub struct Stats { pub count: usize }
+         pub fn parse(data: &Vec<u8>) -> usize { data.len() }
+         pub fn push_zero(data: &mut Vec<u8>) { data.push(0); }
+         pub fn consume(data: Vec<u8>) -> Stats { Stats { count: data.len() } }
+         fn private_helper() {}",
+    );
+    write_file(&root, "util/math.rs", "pub fn double(values: &Vec<i64>) -> i64 { values.iter().sum::<i64>() * 2 }");
+    write_file(
+        &root,
+        "main.hrs",
+        "import rust \"parser.rs\"
+         import rust \"util/math.rs\" as math
+         import rust util::math::double as twice
+         fn main() {
+             let data: Vec<u8> = vec(1, 2, 3);
+             parser::push_zero(data);
+             let n = parser::parse(data);
+             let values: Vec<i64> = vec(1, 2);
+             println(\"{} {} {}\", n, twice(values), parser::consume(data).count);
+         }",
+    );
+
+    let output = root.join("main.rs");
+    transpile_file(root.join("main.hrs"), output.clone()).unwrap_or_else(|e| panic!("transpilation failed: {:?}", e));
+    let code = std::fs::read_to_string(&output).unwrap();
+    assert!(
+        code.starts_with("mod parser;\n#[path = \"util/math.rs\"]\nmod math;\nuse crate::util::math::double as twice;\n\n"),
+        "got: {}",
+        code
+    );
+    assert!(code.contains("let mut data: Vec<u8> = vec![1, 2, 3];"), "got: {}", code);
+    assert!(code.contains("parser::push_zero(&mut data);"), "got: {}", code);
+    assert!(code.contains("let n = parser::parse(&data);"), "got: {}", code);
+    assert!(code.contains("twice(&values)"), "got: {}", code);
+    assert!(code.contains("parser::consume(data).count"), "got: {}", code);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_invalid_imported_rust_is_an_error() {
+    let root = scratch_dir("invalid-rust-import");
+    write_file(&root, "broken.rs", "pub fn broken(values: ) {}");
+    write_file(&root, "main.hrs", "import rust \"broken.rs\"\nfn main() {}");
+    let error = transpile_file(root.join("main.hrs"), root.join("main.rs")).unwrap_err();
+    let message = format!("{:?}", error);
+    assert!(message.contains("InvalidRust") && message.contains("line 1"), "got: {}", message);
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -5,7 +5,7 @@
 
 use highrust_transpiler::{
     ast::{Block, Expr, FunctionDef, Literal, Module, ModuleItem, Param, Pattern, Span, Stmt},
-    ownership::{OwnershipInference, MutabilityRequirement},
+    ownership::OwnershipInference,
     lowering::lower_module,
     codegen::{generate_rust_code, CodegenContext},
 };
@@ -75,7 +75,7 @@ fn test_variable_reassignment_mutability() {
     // Additionally, verify the generated code
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     
     // Check that the code contains "let mut x"
     assert!(code.contains("let mut x"), 
@@ -147,7 +147,7 @@ fn test_method_call_mutability() {
     // Additionally, verify the generated code
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     
     // Check that the code contains "let mut v"
     assert!(code.contains("let mut v"), 
@@ -0,0 +1,91 @@
+//! Tests for nullable types in the HighRust transpiler.
+//!
+//! These tests verify that `T?` lowers to `Option<T>`, that non-null values are
+//! wrapped in `Some`, and that `?.`, `??` and null checks produce idiomatic
+//! `Option` handling.
+
+mod test_utils;
+
+use test_utils::transpile;
+
+#[test]
+fn test_nullable_type_and_implicit_some() {
+    let code = transpile(
+        "fn find(id: u32?, name: String?) -> i64? {
+             let n: i32? = 5;
+             let missing: i32? = null;
+             find(7, \"Ada\");
+             return n;
+         }",
+    );
+    assert!(code.contains("fn find(id: Option<u32>, name: Option<String>) -> Option<i64>"), "got: {}", code);
+    assert!(code.contains("let n: Option<i32> = Some(5);"), "got: {}", code);
+    assert!(code.contains("let missing: Option<i32> = None;"), "got: {}", code);
+    assert!(code.contains("find(Some(7), Some(\"Ada\".to_string()));"), "got: {}", code);
+    assert!(code.contains("return n.map(|value| i64::from(value));"), "got: {}", code);
+}
+
+#[test]
+fn test_optional_chaining() {
+    let code = transpile(
+        "data Address = { city: String, zip: u32? }
+         data User = { name: String, address: Address? }
+         fn lookup(user: User?) {
+             let name = user?.name;
+             let size = user?.name.len();
+             let zip = user?.address?.zip;
+             let city = user?.address?.city;
+         }",
+    );
+    assert!(code.contains("let name = user.as_ref().map(|user| user.name.clone());"), "got: {}", code);
+    assert!(code.contains("let size = user.as_ref().map(|user| user.name.len());"), "got: {}", code);
+    assert!(
+        code.contains("let zip = user.as_ref().and_then(|user| user.address.as_ref()).and_then(|address| address.zip);"),
+        "got: {}",
+        code
+    );
+    assert!(
+        code.contains("let city = user.as_ref().and_then(|user| user.address.as_ref()).map(|address| address.city.clone());"),
+        "got: {}",
+        code
+    );
+}
+
+#[test]
+fn test_null_coalescing() {
+    let code = transpile(
+        "fn pick(a: String?, b: String?, n: u8?) -> String {
+             let count: u8 = n ?? 0;
+             let either = a ?? b;
+             return either ?? \"none\";
+         }",
+    );
+    assert!(code.contains("let count: u8 = n.unwrap_or_else(|| 0);"), "got: {}", code);
+    assert!(code.contains("let either = a.or_else(|| b);"), "got: {}", code);
+    assert!(code.contains("return either.unwrap_or_else(|| \"none\".to_string());"), "got: {}", code);
+}
+
+#[test]
+fn test_null_checks_narrow() {
+    let code = transpile(
+        "fn show(name: String?, count: i32?) -> i32 {
+             if name != null {
+                 println(name);
+             } else {
+                 println(\"anonymous\");
+             }
+             if count == null {
+                 return 0;
+             }
+             if name == null || count != null {
+                 return count;
+             }
+             return count + 1;
+         }",
+    );
+    assert!(code.contains("if let Some(name) = name.as_ref() {"), "got: {}", code);
+    assert!(code.contains("let Some(count) = count else {"), "got: {}", code);
+    // `count` is known to be non-null after the early return
+    assert!(code.contains("if name.is_none() || true {"), "got: {}", code);
+    assert!(code.contains("return count + 1;"), "got: {}", code);
+}
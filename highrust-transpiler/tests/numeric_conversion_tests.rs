@@ -0,0 +1,130 @@
+//! Tests for numeric literal typing and implicit numeric conversions.
+//!
+//! These tests verify that literals take their type from context, that lossless
+//! widening inserts `From`/`as` conversions, and that narrowing is rejected.
+
+mod test_utils;
+
+use highrust_transpiler::{
+    lowering::LoweringError,
+    transpile_source, TranspilerError,
+};
+use test_utils::transpile;
+
+#[test]
+fn test_float_literals_keep_decimal_point() {
+    let code = transpile("fn main() { let x = 1.0; let y = 2.5; }");
+    assert!(code.contains("let x = 1.0;"), "got: {}", code);
+    assert!(code.contains("let y = 2.5;"), "got: {}", code);
+}
+
+#[test]
+fn test_int_literal_in_float_context() {
+    let code = transpile(
+        "fn scale(x: f64) -> f64 { return x * 2; }
+         fn main() { let f: f32 = 1; let g: f64 = 3 + 4; }",
+    );
+    assert!(code.contains("return x * 2.0;"), "got: {}", code);
+    assert!(code.contains("let f: f32 = 1.0;"), "got: {}", code);
+    assert!(code.contains("let g: f64 = 3.0 + 4.0;"), "got: {}", code);
+}
+
+#[test]
+fn test_integers_mixed_with_float_literals() {
+    let code = transpile("fn main() { let h = 1.5 + 2; let a = 2; let b = a + 1.5; let c = 2 * (a + 1) + 0.5; }");
+    assert!(code.contains("let h = 1.5 + 2.0;"), "got: {}", code);
+    assert!(code.contains("let a = 2;"), "got: {}", code);
+    assert!(code.contains("let b = a as f64 + 1.5;"), "got: {}", code);
+    assert!(code.contains("let c = 2.0 * (a as f64 + 1.0) + 0.5;"), "got: {}", code);
+}
+
+#[test]
+fn test_untyped_large_literal_is_i64() {
+    let code = transpile("fn main() { let big = 3000000000; let small = 7; }");
+    assert!(code.contains("let big = 3000000000i64;"), "got: {}", code);
+    assert!(code.contains("let small = 7;"), "got: {}", code);
+}
+
+#[test]
+fn test_widening_conversions() {
+    let code = transpile(
+        "fn widen(a: i32, b: u8, c: usize, d: f32) {
+             let x: i64 = a;
+             let y: u32 = b;
+             let z: u64 = c;
+             let w: f64 = d;
+             let sum = x + a;
+         }",
+    );
+    assert!(code.contains("let x: i64 = i64::from(a);"), "got: {}", code);
+    assert!(code.contains("let y: u32 = u32::from(b);"), "got: {}", code);
+    assert!(code.contains("let z: u64 = c as u64;"), "got: {}", code);
+    assert!(code.contains("let w: f64 = f64::from(d);"), "got: {}", code);
+    assert!(code.contains("let sum = x + i64::from(a);"), "got: {}", code);
+}
+
+#[test]
+fn test_widening_call_arguments() {
+    let code = transpile(
+        "fn total(n: u64) -> u64 { return n; }
+         fn main() { let count: u32 = 3; total(count); total(5); }",
+    );
+    assert!(code.contains("total(u64::from(count))"), "got: {}", code);
+    assert!(code.contains("total(5)"), "got: {}", code);
+}
+
+#[test]
+fn test_narrowing_is_rejected() {
+    let source = "fn shrink(a: i64) { let x: i32 = a; }";
+    match transpile_source(source) {
+        Err(TranspilerError::LoweringError(LoweringError::NarrowingConversion { from, to, span })) => {
+            assert_eq!(from.to_string(), "i64");
+            assert_eq!(to.to_string(), "i32");
+            assert_eq!(&source[span.start..span.end], "a");
+        }
+        other => panic!("expected narrowing error, got: {:?}", other),
+    }
+
+    let err = transpile_source("fn main() { let x: i32 = 1.5; }").unwrap_err();
+    assert!(
+        matches!(err, TranspilerError::LoweringError(LoweringError::NarrowingConversion { .. })),
+        "got: {:?}",
+        err
+    );
+}
+
+#[test]
+fn test_literal_out_of_range() {
+    let err = transpile_source("fn main() { let x: u8 = 300; }").unwrap_err();
+    match err {
+        TranspilerError::LoweringError(LoweringError::LiteralOutOfRange { value, ty, .. }) => {
+            assert_eq!(value, 300);
+            assert_eq!(ty.to_string(), "u8");
+        }
+        other => panic!("expected out-of-range error, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_literals_beyond_i64_fit_wide_types() {
+    let code = transpile("fn main() { let a: u64 = 18446744073709551615; let b: i64 = -9223372036854775808; let c = 99999999999999999999; }");
+    assert!(code.contains("let a: u64 = 18446744073709551615;"), "got: {}", code);
+    assert!(code.contains("let b: i64 = -9223372036854775808;"), "got: {}", code);
+    assert!(code.contains("let c = 99999999999999999999i128;"), "got: {}", code);
+
+    let err = transpile_source("fn main() { let a: u64 = 18446744073709551616; }").unwrap_err();
+    assert!(
+        matches!(err, TranspilerError::LoweringError(LoweringError::LiteralOutOfRange { value: 18446744073709551616, .. })),
+        "got: {:?}",
+        err
+    );
+}
+
+#[test]
+fn test_unrepresentable_literals_point_at_the_literal() {
+    let source = "fn main() {\n    let big = 1000000000000000000000000000000000000000;\n}\n";
+    let diagnostic = transpile_source(source).unwrap_err().diagnostic();
+    assert_eq!(diagnostic.code, "E0004");
+    let label = diagnostic.primary_label().expect("a label on the literal");
+    assert_eq!(&source[label.span.start..label.span.end], "1000000000000000000000000000000000000000");
+}
@@ -43,7 +43,7 @@ fn test_option_mapping() {
     let analysis_result = ownership_inference.analyze_module(&module);
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     assert!(code.contains("Option"), "Generated code should use Option: {}", code);
 }
 
@@ -118,7 +118,7 @@ fn test_result_mapping() {
     let analysis_result = ownership_inference.analyze_module(&module);
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     assert!(code.contains("Result"), "Generated code should use Result: {}", code);
     assert!(code.contains("Ok(") && code.contains("Err("), "Generated code should use Ok/Err: {}", code);
 }
@@ -155,7 +155,7 @@ fn test_lifetime_inference() {
     let analysis_result = ownership_inference.analyze_module(&module);
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     assert!(code.contains("fn get_ref<'a>"), "Generated code should have lifetime parameter: {}", code);
     assert!(code.contains("&'a i32"), "Generated code should use lifetime in type: {}", code);
 }
@@ -225,7 +225,7 @@ fn test_result_propagation() {
     let analysis_result = ownership_inference.analyze_module(&module);
     let lowered = lower_module(&module).unwrap();
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     assert!(code.contains("get_val()?"), "Generated code should use ? operator for propagation: {}", code);
     assert!(code.contains("Result"), "Generated code should use Result type: {}", code);
 }
\ No newline at end of file
@@ -0,0 +1,169 @@
+//! Tests for generating Cargo packages from HighRust projects.
+//!
+//! These tests verify the layout and manifest of a generated package, that
+//! regenerating it only touches what changed, that directories the
+//! transpiler does not own are left alone, and that the package builds.
+
+mod test_utils;
+
+use highrust_transpiler::config::Config;
+use highrust_transpiler::package::{generate_package, generate_package_in, PackageError};
+use highrust_transpiler::{TranspileOptions, TranspilerError};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use test_utils::{scratch_dir, write_file};
+
+#[path = "../stdlib/highrust_std.rs"]
+mod highrust_std;
+
+/// Writes a small project with a `highrust.toml` and returns its config.
+fn write_project(root: &Path, config: &str) -> Config {
+    write_file(root, "highrust.toml", config);
+    write_file(
+        root,
+        "src/main.hrs",
+        "import util::shout
+         fn main() {
+             let name: String = \"hello\";
+             println(\"{}\", shout(name));
+         }",
+    );
+    write_file(root, "src/util.hrs", "export fn shout(text: &String) -> String { text.to_uppercase() }");
+    Config::load(&root.join("highrust.toml")).unwrap_or_else(|e| panic!("invalid config: {}", e))
+}
+
+#[test]
+fn test_package_layout_and_regeneration() {
+    let root = scratch_dir("package");
+    let config = write_project(
+        &root,
+        "[project]\nname = \"Demo App\"\nversion = \"1.2.0\"\n\n[dependencies]\nrand = \"0.8\"\n",
+    );
+    let options = TranspileOptions::default();
+    let package = generate_package(&config, &options).unwrap_or_else(|e| panic!("generation failed: {:?}", e));
+    let dir = root.join("target/highrust/package");
+    assert_eq!(package.dir, dir);
+    let files: Vec<&str> = package.files.iter().map(|file| file.to_str().unwrap()).collect();
+    assert_eq!(
+        files,
+        ["Cargo.toml", "src/highrust_std.rs", "src/main.rs", "src/main.rs.map", "src/util.rs", "src/util.rs.map"]
+    );
+    assert_eq!(package.changed, package.files);
+
+    let manifest = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    assert!(
+        manifest.contains("[package]\nname = \"demo-app\"\nversion = \"1.2.0\"\nedition = \"2021\"\n\n[dependencies]\nrand = \"0.8\"\n\n[workspace]\n"),
+        "got: {}",
+        manifest
+    );
+    let main = std::fs::read_to_string(dir.join("src/main.rs")).unwrap();
+    assert!(main.starts_with("mod highrust_std;\nmod util;\n\nuse crate::util::shout;\n"), "got: {}", main);
+    assert!(main.contains("shout(&name)"), "got: {}", main);
+
+    let again = generate_package(&config, &options).unwrap();
+    assert!(again.changed.is_empty() && again.removed.is_empty(), "regenerated: {:?}", again);
+
+    write_file(&root, "src/main.hrs", "fn main() { println(\"bye\"); }");
+    std::fs::remove_file(root.join("src/util.hrs")).unwrap();
+    let changed = generate_package(&config, &options).unwrap();
+    assert_eq!(changed.changed, [PathBuf::from("src/main.rs"), PathBuf::from("src/main.rs.map")]);
+    assert_eq!(changed.removed, [PathBuf::from("src/util.rs"), PathBuf::from("src/util.rs.map")]);
+    assert!(!dir.join("src/util.rs").exists());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_package_refuses_foreign_directory() {
+    let root = scratch_dir("package-foreign");
+    let config = write_project(&root, "[build]\noutput_dir = \"legacy\"\n");
+    write_file(&root, "legacy/Cargo.toml", "[package]\nname = \"legacy\"\n");
+    write_file(&root, "legacy/notes.txt", "kept\n");
+    match generate_package(&config, &TranspileOptions::default()) {
+        Err(TranspilerError::PackageError(PackageError::ForeignDirectory { dir, files })) => {
+            assert_eq!(dir, root.join("legacy"));
+            assert_eq!(files, [PathBuf::from("Cargo.toml")]);
+        }
+        other => panic!("expected a foreign directory error, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(std::fs::read_to_string(root.join("legacy/Cargo.toml")).unwrap(), "[package]\nname = \"legacy\"\n");
+    assert!(!root.join("legacy/src").exists());
+
+    // Files out of the generator's way stay, without making the directory foreign
+    std::fs::remove_file(root.join("legacy/Cargo.toml")).unwrap();
+    let package = generate_package(&config, &TranspileOptions::default()).unwrap_or_else(|e| panic!("generation failed: {:?}", e));
+    assert!(!package.files.contains(&PathBuf::from("notes.txt")));
+    assert_eq!(std::fs::read_to_string(root.join("legacy/notes.txt")).unwrap(), "kept\n");
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_package_builds_offline() {
+    let root = scratch_dir("package-build");
+    let config = write_project(&root, "[project]\nname = \"demo\"\n");
+    let dir = root.join("generated");
+    generate_package_in(&config, &dir, &TranspileOptions::default()).unwrap_or_else(|e| panic!("generation failed: {:?}", e));
+
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["run", "--offline", "--quiet"])
+        .current_dir(&dir)
+        .env("CARGO_TARGET_DIR", root.join("target"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "cargo failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "HELLO\n");
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_package_regenerates_after_cargo_build() {
+    let root = scratch_dir("package-rebuild");
+    let config = write_project(&root, "[project]\nname = \"demo\"\n");
+    let dir = root.join("generated");
+    let options = TranspileOptions::default();
+    generate_package_in(&config, &dir, &options).unwrap_or_else(|e| panic!("generation failed: {:?}", e));
+
+    // Cargo leaves `Cargo.lock` and `target/` in the package directory
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo).args(["build", "--offline", "--quiet"]).current_dir(&dir).output().unwrap();
+    assert!(output.status.success(), "cargo failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("Cargo.lock").is_file() && dir.join("target").is_dir());
+
+    write_file(&root, "src/main.hrs", "fn main() { println(\"bye\"); }");
+    let again = generate_package_in(&config, &dir, &options).unwrap_or_else(|e| panic!("regeneration failed: {:?}", e));
+    assert!(again.changed.contains(&PathBuf::from("src/main.rs")), "regenerated: {:?}", again);
+    // Even without the list of generated files, only files in the way count
+    std::fs::remove_file(dir.join(".highrust-generated")).unwrap();
+    generate_package_in(&config, &dir, &options).unwrap_or_else(|e| panic!("regeneration failed: {:?}", e));
+    assert!(dir.join("Cargo.lock").is_file() && dir.join("target").is_dir());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_package_depends_on_runtime_for_inferred_async_main() {
+    let root = scratch_dir("package-async");
+    let config = write_project(&root, "[project]\nname = \"demo\"\n");
+    // `main` awaits without saying `async`, so it still needs a runtime
+    write_file(
+        &root,
+        "src/main.hrs",
+        "async fn fetch() -> i64 { 1 }
+         fn main() { println(\"{}\", await fetch()); }",
+    );
+    let dir = root.join("generated");
+    generate_package_in(&config, &dir, &TranspileOptions::default()).unwrap_or_else(|e| panic!("generation failed: {:?}", e));
+    let main = std::fs::read_to_string(dir.join("src/main.rs")).unwrap();
+    assert!(main.contains("#[tokio::main]\nasync fn main() {"), "got: {}", main);
+    let manifest = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("tokio = { version = \"1\""), "got: {}", manifest);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_standard_helpers() {
+    let groups = highrust_std::group_by(vec![1, 2, 3, 4, 5], |n| n % 2);
+    assert_eq!(groups[&1], [1, 3, 5]);
+    assert_eq!(groups[&0], [2, 4]);
+    assert_eq!(highrust_std::unique(vec![3, 1, 3, 2, 1]), [3, 1, 2]);
+    assert_eq!(highrust_std::partition(vec![1, 2, 3], |n| *n > 1), (vec![2, 3], vec![1]));
+}
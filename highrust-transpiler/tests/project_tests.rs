@@ -0,0 +1,88 @@
+//! Tests for transpiling multi-file HighRust projects.
+//!
+//! These tests verify that a project directory transpiles into a matching
+//! tree of Rust files with `mod` declarations, that signatures of imported
+//! functions and data types drive borrow inference across modules, and that
+//! import cycles and unresolved imports are reported.
+
+mod test_utils;
+
+use highrust_transpiler::modules::ModuleError;
+use highrust_transpiler::project::ModuleGraph;
+use highrust_transpiler::{transpile_project, TranspilerError};
+use std::path::PathBuf;
+use test_utils::{scratch_dir, write_file};
+
+#[test]
+fn test_project_transpiles_to_module_tree() {
+    let root = scratch_dir("project");
+    let src = root.join("src");
+    write_file(
+        &src,
+        "main.hrs",
+        "import { area, Rect } from shapes::rect
+         import net::http
+         import util
+         fn main() {
+             let name: String = \"x\";
+             util::greet(name);
+             println(\"{} {}\", http::get(name), name);
+         }",
+    );
+    write_file(&src, "util.hrs", "export fn greet(name: &String) { println(\"hi {}\", name); }");
+    write_file(&src, "shapes/rect.hrs", "export data Rect = { w: i32, h: i32 }\nexport fn area(r: Rect) -> i32 { r.w * r.h }");
+    write_file(&src, "net/mod.hrs", "export fn host() -> i32 { 0 }");
+    write_file(&src, "net/http.hrs", "export fn get(url: &String) -> usize { url.len() }");
+
+    let graph = ModuleGraph::load(&src).unwrap_or_else(|e| panic!("loading failed: {}", e));
+    let order: Vec<String> = graph.modules().iter().map(|module| module.path.join("::")).collect();
+    assert_eq!(order, ["net::http", "shapes::rect", "util", "", "net"]);
+    assert_eq!(graph.children(&[]), ["net", "shapes", "util"]);
+
+    let out = root.join("out");
+    let mut written = transpile_project(&src, &out).unwrap_or_else(|e| panic!("transpilation failed: {:?}", e));
+    written.sort();
+    let expected: Vec<PathBuf> =
+        ["main.rs", "net/http.rs", "net/mod.rs", "shapes/rect.rs", "shapes.rs", "util.rs"].iter().map(|f| out.join(f)).collect();
+    assert_eq!(written, expected);
+
+    let main = std::fs::read_to_string(out.join("main.rs")).unwrap();
+    assert!(
+        main.starts_with("mod net;\nmod shapes;\nmod util;\n\nuse crate::shapes::rect::{area, Rect};\nuse crate::net::http;\n\nfn main() {"),
+        "got: {}",
+        main
+    );
+    assert!(main.contains("util::greet(&name);"), "got: {}", main);
+    assert!(main.contains("http::get(&name)"), "got: {}", main);
+    assert!(std::fs::read_to_string(out.join("net/mod.rs")).unwrap().starts_with("pub mod http;\n\npub fn host()"));
+    assert_eq!(std::fs::read_to_string(out.join("shapes.rs")).unwrap(), "pub mod rect;\n");
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_import_cycle_is_an_error() {
+    let root = scratch_dir("project-cycle");
+    write_file(&root, "main.hrs", "import a::f\nfn main() {}");
+    write_file(&root, "a.hrs", "import b::g\nexport fn f() {}");
+    write_file(&root, "b.hrs", "import a::f\nexport fn g() {}");
+    match ModuleGraph::load(&root) {
+        Err(ModuleError::Cycle { modules }) => assert_eq!(modules, ["crate::a", "crate::b", "crate::a"]),
+        other => panic!("expected an import cycle, got {:?}", other.map(|_| ())),
+    }
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_unexported_import_is_an_error() {
+    let root = scratch_dir("project-unresolved");
+    write_file(&root, "main.hrs", "import { helper } from util\nfn main() {}");
+    write_file(&root, "util.hrs", "fn helper() {}");
+    let error = transpile_project(&root, &root).unwrap_err();
+    match error {
+        TranspilerError::ModuleError(error) => {
+            assert!(error.to_string().ends_with("`helper` is not exported by `crate::util`"), "got: {}", error)
+        }
+        other => panic!("expected a module error, got {:?}", other),
+    }
+    let _ = std::fs::remove_dir_all(&root);
+}
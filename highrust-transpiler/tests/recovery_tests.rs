@@ -0,0 +1,135 @@
+//! Tests for parser error recovery.
+//!
+//! These tests verify that a file with several syntax errors reports all of
+//! them, that parsing resumes at the next statement or item after each,
+//! that the partial module keeps what parses with error nodes in place of
+//! what does not, and that what parses is still lowered to report its own
+//! errors along with the syntax errors.
+
+mod test_utils;
+
+use highrust_transpiler::ast::{ModuleItem, Stmt};
+use highrust_transpiler::parser::{parse, parse_recovering, ParseError};
+use highrust_transpiler::{transpile_project, transpile_source};
+use test_utils::{scratch_dir, write_file};
+
+/// The source text of each error's primary label.
+fn error_texts<'a>(source: &'a str, errors: &[ParseError]) -> Vec<&'a str> {
+    errors
+        .iter()
+        .map(|error| {
+            let span = error.diagnostic().primary_label().expect("syntax errors have a span").span.clone();
+            let end = source[span.start..].find('\n').map_or(source.len(), |end| span.start + end);
+            &source[span.start..end]
+        })
+        .collect()
+}
+
+#[test]
+fn test_statements_after_an_error_still_parse() {
+    let source = "fn main() {\n    let x = ;\n    println(\"ok\");\n    let y = (1;\n    let z: i32 = 2;\n    z\n}\n";
+    let recovered = parse_recovering(source);
+    assert_eq!(error_texts(source, &recovered.errors), [";", ";"]);
+
+    let [ModuleItem::Function(main)] = recovered.module.items.as_slice() else {
+        panic!("expected one function, got: {:?}", recovered.module.items);
+    };
+    let stmts = &main.body.stmts;
+    assert_eq!(stmts.len(), 5, "got: {:?}", stmts);
+    assert!(matches!(&stmts[0], Stmt::Error(span) if &source[span.start..span.end] == "let x = ;"));
+    assert!(matches!(&stmts[1], Stmt::Expr(_)));
+    assert!(matches!(&stmts[2], Stmt::Error(span) if &source[span.start..span.end] == "let y = (1;"));
+    assert!(matches!(&stmts[3], Stmt::Let { .. }));
+    assert!(matches!(&stmts[4], Stmt::TailExpr(_)));
+}
+
+#[test]
+fn test_items_after_an_error_still_parse() {
+    let source = "import util\n\ndata Point = { x: i32, y: }\n\nfn ok() {\n    if true {\n        f(\n    }\n}\n\nexport fn broken(a: i32 -> i32 { a }\n\nfn last() {\n    println(\"fine\");\n}\n";
+    let recovered = parse_recovering(source);
+    assert_eq!(recovered.errors.len(), 3, "got: {:?}", recovered.errors);
+
+    let items = &recovered.module.items;
+    assert!(matches!(&items[0], ModuleItem::Import(_)));
+    assert!(matches!(&items[1], ModuleItem::Error(span) if source[span.start..span.end].starts_with("data Point")));
+    assert!(matches!(&items[2], ModuleItem::Function(func) if func.name == "ok" && matches!(func.body.stmts[..], [Stmt::Error(_)])));
+    assert!(matches!(&items[3], ModuleItem::Error(span) if source[span.start..span.end].ends_with("{ a }")));
+    assert!(matches!(&items[4], ModuleItem::Function(func) if func.name == "last" && func.body.stmts.len() == 1));
+}
+
+#[test]
+fn test_brackets_in_strings_and_comments_are_not_boundaries() {
+    let source = "fn main() {\n    let s = \"a { b; }\";\n    // not a } boundary;\n    let t = ;\n    rust {\n        let c = '}';\n    }\n}\n\nfn other() { let u = ; }\n";
+    let recovered = parse_recovering(source);
+    assert_eq!(error_texts(source, &recovered.errors), [";", "; }"]);
+    let ModuleItem::Function(main) = &recovered.module.items[0] else {
+        panic!("expected a function, got: {:?}", recovered.module.items);
+    };
+    assert!(matches!(main.body.stmts[..], [Stmt::Let { .. }, Stmt::Error(_), Stmt::EmbeddedRust(_)]), "got: {:?}", main.body.stmts);
+}
+
+#[test]
+fn test_unclosed_function_body() {
+    let source = "fn main() {\n    println(\"a\");\n";
+    let recovered = parse_recovering(source);
+    let [ParseError::UnclosedDelimiter { span }] = recovered.errors.as_slice() else {
+        panic!("expected an unclosed delimiter, got: {:?}", recovered.errors);
+    };
+    assert_eq!(span.start, 10);
+    assert!(matches!(&recovered.module.items[..], [ModuleItem::Function(func)] if func.body.stmts.len() == 1));
+}
+
+#[test]
+fn test_parse_reports_every_error() {
+    let source = "fn main() {\n    let x = ;\n}\n\nfn other() {\n    let y = ;\n}\n";
+    match parse(source) {
+        Err(ParseError::Multiple(errors)) => assert_eq!(errors.len(), 2),
+        other => panic!("expected two errors, got: {:?}", other),
+    }
+    let diagnostics = transpile_source(source).unwrap_err().diagnostics();
+    let lines: Vec<usize> = diagnostics.iter().map(|d| source[..d.primary_label().unwrap().span.start].lines().count()).collect();
+    assert_eq!(lines, [2, 6]);
+
+    // A file that parses has no errors and no error nodes
+    let recovered = parse_recovering("fn main() {\n    let x = 1;\n}\n");
+    assert!(recovered.errors.is_empty());
+    assert!(matches!(&recovered.module.items[..], [ModuleItem::Function(func)] if func.body.stmts.len() == 1));
+}
+
+#[test]
+fn test_statements_that_parse_keep_their_own_errors() {
+    // The literal parses but does not fit in an `i128`
+    let source = "fn main() {\n    let big = 1000000000000000000000000000000000000000;\n}\n";
+    let error = transpile_source(source).unwrap_err();
+    assert_eq!(error.diagnostic().code, "E0004");
+    assert!(error.diagnostic().message.contains("too large"), "got: {}", error.diagnostic().message);
+}
+
+#[test]
+fn test_semantic_errors_are_reported_with_syntax_errors() {
+    let source = "fn narrow(a: i64) {\n    let x: i32 = a;\n}\n\nfn broken() {\n    let y = ;\n}\n\nfn wide(b: i64) {\n    let z: u8 = b;\n}\n\nexport missing\n";
+    let diagnostics = transpile_source(source).unwrap_err().diagnostics();
+    let codes: Vec<&str> = diagnostics.iter().map(|diagnostic| diagnostic.code).collect();
+    assert_eq!(codes, ["E0102", "E0001", "E0102", "E0104"]);
+
+    // Without syntax errors, every item that fails to lower is reported
+    let source = "fn narrow(a: i64) {\n    let x: i32 = a;\n}\n\nfn wide(b: i64) {\n    let z: u8 = b;\n}\n";
+    let lines: Vec<usize> = transpile_source(source)
+        .unwrap_err()
+        .diagnostics()
+        .iter()
+        .map(|d| source[..d.primary_label().unwrap().span.start].lines().count())
+        .collect();
+    assert_eq!(lines, [2, 6]);
+}
+
+#[test]
+fn test_projects_report_semantic_errors_with_syntax_errors() {
+    let root = scratch_dir("recovery-project");
+    write_file(&root, "main.hrs", "fn main() {\n    let y = ;\n}\n\nfn narrow(a: i64) {\n    let x: i32 = a;\n}\n");
+    let error = transpile_project(&root, &root.join("out")).unwrap_err();
+    let codes: Vec<&str> = error.diagnostics().iter().map(|diagnostic| diagnostic.code).collect();
+    assert_eq!(codes, ["E0001", "E0102"]);
+    assert!(error.diagnostics().iter().all(|diagnostic| diagnostic.file.as_deref() == Some(&*root.join("main.hrs"))));
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,109 @@
+//! Tests for implicit `Ok`/`Some` wrapping and error conversion in fallible
+//! functions.
+//!
+//! These tests verify that tail expressions and `return` values of functions
+//! returning `Result` or `Option` are wrapped, that `?` converts errors the
+//! function's error type cannot absorb, and that `Result<T>` generates a module
+//! error enum.
+
+mod test_utils;
+
+use test_utils::transpile;
+
+#[test]
+fn test_tail_and_return_are_wrapped() {
+    let code = transpile(
+        "fn parse(s: &str) -> Result<i32, ParseIntError> { s.parse() }
+         fn double(s: &str) -> Result<i64, ParseIntError> {
+             let n = parse(s)?;
+             if n < 0 {
+                 return 0;
+             }
+             n * 2
+         }
+         fn first(names: [String]) -> String? {
+             if names.is_empty() {
+                 return null;
+             }
+             \"first\"
+         }",
+    );
+    // Values that are already a `Result` pass through
+    assert!(code.contains("    s.parse()\n"), "got: {}", code);
+    assert!(code.contains("return Ok(0);"), "got: {}", code);
+    assert!(code.contains("    Ok(i64::from(n * 2))\n"), "got: {}", code);
+    assert!(code.contains("return None;"), "got: {}", code);
+    assert!(code.contains("    Some(\"first\".to_string())\n"), "got: {}", code);
+}
+
+#[test]
+fn test_branches_and_unit_results() {
+    let code = transpile(
+        "fn sign(n: i32) -> Result<String, String> {
+             if n < 0 {
+                 Err(\"negative\")
+             } else {
+                 \"positive\"
+             }
+         }
+         fn check(n: i32) -> Result<(), String> {
+             sign(n)?;
+         }
+         fn pick(n: i32) -> i32? {
+             match n {
+                 0 => null,
+                 _ => n,
+             }
+         }",
+    );
+    assert!(code.contains("Err(\"negative\".to_string())"), "got: {}", code);
+    assert!(code.contains("Ok(\"positive\".to_string())"), "got: {}", code);
+    assert!(code.contains("    sign(n)?;\n    Ok(())\n"), "got: {}", code);
+    assert!(code.contains("0 => None"), "got: {}", code);
+    assert!(code.contains("_ => Some(n)"), "got: {}", code);
+}
+
+#[test]
+fn test_values_unwrapped_by_question_mark_are_wrapped() {
+    let code = transpile(
+        "fn fetch_users(s: String) -> Result<u32, String> {
+             let users = ext(s)?;
+             users
+         }
+         fn fetch_again(s: String) -> Result<u32, String> {
+             let users = ext(s)?;
+             let users = ext(s);
+             users
+         }",
+    );
+    // `ext` is unknown, but what `?` unwraps from it is a plain value
+    assert!(code.contains("    let users = ext(s)?;\n    Ok(users)\n}"), "got: {}", code);
+    // A later binding of unknown type shadows it
+    assert!(code.contains("    let users = ext(s);\n    users\n}"), "got: {}", code);
+}
+
+#[test]
+fn test_question_mark_converts_to_string_errors() {
+    let code = transpile(
+        "fn parse(s: &str) -> Result<i32, ParseIntError> { s.parse() }
+         fn run(s: &str) -> Result<i32, String> { parse(s)? }",
+    );
+    assert!(code.contains("Ok(parse(s).map_err(|error| error.to_string())?)"), "got: {}", code);
+}
+
+#[test]
+fn test_module_error_enum() {
+    let code = transpile(
+        "fn parse(s: &str) -> Result<i32, ParseIntError> { s.parse() }
+         fn read(path: &str) -> Result<String, io::Error> { load(path) }
+         fn run(path: &str) -> Result<i32> {
+             let text = read(path)?;
+             parse(text)?
+         }",
+    );
+    assert!(code.contains("fn run(path: &str) -> Result<i32, Error>"), "got: {}", code);
+    assert!(code.contains("enum Error {\n    Io(io::Error),\n    ParseInt(ParseIntError),\n}"), "got: {}", code);
+    assert!(code.contains("impl std::error::Error for Error {}"), "got: {}", code);
+    assert!(code.contains("impl From<ParseIntError> for Error {"), "got: {}", code);
+    assert!(code.contains("Error::Io(error) => write!(f, \"{}\", error),"), "got: {}", code);
+}
@@ -0,0 +1,112 @@
+//! Tests for source maps from generated Rust back to HighRust.
+//!
+//! These tests verify that statements, functions and embedded Rust map to
+//! the source lines they come from, that maps are written alongside the
+//! generated files and account for the lines added above the module's code,
+//! and that `// hrs:N` comments are emitted on request.
+
+mod test_utils;
+
+use highrust_transpiler::codegen::{generate_rust_code, CodegenContext};
+use highrust_transpiler::lowering::lower_module;
+use highrust_transpiler::parser::parse;
+use highrust_transpiler::sourcemap::{map_file, LineIndex, Position, SourceMap};
+use highrust_transpiler::{transpile_file, transpile_project, transpile_source_with, TranspileOptions};
+use std::path::Path;
+use test_utils::scratch_dir;
+
+/// Generates Rust for `source`, recording a source map.
+fn generate(source: &str) -> (String, SourceMap) {
+    let module = parse(source).unwrap_or_else(|e| panic!("parsing failed: {:?}", e));
+    let lowered = lower_module(&module).unwrap_or_else(|e| panic!("lowering failed: {:?}", e));
+    let mut ctx = CodegenContext::new();
+    ctx.line_index = Some(LineIndex::new(source));
+    let generated = generate_rust_code(&lowered, &mut ctx).unwrap();
+    (generated.code, generated.source_map)
+}
+
+/// Returns the one-based line of `code` containing `text`.
+fn line_of(code: &str, text: &str) -> usize {
+    code.lines().position(|line| line.contains(text)).unwrap_or_else(|| panic!("no `{}` in: {}", text, code)) + 1
+}
+
+#[test]
+fn test_statements_map_to_their_source_lines() {
+    let source = "fn main() {\n    let x: i32 = 1;\n\n    if x > 0 {\n        println(\"{}\", x);\n    }\n}\n";
+    let (code, map) = generate(source);
+    assert_eq!(map.lookup(Position::new(1, 1)), Some(Position::new(1, 1)));
+    let print = line_of(&code, "println!");
+    assert_eq!(map.lookup(Position::new(line_of(&code, "let x"), 5)), Some(Position::new(2, 5)));
+    assert_eq!(map.lookup(Position::new(line_of(&code, "if x > 0"), 8)), Some(Position::new(4, 5)));
+    assert_eq!(map.lookup(Position::new(print, 9)), Some(Position::new(5, 9)));
+    // A position inside a statement maps to the statement's start
+    assert_eq!(map.lookup(Position::new(print, 20)), Some(Position::new(5, 9)));
+}
+
+#[test]
+fn test_embedded_rust_maps_line_by_line() {
+    let source = "fn main() {\n    rust {\n        let v = vec![1, 2];\n        println!(\"{:?}\", v);\n    }\n}\n";
+    let (code, map) = generate(source);
+    assert_eq!(map.lookup(Position::new(line_of(&code, "let v"), 5)), Some(Position::new(3, 9)));
+    assert_eq!(map.lookup(Position::new(line_of(&code, "println!"), 5)), Some(Position::new(4, 9)));
+}
+
+#[test]
+fn test_transpile_file_writes_source_map() {
+    let dir = scratch_dir("source-map-file");
+    let input = dir.join("main.hrs");
+    let output = dir.join("main.rs");
+    std::fs::write(&input, "fn main() {\n    println(\"hi\");\n}\n").unwrap();
+    transpile_file(&input, &output).unwrap_or_else(|e| panic!("transpilation failed: {:?}", e));
+
+    assert_eq!(map_file(&output), dir.join("main.rs.map"));
+    let map = SourceMap::read_for(&output).unwrap();
+    assert_eq!(map.file.as_deref(), Some("main.rs"));
+    assert_eq!(map.source, Some(std::path::absolute(&input).unwrap()));
+    assert_eq!(map.lookup(Position::new(2, 5)), Some(Position::new(2, 5)));
+    assert_eq!(SourceMap::from_json(&map.to_json()).unwrap(), map);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_project_maps_account_for_mod_declarations() {
+    let root = scratch_dir("source-map-project");
+    let write = |relative: &str, contents: &str| std::fs::write(root.join(relative), contents).unwrap();
+    write("main.hrs", "import util\n\nfn main() {\n    util::greet();\n}\n");
+    write("util.hrs", "export fn greet() {\n    println(\"hi\");\n}\n");
+    let out = root.join("out");
+    transpile_project(root.as_path(), out.as_path()).unwrap_or_else(|e| panic!("transpilation failed: {:?}", e));
+
+    let main = std::fs::read_to_string(out.join("main.rs")).unwrap();
+    assert!(main.starts_with("mod util;\n\n"), "got: {}", main);
+    let map = SourceMap::read_for(&out.join("main.rs")).unwrap();
+    assert_eq!(map.lookup(Position::new(line_of(&main, "fn main"), 1)), Some(Position::new(3, 1)));
+    assert_eq!(map.lookup(Position::new(line_of(&main, "util::greet"), 5)), Some(Position::new(4, 5)));
+    assert_eq!(map.source.as_deref(), Some(std::path::absolute(root.join("main.hrs")).unwrap().as_path()));
+    assert!(Path::new(&out.join("util.rs.map")).is_file());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_line_comments() {
+    let source = "fn main() {\n    let s: String = \"a\\nb\";\n    println(\"{}\", s);\n}\n";
+    let options = TranspileOptions { line_comments: true, ..TranspileOptions::default() };
+    let code = transpile_source_with(source, &options).unwrap_or_else(|e| panic!("transpilation failed: {:?}", e));
+    assert!(code.starts_with("fn main() { // hrs:1\n"), "got: {}", code);
+    assert!(code.contains("; // hrs:2\n"), "got: {}", code);
+    assert!(code.contains("println!(\"{}\", s); // hrs:3\n"), "got: {}", code);
+    assert!(!transpile_source_with(source, &TranspileOptions::default()).unwrap().contains("hrs:"));
+}
+
+#[test]
+fn test_line_index_offsets() {
+    let index = LineIndex::new("fn main() {\n    let é = 1;\n}\n");
+    assert_eq!(index.offset(Position::new(1, 1)), Some(0));
+    assert_eq!(index.offset(Position::new(2, 10)), Some(22));
+    assert_eq!(index.position(22), Position::new(2, 10));
+    // The end of a line is a position, past it is not
+    assert_eq!(index.offset(Position::new(2, 15)), Some(27));
+    assert_eq!(index.offset(Position::new(2, 16)), None);
+    assert_eq!(index.offset(Position::new(9, 1)), None);
+    assert_eq!(index.offset(Position::new(1, 0)), None);
+}
@@ -3,12 +3,15 @@
 //! These tests verify that the transpiler automatically inserts appropriate
 //! .to_string() conversions where needed.
 
+mod test_utils;
+
 use highrust_transpiler::{
-    ast::{Block, Expr, FunctionDef, Literal, Module, ModuleItem, Pattern, Span, Stmt, Type},
+    ast::{Block, DataDef, DataKind, Expr, Field, FunctionDef, Literal, Module, ModuleItem, Param, Pattern, Span, Stmt, Type},
     lowering::lower_module,
     codegen::{generate_rust_code, CodegenContext},
     ownership::OwnershipInference,
 };
+use test_utils::transpile;
 
 /// Helper function to create a span for testing.
 fn test_span() -> Span {
@@ -58,7 +61,7 @@ fn test_string_literal_to_string_conversion() {
     
     // Generate Rust code with the ownership analysis
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     
     // Verify that the generated code includes .to_string()
     assert!(code.contains(".to_string()"), 
@@ -126,9 +129,187 @@ fn test_string_concat_conversion() {
     
     // Generate Rust code with the ownership analysis
     let mut ctx = CodegenContext::with_analysis(analysis_result);
-    let code = generate_rust_code(&lowered, &mut ctx).unwrap();
+    let code = generate_rust_code(&lowered, &mut ctx).unwrap().code;
     
     // Verify that the generated code includes .to_string() for string concatenation
     assert!(code.contains(".to_string()"), 
             "Generated code should include .to_string() conversion for string concatenation, but got: {}", code);
-}
\ No newline at end of file
+}
+/// Runs the full pipeline on a module and returns the generated Rust code.
+fn transpile_module(module: &Module) -> String {
+    let ownership_inference = OwnershipInference::new();
+    let analysis_result = ownership_inference.analyze_module(module);
+    let lowered = lower_module(module).unwrap();
+    let mut ctx = CodegenContext::with_analysis(analysis_result);
+    generate_rust_code(&lowered, &mut ctx).unwrap().code
+}
+
+fn named(name: &str) -> Type {
+    Type::Named(name.to_string(), vec![])
+}
+
+fn str_ref() -> Type {
+    Type::Named("&".to_string(), vec![named("str")])
+}
+
+fn function(name: &str, params: Vec<Param>, ret_type: Option<Type>, stmts: Vec<Stmt>) -> ModuleItem {
+    ModuleItem::Function(FunctionDef {
+        name: name.to_string(),
+        params,
+        ret_type,
+        body: Block { stmts, span: test_span() },
+        is_async: false,
+        is_rust: false,
+        span: test_span(),
+    })
+}
+
+fn param(name: &str, ty: Type) -> Param {
+    Param { name: name.to_string(), ty: Some(ty), span: test_span() }
+}
+
+fn var(name: &str) -> Expr {
+    Expr::Variable(name.to_string(), test_span())
+}
+
+fn string_lit(value: &str) -> Expr {
+    Expr::Literal(Literal::String(value.to_string()), test_span())
+}
+
+fn call(name: &str, args: Vec<Expr>) -> Expr {
+    Expr::Call { func: Box::new(var(name)), args, span: test_span() }
+}
+
+fn let_stmt(name: &str, ty: Option<Type>, value: Expr) -> Stmt {
+    Stmt::Let {
+        pattern: Pattern::Variable(name.to_string(), test_span()),
+        value,
+        ty,
+        span: test_span(),
+    }
+}
+
+#[test]
+fn test_concat_converts_left_operand_only() {
+    // let name = "World"; let greeting = "Hello, " + name;
+    let module = Module {
+        items: vec![function("concat", vec![], None, vec![
+            let_stmt("name", None, string_lit("World")),
+            let_stmt("greeting", None, call("+", vec![string_lit("Hello, "), var("name")])),
+        ])],
+        span: test_span(),
+    };
+    let code = transpile_module(&module);
+    assert!(code.contains("let greeting = \"Hello, \".to_string() + name;"), "got: {}", code);
+    assert!(code.contains("let name = \"World\";"), "name should stay a &str: {}", code);
+}
+
+#[test]
+fn test_concat_with_string_rhs_uses_as_str() {
+    // fn join(a: String, b: String) { let c = a + b; }
+    let module = Module {
+        items: vec![function("join", vec![param("a", named("String")), param("b", named("String"))], None, vec![
+            let_stmt("c", None, call("+", vec![var("a"), var("b")])),
+        ])],
+        span: test_span(),
+    };
+    let code = transpile_module(&module);
+    assert!(code.contains("let c = a + b.as_str();"), "got: {}", code);
+}
+
+#[test]
+fn test_function_param_conversions() {
+    // fn greet(name: String) {}
+    // fn shout(msg: &str) {}
+    // fn main(owned: String) { greet("Ada"); shout(owned); }
+    let module = Module {
+        items: vec![
+            function("greet", vec![param("name", named("String"))], None, vec![]),
+            function("shout", vec![param("msg", str_ref())], None, vec![]),
+            function("run", vec![param("owned", named("String"))], None, vec![
+                Stmt::Expr(call("greet", vec![string_lit("Ada")])),
+                Stmt::Expr(call("shout", vec![var("owned")])),
+            ]),
+        ],
+        span: test_span(),
+    };
+    let code = transpile_module(&module);
+    assert!(code.contains("greet(\"Ada\".to_string());"), "got: {}", code);
+    assert!(code.contains("shout(owned.as_str());"), "got: {}", code);
+}
+
+#[test]
+fn test_return_and_collection_conversions() {
+    // fn label() -> String { return "none"; }
+    // fn names() { let v: Vec<String> = vec("a", "b"); }
+    let module = Module {
+        items: vec![
+            function("label", vec![], Some(named("String")), vec![
+                Stmt::Return(Some(string_lit("none")), test_span()),
+            ]),
+            function("names", vec![], None, vec![
+                let_stmt("v", Some(Type::Named("Vec".to_string(), vec![named("String")])),
+                    call("vec", vec![string_lit("a"), string_lit("b")])),
+            ]),
+        ],
+        span: test_span(),
+    };
+    let code = transpile_module(&module);
+    assert!(code.contains("return \"none\".to_string();"), "got: {}", code);
+    assert!(code.contains("vec![\"a\".to_string(), \"b\".to_string()]"), "got: {}", code);
+}
+
+#[test]
+fn test_struct_field_conversion() {
+    // data User { name: String, nick: &str }
+    // fn make() { let u = User { name: "Ada", nick: "ada" }; }
+    let module = Module {
+        items: vec![
+            ModuleItem::Data(DataDef {
+                name: "User".to_string(),
+                kind: DataKind::Struct(vec![
+                    Field { name: "name".to_string(), ty: named("String"), span: test_span() },
+                    Field { name: "nick".to_string(), ty: str_ref(), span: test_span() },
+                ]),
+                generics: vec![],
+                span: test_span(),
+            }),
+            function("make", vec![], None, vec![
+                let_stmt("u", None, Expr::Struct {
+                    name: "User".to_string(),
+                    fields: vec![
+                        ("name".to_string(), string_lit("Ada")),
+                        ("nick".to_string(), string_lit("ada")),
+                    ],
+                    span: test_span(),
+                }),
+            ]),
+        ],
+        span: test_span(),
+    };
+    let code = transpile_module(&module);
+    assert!(code.contains("User { name: \"Ada\".to_string(), nick: \"ada\" }"), "got: {}", code);
+}
+
+#[test]
+fn test_no_conversion_outside_string_slots() {
+    // fn log(msg: &str) { println("{}", msg); let copy = msg; }
+    let module = Module {
+        items: vec![function("log", vec![param("msg", str_ref())], None, vec![
+            Stmt::Expr(call("println", vec![string_lit("{}"), var("msg")])),
+            let_stmt("copy", None, var("msg")),
+        ])],
+        span: test_span(),
+    };
+    let code = transpile_module(&module);
+    assert!(!code.contains(".to_string()"), "no conversion expected: {}", code);
+    assert!(!code.contains(".as_str()"), "no conversion expected: {}", code);
+}
+
+#[test]
+fn test_struct_literal_fields_convert_from_source() {
+    let source = "data User = { name: String, nick: &str }\n\nfn make(first: &str) {\n    let u = User { name: first, nick: \"ada\" };\n    if ready { println(\"ready\"); }\n}\n";
+    let code = transpile(source);
+    assert!(code.contains("User { name: first.to_string(), nick: \"ada\" }"), "got: {}", code);
+    assert!(code.contains("if ready {"), "got: {}", code);
+}
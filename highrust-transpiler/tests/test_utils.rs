@@ -1,8 +1,12 @@
 //! Test utilities for the HighRust transpiler
 //!
 //! This module provides common utilities for testing the HighRust transpiler,
-//! including functions for running golden file tests.
+//! including functions for running golden file tests and for setting up
+//! scratch projects.
 
+#![allow(dead_code)]
+
+use highrust_transpiler::transpile_source;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -15,7 +19,7 @@ pub fn get_fixture_files(dir: &str, extension: &str) -> Vec<PathBuf> {
                 let path = entry.path();
                 if path.is_dir() {
                     visit_dirs(&path, extension, files)?;
-                } else if path.is_file() && path.extension().map_or(false, |ext| ext == extension) {
+                } else if path.is_file() && path.extension().is_some_and(|ext| ext == extension) {
                     files.push(path);
                 }
             }
@@ -26,7 +30,7 @@ pub fn get_fixture_files(dir: &str, extension: &str) -> Vec<PathBuf> {
     let fixtures_dir = Path::new(dir);
     let mut files = Vec::new();
     visit_dirs(fixtures_dir, extension, &mut files)
-        .expect(&format!("Failed to read fixtures directory: {}", dir));
+        .unwrap_or_else(|_| panic!("Failed to read fixtures directory: {}", dir));
     files
 }
 
@@ -42,13 +46,31 @@ pub fn get_expected_path(fixture_path: &Path, input_dir: &str, output_dir: &str)
 
 /// Read the content of a file
 pub fn read_file_content(path: &Path) -> String {
-    fs::read_to_string(path).expect(&format!("Failed to read file: {}", path.display()))
+    fs::read_to_string(path).unwrap_or_else(|_| panic!("Failed to read file: {}", path.display()))
 }
 
 /// Write content to a file, creating parent directories if needed
 pub fn write_file_content(path: &Path, content: &str) {
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).expect(&format!("Failed to create directory: {}", parent.display()));
+        fs::create_dir_all(parent).unwrap_or_else(|_| panic!("Failed to create directory: {}", parent.display()));
     }
-    fs::write(path, content).expect(&format!("Failed to write file: {}", path.display()));
-}
\ No newline at end of file
+    fs::write(path, content).unwrap_or_else(|_| panic!("Failed to write file: {}", path.display()));
+}
+
+/// Creates an empty scratch directory for a test.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("highrust-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes `contents` to `dir/relative`, creating parent directories.
+pub fn write_file(dir: &Path, relative: &str, contents: &str) {
+    write_file_content(&dir.join(relative), contents);
+}
+
+/// Transpiles `source`, panicking with the error on failure.
+pub fn transpile(source: &str) -> String {
+    transpile_source(source).unwrap_or_else(|e| panic!("transpilation failed: {:?}", e))
+}
@@ -0,0 +1,80 @@
+//! Tests for try/catch lowering in the HighRust transpiler.
+//!
+//! These tests verify that try bodies become labeled blocks evaluating to a
+//! `Result`, that `?` inside them targets the block, and that catch clauses
+//! bind the error.
+
+mod test_utils;
+
+use test_utils::transpile;
+
+#[test]
+fn test_try_catch_lowers_to_labeled_block() {
+    let code = transpile(
+        "fn parse(s: &str) -> Result<i32, ParseIntError> { return s.parse(); }
+         fn run(input: &str) {
+             try {
+                 let n = parse(input)?;
+                 println(\"{}\", n);
+             } catch e {
+                 println(\"failed: {}\", e);
+             }
+         }",
+    );
+    assert!(code.contains("if let Err(e) = 'try_block: {"), "got: {}", code);
+    assert!(
+        code.contains("let n = match parse(input) { Ok(value) => value, Err(error) => break 'try_block Err(error.into()) };"),
+        "got: {}",
+        code
+    );
+    assert!(code.contains("Ok::<(), ParseIntError>(())"), "got: {}", code);
+    assert!(code.contains("println!(\"failed: {}\", e);"), "got: {}", code);
+}
+
+#[test]
+fn test_question_mark_targets_innermost_try() {
+    let code = transpile(
+        "fn parse(s: &str) -> Result<i32, ParseIntError> { return s.parse(); }
+         fn run(a: &str, b: &str) -> Result<i32, ParseIntError> {
+             try {
+                 parse(a)?;
+                 try {
+                     parse(b)?;
+                 } catch inner {
+                     println(\"inner\");
+                 }
+             } catch outer {
+                 println(\"outer\");
+             }
+             return parse(a)?;
+         }",
+    );
+    assert!(code.contains("break 'try_block Err(error.into())"), "got: {}", code);
+    assert!(code.contains("if let Err(inner) = 'try_block_1: {"), "got: {}", code);
+    assert!(code.contains("break 'try_block_1 Err(error.into())"), "got: {}", code);
+    // Outside any try block, `?` still propagates from the function
+    assert!(code.contains("parse(a)?"), "got: {}", code);
+}
+
+#[test]
+fn test_mixed_error_types_are_boxed() {
+    let code = transpile(
+        "fn parse(s: &str) -> Result<i32, ParseIntError> { return s.parse(); }
+         fn read(path: &str) -> Result<String, IoError> { return load(path); }
+         fn run(path: &str) {
+             try {
+                 let text = read(path)?;
+                 parse(text)?;
+             } catch {
+                 println(\"something failed\");
+             }
+             try {
+                 parse(path)?;
+             }
+         }",
+    );
+    assert!(code.contains("if let Err(_) = 'try_block: {"), "got: {}", code);
+    assert!(code.contains("Ok::<(), Box<dyn std::error::Error>>(())"), "got: {}", code);
+    // Without a catch clause, the error is discarded
+    assert!(code.contains("let _ = 'try_block: {"), "got: {}", code);
+}